@@ -0,0 +1,180 @@
+use once_cell::sync::Lazy;
+use stat_common::server_status::CgroupStat;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLE_PERIOD: u64 = 10_000;
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+// 只遍历 root/xxx.slice/xxx.service 这几层
+const MAX_DEPTH: usize = 3;
+// 按内存排序后上报的个数
+const TOP: usize = 20;
+
+static RESULTS: Lazy<Mutex<Vec<CgroupStat>>> = Lazy::new(Default::default);
+
+// cpu.stat 中的 usage_usec
+fn parse_usage_usec(content: &str) -> Option<u64> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .and_then(|o| o.trim().parse().ok())
+}
+
+// memory.current 减去 memory.stat 中的 inactive_file, 与 cAdvisor 的 working set 相同
+fn working_set(current: &str, stat: &str) -> Option<u64> {
+    let current = current.trim().parse::<u64>().ok()?;
+    let inactive = stat
+        .lines()
+        .find_map(|line| line.strip_prefix("inactive_file "))
+        .and_then(|o| o.trim().parse::<u64>().ok())
+        .unwrap_or_default();
+    Some(current.saturating_sub(inactive))
+}
+
+// 只统计 systemd 的 slice 及 service
+fn is_unit(name: &str) -> bool {
+    name.ends_with(".slice") || name.ends_with(".service")
+}
+
+// (名称, cpu 累计使用时间 usec, 内存)
+fn walk(dir: &Path, depth: usize, out: &mut Vec<(String, u64, u64)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !path.is_dir() || !is_unit(&name) {
+            continue;
+        }
+        let read = |file: &str| std::fs::read_to_string(path.join(file)).unwrap_or_default();
+        if let (Some(usec), Some(memory)) = (
+            parse_usage_usec(&read("cpu.stat")),
+            working_set(&read("memory.current"), &read("memory.stat")),
+        ) {
+            out.push((name, usec, memory));
+        }
+        if depth < MAX_DEPTH {
+            walk(&path, depth + 1, out);
+        }
+    }
+}
+
+// 根据两次采样的 usage_usec 计算占全部 cpu 的百分比, 按内存从大到小取前 TOP 个
+fn compute(
+    samples: Vec<(String, u64, u64)>,
+    last: &HashMap<String, u64>,
+    elapsed_usec: u64,
+    cpus: usize,
+) -> Vec<CgroupStat> {
+    let mut results = samples
+        .into_iter()
+        .map(|(name, usec, memory)| {
+            let cpu = match (last.get(&name), elapsed_usec) {
+                (Some(&prev), 1..) if usec >= prev => {
+                    let percent = (usec - prev) as f64 * 100.0 / (elapsed_usec as f64 * cpus.max(1) as f64);
+                    (percent.min(100.0) * 10.0).round() / 10.0
+                }
+                _ => 0.0,
+            };
+            CgroupStat { name, cpu, memory }
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| b.memory.cmp(&a.memory).then_with(|| a.name.cmp(&b.name)));
+    results.truncate(TOP);
+    results
+}
+
+// 只支持 cgroup v2(unified), 其它情况不上报
+pub fn start_cgroup_collect_t() {
+    let root = Path::new(CGROUP_ROOT);
+    if !root.join("cgroup.controllers").exists() {
+        warn!("cgroup v2 not found at {CGROUP_ROOT}, cgroup stats disabled");
+        return;
+    }
+    let cpus = thread::available_parallelism().map(|o| o.get()).unwrap_or(1);
+    thread::spawn(move || {
+        let mut last = HashMap::new();
+        let mut last_at = Instant::now();
+        loop {
+            let mut samples = Vec::new();
+            walk(root, 1, &mut samples);
+            let now = Instant::now();
+            let elapsed = match last.is_empty() {
+                true => 0,
+                false => now.duration_since(last_at).as_micros() as u64,
+            };
+            let next = samples
+                .iter()
+                .map(|(name, usec, _)| (name.to_string(), *usec))
+                .collect();
+            let results = compute(samples, &last, elapsed, cpus);
+            last = next;
+            last_at = now;
+            if let Ok(mut o) = RESULTS.lock() {
+                *o = results;
+            }
+
+            thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+        }
+    });
+}
+
+pub fn results() -> Vec<CgroupStat> {
+    RESULTS.lock().map(|o| o.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute() {
+        assert_eq!(parse_usage_usec("usage_usec 1500\nuser_usec 1000\n"), Some(1500));
+        assert_eq!(parse_usage_usec("user_usec 1000\n"), None);
+        assert_eq!(
+            working_set("1048576\n", "anon 10\ninactive_file 524288\n"),
+            Some(524288)
+        );
+        assert_eq!(working_set("max\n", ""), None);
+        assert!(is_unit("nginx.service") && is_unit("user.slice") && !is_unit("session-1.scope"));
+
+        let last = HashMap::from([("nginx.service".to_string(), 1_000_000)]);
+        let samples = vec![
+            ("nginx.service".to_string(), 3_000_000, 100),
+            ("redis.service".to_string(), 5_000_000, 200),
+        ];
+        // 10s 内使用了 2s cpu, 共 2 个 cpu
+        let o = compute(samples, &last, 10_000_000, 2);
+        assert_eq!(
+            o.iter().map(|o| (o.name.as_str(), o.cpu, o.memory)).collect::<Vec<_>>(),
+            [("redis.service", 0.0, 200), ("nginx.service", 10.0, 100)]
+        );
+
+        let root = std::env::temp_dir().join(format!("cgroup-{}", std::process::id()));
+        for (dir, usec, memory) in [
+            ("system.slice", 9, 300),
+            ("system.slice/nginx.service", 3, 100),
+            ("init.scope", 1, 1),
+        ] {
+            let path = root.join(dir);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("cpu.stat"), format!("usage_usec {usec}\n")).unwrap();
+            std::fs::write(path.join("memory.current"), format!("{memory}\n")).unwrap();
+        }
+        let mut samples = Vec::new();
+        walk(&root, 1, &mut samples);
+        samples.sort();
+        assert_eq!(
+            samples,
+            [
+                ("nginx.service".to_string(), 3, 100),
+                ("system.slice".to_string(), 9, 300)
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
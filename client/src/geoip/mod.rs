@@ -3,7 +3,7 @@ use serde::Deserialize;
 use stat_common::server_status::IpInfo;
 use std::time::Duration;
 
-use crate::Args;
+use crate::{with_proxy, Args};
 
 mod ip_api_com;
 mod ip_sb;
@@ -31,7 +31,7 @@ pub async fn do_http_req<T>(ip_api_url: &'static str, args: &Args) -> Result<IpI
 where
     T: for<'de> Deserialize<'de> + Default + Clone + Send + Sync + 'static + Into<IpInfo>,
 {
-    let http_client = reqwest::Client::builder()
+    let http_client = with_proxy(reqwest::Client::builder(), args)?
         .pool_max_idle_per_host(1)
         .connect_timeout(Duration::from_secs(5))
         .user_agent(random_agent())
@@ -2,17 +2,21 @@
 use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
-use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
-use tonic::{metadata::MetadataValue, Request};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity, Uri};
+use tonic::{metadata::MetadataValue, Code, Request};
+use tower::service_fn;
 use tower::timeout::Timeout;
 use url::Url;
 
 use stat_common::server_status::server_status_client::ServerStatusClient;
 use stat_common::server_status::StatRequest;
 
-use crate::sample_all;
+use crate::status;
+use crate::tunnel;
 use crate::Args;
+use crate::{mesh, watch, report_interval, sample_all, set_clock_skew, set_retry_after, set_server_interval, DEFAULT_RETRY_AFTER};
 
+#[allow(clippy::result_large_err)]
 pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<()> {
     let auth_user: String;
     let ssr_auth: &[u8];
@@ -23,16 +27,23 @@ pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<
         auth_user = args.gid.to_string();
         ssr_auth = b"group";
     }
-    let token = MetadataValue::try_from(format!("{}@_@{}", auth_user, args.pass))?;
+    let token = match args.token.is_empty() {
+        true => MetadataValue::try_from(format!("{}@_@{}", auth_user, args.pass))?,
+        false => MetadataValue::try_from(format!("Bearer {}", args.token))?,
+    };
 
     let addr = args.addr.replace("grpcs://", "https://");
-    let channel: Channel;
+    let mut endpoint = Channel::from_shared(addr.clone())?;
     // mTLS
     if args.mtls {
         let u = Url::parse(addr.as_str())?;
 
         let tls_dir = std::path::PathBuf::from_str(&args.tls_dir)?;
-        let ca = std::fs::read_to_string(tls_dir.join("ca.pem"))?;
+        let ca = if args.ca_cert.is_empty() {
+            std::fs::read_to_string(tls_dir.join("ca.pem"))?
+        } else {
+            std::fs::read_to_string(&args.ca_cert)?
+        };
         let client_cert = std::fs::read_to_string(tls_dir.join("client.pem"))?;
         let client_key = std::fs::read_to_string(tls_dir.join("client.key"))?;
         let client_identity = Identity::from_pem(client_cert, client_key);
@@ -42,17 +53,42 @@ pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<
             .domain_name(u.host_str().expect("invalid domain"))
             .ca_certificate(ca)
             .identity(client_identity);
-        channel = Channel::from_shared(addr)?.tls_config(tls)?.connect().await?;
-    } else {
+        endpoint = endpoint.tls_config(tls)?;
+    } else if addr.starts_with("https://") {
         // TLS
-        if addr.starts_with("https://") {
-            let tls = ClientTlsConfig::new();
-            channel = Channel::from_shared(addr)?.tls_config(tls)?.connect().await?;
-        } else {
-            channel = Channel::from_shared(addr)?.connect().await?;
+        let mut tls = ClientTlsConfig::new();
+        if !args.ca_cert.is_empty() {
+            tls = tls.ca_certificate(Certificate::from_pem(std::fs::read_to_string(&args.ca_cert)?));
         }
+        endpoint = endpoint.tls_config(tls)?;
     }
 
+    // 直连时按实际可达的协议族标记 online4/online6
+    let u = Url::parse(addr.as_str())?;
+    let host = u.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_string();
+    if args.proxy.is_empty() || tunnel::bypass(&args.no_proxy, &host) {
+        let port = u.port_or_known_default().unwrap_or(80);
+        let prefer_v6 = args.ipv6;
+        if let Ok(Ok(tcp_addr)) =
+            tokio::task::spawn_blocking(move || status::reachable_addr((host.as_str(), port), prefer_v6)).await
+        {
+            if tcp_addr.is_ipv4() {
+                stat_base.online4 = true;
+            } else {
+                stat_base.online6 = true;
+            }
+        }
+    }
+
+    // 经代理建立 tcp 连接, tls 由 tonic 在其上完成
+    let (proxy, no_proxy) = (args.proxy.to_string(), args.no_proxy.to_string());
+    let channel = endpoint
+        .connect_with_connector(service_fn(move |uri: Uri| {
+            let (proxy, no_proxy) = (proxy.clone(), no_proxy.clone());
+            async move { tunnel::connect(&proxy, &no_proxy, uri).await }
+        }))
+        .await?;
+
     let timeout_channel = Timeout::new(channel, Duration::from_millis(3000));
     let grpc_client = ServerStatusClient::with_interceptor(timeout_channel, move |mut req: Request<()>| {
         req.metadata_mut().insert("authorization", token.clone());
@@ -72,6 +108,20 @@ pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<
             match client.report(request).await {
                 Ok(resp) => {
                     info!("grpc report resp => {:?}", resp);
+                    set_server_interval(resp.get_ref().interval.into());
+                    set_clock_skew(resp.get_ref().skew);
+                    mesh::set_targets(resp.get_ref().mesh.clone());
+                    watch::set_targets(resp.get_ref().watch.clone());
+                }
+                Err(status) if status.code() == Code::ResourceExhausted => {
+                    set_retry_after(
+                        status
+                            .metadata()
+                            .get("retry-after")
+                            .and_then(|v| v.to_str().ok())
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(DEFAULT_RETRY_AFTER),
+                    );
                 }
                 Err(status) => {
                     error!("grpc report status => {:?}", status);
@@ -79,6 +129,6 @@ pub async fn report(args: &Args, stat_base: &mut StatRequest) -> anyhow::Result<
             }
         });
 
-        thread::sleep(Duration::from_secs(args.report_interval));
+        thread::sleep(report_interval(args));
     }
 }
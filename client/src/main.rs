@@ -6,8 +6,8 @@ use clap::Parser;
 use hyper::header;
 use once_cell::sync::Lazy;
 use prost::Message;
-use std::net::ToSocketAddrs;
 use std::process;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
@@ -18,11 +18,15 @@ use std::thread::sleep;
 use stat_common::server_status::{IpInfo, StatRequest, SysInfo};
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
+mod cgroup;
 mod geoip;
 mod grpc;
+mod mesh;
 mod status;
 mod sys_info;
+mod tunnel;
 mod vnstat;
+mod watch;
 
 static CU: &str = "cu.tz.cloudcpp.com:80";
 static CT: &str = "ct.tz.cloudcpp.com:80";
@@ -36,6 +40,55 @@ pub struct ClientConfig {
 
 pub static G_CONFIG: Lazy<Mutex<ClientConfig>> = Lazy::new(|| Mutex::new(ClientConfig::default()));
 
+// 服务端下发的上报间隔(s), 0 表示使用本地配置
+pub static G_SERVER_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+// 服务端返回的本机时钟偏差(s), 超过 CLOCK_SKEW_WARN 时告警
+const CLOCK_SKEW_WARN: i64 = 10;
+pub static G_CLOCK_SKEW: AtomicI64 = AtomicI64::new(0);
+
+// 服务端过载时返回的 Retry-After(s), 下一轮上报前退避
+pub const DEFAULT_RETRY_AFTER: u64 = 5;
+pub static G_RETRY_AFTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn report_interval(args: &Args) -> Duration {
+    let interval = match G_SERVER_INTERVAL.load(Ordering::Relaxed) {
+        0 => Duration::from_secs(args.report_interval),
+        v => Duration::from_secs(v),
+    };
+    match G_RETRY_AFTER.swap(0, Ordering::Relaxed) {
+        0 => interval,
+        // 加随机抖动, 避免大量客户端同时重试
+        v => {
+            let base = interval.max(Duration::from_secs(v));
+            base + Duration::from_millis(fastrand::u64(0..=base.as_millis() as u64 / 2))
+        }
+    }
+}
+
+pub fn set_retry_after(retry_after: u64) {
+    warn!("server is busy, back off {}s", retry_after);
+    G_RETRY_AFTER.store(retry_after.max(1), Ordering::Relaxed);
+}
+
+pub fn set_server_interval(interval: u64) {
+    let pre = G_SERVER_INTERVAL.swap(interval, Ordering::Relaxed);
+    if pre != interval {
+        info!("server suggested report interval => {}s", interval);
+    }
+}
+
+pub fn set_clock_skew(skew: i64) {
+    let pre = G_CLOCK_SKEW.swap(skew, Ordering::Relaxed);
+    let skewed = skew.abs() >= CLOCK_SKEW_WARN;
+    if skewed && (pre.abs() < CLOCK_SKEW_WARN || (pre - skew).abs() >= CLOCK_SKEW_WARN) {
+        let side = if skew > 0 { "ahead of" } else { "behind" };
+        warn!("local clock is {}s {} the server, please check ntp", skew.abs(), side);
+    } else if !skewed && pre.abs() >= CLOCK_SKEW_WARN {
+        info!("local clock is in sync with the server");
+    }
+}
+
 // https://docs.rs/clap/latest/clap/_derive/index.html#command-attributes
 #[derive(Parser, Debug, Clone)]
 #[command(author, version = env!("APP_VERSION"), about, long_about = None)]
@@ -46,6 +99,13 @@ pub struct Args {
     user: String,
     #[arg(short, long, env = "SSR_PASS", default_value = "p1", help = "password")]
     pass: String,
+    #[arg(
+        long,
+        env = "SSR_TOKEN",
+        default_value = "",
+        help = "per-host token issued by the server, used instead of user/pass"
+    )]
+    token: String,
     #[arg(short = 'n', long, env = "SSR_VNSTAT", help = "enable vnstat, default:false")]
     vnstat: bool,
     #[arg(
@@ -74,6 +134,24 @@ pub struct Args {
         help = "disable ping, default:false"
     )]
     disable_ping: bool,
+    #[arg(
+        long = "disable-mesh",
+        env = "SSR_DISABLE_MESH",
+        help = "disable latency mesh between hosts, default:false"
+    )]
+    disable_mesh: bool,
+    #[arg(
+        long = "disable-watch",
+        env = "SSR_DISABLE_WATCH",
+        help = "disable checking processes/systemd units watched by the server, default:false"
+    )]
+    disable_watch: bool,
+    #[arg(
+        long = "cgroups",
+        env = "SSR_CGROUPS",
+        help = "report cpu/memory of systemd slices and services (cgroup v2), default:false"
+    )]
+    cgroups: bool,
     #[arg(
         long = "disable-extra",
         env = "SSR_DISABLE_EXTRA",
@@ -169,10 +247,24 @@ pub struct Args {
         help = "exclude iface"
     )]
     exclude_iface: Vec<String>,
-    #[arg(long, env = "SSR_PROXY", default_value = "", help = "proxy")]
+    #[arg(long, env = "SSR_PROXY", default_value = "", help = "proxy for report & ip info, eg: socks5h://127.0.0.1:1080")]
     proxy: String,
     #[arg(long, env = "SSR_NO_PROXY", default_value = "", help = "no proxy, eg: ip-api.com")]
     no_proxy: String,
+    #[arg(long = "ca-cert", env = "SSR_CA_CERT", default_value = "", help = "extra CA cert (pem) to verify the server")]
+    ca_cert: String,
+}
+
+// 上报和 ip 信息查询共用的代理设置
+pub fn with_proxy(builder: reqwest::ClientBuilder, args: &Args) -> anyhow::Result<reqwest::ClientBuilder> {
+    if args.proxy.is_empty() {
+        return Ok(builder);
+    }
+    let mut proxy = reqwest::Proxy::all(&args.proxy)?;
+    if !args.no_proxy.is_empty() {
+        proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&args.no_proxy));
+    }
+    Ok(builder.proxy(proxy))
 }
 
 impl Args {
@@ -200,6 +292,9 @@ fn sample_all(args: &Args, stat_base: &StatRequest) -> StatRequest {
     sys_info::sample(args, &mut stat_rt);
 
     stat_rt.latest_ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    stat_rt.mesh = mesh::results();
+    stat_rt.watch = watch::results();
+    stat_rt.cgroups = cgroup::results();
 
     if !args.disable_extra {
         if let Ok(o) = G_CONFIG.lock() {
@@ -216,19 +311,20 @@ fn sample_all(args: &Args, stat_base: &StatRequest) -> StatRequest {
 }
 
 fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
-    let mut domain = args.addr.split('/').collect::<Vec<&str>>()[2].to_owned();
-    if !domain.contains(':') {
-        if args.addr.contains("https") {
-            domain = format!("{domain}:443");
-        } else {
-            domain = format!("{domain}:80");
-        }
-    }
+    // 支持 http://[::1]:8080/report 形式的 ipv6 地址
+    let url = reqwest::Url::parse(&args.addr)?;
+    let host = url
+        .host_str()
+        .ok_or("invalid server addr")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
     let mut retries = 0;
     let max_retries = 10; // Indicates a maximum of 1024s delay = 17 minutes
     let tcp_addr = loop {
-        match domain.to_socket_addrs() {
-            Ok(mut addrs) => break addrs.next().unwrap(),
+        match status::reachable_addr((host.as_str(), port), args.ipv6) {
+            Ok(addr) => break addr,
             Err(e) => {
                 if retries >= max_retries {
                     return Err(Box::new(e)); // Return the error if retries are exhausted
@@ -248,20 +344,18 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
         stat_base.online6 = ipv6;
     }
 
-    let mut http_client_builder = reqwest::Client::builder()
+    let mut http_client_builder = with_proxy(reqwest::Client::builder(), args)?
         .pool_max_idle_per_host(1)
         .connect_timeout(Duration::from_secs(5))
         .user_agent(format!("{}/{}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION")));
-
-    if !args.proxy.is_empty() {
-        let mut proxy = reqwest::Proxy::all(&args.proxy)?;
-        if !args.no_proxy.is_empty() {
-            proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&args.no_proxy));
-        }
-
-        http_client_builder = http_client_builder.proxy(proxy);
+    // 固定使用探测到的可达地址, 避免双栈域名解析到不通的协议族
+    if host.parse::<std::net::IpAddr>().is_err() {
+        http_client_builder = http_client_builder.resolve(&host, tcp_addr);
+    }
+    if !args.ca_cert.is_empty() {
+        let pem = std::fs::read(&args.ca_cert)?;
+        http_client_builder = http_client_builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
     }
-
     let http_client = http_client_builder.build()?;
     loop {
         let stat_rt = sample_all(args, stat_base);
@@ -284,6 +378,7 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
         let client = http_client.clone();
         let url = args.addr.to_string();
         let auth_pass = args.pass.to_string();
+        let auth_token = args.token.to_string();
         let auth_user: String;
         let ssr_auth: &str;
         if args.gid.is_empty() {
@@ -296,9 +391,11 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
 
         // http
         tokio::spawn(async move {
-            match client
-                .post(&url)
-                .basic_auth(auth_user, Some(auth_pass))
+            let req = match auth_token.is_empty() {
+                true => client.post(&url).basic_auth(auth_user, Some(auth_pass)),
+                false => client.post(&url).bearer_auth(auth_token),
+            };
+            match req
                 .timeout(Duration::from_secs(3))
                 .header(header::CONTENT_TYPE.as_str(), content_type)
                 .header("ssr-auth", ssr_auth)
@@ -306,8 +403,23 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
                 .send()
                 .await
             {
+                Ok(resp) if resp.status().as_u16() == 429 => {
+                    set_retry_after(
+                        resp.headers()
+                            .get("retry-after")
+                            .and_then(|v| v.to_str().ok())
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(DEFAULT_RETRY_AFTER),
+                    );
+                }
                 Ok(resp) => {
                     info!("report resp => {:?}", resp);
+                    if let Ok(ack) = resp.json::<serde_json::Value>().await {
+                        set_server_interval(ack["interval"].as_u64().unwrap_or(0));
+                        set_clock_skew(ack["skew"].as_i64().unwrap_or(0));
+                        mesh::set_targets(serde_json::from_value(ack["mesh"].clone()).unwrap_or_default());
+                        watch::set_targets(serde_json::from_value(ack["watch"].clone()).unwrap_or_default());
+                    }
                 }
                 Err(err) => {
                     error!("report error => {:?}", err);
@@ -315,7 +427,7 @@ fn http_report(args: &Args, stat_base: &mut StatRequest) -> Result<()> {
             }
         });
 
-        thread::sleep(Duration::from_secs(args.report_interval));
+        thread::sleep(report_interval(args));
     }
 }
 
@@ -393,6 +505,15 @@ async fn main() -> Result<()> {
     }
 
     status::start_all_ping_collect_t(&args);
+    if !args.disable_mesh {
+        mesh::start_mesh_collect_t();
+    }
+    if !args.disable_watch {
+        watch::start_watch_collect_t();
+    }
+    if args.cgroups {
+        cgroup::start_cgroup_collect_t();
+    }
     let (ipv4, ipv6) = status::get_network(&args);
     eprintln!("get_network (ipv4, ipv6) => ({ipv4}, {ipv6})");
 
@@ -0,0 +1,101 @@
+use once_cell::sync::Lazy;
+use stat_common::server_status::{MeshResult, MeshTarget};
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind::ConnectionRefused;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT_MS: u64 = 1000;
+const SAMPLE_PERIOD: u64 = 5000;
+// 每个目标保留的采样数
+const SAMPLES: usize = 20;
+// 同时探测的目标数
+const CONCURRENCY: usize = 16;
+
+// 服务端下发的探测目标
+static TARGETS: Lazy<Mutex<Vec<MeshTarget>>> = Lazy::new(Default::default);
+// name => 最近的采样, None 为丢包
+static HISTORY: Lazy<Mutex<HashMap<String, VecDeque<Option<u32>>>>> = Lazy::new(Default::default);
+
+pub fn set_targets(targets: Vec<MeshTarget>) {
+    if let Ok(mut o) = TARGETS.lock() {
+        if *o != targets {
+            info!("mesh targets => {:?}", targets);
+            *o = targets;
+        }
+    }
+}
+
+fn ping(addr: &str) -> Option<u32> {
+    let addr = addr.parse::<SocketAddr>().ok()?;
+    let instant = Instant::now();
+    match TcpStream::connect_timeout(&addr, Duration::from_millis(TIMEOUT_MS)) {
+        Ok(s) => {
+            let _ = s.shutdown(Shutdown::Both);
+        }
+        Err(e) if e.kind() == ConnectionRefused => {}
+        Err(_) => return None,
+    }
+    Some(instant.elapsed().as_millis() as u32)
+}
+
+fn summary(samples: &VecDeque<Option<u32>>) -> (u32, u32) {
+    let ok = samples.iter().flatten().collect::<Vec<_>>();
+    let latency = match ok.len() {
+        0 => 0,
+        n => ok.into_iter().sum::<u32>() / n as u32,
+    };
+    let loss = (samples.len() - samples.iter().flatten().count()) * 100 / samples.len().max(1);
+    (latency, loss as u32)
+}
+
+pub fn start_mesh_collect_t() {
+    thread::spawn(|| loop {
+        let targets = TARGETS.lock().map(|o| o.clone()).unwrap_or_default();
+        let mut results = Vec::with_capacity(targets.len());
+        for chunk in targets.chunks(CONCURRENCY) {
+            thread::scope(|s| {
+                let handles = chunk.iter().map(|o| s.spawn(|| ping(&o.addr))).collect::<Vec<_>>();
+                for (o, h) in chunk.iter().zip(handles) {
+                    results.push((o.name.to_string(), h.join().unwrap_or_default()));
+                }
+            });
+        }
+
+        if let Ok(mut history) = HISTORY.lock() {
+            history.retain(|name, _| targets.iter().any(|o| &o.name == name));
+            for (name, r) in results {
+                let samples = history.entry(name).or_default();
+                if samples.len() >= SAMPLES {
+                    samples.pop_front();
+                }
+                samples.push_back(r);
+            }
+        }
+
+        thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+    });
+}
+
+// 各目标的平均延迟(ms)及丢包率(%)
+pub fn results() -> Vec<MeshResult> {
+    let Ok(history) = HISTORY.lock() else {
+        return Vec::new();
+    };
+    let mut o = history
+        .iter()
+        .filter(|(_, samples)| !samples.is_empty())
+        .map(|(name, samples)| {
+            let (latency, loss) = summary(samples);
+            MeshResult {
+                name: name.to_string(),
+                latency,
+                loss,
+            }
+        })
+        .collect::<Vec<_>>();
+    o.sort_by(|a, b| a.name.cmp(&b.name));
+    o
+}
@@ -10,7 +10,7 @@ use std::io::BufRead;
 use std::io::BufReader;
 use std::io::ErrorKind::ConnectionRefused;
 use std::net::TcpStream;
-use std::net::{Shutdown, ToSocketAddrs};
+use std::net::{Shutdown, SocketAddr, ToSocketAddrs};
 use std::process::Command;
 use std::str;
 use std::sync::Arc;
@@ -164,6 +164,45 @@ fn get_zfs_pools() -> Vec<(String, u64, u64)> {
     }
 }
 
+// /dev/disk/by-uuid 中指向该设备的文件系统 UUID, 找不到时为空
+pub fn device_id(device: &str) -> String {
+    let Ok(device) = fs::canonicalize(device) else {
+        return String::new();
+    };
+    fs::read_dir("/dev/disk/by-uuid")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .find(|o| fs::canonicalize(o.path()).is_ok_and(|p| p == device))
+        .map(|o| o.file_name().to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// 挂载点 => (inode 总数, 已用), 需要 GNU df; btrfs 等不固定 inode 数的文件系统为 0
+static DF_INODE_CMD: [&str; 2] = ["-l", "--output=target,itotal,iused"];
+
+fn parse_inodes(content: &str) -> HashMap<String, (u64, u64)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // 挂载点可能包含空格, 数值在最后两列
+            let mut cols = line.split_whitespace().rev();
+            let used = cols.next()?.parse::<u64>().unwrap_or(0);
+            let total = cols.next()?.parse::<u64>().unwrap_or(0);
+            let target = cols.rev().collect::<Vec<_>>().join(" ");
+            Some((target, (total, used))).filter(|o| !o.0.is_empty())
+        })
+        .collect()
+}
+
+pub fn get_inodes() -> HashMap<String, (u64, u64)> {
+    match Command::new("df").args(DF_INODE_CMD).output() {
+        Ok(output) if output.status.success() => parse_inodes(&String::from_utf8_lossy(&output.stdout)),
+        _ => HashMap::new(),
+    }
+}
+
 pub fn get_hdd(stat: &mut StatRequest) {
     let (mut hdd_total, mut hdd_used) = (0, 0);
     let a = &Command::new("/bin/sh")
@@ -172,6 +211,8 @@ pub fn get_hdd(stat: &mut StatRequest) {
         .expect("failed to execute df")
         .stdout;
 
+    let inodes = get_inodes();
+
     let _ = str::from_utf8(a).map(|content| {
         let vs = content.trim().split('\n').collect::<Vec<&str>>().to_vec();
         let mut zfs_found = false;
@@ -200,6 +241,9 @@ pub fn get_hdd(stat: &mut StatRequest) {
                     total: vec[2].parse::<u64>().unwrap() * 1024 * 1024,
                     used: vec[3].parse::<u64>().unwrap() * 1024 * 1024,
                     free: vec[4].parse::<u64>().unwrap() * 1024 * 1024,
+                    device_id: device_id(vec[0]),
+                    inode_total: inodes.get(vec[6]).map(|o| o.0).unwrap_or_default(),
+                    inode_used: inodes.get(vec[6]).map(|o| o.1).unwrap_or_default(),
                 };
                 stat.disks.push(di);
             }
@@ -215,6 +259,8 @@ pub fn get_hdd(stat: &mut StatRequest) {
                     total: total * 1024 * 1024,
                     used: used * 1024 * 1024,
                     free: (total - used) * 1024 * 1024,
+                    device_id: String::new(),
+                    ..Default::default()
                 };
                 stat.disks.push(di);
             }
@@ -329,8 +375,9 @@ pub fn get_network(args: &Args) -> (bool, bool) {
     }
     let addrs = vec![&args.ipv4_address, &args.ipv6_address];
     for (idx, probe_addr) in addrs.into_iter().enumerate() {
+        // 只探测对应协议族的地址
         let _ = probe_addr.to_socket_addrs().map(|mut iter| {
-            if let Some(addr) = iter.next() {
+            if let Some(addr) = iter.find(|a| a.is_ipv6() == (idx == 1)) {
                 info!("{} => {}", probe_addr, addr);
 
                 let r = TcpStream::connect_timeout(&addr, Duration::from_millis(TIMEOUT_MS)).map(|s| {
@@ -346,6 +393,30 @@ pub fn get_network(args: &Args) -> (bool, bool) {
     network.into()
 }
 
+// 同时解析出 ipv4/ipv6 地址时, 返回第一个可连通的地址, 都不通则返回第一个; prefer_v6 时优先尝试 ipv6
+pub fn reachable_addr<A: ToSocketAddrs>(addr: A, prefer_v6: bool) -> std::io::Result<SocketAddr> {
+    let mut addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+    if prefer_v6 {
+        addrs.sort_by_key(|a| !a.is_ipv6());
+    }
+    if addrs.len() > 1 {
+        for addr in addrs.iter() {
+            match TcpStream::connect_timeout(addr, Duration::from_millis(TIMEOUT_MS)) {
+                Ok(s) => {
+                    let _ = s.shutdown(Shutdown::Both);
+                    return Ok(*addr);
+                }
+                Err(e) if e.kind() == ConnectionRefused => return Ok(*addr),
+                Err(e) => info!("{} => {:?}", addr, e),
+            }
+        }
+    }
+    addrs
+        .first()
+        .copied()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address resolved"))
+}
+
 #[derive(Debug, Default)]
 pub struct PingData {
     pub probe_uri: String,
@@ -353,16 +424,11 @@ pub struct PingData {
     pub ping_time: u32,
 }
 
-fn start_ping_collect_t(data: &Arc<Mutex<PingData>>) {
+fn start_ping_collect_t(data: &Arc<Mutex<PingData>>, prefer_v6: bool) {
     let mut package_list: LinkedList<i32> = LinkedList::new();
     let mut package_lost: u32 = 0;
     let pt = &*data.lock().unwrap();
-    let addr = pt
-        .probe_uri
-        .to_socket_addrs()
-        .unwrap()
-        .next()
-        .expect("can't get addr info");
+    let addr = reachable_addr(pt.probe_uri.as_str(), prefer_v6).expect("can't get addr info");
     info!("{} => {:?}", pt.probe_uri, addr);
 
     let ping_data = data.clone();
@@ -428,9 +494,9 @@ pub fn start_all_ping_collect_t(args: &Args) {
         .unwrap();
 
     if !args.disable_ping {
-        start_ping_collect_t(G_PING_10010.get().unwrap());
-        start_ping_collect_t(G_PING_189.get().unwrap());
-        start_ping_collect_t(G_PING_10086.get().unwrap());
+        start_ping_collect_t(G_PING_10010.get().unwrap(), args.ipv6);
+        start_ping_collect_t(G_PING_189.get().unwrap(), args.ipv6);
+        start_ping_collect_t(G_PING_10086.get().unwrap(), args.ipv6);
     }
 }
 
@@ -495,3 +561,19 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
         stat.time_10086 = o.ping_time.into();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inodes() {
+        let content = "Mounted on         Inodes  IUsed\n/                 6553600 412345\n/mnt/my disk      1000    10\n/data                   0      0\n";
+        let o = parse_inodes(content);
+        assert_eq!(o.len(), 3);
+        assert_eq!(o["/"], (6553600, 412345));
+        assert_eq!(o["/mnt/my disk"], (1000, 10));
+        assert_eq!(o["/data"], (0, 0));
+        assert!(parse_inodes("Mounted on Inodes IUsed\n").is_empty());
+    }
+}
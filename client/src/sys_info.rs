@@ -166,6 +166,7 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
     let mut uniq_disk_set = HashSet::new();
 
     let disks = Disks::new_with_refreshed_list();
+    let inodes = status::get_inodes();
     let mut zfs_found = false;
     
     for disk in &disks {
@@ -175,13 +176,18 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
             continue;  // 跳过 ZFS 文件系统，避免重复计算
         }
         
+        let mount_point = disk.mount_point().to_str().unwrap().to_string();
+        let (inode_total, inode_used) = inodes.get(&mount_point).copied().unwrap_or_default();
         let di = DiskInfo {
             name: disk.name().to_str().unwrap().to_string(),
-            mount_point: disk.mount_point().to_str().unwrap().to_string(),
+            mount_point,
             file_system: fs.clone(),
             total: disk.total_space(),
             used: disk.total_space() - disk.available_space(),
             free: disk.available_space(),
+            device_id: status::device_id(disk.name().to_str().unwrap_or_default()),
+            inode_total,
+            inode_used,
         };
 
         let fs = fs.to_lowercase();
@@ -212,6 +218,8 @@ pub fn sample(args: &Args, stat: &mut StatRequest) {
                 total,
                 used,
                 free: total - used,
+                device_id: String::new(),
+                ..Default::default()
             };
             stat.disks.push(di);
         }
@@ -0,0 +1,180 @@
+// grpc 通过代理连接服务端, 支持 http CONNECT 与 socks5
+use std::io::{Error, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tonic::transport::Uri;
+use url::Url;
+
+fn io_err<E: ToString>(e: E) -> Error {
+    Error::other(e.to_string())
+}
+
+fn target_of(uri: &Uri) -> Result<(String, u16)> {
+    let host = uri.host().ok_or_else(|| io_err("missing host"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+    Ok((host, port))
+}
+
+// no_proxy: 逗号分隔, 完全匹配或后缀匹配
+pub fn bypass(no_proxy: &str, host: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|s| s.trim().trim_start_matches('.'))
+        .filter(|s| !s.is_empty())
+        .any(|s| s == "*" || host == s || host.ends_with(&format!(".{s}")))
+}
+
+pub async fn connect(proxy: &str, no_proxy: &str, uri: Uri) -> Result<TcpStream> {
+    let (host, port) = target_of(&uri)?;
+    if proxy.is_empty() || bypass(no_proxy, &host) {
+        return TcpStream::connect((host.as_str(), port)).await;
+    }
+
+    let proxy = Url::parse(proxy).map_err(io_err)?;
+    let proxy_host = proxy.host_str().ok_or_else(|| io_err("invalid proxy host"))?;
+    let proxy_host = proxy_host.trim_start_matches('[').trim_end_matches(']');
+    let proxy_port = proxy.port_or_known_default().unwrap_or(1080);
+    let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+
+    match proxy.scheme() {
+        "http" => http_connect(&mut stream, &proxy, &host, port).await?,
+        "socks5" | "socks5h" => socks5_connect(&mut stream, &proxy, &host, port).await?,
+        scheme => return Err(io_err(format!("unsupported proxy scheme `{scheme}`"))),
+    }
+    Ok(stream)
+}
+
+async fn http_connect(stream: &mut TcpStream, proxy: &Url, host: &str, port: u16) -> Result<()> {
+    let authority = if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut req = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if !proxy.username().is_empty() {
+        let cred = format!("{}:{}", proxy.username(), proxy.password().unwrap_or(""));
+        req.push_str(&format!("Proxy-Authorization: Basic {}\r\n", base64_encode(cred.as_bytes())));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).await?;
+
+    // 读到空行为止
+    let mut buf = Vec::with_capacity(256);
+    let mut b = [0_u8; 1];
+    while !buf.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut b).await? == 0 || buf.len() > 8192 {
+            return Err(io_err("proxy closed connection"));
+        }
+        buf.push(b[0]);
+    }
+    let status = String::from_utf8_lossy(&buf);
+    let code = status.split_whitespace().nth(1).unwrap_or("");
+    if code != "200" {
+        return Err(io_err(format!("proxy CONNECT failed: {}", status.lines().next().unwrap_or(""))));
+    }
+    Ok(())
+}
+
+async fn socks5_connect(stream: &mut TcpStream, proxy: &Url, host: &str, port: u16) -> Result<()> {
+    let auth = !proxy.username().is_empty();
+    // 0x00 无认证, 0x02 用户名密码
+    if auth {
+        stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
+    } else {
+        stream.write_all(&[0x05, 0x01, 0x00]).await?;
+    }
+    let mut resp = [0_u8; 2];
+    stream.read_exact(&mut resp).await?;
+    match resp {
+        [0x05, 0x00] => {}
+        [0x05, 0x02] if auth => {
+            let user = proxy.username().as_bytes();
+            let pass = proxy.password().unwrap_or("").as_bytes();
+            let mut req = vec![0x01, user.len() as u8];
+            req.extend_from_slice(user);
+            req.push(pass.len() as u8);
+            req.extend_from_slice(pass);
+            stream.write_all(&req).await?;
+            stream.read_exact(&mut resp).await?;
+            if resp[1] != 0x00 {
+                return Err(io_err("socks5 auth failed"));
+            }
+        }
+        _ => return Err(io_err("socks5 no acceptable auth method")),
+    }
+
+    let mut req = vec![0x05, 0x01, 0x00];
+    match host.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(ip)) => {
+            req.push(0x01);
+            req.extend_from_slice(&ip.octets());
+        }
+        Ok(std::net::IpAddr::V6(ip)) => {
+            req.push(0x04);
+            req.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            req.push(0x03);
+            req.push(host.len() as u8);
+            req.extend_from_slice(host.as_bytes());
+        }
+    }
+    req.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&req).await?;
+
+    let mut head = [0_u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0x00 {
+        return Err(io_err(format!("socks5 connect failed, code {}", head[1])));
+    }
+    // 跳过绑定地址
+    let skip = match head[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        _ => return Err(io_err("socks5 invalid reply")),
+    };
+    let mut rest = vec![0_u8; skip + 2];
+    stream.read_exact(&mut rest).await?;
+    Ok(())
+}
+
+fn base64_encode(input: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b"user:pass"), "dXNlcjpwYXNz");
+        assert_eq!(base64_encode(b"a"), "YQ==");
+        assert_eq!(base64_encode(b"ab"), "YWI=");
+    }
+
+    #[test]
+    fn test_bypass() {
+        assert!(bypass("localhost,.example.com", "api.example.com"));
+        assert!(bypass("localhost,.example.com", "localhost"));
+        assert!(!bypass("localhost,.example.com", "example.org"));
+        assert!(!bypass("", "localhost"));
+    }
+}
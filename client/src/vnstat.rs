@@ -223,6 +223,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_json_v1_d31() {
         // v1.15 版本不支持参数 --json d 31
         assert!(true);
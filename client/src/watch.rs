@@ -0,0 +1,195 @@
+use once_cell::sync::Lazy;
+use stat_common::server_status::WatchResult;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use sysinfo::System;
+
+const SAMPLE_PERIOD: u64 = 10_000;
+const SYSTEMD_PREFIX: &str = "systemd:";
+const PORT_PREFIX: &str = "port:";
+const CONNECT_TIMEOUT_MS: u64 = 500;
+
+// 服务端下发的进程名, systemd:<unit> 或 port:<port>[/udp]
+static TARGETS: Lazy<Mutex<Vec<String>>> = Lazy::new(Default::default);
+static RESULTS: Lazy<Mutex<Vec<WatchResult>>> = Lazy::new(Default::default);
+
+pub fn set_targets(targets: Vec<String>) {
+    if let Ok(mut o) = TARGETS.lock() {
+        if *o != targets {
+            info!("watch targets => {:?}", targets);
+            *o = targets;
+        }
+    }
+}
+
+// 进程名或可执行文件名相同即匹配, linux 的进程名最长 15 个字符
+fn matches(target: &str, name: &str, exe: Option<&Path>) -> bool {
+    name == target
+        || exe.and_then(|o| o.file_name()).is_some_and(|o| o == target)
+        || (name.len() == 15 && target.starts_with(name))
+}
+
+fn check_processes(sys: &System, target: &str) -> WatchResult {
+    let count = sys
+        .processes()
+        .values()
+        .filter(|p| matches(target, p.name(), p.exe()))
+        .count() as u32;
+    WatchResult {
+        name: target.to_string(),
+        running: count > 0,
+        state: if count > 0 { "running" } else { "missing" }.to_string(),
+        count,
+    }
+}
+
+fn check_unit(target: &str, unit: &str) -> WatchResult {
+    let state = Command::new("systemctl")
+        .args(["show", "-p", "ActiveState", "--value", unit])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|o| !o.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    WatchResult {
+        name: target.to_string(),
+        running: state == "active" || state == "reloading",
+        state,
+        count: 0,
+    }
+}
+
+// 443 或 53/udp, 返回 (端口, 是否 udp)
+fn parse_port(spec: &str) -> Option<(u16, bool)> {
+    let (port, udp) = match spec.split_once('/') {
+        Some((port, "udp")) => (port, true),
+        Some((port, "tcp")) => (port, false),
+        Some(_) => return None,
+        None => (spec, false),
+    };
+    port.parse().ok().filter(|&o| o > 0).map(|o| (o, udp))
+}
+
+// /proc/net/{tcp,udp}{,6} 中本地端口为 port 且处于 LISTEN(0A)/未连接(07) 状态的 socket 数
+#[cfg(target_os = "linux")]
+fn proc_listening(port: u16, udp: bool) -> Option<u32> {
+    let (files, state) = match udp {
+        true => (["/proc/net/udp", "/proc/net/udp6"], "07"),
+        false => (["/proc/net/tcp", "/proc/net/tcp6"], "0A"),
+    };
+    let mut found = None;
+    for file in files {
+        let Ok(content) = std::fs::read_to_string(file) else {
+            continue;
+        };
+        let n = content
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let local_port = fields.get(1)?.rsplit_once(':')?.1;
+                Some((u16::from_str_radix(local_port, 16).ok()?, *fields.get(3)?))
+            })
+            .filter(|&(p, s)| p == port && s == state)
+            .count() as u32;
+        found = Some(found.unwrap_or(0) + n);
+    }
+    found
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_listening(_port: u16, _udp: bool) -> Option<u32> {
+    None
+}
+
+// 没有 /proc 时, tcp 尝试连接本机, udp 尝试绑定, 端口已被占用即视为在侦听
+fn probe_listening(port: u16, udp: bool) -> u32 {
+    let listening = match udp {
+        true => UdpSocket::bind(("0.0.0.0", port)).is_err_and(|e| e.kind() == ErrorKind::AddrInUse),
+        false => ["127.0.0.1", "[::1]"].iter().any(|host| {
+            format!("{host}:{port}")
+                .parse::<SocketAddr>()
+                .is_ok_and(|addr| TcpStream::connect_timeout(&addr, Duration::from_millis(CONNECT_TIMEOUT_MS)).is_ok())
+        }),
+    };
+    listening as u32
+}
+
+fn check_port(target: &str, spec: &str) -> WatchResult {
+    let Some((port, udp)) = parse_port(spec) else {
+        return WatchResult {
+            name: target.to_string(),
+            running: false,
+            state: "invalid".to_string(),
+            count: 0,
+        };
+    };
+    let count = proc_listening(port, udp).unwrap_or_else(|| probe_listening(port, udp));
+    WatchResult {
+        name: target.to_string(),
+        running: count > 0,
+        state: if count > 0 { "listening" } else { "closed" }.to_string(),
+        count,
+    }
+}
+
+pub fn start_watch_collect_t() {
+    thread::spawn(|| {
+        let mut sys = System::new();
+        loop {
+            let targets = TARGETS.lock().map(|o| o.clone()).unwrap_or_default();
+            if targets.iter().any(|o| !o.starts_with(SYSTEMD_PREFIX) && !o.starts_with(PORT_PREFIX)) {
+                sys.refresh_processes();
+            }
+            let results = targets
+                .iter()
+                .map(|target| {
+                    if let Some(unit) = target.strip_prefix(SYSTEMD_PREFIX) {
+                        check_unit(target, unit)
+                    } else if let Some(spec) = target.strip_prefix(PORT_PREFIX) {
+                        check_port(target, spec)
+                    } else {
+                        check_processes(&sys, target)
+                    }
+                })
+                .collect::<Vec<_>>();
+            if let Ok(mut o) = RESULTS.lock() {
+                *o = results;
+            }
+
+            thread::sleep(Duration::from_millis(SAMPLE_PERIOD));
+        }
+    });
+}
+
+pub fn results() -> Vec<WatchResult> {
+    RESULTS.lock().map(|o| o.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port() {
+        assert_eq!(parse_port("443"), Some((443, false)));
+        assert_eq!(parse_port("53/udp"), Some((53, true)));
+        assert_eq!(parse_port("22/tcp"), Some((22, false)));
+        assert_eq!(parse_port("0"), None);
+        assert_eq!(parse_port("53/sctp"), None);
+        assert_eq!(parse_port("http"), None);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let o = check_port("port:x", &port.to_string());
+        assert_eq!((o.running, o.state.as_str()), (true, "listening"));
+        drop(listener);
+        assert!(!check_port("port:x", &port.to_string()).running);
+    }
+}
@@ -27,6 +27,10 @@ fn main() {
 
     tonic_build::configure()
         .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        // 旧版客户端的 json 上报中没有
+        .field_attribute("DiskInfo.device_id", "#[serde(default)]")
+        .field_attribute("DiskInfo.inode_total", "#[serde(default)]")
+        .field_attribute("DiskInfo.inode_used", "#[serde(default)]")
         .compile(&["proto/server_status.proto"], &["proto"])
         .unwrap();
 }
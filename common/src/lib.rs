@@ -1,3 +1,4 @@
+#[allow(clippy::empty_docs)]
 pub mod server_status {
     tonic::include_proto!("server_status");
 }
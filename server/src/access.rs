@@ -0,0 +1,106 @@
+#![deny(warnings)]
+// http 访问日志及按路由的请求统计, 用于排查谁在频繁请求 history.json 等开销大的接口
+use anyhow::Result;
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::AccessLog;
+use crate::metrics;
+
+static WRITER: OnceCell<Mutex<Box<dyn Write + Send>>> = OnceCell::new();
+
+pub fn init(cfg: &AccessLog) -> Result<()> {
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let writer: Box<dyn Write + Send> = match cfg.path.is_empty() {
+        true => Box::new(io::stdout()),
+        false => Box::new(OpenOptions::new().create(true).append(true).open(&cfg.path)?),
+    };
+    let _ = WRITER.set(Mutex::new(writer));
+    Ok(())
+}
+
+// 未匹配路由的请求(静态文件及 404)统一归为 fallback, 避免按路径统计时数量无限增长
+fn route_of(matched: Option<&MatchedPath>) -> &str {
+    matched.map(|o| o.as_str()).unwrap_or("fallback")
+}
+
+// 不记录查询参数, /i 等接口的查询参数中含有密码, 状态码及耗时在响应后补充
+fn entry(req: &Request, route: &str) -> Value {
+    let get = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|o| o.to_str().ok())
+            .unwrap_or_default()
+    };
+    let remote = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|o| o.0.ip().to_string())
+        .unwrap_or_default();
+    json!({
+        "ts": chrono::Utc::now().timestamp(),
+        "remote": remote,
+        "forwarded_for": get("x-forwarded-for"),
+        "method": req.method().as_str(),
+        "path": req.uri().path(),
+        "route": route,
+        "user_agent": get(header::USER_AGENT.as_str()),
+    })
+}
+
+pub async fn track(req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let route = route_of(req.extensions().get::<MatchedPath>()).to_string();
+    let log = WRITER.get().map(|_| entry(&req, &route));
+    let resp = next.run(req).await;
+
+    let (status, ms) = (resp.status().as_u16(), start.elapsed().as_millis() as u64);
+    metrics::observe_route(&route, status, ms);
+    if let (Some(writer), Some(mut log)) = (WRITER.get(), log) {
+        log["status"] = status.into();
+        log["ms"] = ms.into();
+        let mut line = log.to_string();
+        line.push('\n');
+        if let Err(err) = writer.lock().unwrap().write_all(line.as_bytes()) {
+            error!("write access log error => {:?}", err);
+        }
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let mut req = Request::builder()
+            .uri("/i?pass=secret&uid=h1")
+            .header(header::USER_AGENT, "curl/8.0")
+            .header("x-forwarded-for", "10.0.0.1")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo("127.0.0.1:5000".parse::<SocketAddr>().unwrap()));
+        let o = entry(&req, route_of(None));
+        assert_eq!(o["path"], "/i");
+        assert_eq!(o["route"], "fallback");
+        assert_eq!(o["remote"], "127.0.0.1");
+        assert_eq!(o["forwarded_for"], "10.0.0.1");
+        assert_eq!(o["user_agent"], "curl/8.0");
+        assert!(!o.to_string().contains("secret"));
+    }
+}
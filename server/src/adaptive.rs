@@ -0,0 +1,67 @@
+#![deny(warnings)]
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::config::Adaptive;
+use crate::payload::HostStat;
+
+#[derive(Debug, Default)]
+struct SampleState {
+    cpu: f64,
+    network_rx: u64,
+    network_tx: u64,
+    flat: u32,
+}
+
+// 根据上报数据决定下发给客户端的上报间隔
+pub struct Sampler {
+    config: &'static Adaptive,
+    states: Mutex<HashMap<String, SampleState>>,
+}
+
+impl Sampler {
+    pub fn new(cfg: &'static Adaptive) -> Self {
+        Self {
+            config: cfg,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 0: 不干预, 客户端使用自身配置
+    pub fn interval(&self, stat: &HostStat) -> u32 {
+        if !self.config.enabled {
+            return 0;
+        }
+
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(stat.name.to_string()).or_default();
+
+        let anomaly = stat.cpu >= self.config.cpu_threshold
+            || stat.network_rx >= self.config.net_threshold
+            || stat.network_tx >= self.config.net_threshold;
+
+        let flat = (stat.cpu - state.cpu).abs() <= self.config.flat_cpu_delta
+            && stat.network_rx.abs_diff(state.network_rx) <= self.config.flat_net_delta
+            && stat.network_tx.abs_diff(state.network_tx) <= self.config.flat_net_delta;
+
+        state.cpu = stat.cpu;
+        state.network_rx = stat.network_rx;
+        state.network_tx = stat.network_tx;
+
+        if anomaly {
+            state.flat = 0;
+            return self.config.active_interval;
+        }
+
+        if flat {
+            state.flat = state.flat.saturating_add(1);
+        } else {
+            state.flat = 0;
+        }
+
+        if state.flat >= self.config.flat_samples {
+            return self.config.idle_interval;
+        }
+        0
+    }
+}
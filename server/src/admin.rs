@@ -0,0 +1,51 @@
+#![deny(warnings)]
+use axum::{
+    extract::{FromRequestParts, Path, Request},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+
+use crate::assets::{self, StaticFile};
+use crate::http;
+use crate::jwt;
+use crate::live;
+
+// 管理页面, 登录后通过 /api/admin/authorize 获取 jwt
+async fn asset(Path(path): Path<String>) -> impl IntoResponse {
+    StaticFile(format!("/admin/{path}"))
+}
+
+// 管理接口统一校验 jwt, 处理函数不再单独提取
+async fn require_auth(req: Request, next: Next) -> Response {
+    let (mut parts, body) = req.into_parts();
+    if let Err(err) = jwt::Claims::from_request_parts(&mut parts, &()).await {
+        return err.into_response();
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
+
+pub fn router() -> Router {
+    let api = Router::new()
+        .route("/api/admin/:path", get(http::admin_api)) // stats.json || config.json || metrics.json || tasks || expired.json || certs.json || disks.json || notifiers.json || auth_guard.json || events.json || silences.json || rules.json || alerts.json || announcements.json
+        .route("/api/admin/notifiers/:name/test", post(http::admin_notifier_test))
+        .route("/api/admin/hosts/:name/hidden", post(http::admin_host_hidden)) // {"hidden": true}
+        .route("/api/admin/hosts/:name/secret", post(http::admin_host_secret)) // {"secret": "..."}
+        .route("/api/admin/hosts/:name/token", post(http::admin_host_token)) // {} || {"revoke": true}
+        .route("/api/admin/hosts/:name/rename", post(http::admin_host_rename)) // {"to": "new-name"}
+        .route("/api/admin/hosts/:name/silence", post(http::admin_host_silence)) // {"duration": "2h"} || {"ack": true} || {"unsilence": true}
+        .route("/api/admin/hosts/:name/chaos", post(http::admin_host_chaos)) // {"offline": true, "duration": "10m"} || {"offline": false}
+        .route("/api/admin/hosts/:name/disk", post(http::admin_host_disk)) // {"device_id": "<uuid>", "series": "/data"}
+        .route("/api/admin/announcements", post(http::admin_announcement)) // {"message": "...", "severity": "warning", "start": 0, "end": 0} || {"id": 1, ...} || {"id": 1, "delete": true}
+        .route("/api/admin/history/:action", post(http::admin_history)) // delete || clamp || recompute
+        .route_layer(middleware::from_fn(require_auth));
+
+    Router::new()
+        .route("/api/admin/authorize", post(jwt::authorize))
+        .route("/ws/events", get(live::handler)) // ?token=xxx, 浏览器的 WebSocket 不能设置请求头
+        .route("/admin", get(assets::admin_index_handler))
+        .route("/admin/", get(assets::admin_index_handler))
+        .route("/admin/*path", get(asset))
+        .merge(api)
+}
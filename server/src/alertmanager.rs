@@ -0,0 +1,165 @@
+#![deny(warnings)]
+// 接收 Alertmanager webhook, 与本项目的告警使用同一套通知方式/路由/静默
+// 标签 host/hostname/instance 对应到主机名或别名时按主机告警处理, 否则按告警自带的标签路由
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::notifier;
+use crate::payload::HostStat;
+use crate::{G_CONFIG, G_STATS_MGR};
+
+// 按顺序查找主机的标签, instance 去掉端口
+const HOST_LABELS: [&str; 3] = ["host", "hostname", "instance"];
+
+// https://prometheus.io/docs/alerting/latest/configuration/#webhook_config
+#[derive(Debug, Deserialize)]
+pub struct Payload {
+    #[serde(default = "Default::default")]
+    pub alerts: Vec<Alert>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    // firing | resolved
+    pub status: String,
+    #[serde(default = "Default::default")]
+    pub labels: HashMap<String, String>,
+    #[serde(default = "Default::default")]
+    pub annotations: HashMap<String, String>,
+}
+
+impl Alert {
+    fn host_keys(&self) -> impl Iterator<Item = &str> {
+        HOST_LABELS.iter().filter_map(|k| self.labels.get(*k)).map(|v| strip_port(v))
+    }
+
+    fn message(&self, host: Option<&str>) -> String {
+        let name = self.labels.get("alertname").map(String::as_str).unwrap_or("alert");
+        let target = host.map(|o| format!(" {o}")).unwrap_or_default();
+        if self.status == "resolved" {
+            return format!("✅ [{name}]{target} resolved");
+        }
+        let severity = self.labels.get("severity").map(|o| format!(" ({o})")).unwrap_or_default();
+        let summary = ["summary", "description", "message"]
+            .iter()
+            .find_map(|k| self.annotations.get(*k))
+            .map(|o| format!(": {o}"))
+            .unwrap_or_default();
+        format!("❗[{name}]{target}{severity}{summary}")
+    }
+}
+
+// host:port 及 [v6]:port 去掉端口, 不带端口的 ipv6 地址(如 fe80::1:2)保持原样
+fn strip_port(v: &str) -> &str {
+    if let Some((host, port)) = v.strip_prefix('[').and_then(|o| o.split_once("]:")) {
+        if port.parse::<u16>().is_ok() {
+            return host;
+        }
+    }
+    match v.split_once(':') {
+        Some((host, port)) if !port.contains(':') && port.parse::<u16>().is_ok() => host,
+        _ => v,
+    }
+}
+
+fn find_host<'a>(alert: &Alert, servers: &'a [HostStat]) -> Option<&'a HostStat> {
+    alert
+        .host_keys()
+        .find_map(|key| servers.iter().find(|o| o.name == key || o.alias == key))
+}
+
+pub fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|o| o.to_str().ok())
+        .and_then(|o| o.strip_prefix("Bearer "))
+        .is_some_and(|o| !token.is_empty() && constant_time_eq(o.as_bytes(), token.as_bytes()))
+}
+
+// 耗时与内容无关的比较, 避免按响应时间逐字节猜测 token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub async fn handler(headers: HeaderMap, Json(payload): Json<Payload>) -> (StatusCode, Json<Value>) {
+    let token = &G_CONFIG.get().unwrap().ingest.alert_token;
+    if token.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "code": 404, "message": "alert ingest is disabled" })),
+        );
+    }
+    if !authorized(&headers, token) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "code": 401, "message": "invalid token" })),
+        );
+    }
+
+    let count = payload.alerts.len();
+    // 通知方式的发送会阻塞, 不在 runtime 的工作线程内调用
+    tokio::task::spawn_blocking(move || {
+        let stats = G_STATS_MGR.get().unwrap().get_all_stats();
+        for alert in payload.alerts.iter() {
+            match find_host(alert, &stats.servers) {
+                Some(stat) => notifier::alert_host(&alert.message(Some(&stat.alias)), stat),
+                None => notifier::alert_labels(&alert.message(alert.host_keys().next()), &alert.labels),
+            }
+        }
+    });
+    (StatusCode::OK, Json(json!({ "code": 0, "message": "ok", "alerts": count })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert() {
+        let payload: Payload = serde_json::from_value(json!({
+            "version": "4",
+            "status": "firing",
+            "alerts": [
+                {
+                    "status": "firing",
+                    "labels": { "alertname": "HighLoad", "instance": "h1:9100", "severity": "critical" },
+                    "annotations": { "summary": "load is high" },
+                    "startsAt": "2024-01-01T00:00:00Z"
+                },
+                { "status": "resolved", "labels": { "alertname": "Disk", "instance": "[::1]:9100" } },
+                { "status": "firing", "labels": {} },
+                { "status": "firing", "labels": { "instance": "fe80::1:2" } }
+            ]
+        }))
+        .unwrap();
+        let [a, b, c, d] = &payload.alerts[..] else { panic!() };
+        assert_eq!(a.host_keys().collect::<Vec<_>>(), ["h1"]);
+        assert_eq!(a.message(Some("web")), "❗[HighLoad] web (critical): load is high");
+        assert_eq!(b.host_keys().collect::<Vec<_>>(), ["::1"]);
+        assert_eq!(b.message(None), "✅ [Disk] resolved");
+        assert_eq!(c.message(None), "❗[alert]");
+        assert_eq!(d.host_keys().collect::<Vec<_>>(), ["fe80::1:2"]);
+        assert_eq!(strip_port("[fe80::1]:9100"), "fe80::1");
+        assert_eq!(strip_port("h1"), "h1");
+
+        let servers = vec![HostStat {
+            name: "h1".to_string(),
+            ..Default::default()
+        }];
+        assert!(find_host(a, &servers).is_some());
+        assert!(find_host(b, &servers).is_none());
+
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "t"));
+        headers.insert(header::AUTHORIZATION, "Bearer t".parse().unwrap());
+        assert!(authorized(&headers, "t"));
+        assert!(!authorized(&headers, ""));
+        assert!(!authorized(&headers, "u") && !authorized(&headers, "tt"));
+    }
+}
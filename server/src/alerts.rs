@@ -0,0 +1,411 @@
+#![deny(warnings)]
+// 告警规则, 每次上报后按主机检查表达式, 分组规则在 stats.json 重建时按汇总检查, 触发及恢复时各通知一次
+use anyhow::{anyhow, Result};
+use minijinja::context;
+use once_cell::sync::{Lazy, OnceCell};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::config::{AlertRule, Config, Scope};
+use crate::expr::{Agg, Env, Expr};
+use crate::jinja;
+use crate::labels::Selector;
+use crate::notifier;
+use crate::payload::HostStat;
+use crate::summary::Rollup;
+
+const KIND: &str = "alert";
+const DEFAULT_MESSAGE: &str = "❗{{host.alias}} alert {{rule.name}}: {{rule.expr}}";
+const DEFAULT_GROUP_MESSAGE: &str = "❗alert {{rule.name}}: {{rule.expr}}, {{group.online}}/{{group.total}} online";
+
+// HostStat 中可以在表达式中使用的数值字段, 如 cpu, memory_used, load_1
+static METRICS: Lazy<Vec<String>> = Lazy::new(|| match serde_json::to_value(HostStat::default()) {
+    Ok(Value::Object(o)) => o.into_iter().filter(|(_, v)| v.is_number()).map(|(k, _)| k).collect(),
+    _ => vec![],
+});
+// 分组规则可以使用的汇总字段, 另有 offline = total - online
+static GROUP_METRICS: Lazy<Vec<String>> = Lazy::new(|| group_sample(&Rollup::default()).into_keys().collect());
+
+static RULES: OnceCell<Rules> = OnceCell::new();
+static STATE: Lazy<Mutex<State>> = Lazy::new(Default::default);
+
+pub struct Rule {
+    name: String,
+    src: String,
+    expr: Expr,
+    scope: Scope,
+    selector: Selector,
+}
+
+struct Rules {
+    rules: Vec<Rule>,
+    // 规则引用的指标及需要保留的采样时间(s)
+    metrics: Vec<String>,
+    window: u64,
+}
+
+type Sample = (u64, HashMap<String, f64>);
+
+#[derive(Default)]
+struct State {
+    samples: HashMap<String, VecDeque<Sample>>,
+    // (规则, 主机) 不含 duration 的条件开始成立的时间
+    since: HashMap<(usize, String), u64>,
+    firing: HashSet<(usize, String)>,
+}
+
+// 配置加载时检查, 错误信息包含出错的列号
+pub fn compile(rule: &AlertRule) -> Result<Rule> {
+    let metrics = match rule.scope {
+        Scope::Host => &METRICS,
+        Scope::Group => &GROUP_METRICS,
+    };
+    let expr = Expr::parse(&rule.expr, &|o| metrics.iter().any(|m| m == o))?;
+    let selector = Selector::parse(&rule.selector).map_err(|err| anyhow!("invalid selector: {err}"))?;
+    minijinja::Environment::new()
+        .template_from_str(&rule.message)
+        .map_err(|err| anyhow!("invalid message template: {err}"))?;
+    Ok(Rule {
+        name: rule.name.to_string(),
+        src: rule.expr.to_string(),
+        expr,
+        scope: rule.scope,
+        selector,
+    })
+}
+
+pub fn init(cfg: &Config) -> Result<()> {
+    let mut rules = Rules {
+        rules: Vec::new(),
+        metrics: Vec::new(),
+        window: 0,
+    };
+    for o in cfg.alert_rules.iter() {
+        let rule = compile(o)?;
+        let message = match (o.message.is_empty(), o.scope) {
+            (false, _) => o.message.to_string(),
+            (true, Scope::Host) => DEFAULT_MESSAGE.to_string(),
+            (true, Scope::Group) => DEFAULT_GROUP_MESSAGE.to_string(),
+        };
+        jinja::try_add_template(KIND, &rule.name, message, false)?;
+        rule.expr.metrics(&mut rules.metrics);
+        rules.window = rules.window.max(rule.expr.window());
+        rules.rules.push(rule);
+    }
+    if !rules.rules.is_empty() {
+        eprintln!("✨ {} alert rules loaded", rules.rules.len());
+    }
+    let _ = RULES.set(rules);
+    Ok(())
+}
+
+struct HostEnv<'a> {
+    samples: &'a VecDeque<Sample>,
+    now: u64,
+    // None 时 duration() 视为成立, 用于判断其余条件是否成立
+    since: Option<Option<u64>>,
+}
+
+impl Env for HostEnv<'_> {
+    fn metric(&self, name: &str) -> f64 {
+        self.samples
+            .back()
+            .and_then(|(_, o)| o.get(name))
+            .copied()
+            .unwrap_or(f64::NAN)
+    }
+
+    fn window(&self, agg: Agg, name: &str, secs: u64) -> f64 {
+        let values = self
+            .samples
+            .iter()
+            .filter(|(ts, _)| ts + secs >= self.now)
+            .filter_map(|(_, o)| o.get(name).copied());
+        match agg {
+            Agg::Avg => {
+                let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+                sum / n as f64
+            }
+            Agg::Min => values.fold(f64::NAN, f64::min),
+            Agg::Max => values.fold(f64::NAN, f64::max),
+        }
+    }
+
+    fn duration(&self, secs: u64) -> bool {
+        match self.since {
+            None => true,
+            Some(since) => since.is_some_and(|o| self.now >= o + secs),
+        }
+    }
+}
+
+fn sample(metrics: &[String], stat: &HostStat) -> HashMap<String, f64> {
+    let value = serde_json::to_value(stat).unwrap_or_default();
+    metrics
+        .iter()
+        .filter_map(|name| value[name].as_f64().map(|v| (name.to_string(), v)))
+        .collect()
+}
+
+fn group_sample(rollup: &Rollup) -> HashMap<String, f64> {
+    let mut o = match serde_json::to_value(rollup) {
+        Ok(Value::Object(o)) => o
+            .into_iter()
+            .filter_map(|(k, v)| v.as_f64().map(|v| (k, v)))
+            .collect(),
+        _ => HashMap::new(),
+    };
+    o.insert("offline".to_string(), (rollup.total - rollup.online) as f64);
+    o
+}
+
+// 分组规则的采样以 @规则名 为 key, 与主机名区分
+fn group_key(rule: &Rule) -> String {
+    format!("@{}", rule.name)
+}
+
+// key 为主机名或分组规则, matched 为需要检查的规则, 返回状态变化的规则, true 为触发, false 为恢复
+fn evaluate(
+    state: &mut State,
+    rules: &Rules,
+    key: &str,
+    values: HashMap<String, f64>,
+    now: u64,
+    matched: impl Fn(usize, &Rule) -> bool,
+) -> Vec<(usize, bool)> {
+    let State { samples, since, firing } = state;
+    let samples = samples.entry(key.to_string()).or_default();
+    samples.push_back((now, values));
+    while samples.front().is_some_and(|(ts, _)| ts + rules.window < now) {
+        samples.pop_front();
+    }
+
+    let mut changed = Vec::new();
+    for (idx, rule) in rules.rules.iter().enumerate() {
+        if !matched(idx, rule) {
+            continue;
+        }
+        let key = (idx, key.to_string());
+        let mut env = HostEnv {
+            samples,
+            now,
+            since: None,
+        };
+        env.since = match rule.expr.test(&env) {
+            true => Some(Some(*since.entry(key.clone()).or_insert(now))),
+            false => {
+                since.remove(&key);
+                Some(None)
+            }
+        };
+        let fire = rule.expr.test(&env);
+        if fire && firing.insert(key.clone()) {
+            changed.push((idx, true));
+        } else if !fire && firing.remove(&key) {
+            changed.push((idx, false));
+        }
+    }
+    changed
+}
+
+fn evaluate_host(state: &mut State, rules: &Rules, stat: &HostStat, now: u64) -> Vec<(usize, bool)> {
+    let values = sample(&rules.metrics, stat);
+    evaluate(state, rules, &stat.name, values, now, |_, rule| {
+        rule.scope == Scope::Host && rule.selector.matches(|k| stat.label(k))
+    })
+}
+
+// 在入库线程中调用, 通知在单独的线程中发送
+pub fn check(stat: &HostStat, now: u64) {
+    let Some(rules) = RULES.get().filter(|o| !o.rules.is_empty()) else {
+        return;
+    };
+    let changed = evaluate_host(&mut STATE.lock().unwrap(), rules, stat, now);
+    if changed.is_empty() {
+        return;
+    }
+    let msgs = changed
+        .into_iter()
+        .map(|(idx, fire)| {
+            let rule = &rules.rules[idx];
+            match fire {
+                true => {
+                    let ctx = context!(host => stat, rule => context!(name => rule.name, expr => rule.src));
+                    jinja::render_template(KIND, &rule.name, ctx, true).unwrap_or_default()
+                }
+                false => format!("✅ {} alert {} resolved", stat.alias, rule.name),
+            }
+        })
+        .filter(|o| !o.is_empty())
+        .collect::<Vec<_>>();
+    let stat = stat.clone();
+    thread::spawn(move || {
+        for msg in msgs {
+            notifier::alert_host(&msg, &stat);
+        }
+    });
+}
+
+fn evaluate_group(state: &mut State, rules: &Rules, idx: usize, rollup: &Rollup, now: u64) -> Option<bool> {
+    let key = group_key(&rules.rules[idx]);
+    evaluate(state, rules, &key, group_sample(rollup), now, |i, _| i == idx)
+        .first()
+        .map(|(_, fire)| *fire)
+}
+
+// 在 timer 线程中调用, 对选中主机(含离线)的汇总检查, 与主机无关, 发给所有通知方式
+pub fn check_groups(servers: &[HostStat], now: u64) {
+    let Some(rules) = RULES.get() else {
+        return;
+    };
+    let mut msgs = Vec::new();
+    for (idx, rule) in rules.rules.iter().enumerate().filter(|(_, o)| o.scope == Scope::Group) {
+        let hosts = servers
+            .iter()
+            .filter(|o| rule.selector.matches(|k| o.label(k)))
+            .collect::<Vec<_>>();
+        let rollup = Rollup::new(&rule.name, &hosts);
+        let msg = match evaluate_group(&mut STATE.lock().unwrap(), rules, idx, &rollup, now) {
+            Some(true) => {
+                let offline = hosts
+                    .iter()
+                    .filter(|o| !o.online4 && !o.online6)
+                    .map(|o| o.alias.as_str())
+                    .collect::<Vec<_>>();
+                let ctx = context!(group => rollup, offline => offline, rule => context!(name => rule.name, expr => rule.src));
+                jinja::render_template(KIND, &rule.name, ctx, true).unwrap_or_default()
+            }
+            Some(false) => format!("✅ alert {} resolved, {}/{} online", rule.name, rollup.online, rollup.total),
+            None => continue,
+        };
+        if !msg.is_empty() {
+            msgs.push(msg);
+        }
+    }
+    if !msgs.is_empty() {
+        thread::spawn(move || {
+            for msg in msgs {
+                notifier::alert(&msg);
+            }
+        });
+    }
+}
+
+// 管理接口展示的规则及触发中的主机, 分组规则触发时为 @规则名
+pub fn list() -> Vec<Value> {
+    let Some(rules) = RULES.get() else {
+        return vec![];
+    };
+    let state = STATE.lock().unwrap();
+    rules
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(idx, rule)| {
+            let mut firing = state
+                .firing
+                .iter()
+                .filter(|(i, _)| *i == idx)
+                .map(|(_, name)| name.to_string())
+                .collect::<Vec<_>>();
+            firing.sort();
+            json!({ "name": rule.name, "expr": rule.src, "scope": rule.scope, "firing": firing })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, expr: &str) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            expr: expr.to_string(),
+            enabled: true,
+            scope: Scope::Host,
+            selector: String::new(),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert!(compile(&rule("bad", "cpu > 90 && memory_free > 0")).is_err());
+        let rules = [
+            rule("cpu", r#"cpu > 90 && duration("60s")"#),
+            rule("mem", "memory_used / memory_total > 0.95"),
+            rule("avg", r#"avg(cpu, "2m") > 50"#),
+        ]
+        .iter()
+        .map(compile)
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        let mut metrics = Vec::new();
+        rules.iter().for_each(|o| o.expr.metrics(&mut metrics));
+        let rules = Rules {
+            rules,
+            metrics,
+            window: 120,
+        };
+
+        let mut state = State::default();
+        let mut stat = HostStat {
+            name: "h1".to_string(),
+            cpu: 95.0,
+            memory_total: 100,
+            memory_used: 96,
+            ..Default::default()
+        };
+        assert_eq!(evaluate_host(&mut state, &rules, &stat, 1000), [(1, true), (2, true)]);
+        assert_eq!(evaluate_host(&mut state, &rules, &stat, 1030), []);
+        assert_eq!(evaluate_host(&mut state, &rules, &stat, 1060), [(0, true)]);
+
+        stat.cpu = 10.0;
+        stat.memory_used = 10;
+        assert_eq!(evaluate_host(&mut state, &rules, &stat, 1090), [(0, false), (1, false)]);
+        // 2 分钟内的平均值: (95 * 3 + 10 * 2) / 5, 之后 1000 的采样过期: (95 * 2 + 10 * 3) / 5
+        assert_eq!(evaluate_host(&mut state, &rules, &stat, 1120), []);
+        assert_eq!(evaluate_host(&mut state, &rules, &stat, 1150), [(2, false)]);
+        assert_eq!(state.samples["h1"].len(), 5);
+    }
+
+    #[test]
+    fn test_evaluate_group() {
+        let mut down = rule("down", r#"offline >= 2 && duration("60s")"#);
+        down.scope = Scope::Group;
+        assert!(compile(&down).is_ok());
+        let mut bad = rule("bad", "memory_free > 0");
+        bad.scope = Scope::Group;
+        assert!(compile(&bad).is_err());
+        let rules = Rules {
+            rules: vec![compile(&down).unwrap(), compile(&rule("cpu", "cpu > 90")).unwrap()],
+            metrics: vec!["offline".to_string()],
+            window: 60,
+        };
+
+        let mut state = State::default();
+        let mut servers = (0..3)
+            .map(|i| HostStat {
+                name: format!("h{i}"),
+                online4: i == 0,
+                cpu: 95.0,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let hosts = servers.iter().collect::<Vec<_>>();
+        let rollup = Rollup::new("down", &hosts);
+        assert_eq!(evaluate_group(&mut state, &rules, 0, &rollup, 1000), None);
+        assert_eq!(evaluate_group(&mut state, &rules, 0, &rollup, 1060), Some(true));
+        assert!(state.firing.contains(&(0, "@down".to_string())));
+
+        servers[1].online4 = true;
+        let hosts = servers.iter().collect::<Vec<_>>();
+        let rollup = Rollup::new("down", &hosts);
+        assert_eq!(evaluate_group(&mut state, &rules, 0, &rollup, 1090), Some(false));
+        // 主机规则不参与分组检查
+        assert!(!state.firing.iter().any(|(i, _)| *i == 1));
+    }
+}
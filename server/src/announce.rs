@@ -0,0 +1,111 @@
+#![deny(warnings)]
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "warning" => Severity::Warning,
+            "critical" => Severity::Critical,
+            _ => Severity::Info,
+        }
+    }
+}
+
+// 站点公告, 由管理接口维护并保存在 stats.db, 生效期间随 stats.json 及 /config.pub.json 下发
+// start/end 为 unix 时间(s), 0 表示不限
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Announcement {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub start: i64,
+    #[serde(default)]
+    pub end: i64,
+}
+
+impl Announcement {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.start <= now && (self.end == 0 || now < self.end)
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.message.trim().is_empty() {
+            return Err("message is empty");
+        }
+        if self.end > 0 && self.end <= self.start {
+            return Err("end must be later than start");
+        }
+        Ok(())
+    }
+}
+
+// 当前生效的公告, 严重的在前, 同级别新开始的在前
+pub fn active(list: &[Announcement], now: i64) -> Vec<Announcement> {
+    let mut o = list.iter().filter(|o| o.is_active(now)).cloned().collect::<Vec<_>>();
+    o.sort_by_key(|o| Reverse((o.severity as u8, o.start, o.id)));
+    o
+}
+
+// 下一个开始或结束的时间点, stats.json 需在此时重建
+pub fn next_change(list: &[Announcement], now: i64) -> Option<i64> {
+    list.iter().flat_map(|o| [o.start, o.end]).filter(|&ts| ts > now).min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i64, severity: Severity, start: i64, end: i64) -> Announcement {
+        Announcement {
+            id,
+            message: format!("m{id}"),
+            severity,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_active() {
+        let list = vec![
+            item(1, Severity::Info, 0, 0),
+            item(2, Severity::Critical, 100, 200),
+            item(3, Severity::Warning, 150, 0),
+            item(4, Severity::Info, 10, 50),
+        ];
+        let ids = |now| active(&list, now).iter().map(|o| o.id).collect::<Vec<_>>();
+        assert_eq!(ids(60), vec![1]);
+        assert_eq!(ids(160), vec![2, 3, 1]);
+        assert_eq!(ids(200), vec![3, 1]);
+
+        assert_eq!(next_change(&list, 60), Some(100));
+        assert_eq!(next_change(&list, 160), Some(200));
+        assert_eq!(next_change(&list, 200), None);
+
+        assert!(item(5, Severity::Info, 100, 100).validate().is_err());
+        assert!(item(5, Severity::Info, 100, 0).validate().is_ok());
+        assert_eq!(Severity::parse(Severity::Critical.as_str()), Severity::Critical);
+    }
+}
@@ -0,0 +1,476 @@
+#![deny(warnings)]
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::RowAccessor;
+use parquet::schema::parser::parse_message_type;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::Archive;
+use crate::db::{Database, DiskRecord, HostStatRecord};
+
+// 归档的聚合表, 列按 ints / strs / floats / bools 的顺序写入 parquet
+struct Spec {
+    kind: &'static str,
+    table: &'static str,
+    // 前两列固定为 timestamp, interval_minutes
+    ints: &'static [&'static str],
+    strs: &'static [&'static str],
+    floats: &'static [&'static str],
+    bools: &'static [&'static str],
+}
+
+const STATS: Spec = Spec {
+    kind: "stats",
+    table: "aggregated_stats",
+    ints: &["timestamp", "interval_minutes"],
+    strs: &[],
+    floats: &[
+        "cpu_usage",
+        "memory_total",
+        "memory_used",
+        "network_in",
+        "network_out",
+        "network_in_speed",
+        "network_out_speed",
+    ],
+    bools: &["online"],
+};
+
+const DISK: Spec = Spec {
+    kind: "disk",
+    table: "aggregated_disk_stats",
+    ints: &["timestamp", "interval_minutes"],
+    strs: &["mount_point"],
+    // inode 为后来追加的列, 旧的归档文件中没有
+    floats: &["disk_total", "disk_used", "inode_total", "inode_used"],
+    bools: &[],
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Row {
+    ints: Vec<i64>,
+    strs: Vec<String>,
+    floats: Vec<f64>,
+    bools: Vec<bool>,
+}
+
+impl Row {
+    fn ts(&self) -> i64 {
+        self.ints[0]
+    }
+    fn interval(&self) -> i64 {
+        self.ints[1]
+    }
+    // 同一时间段同一挂载点只保留一行, 重复归档时覆盖
+    fn key(&self) -> (Vec<i64>, Vec<String>) {
+        (self.ints.clone(), self.strs.clone())
+    }
+}
+
+impl Spec {
+    fn schema(&self) -> String {
+        let mut fields = Vec::new();
+        fields.extend(self.ints.iter().map(|o| format!("REQUIRED INT64 {o};")));
+        fields.extend(self.strs.iter().map(|o| format!("REQUIRED BYTE_ARRAY {o} (UTF8);")));
+        fields.extend(self.floats.iter().map(|o| format!("REQUIRED DOUBLE {o};")));
+        fields.extend(self.bools.iter().map(|o| format!("REQUIRED BOOLEAN {o};")));
+        format!("message {} {{ {} }}", self.kind, fields.join(" "))
+    }
+
+    fn select(&self) -> String {
+        let mut cols = Vec::new();
+        cols.extend(self.ints.iter().map(|o| format!("CAST(COALESCE({o}, 0) AS INTEGER)")));
+        cols.extend(self.strs.iter().map(|o| format!("COALESCE({o}, '')")));
+        cols.extend(self.floats.iter().map(|o| format!("CAST(COALESCE({o}, 0) AS REAL)")));
+        cols.extend(self.bools.iter().map(|o| format!("COALESCE({o}, 0)")));
+        format!(
+            "SELECT {} FROM {} WHERE host_id = ? AND timestamp < ? ORDER BY timestamp",
+            cols.join(", "),
+            self.table
+        )
+    }
+
+    fn map_row(&self, row: &rusqlite::Row) -> rusqlite::Result<Row> {
+        let mut idx = 0;
+        let mut next = || {
+            idx += 1;
+            idx - 1
+        };
+        Ok(Row {
+            ints: self.ints.iter().map(|_| row.get(next())).collect::<rusqlite::Result<_>>()?,
+            strs: self.strs.iter().map(|_| row.get(next())).collect::<rusqlite::Result<_>>()?,
+            floats: self.floats.iter().map(|_| row.get(next())).collect::<rusqlite::Result<_>>()?,
+            bools: self.bools.iter().map(|_| row.get(next())).collect::<rusqlite::Result<_>>()?,
+        })
+    }
+
+    fn write(&self, path: &Path, rows: &[&Row]) -> Result<()> {
+        let schema = Arc::new(parse_message_type(&self.schema())?);
+        let props = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        // 先写临时文件再改名, 中途失败不会破坏已有的归档
+        let tmp = path.with_extension("parquet.tmp");
+        let mut writer = SerializedFileWriter::new(File::create(&tmp)?, schema, props)?;
+        let mut row_group = writer.next_row_group()?;
+        let (n_ints, n_strs, n_floats) = (self.ints.len(), self.strs.len(), self.floats.len());
+        let mut idx = 0;
+        while let Some(mut col) = row_group.next_column()? {
+            if idx < n_ints {
+                let values = rows.iter().map(|o| o.ints[idx]).collect::<Vec<_>>();
+                col.typed::<Int64Type>().write_batch(&values, None, None)?;
+            } else if idx < n_ints + n_strs {
+                let i = idx - n_ints;
+                let values = rows.iter().map(|o| ByteArray::from(o.strs[i].as_str())).collect::<Vec<_>>();
+                col.typed::<ByteArrayType>().write_batch(&values, None, None)?;
+            } else if idx < n_ints + n_strs + n_floats {
+                let i = idx - n_ints - n_strs;
+                let values = rows.iter().map(|o| o.floats[i]).collect::<Vec<_>>();
+                col.typed::<DoubleType>().write_batch(&values, None, None)?;
+            } else {
+                let i = idx - n_ints - n_strs - n_floats;
+                let values = rows.iter().map(|o| o.bools[i]).collect::<Vec<_>>();
+                col.typed::<BoolType>().write_batch(&values, None, None)?;
+            }
+            col.close()?;
+            idx += 1;
+        }
+        row_group.close()?;
+        writer.close()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<Row>> {
+        let reader = SerializedFileReader::new(File::open(path)?)?;
+        let mut rows = Vec::new();
+        for row in reader.get_row_iter(None)? {
+            let row = row?;
+            let mut idx = 0;
+            // 旧版本写入的文件缺少末尾追加的列, 读取为 0
+            let mut next = || {
+                idx += 1;
+                (idx <= row.len()).then_some(idx - 1)
+            };
+            rows.push(Row {
+                ints: self
+                    .ints
+                    .iter()
+                    .map(|_| next().map_or(Ok(0), |i| row.get_long(i)))
+                    .collect::<Result<_, _>>()?,
+                strs: self
+                    .strs
+                    .iter()
+                    .map(|_| next().map_or(Ok(String::new()), |i| row.get_string(i).cloned()))
+                    .collect::<Result<_, _>>()?,
+                floats: self
+                    .floats
+                    .iter()
+                    .map(|_| next().map_or(Ok(0.0), |i| row.get_double(i)))
+                    .collect::<Result<_, _>>()?,
+                bools: self
+                    .bools
+                    .iter()
+                    .map(|_| next().map_or(Ok(false), |i| row.get_bool(i)))
+                    .collect::<Result<_, _>>()?,
+            });
+        }
+        Ok(rows)
+    }
+
+    // <dir>/<host>/<YYYY-MM>.<kind>.parquet
+    fn path(&self, dir: &Path, month: &str) -> PathBuf {
+        dir.join(format!("{month}.{}.parquet", self.kind))
+    }
+
+    // 目录下的全部月份文件, 按月份排序
+    fn months(&self, dir: &Path) -> Vec<(String, PathBuf)> {
+        let suffix = format!(".{}.parquet", self.kind);
+        let mut files = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|o| {
+                let name = o.file_name().to_string_lossy().to_string();
+                Some((name.strip_suffix(&suffix)?.to_string(), o.path()))
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    // 与 [start_time, end_time] 有交集的月份文件
+    fn files(&self, dir: &Path, start_time: i64, end_time: i64) -> Vec<PathBuf> {
+        let (first, last) = (month(start_time), month(end_time));
+        self.months(dir)
+            .into_iter()
+            .filter(|(m, _)| &first <= m && m <= &last)
+            .map(|o| o.1)
+            .collect()
+    }
+}
+
+fn month(ts: i64) -> String {
+    DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .format("%Y-%m")
+        .to_string()
+}
+
+// 主机名可能包含路径分隔符等字符, 替换过字符的名称加上原名的 hash, 避免 a/b 与 a_b 共用目录
+fn host_dir(cfg: &Archive, host: &str) -> PathBuf {
+    let name = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.@".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_start_matches('.');
+    match name == host {
+        true => Path::new(&cfg.dir).join(name),
+        false => Path::new(&cfg.dir).join(format!("{name}-{}", &format!("{:x}", md5::compute(host))[..8])),
+    }
+}
+
+// 早于该时间的聚合数据已归档, 按天对齐
+pub fn cutoff(cfg: &Archive) -> i64 {
+    let day = 24 * 3600;
+    (Utc::now().timestamp() - cfg.after_days.max(1) * day) / day * day
+}
+
+// 把主机 cutoff 之前的聚合数据按月份追加到 parquet 文件, 写入成功后再从数据库删除
+fn archive_host(cfg: &Archive, db: &Database, host_id: i64, name: &str, cutoff: i64) -> Result<usize> {
+    let dir = host_dir(cfg, name);
+    let mut archived = 0;
+    for spec in [&STATS, &DISK] {
+        let rows = db.query_before(&spec.select(), host_id, cutoff, |row| spec.map_row(row))?;
+        if rows.is_empty() {
+            continue;
+        }
+        fs::create_dir_all(&dir)?;
+
+        let mut months: BTreeMap<String, Vec<Row>> = BTreeMap::new();
+        for row in rows {
+            months.entry(month(row.ts())).or_default().push(row);
+        }
+        for (month, rows) in months {
+            let path = spec.path(&dir, &month);
+            let mut merged = BTreeMap::new();
+            if path.exists() {
+                for row in spec.read(&path)? {
+                    merged.insert(row.key(), row);
+                }
+            }
+            for row in rows {
+                merged.insert(row.key(), row);
+            }
+            spec.write(&path, &merged.values().collect::<Vec<_>>())?;
+        }
+        archived += db.delete_before(spec.table, host_id, cutoff)?;
+    }
+    Ok(archived)
+}
+
+// 归档主机的全部聚合数据, 用于过期的分组主机
+pub fn host(cfg: &Archive, db: &Database, name: &str) -> Result<usize> {
+    match db.list_hosts()?.into_iter().find(|(_, o)| o == name) {
+        Some((host_id, _)) => archive_host(cfg, db, host_id, name, i64::MAX),
+        None => Ok(0),
+    }
+}
+
+// 把 cutoff 之前的聚合数据按主机和月份追加到 parquet 文件, 写入成功后再从数据库删除
+pub fn run(cfg: &Archive, db: &Database) -> Result<usize> {
+    let cutoff = cutoff(cfg);
+    let mut archived = 0;
+    for (host_id, name) in db.list_hosts()? {
+        archived += archive_host(cfg, db, host_id, &name, cutoff)?;
+    }
+    if archived > 0 {
+        eprintln!("✨ archived {archived} aggregated rows to `{}`", cfg.dir);
+    }
+    Ok(archived)
+}
+
+// 主机改名或合并时移动归档, 同一月份两边都有文件时按行合并, 与数据库一致重复的行以 to 为准, 返回移动的文件数
+pub fn rename(cfg: &Archive, from: &str, to: &str) -> Result<usize> {
+    let (src, dst) = (host_dir(cfg, from), host_dir(cfg, to));
+    if !src.exists() || src == dst {
+        return Ok(0);
+    }
+    let mut moved = 0;
+    fs::create_dir_all(&dst)?;
+    for spec in [&STATS, &DISK] {
+        for (month, path) in spec.months(&src) {
+            let target = spec.path(&dst, &month);
+            if target.exists() {
+                let mut merged = BTreeMap::new();
+                for row in spec.read(&path)?.into_iter().chain(spec.read(&target)?) {
+                    merged.insert(row.key(), row);
+                }
+                spec.write(&target, &merged.values().collect::<Vec<_>>())?;
+                fs::remove_file(&path)?;
+            } else {
+                fs::rename(&path, &target)?;
+            }
+            moved += 1;
+        }
+    }
+    // 只剩下其它文件时保留原目录
+    let _ = fs::remove_dir(&src);
+    Ok(moved)
+}
+
+// 从归档中读取 [start_time, end_time] 内指定聚合级别的数据
+pub fn read_history(
+    cfg: &Archive,
+    host: &str,
+    alias: &str,
+    interval_minutes: i64,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Vec<HostStatRecord>> {
+    let dir = host_dir(cfg, host);
+    let matched = |row: &Row| row.interval() == interval_minutes && start_time <= row.ts() && row.ts() <= end_time;
+
+    let mut disks: HashMap<i64, Vec<DiskRecord>> = HashMap::new();
+    for path in DISK.files(&dir, start_time, end_time) {
+        for row in DISK.read(&path)?.into_iter().filter(matched) {
+            disks.entry(row.ts()).or_default().push(DiskRecord {
+                timestamp: row.ts(),
+                mount_point: row.strs[0].to_string(),
+                total: row.floats[0] as i64,
+                used: row.floats[1] as i64,
+                inode_total: row.floats[2] as i64,
+                inode_used: row.floats[3] as i64,
+            });
+        }
+    }
+
+    let mut records = Vec::new();
+    for path in STATS.files(&dir, start_time, end_time) {
+        for row in STATS.read(&path)?.into_iter().filter(matched) {
+            let f = &row.floats;
+            records.push(HostStatRecord {
+                timestamp: row.ts(),
+                alias: alias.to_string(),
+                cpu: f[0],
+                memory_total: f[1] as i64,
+                memory_used: f[2] as i64,
+                network_in: f[3] as i64,
+                network_out: f[4] as i64,
+                network_in_speed: f[5] as i64,
+                network_out_speed: f[6] as i64,
+                online: row.bools[0],
+                disks: disks.remove(&row.ts()).unwrap_or_default(),
+            });
+        }
+    }
+    records.sort_by_key(|o| o.timestamp);
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let dir = std::env::temp_dir().join(format!("ss-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = DISK.path(&dir, "2024-01");
+        let rows = [
+            Row {
+                ints: vec![1704067200, 5],
+                strs: vec!["/".to_string()],
+                floats: vec![100.0, 50.5, 6400.0, 320.0],
+                bools: vec![],
+            },
+            Row {
+                ints: vec![1704067500, 5],
+                strs: vec!["/data".to_string()],
+                floats: vec![200.0, 10.0, 0.0, 0.0],
+                bools: vec![],
+            },
+        ];
+        DISK.write(&path, &rows.iter().collect::<Vec<_>>()).unwrap();
+        assert_eq!(DISK.read(&path).unwrap(), rows);
+
+        // 没有 inode 列的旧文件
+        let legacy = Spec {
+            floats: &["disk_total", "disk_used"],
+            ..DISK
+        };
+        let old = legacy.path(&dir, "2023-12");
+        let mut row = rows[0].clone();
+        row.floats.truncate(2);
+        legacy.write(&old, &[&row]).unwrap();
+        row.floats.extend([0.0, 0.0]);
+        assert_eq!(DISK.read(&old).unwrap(), [row]);
+        assert_eq!(DISK.files(&dir, 1704067200, 1704067200), vec![path.clone()]);
+        assert!(DISK.files(&dir, 1706745600, 1706745600).is_empty());
+        assert!(STATS.files(&dir, 1704067200, 1704067200).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_host_dir() {
+        let cfg = Archive::default();
+        assert_eq!(host_dir(&cfg, "h1"), Path::new("archive/h1"));
+        assert_eq!(host_dir(&cfg, "../a/b"), Path::new("archive/_a_b-084c5899"));
+        assert_ne!(host_dir(&cfg, "a/b"), host_dir(&cfg, "a_b"));
+    }
+
+    #[test]
+    fn test_rename() {
+        let root = std::env::temp_dir().join(format!("ss-archive-rename-{}", std::process::id()));
+        let cfg = Archive {
+            dir: root.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let row = |ts: i64, cpu: f64| Row {
+            ints: vec![ts, 5],
+            strs: vec![],
+            floats: vec![cpu, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            bools: vec![true],
+        };
+        let write = |host: &str, month: &str, rows: &[Row]| {
+            let dir = host_dir(&cfg, host);
+            fs::create_dir_all(&dir).unwrap();
+            STATS.write(&STATS.path(&dir, month), &rows.iter().collect::<Vec<_>>()).unwrap();
+        };
+        let cpus = |host: &str| {
+            read_history(&cfg, host, host, 5, 0, 1735689600)
+                .unwrap()
+                .iter()
+                .map(|o| (o.timestamp, o.cpu))
+                .collect::<Vec<_>>()
+        };
+
+        // 改名: 整个目录移动
+        write("old", "2024-01", &[row(1704067200, 1.0)]);
+        assert_eq!(rename(&cfg, "old", "mid").unwrap(), 1);
+        assert!(!host_dir(&cfg, "old").exists());
+        assert_eq!(cpus("mid"), [(1704067200, 1.0)]);
+
+        // 合并: 同月份按行合并, 重复的行以目标为准, 其它月份直接移动
+        write("mid", "2024-02", &[row(1706745600, 2.0), row(1706745900, 3.0)]);
+        write("new", "2024-02", &[row(1706745900, 9.0)]);
+        assert_eq!(rename(&cfg, "mid", "new").unwrap(), 2);
+        assert!(!host_dir(&cfg, "mid").exists());
+        assert_eq!(cpus("new"), [(1704067200, 1.0), (1706745600, 2.0), (1706745900, 9.0)]);
+
+        assert_eq!(rename(&cfg, "missing", "new").unwrap(), 0);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
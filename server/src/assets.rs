@@ -13,9 +13,8 @@ pub async fn index_handler() -> impl IntoResponse {
     static_handler("/index.html".parse::<Uri>().unwrap()).await
 }
 
-#[allow(unused)]
 pub async fn admin_index_handler() -> impl IntoResponse {
-    static_handler("/admin.html".parse::<Uri>().unwrap()).await
+    static_handler("/admin/index.html".parse::<Uri>().unwrap()).await
 }
 
 pub async fn static_handler(uri: Uri) -> impl IntoResponse {
@@ -1,18 +1,140 @@
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{ConnectInfo, FromRequestParts},
     http::{header, request::Parts, StatusCode},
     response::{IntoResponse, Response},
     RequestPartsExt,
 };
 use axum_extra::{
-    headers::{authorization::Basic, Authorization},
+    headers::{
+        authorization::{Basic, Bearer},
+        Authorization,
+    },
     TypedHeader,
 };
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::RwLock;
 
+use crate::config::Config;
+use crate::guard;
 use crate::G_CONFIG;
 
+// 主机名 => stats.db 中设置的独立上报密码
+static SECRETS: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+pub fn load_secrets(secrets: HashMap<String, String>) {
+    *SECRETS.write().unwrap() = secrets;
+}
+
+pub fn set_secret(name: &str, secret: &str) {
+    let mut o = SECRETS.write().unwrap();
+    match secret.is_empty() {
+        true => o.remove(name),
+        false => o.insert(name.to_string(), secret.to_string()),
+    };
+}
+
+// 令牌摘要 => 主机名, 与 http 上报共用
+static TOKENS: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+// 令牌随机生成, 摘要只用于比对, 不需要抗碰撞
+pub fn token_digest(token: &str) -> String {
+    format!("{:x}", md5::compute(token))
+}
+
+pub fn new_token() -> String {
+    format!("ssr_{}", uuid::Uuid::new_v4().simple())
+}
+
+pub fn load_tokens(tokens: HashMap<String, String>) {
+    *TOKENS.write().unwrap() = tokens;
+}
+
+// digest 为空时吊销该主机的令牌
+pub fn set_token(name: &str, digest: &str) {
+    let mut o = TOKENS.write().unwrap();
+    o.retain(|_, v| v != name);
+    if !digest.is_empty() {
+        o.insert(digest.to_string(), name.to_string());
+    }
+}
+
+// 令牌只能上报签发时的主机
+pub fn check_token(token: &str) -> Option<Reporter> {
+    TOKENS
+        .read()
+        .unwrap()
+        .get(&token_digest(token))
+        .map(|name| Reporter::Host(name.to_string()))
+}
+
+// 上报凭据对应的身份
+#[derive(Debug, Clone)]
+pub enum Reporter {
+    Host(String),
+    Group { gid: String, pass: String },
+}
+
+impl Reporter {
+    // 上报的主机名需与凭据匹配, 一台主机的凭据泄露不能冒充其它主机
+    pub fn allows(&self, cfg: &Config, name: &str, gid: &str) -> bool {
+        match self {
+            Reporter::Host(user) => user == name,
+            Reporter::Group { gid: g, pass } => {
+                let secrets = SECRETS.read().unwrap();
+                g == gid && cfg.member_auth(g, name, pass, secrets.get(name).map(String::as_str))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reporter::Host(user) => write!(f, "host `{user}`"),
+            Reporter::Group { gid, .. } => write!(f, "group `{gid}`"),
+        }
+    }
+}
+
+// 上报的主机名与凭据不匹配
+#[derive(Debug, Clone)]
+pub struct Forbidden(pub String);
+
+impl fmt::Display for Forbidden {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Forbidden {}
+
+// 令牌认证失败时计入的用户名
+pub const TOKEN_USER: &str = "<token>";
+
+// 校验上报凭据, group 时 user 为分组 gid
+pub fn check(cfg: &Config, user: &str, pass: &str, group: bool) -> Option<Reporter> {
+    let secrets = SECRETS.read().unwrap();
+    let ok = match group {
+        true => {
+            cfg.group_auth(user, pass)
+                || (cfg.hosts_group_map.contains_key(user) && secrets.values().any(|s| pass.eq(s)))
+        }
+        false => cfg.auth(user, pass, secrets.get(user).map(String::as_str)),
+    };
+    ok.then(|| match group {
+        true => Reporter::Group {
+            gid: user.to_string(),
+            pass: pass.to_string(),
+        },
+        false => Reporter::Host(user.to_string()),
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BasicAuth {
     pub username: String,
@@ -21,8 +143,8 @@ pub struct BasicAuth {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AdminAuth(BasicAuth);
-#[derive(Debug, Serialize, Deserialize)]
-pub struct HostAuth(BasicAuth);
+#[derive(Debug)]
+pub struct HostAuth(pub Reporter);
 
 #[async_trait]
 impl<S> FromRequestParts<S> for BasicAuth
@@ -95,31 +217,47 @@ where
     type Rejection = Response;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|o| o.0.ip());
+        let real_ip = parts.headers.get(guard::real_ip_header()).and_then(|o| o.to_str().ok());
+        let src = guard::source(peer, real_ip);
+        if let Some(remaining) = guard::check(&src) {
+            return Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, remaining.to_string())],
+            )
+                .into_response());
+        }
+
+        // 主机令牌 Authorization: Bearer ssr_xxx
+        if let Ok(TypedHeader(Authorization(bearer))) = parts.extract::<TypedHeader<Authorization<Bearer>>>().await {
+            return match check_token(bearer.token()) {
+                Some(o) => Ok(HostAuth(o)),
+                None => {
+                    guard::failed(&src, TOKEN_USER, false);
+                    Err(StatusCode::UNAUTHORIZED.into_response())
+                }
+            };
+        }
+
         // Extract the token from the authorization header
         let TypedHeader(Authorization(basic_auth)) = parts
             .extract::<TypedHeader<Authorization<Basic>>>()
             .await
             .map_err(|_| StatusCode::UNAUTHORIZED.into_response())?;
 
-        let mut auth_ok = false;
         let mut group_auth = false;
         if let Some(ssr_auth) = parts.headers.get("ssr-auth").and_then(|header| header.to_str().ok()) {
             group_auth = "group".eq(ssr_auth);
         }
-        if let Some(cfg) = G_CONFIG.get() {
-            if group_auth {
-                auth_ok = cfg.group_auth(basic_auth.username(), basic_auth.password());
-            } else {
-                auth_ok = cfg.auth(basic_auth.username(), basic_auth.password());
+        let reporter = G_CONFIG
+            .get()
+            .and_then(|cfg| check(cfg, basic_auth.username(), basic_auth.password(), group_auth));
+        match reporter {
+            Some(o) => Ok(HostAuth(o)),
+            None => {
+                guard::failed(&src, basic_auth.username(), group_auth);
+                Err(StatusCode::UNAUTHORIZED.into_response())
             }
         }
-        if !auth_ok {
-            return Err(StatusCode::UNAUTHORIZED.into_response());
-        }
-
-        Ok(HostAuth(BasicAuth {
-            username: basic_auth.username().into(),
-            password: basic_auth.password().into(),
-        }))
     }
 }
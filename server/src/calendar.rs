@@ -0,0 +1,194 @@
+#![deny(warnings)]
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use crate::config::Maintenance;
+use crate::labels::Labels;
+use crate::payload::HostStat;
+
+// 续费日期提前提醒天数
+const RENEWAL_ALARM_DAYS: u32 = 3;
+
+pub enum When {
+    // 全天事件
+    Day(NaiveDate),
+    // [start, end) unix 时间戳
+    Range(i64, i64),
+}
+
+pub struct CalEvent {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    pub when: When,
+    pub alarm_days: u32,
+}
+
+// 维护窗口, hosts 为空表示全部主机
+pub fn maintenance_events(windows: &[Maintenance], servers: &[HostStat]) -> Vec<CalEvent> {
+    windows
+        .iter()
+        .map(|m| {
+            let hosts = match m.hosts.is_empty() {
+                true => "all".to_string(),
+                false => m
+                    .hosts
+                    .iter()
+                    .map(|name| {
+                        servers
+                            .iter()
+                            .find(|o| &o.name == name)
+                            .map(|o| o.alias.as_str())
+                            .unwrap_or(name)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            let mut description = format!("hosts: {hosts}");
+            if !m.description.is_empty() {
+                description = format!("{}\n{description}", m.description);
+            }
+            CalEvent {
+                uid: format!("maintenance-{}-{}@serverstatus", m.start_ts, m.end_ts),
+                summary: format!("🔧 {}", m.title),
+                description,
+                when: When::Range(m.start_ts, m.end_ts),
+                alarm_days: 0,
+            }
+        })
+        .collect()
+}
+
+// labels 中的 ndd=2022/11/25
+fn next_due_date(labels: &Labels) -> Option<NaiveDate> {
+    let ndd = labels.get("ndd")?;
+    NaiveDate::parse_from_str(ndd, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(ndd, "%Y-%m-%d"))
+        .ok()
+}
+
+pub fn renewal_events(servers: &[HostStat]) -> Vec<CalEvent> {
+    servers
+        .iter()
+        .filter_map(|o| {
+            let date = next_due_date(&o.labels)?;
+            Some(CalEvent {
+                uid: format!("renewal-{}-{}@serverstatus", o.name, date.format("%Y%m%d")),
+                summary: format!("💰 {} renewal", o.alias),
+                description: format!("host: {} ({})", o.alias, o.name),
+                when: When::Day(date),
+                alarm_days: RENEWAL_ALARM_DAYS,
+            })
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+// 每行不超过 75 字节, 续行以空格开头
+fn push_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn utc(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0)
+        .single()
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+// RFC 5545, now 为 DTSTAMP
+pub fn render(events: &[CalEvent], now: i64) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//ServerStatus//calendar//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "X-WR-CALNAME:ServerStatus");
+    for e in events {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}", e.uid));
+        push_line(&mut out, &format!("DTSTAMP:{}", utc(now)));
+        match e.when {
+            When::Day(date) => {
+                push_line(&mut out, &format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+                let end = date.succ_opt().unwrap_or(date);
+                push_line(&mut out, &format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")));
+            }
+            When::Range(start, end) => {
+                push_line(&mut out, &format!("DTSTART:{}", utc(start)));
+                push_line(&mut out, &format!("DTEND:{}", utc(end)));
+            }
+        }
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&e.summary)));
+        if !e.description.is_empty() {
+            push_line(&mut out, &format!("DESCRIPTION:{}", escape(&e.description)));
+        }
+        if e.alarm_days > 0 {
+            push_line(&mut out, "BEGIN:VALARM");
+            push_line(&mut out, "ACTION:DISPLAY");
+            push_line(&mut out, &format!("TRIGGER:-P{}D", e.alarm_days));
+            push_line(&mut out, &format!("DESCRIPTION:{}", escape(&e.summary)));
+            push_line(&mut out, "END:VALARM");
+        }
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let servers = vec![HostStat {
+            name: "h1".to_string(),
+            alias: "n1".to_string(),
+            labels: Labels::parse("os=linux;ndd=2024/11/25;spec=2C/4G/60G;"),
+            ..Default::default()
+        }];
+        assert_eq!(next_due_date(&Labels::parse("ndd=2024-01-02")), NaiveDate::from_ymd_opt(2024, 1, 2));
+        assert_eq!(next_due_date(&Labels::parse("os=linux;")), None);
+
+        let window = Maintenance {
+            title: "network, cutover".to_string(),
+            description: "a".repeat(100),
+            start: String::new(),
+            end: String::new(),
+            hosts: vec!["h1".to_string(), "h9".to_string()],
+            start_ts: 1717178400,
+            end_ts: 1717185600,
+        };
+        let mut events = maintenance_events(&[window], &servers);
+        events.extend(renewal_events(&servers));
+        let ics = render(&events, 1717000000);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.lines().all(|l| l.len() <= 76));
+        assert!(ics.contains("DTSTART:20240531T180000Z\r\n"));
+        assert!(ics.contains("DTEND:20240531T200000Z\r\n"));
+        assert!(ics.contains("SUMMARY:🔧 network\\, cutover\r\n"));
+        assert!(ics.contains("\\nhosts: n1\\, h9\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20241125\r\nDTEND;VALUE=DATE:20241126\r\n"));
+        assert!(ics.contains("TRIGGER:-P3D"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    }
+}
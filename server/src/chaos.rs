@@ -0,0 +1,53 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// 管理接口强制下线的主机及截止时间, 只影响内存中的状态, 用于演练通知路由及升级策略
+static FORCED: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(Default::default);
+
+pub fn force_offline(name: &str, until: u64) {
+    FORCED.lock().unwrap().insert(name.to_string(), until);
+}
+
+pub fn restore(name: &str) -> bool {
+    FORCED.lock().unwrap().remove(name).is_some()
+}
+
+pub fn is_forced(name: &str, now: u64) -> bool {
+    FORCED.lock().unwrap().get(name).is_some_and(|&until| until > now)
+}
+
+// 到期的主机, 由 timer 线程恢复并发送上线通知
+pub fn expired(now: u64) -> Vec<String> {
+    let mut forced = FORCED.lock().unwrap();
+    let names = forced
+        .iter()
+        .filter(|(_, &until)| until <= now)
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    for name in names.iter() {
+        forced.remove(name);
+    }
+    names
+}
+
+pub fn next_expiry() -> Option<u64> {
+    FORCED.lock().unwrap().values().min().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chaos() {
+        force_offline("chaos-1", 100);
+        force_offline("chaos-2", 200);
+        assert!(is_forced("chaos-1", 99) && !is_forced("chaos-1", 100));
+        assert_eq!(expired(150), vec!["chaos-1"]);
+        assert!(!is_forced("chaos-1", 0));
+        assert!(restore("chaos-2") && !restore("chaos-2"));
+        assert_eq!(next_expiry(), None);
+    }
+}
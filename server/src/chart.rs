@@ -0,0 +1,205 @@
+#![deny(warnings)]
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use plotters::prelude::*;
+use stat_common::utils::bytes2human;
+
+use crate::db::HostStatRecord;
+use crate::snapshot::{Canvas, Rgb, CHAR_W, FG};
+
+const LEFT: usize = 120;
+const TOP: usize = 40;
+const BOTTOM: usize = 36;
+const RIGHT: usize = 20;
+const X_TICKS: i64 = 6;
+const Y_TICKS: usize = 4;
+const GRID: RGBColor = RGBColor(225, 228, 232);
+const COLORS: [Rgb; 2] = [[0, 123, 255], [40, 167, 69]];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Cpu,
+    Memory,
+    Disk,
+    Network,
+}
+
+impl Metric {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "cpu" => Some(Metric::Cpu),
+            "memory" => Some(Metric::Memory),
+            "disk" => Some(Metric::Disk),
+            "network" => Some(Metric::Network),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Metric::Cpu => "cpu",
+            Metric::Memory => "memory",
+            Metric::Disk => "disk",
+            Metric::Network => "network",
+        }
+    }
+
+    fn is_percent(&self) -> bool {
+        *self != Metric::Network
+    }
+
+    fn y_label(&self, v: f64) -> String {
+        match self.is_percent() {
+            true => format!("{v:.0}%"),
+            false => format!("{}/s", bytes2human(v as u64, 0, false)),
+        }
+    }
+}
+
+fn percent(used: i64, total: i64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => used as f64 * 100.0 / total as f64,
+    }
+}
+
+// 按离线点分段的曲线
+type Segments = Vec<Vec<(i64, f64)>>;
+type Value = fn(&HostStatRecord) -> f64;
+
+fn series(metric: Metric, records: &[HostStatRecord]) -> Vec<(&'static str, Segments)> {
+    let values: Vec<(&'static str, Value)> = match metric {
+        Metric::Cpu => vec![("cpu", |o| o.cpu)],
+        Metric::Memory => vec![("memory", |o| percent(o.memory_used, o.memory_total))],
+        Metric::Disk => vec![("disk", |o| {
+            percent(o.disks.iter().map(|d| d.used).sum(), o.disks.iter().map(|d| d.total).sum())
+        })],
+        Metric::Network => vec![
+            ("in", |o| o.network_in_speed as f64),
+            ("out", |o| o.network_out_speed as f64),
+        ],
+    };
+    values
+        .into_iter()
+        .map(|(name, f)| {
+            let mut segments = vec![vec![]];
+            for o in records {
+                match o.online {
+                    true => segments.last_mut().unwrap().push((o.timestamp, f(o))),
+                    false if !segments.last().unwrap().is_empty() => segments.push(vec![]),
+                    false => {}
+                }
+            }
+            segments.retain(|o| !o.is_empty());
+            (name, segments)
+        })
+        .collect()
+}
+
+// 历史数据折线图, 坐标轴文字使用 snapshot 的点阵字体
+pub fn render(
+    title: &str,
+    metric: Metric,
+    records: &[HostStatRecord],
+    (start, end): (i64, i64),
+    (width, height): (usize, usize),
+) -> Result<Vec<u8>> {
+    let lines = series(metric, records);
+    let y_max = match metric.is_percent() {
+        true => 100.0,
+        false => lines
+            .iter()
+            .flat_map(|(_, segments)| segments.iter().flatten())
+            .map(|&(_, v)| v)
+            .fold(1024.0, f64::max)
+            * 1.1,
+    };
+
+    let mut canvas = Canvas::new(width, height);
+    let (x_ticks, y_ticks) = {
+        let root = BitMapBackend::with_buffer(&mut canvas.buf, (width as u32, height as u32)).into_drawing_area();
+        let mut chart = ChartBuilder::on(&root)
+            .margin_top(TOP as u32)
+            .margin_right(RIGHT as u32)
+            .set_label_area_size(LabelAreaPosition::Left, LEFT as u32)
+            .set_label_area_size(LabelAreaPosition::Bottom, BOTTOM as u32)
+            .build_cartesian_2d(start..end, 0f64..y_max)?;
+
+        let x_ticks = (0..=X_TICKS).map(|i| start + (end - start) * i / X_TICKS).collect::<Vec<_>>();
+        let y_ticks = (0..=Y_TICKS).map(|i| y_max * i as f64 / Y_TICKS as f64).collect::<Vec<_>>();
+        chart.draw_series(x_ticks.iter().map(|&x| PathElement::new(vec![(x, 0.0), (x, y_max)], GRID)))?;
+        chart.draw_series(y_ticks.iter().map(|&y| PathElement::new(vec![(start, y), (end, y)], GRID)))?;
+        for ((_, segments), color) in lines.iter().zip(COLORS) {
+            let style = RGBColor(color[0], color[1], color[2]).stroke_width(2);
+            for segment in segments {
+                chart.draw_series(LineSeries::new(segment.iter().copied(), style))?;
+            }
+        }
+        root.present()?;
+        (
+            x_ticks.iter().map(|&x| (x, chart.backend_coord(&(x, 0.0)))).collect::<Vec<_>>(),
+            y_ticks.iter().map(|&y| (y, chart.backend_coord(&(start, y)))).collect::<Vec<_>>(),
+        )
+    };
+
+    let half = CHAR_W / 2;
+    canvas.text(LEFT, (TOP - CHAR_W) / 2, title, FG);
+    // 图例
+    let mut x = width - RIGHT;
+    for ((name, _), color) in lines.iter().zip(COLORS).rev() {
+        x -= (name.len() + 1) * CHAR_W;
+        canvas.text(x, (TOP - CHAR_W) / 2, name, color);
+    }
+    let fmt = if end - start > 2 * 86400 { "%m-%d" } else { "%H:%M" };
+    for (ts, (px, py)) in x_ticks {
+        let label = Local.timestamp_opt(ts, 0).single().map(|t| t.format(fmt).to_string()).unwrap_or_default();
+        let x = (px as usize).saturating_sub(label.len() * half).min(width - label.len() * CHAR_W);
+        canvas.text(x, py as usize + CHAR_W, &label, FG);
+    }
+    for (v, (px, py)) in y_ticks {
+        let label = metric.y_label(v);
+        let x = (px as usize).saturating_sub(label.len() * CHAR_W + half);
+        canvas.text(x, (py as usize).saturating_sub(half), &label, FG);
+    }
+    canvas.png()
+}
+
+pub fn title(name: &str, metric: Metric, range: &str) -> String {
+    format!("{name} {} {range}", metric.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let records = (0..100)
+            .map(|i| HostStatRecord {
+                timestamp: 1700000000 + i * 60,
+                alias: "n1".to_string(),
+                cpu: (i % 50) as f64,
+                memory_total: 100,
+                memory_used: 50,
+                network_in: 0,
+                network_out: 0,
+                network_in_speed: i * 1000,
+                network_out_speed: i * 500,
+                online: !(40..50).contains(&i),
+                disks: vec![],
+            })
+            .collect::<Vec<_>>();
+        let lines = series(Metric::Network, &records);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].1.len(), 2);
+        assert_eq!(lines[1].1[1][0], (1700000000 + 50 * 60, 25000.0));
+        assert_eq!(Metric::parse("load"), None);
+
+        let range = (1700000000, 1700000000 + 100 * 60);
+        for metric in [Metric::Cpu, Metric::Network] {
+            let data = render(&title("n1", metric, "2h"), metric, &records, range, (800, 300)).unwrap();
+            let reader = png::Decoder::new(&data[..]).read_info().unwrap();
+            assert_eq!((reader.info().width, reader.info().height), (800, 300));
+        }
+    }
+}
@@ -0,0 +1,349 @@
+#![deny(warnings)]
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use clap::{Subcommand, ValueEnum};
+use prettytable::{row, Table};
+use stat_common::utils::bytes2human;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use crate::columnar;
+use crate::config;
+use crate::db::{Database, HistoryRecords, HostStatRecord, Resolution};
+use crate::Command;
+
+#[derive(Subcommand, Debug)]
+pub enum StatsCommand {
+    /// hosts ranked by average usage
+    Top {
+        #[arg(long, default_value = "1d", value_parser = duration, help = "time range, e.g. 90m 12h 7d")]
+        since: u64,
+        #[arg(long, value_enum, default_value_t = Metric::Cpu)]
+        by: Metric,
+        #[arg(long, default_value = "10")]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// rebuild the database file and refresh query planner statistics
+    Vacuum,
+    /// dump history at the same resolution as history.json
+    Export {
+        #[arg(long, default_value = "1d", value_parser = duration, help = "time range, e.g. 90m 12h 7d")]
+        since: u64,
+        #[arg(long, help = "host name, default: all hosts")]
+        host: Option<String>,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        #[arg(long, default_value = "stats", help = "stats | disks")]
+        table: String,
+        #[arg(short, long, help = "output file, default: stdout")]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HostCommand {
+    /// hosts stored in the database
+    List,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    Cpu,
+    Memory,
+    Disk,
+    NetIn,
+    NetOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Arrow,
+}
+
+fn duration(s: &str) -> Result<u64, String> {
+    config::parse_duration(s).ok_or_else(|| format!("invalid duration `{s}`"))
+}
+
+fn fmt_ts(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0)
+        .single()
+        .map(|o| o.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn percent(used: i64, total: i64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => used as f64 * 100.0 / total as f64,
+    }
+}
+
+// 时间范围内的平均值, 磁盘取最后一个点
+#[derive(Debug, Default, PartialEq)]
+struct Usage {
+    points: usize,
+    cpu: f64,
+    memory: f64,
+    disk: f64,
+    net_in: f64,
+    net_out: f64,
+}
+
+impl Usage {
+    fn from_records(records: &[HostStatRecord]) -> Self {
+        let n = records.len().max(1) as f64;
+        let avg = |f: fn(&HostStatRecord) -> f64| records.iter().map(f).sum::<f64>() / n;
+        let disk = records.last().map_or(0.0, |o| {
+            let (used, total) = o.disks.iter().fold((0, 0), |(u, t), d| (u + d.used, t + d.total));
+            percent(used, total)
+        });
+        Self {
+            points: records.len(),
+            cpu: avg(|o| o.cpu),
+            memory: avg(|o| percent(o.memory_used, o.memory_total)),
+            disk,
+            net_in: avg(|o| o.network_in_speed as f64),
+            net_out: avg(|o| o.network_out_speed as f64),
+        }
+    }
+
+    fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Cpu => self.cpu,
+            Metric::Memory => self.memory,
+            Metric::Disk => self.disk,
+            Metric::NetIn => self.net_in,
+            Metric::NetOut => self.net_out,
+        }
+    }
+}
+
+fn history(db: &Database, since: u64) -> Result<HistoryRecords> {
+    let now = Utc::now().timestamp();
+    let (records, _) = db.get_stats_by_timerange(now - since as i64, now, |_| Resolution::default(), None, None)?;
+    Ok(records)
+}
+
+fn top(db: &Database, since: u64, by: Metric, limit: usize) -> Result<()> {
+    let mut hosts = history(db, since)?
+        .into_iter()
+        .filter(|(_, records)| !records.is_empty())
+        .map(|(name, records)| (name, Usage::from_records(&records)))
+        .collect::<Vec<_>>();
+    hosts.sort_by(|a, b| b.1.get(by).total_cmp(&a.1.get(by)).then_with(|| a.0.cmp(&b.0)));
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Name", "Points", "CPU", "RAM", "Disk", "Net ↓|↑"]);
+    for (name, o) in hosts.iter().take(limit) {
+        table.add_row(row![
+            name,
+            o.points,
+            format!("{:.1}%", o.cpu),
+            format!("{:.1}%", o.memory),
+            format!("{:.1}%", o.disk),
+            format!(
+                "{}|{}",
+                bytes2human(o.net_in as u64, 1, false),
+                bytes2human(o.net_out as u64, 1, false)
+            ),
+        ]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+fn host_list(db: &Database) -> Result<()> {
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Name", "Alias", "Hidden", "Token", "Labels", "Last Seen (UTC)"]);
+    for o in db.host_infos()? {
+        table.add_row(row![
+            o.name,
+            o.alias,
+            o.hidden,
+            o.token,
+            o.labels,
+            o.last_seen.map(fmt_ts).unwrap_or_else(|| "-".to_string()),
+        ]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+fn write_csv(w: &mut impl Write, table: columnar::Table, name: &str, records: &[HostStatRecord]) -> io::Result<()> {
+    for o in records {
+        match table {
+            columnar::Table::Stats => writeln!(
+                w,
+                "{},{},{},{},{},{},{},{},{},{}",
+                name,
+                o.timestamp,
+                o.cpu,
+                o.memory_total,
+                o.memory_used,
+                o.network_in,
+                o.network_out,
+                o.network_in_speed,
+                o.network_out_speed,
+                o.online
+            )?,
+            columnar::Table::Disks => {
+                for d in &o.disks {
+                    // 挂载点可能包含逗号
+                    let mount_point = format!("\"{}\"", d.mount_point.replace('"', "\"\""));
+                    writeln!(w, "{},{},{},{},{}", name, d.timestamp, mount_point, d.total, d.used)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn export(
+    db: &Database,
+    since: u64,
+    host: Option<&str>,
+    format: ExportFormat,
+    table: &str,
+    output: Option<&str>,
+) -> Result<()> {
+    let table = columnar::Table::parse(Some(table))?;
+    let mut hosts = history(db, since)?
+        .into_iter()
+        .filter(|(name, _)| host.map_or(true, |o| o == name))
+        .collect::<Vec<_>>();
+    hosts.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut w: BufWriter<Box<dyn Write>> = BufWriter::new(match output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    });
+    match format {
+        ExportFormat::Csv => {
+            let schema = table.schema();
+            let header = schema.fields().iter().map(|o| o.name().as_str()).collect::<Vec<_>>();
+            writeln!(w, "{}", header.join(","))?;
+            for (name, records) in &hosts {
+                write_csv(&mut w, table, name, records)?;
+            }
+        }
+        ExportFormat::Arrow => {
+            let mut writer = columnar::Writer::new(table)?;
+            w.write_all(&writer.take())?;
+            for (name, records) in &hosts {
+                w.write_all(&writer.write(name, records)?)?;
+            }
+            w.write_all(&writer.finish()?)?;
+        }
+    }
+    w.flush()?;
+    let points = hosts.iter().map(|(_, o)| o.len()).sum::<usize>();
+    eprintln!("✨ exported {} hosts, {} points", hosts.len(), points);
+    Ok(())
+}
+
+// 直接操作 stats.db 的子命令, 不需要加载配置, 服务运行时也可以使用
+// db_path 与服务运行时一致, 取自配置文件中的 db_path
+pub fn run(command: &Command, db_path: &str) -> Result<()> {
+    let db = Database::new(db_path)?;
+    match command {
+        Command::Recompute {
+            host,
+            start_time,
+            end_time,
+        } => {
+            let end_time = end_time.unwrap_or_else(|| Utc::now().timestamp());
+            let rebuilt = db.recompute_aggregates(host.as_deref(), *start_time, end_time)?;
+            eprintln!("✨ recompute aggregates done, {rebuilt} rows rebuilt");
+        }
+        Command::Stats {
+            command: StatsCommand::Top { since, by, limit },
+        } => top(&db, *since, *by, *limit)?,
+        Command::Db {
+            command: DbCommand::Vacuum,
+        } => {
+            let size = || fs::metadata(db_path).map(|o| o.len()).unwrap_or_default();
+            let before = size();
+            db.vacuum()?;
+            eprintln!(
+                "✨ vacuum done, {} => {}",
+                bytes2human(before, 1, false),
+                bytes2human(size(), 1, false)
+            );
+        }
+        Command::Db {
+            command:
+                DbCommand::Export {
+                    since,
+                    host,
+                    format,
+                    table,
+                    output,
+                },
+        } => export(&db, *since, host.as_deref(), *format, table, output.as_deref())?,
+        Command::Host {
+            command: HostCommand::List,
+        } => host_list(&db)?,
+        // 需要加载配置启动服务, 在 main 中处理
+        #[cfg(feature = "harness")]
+        Command::Harness { .. } => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DiskRecord;
+
+    #[test]
+    fn test_usage() {
+        let record = |cpu: f64, used: i64| HostStatRecord {
+            timestamp: 1700000000,
+            alias: "n1".to_string(),
+            cpu,
+            memory_total: 100,
+            memory_used: used,
+            network_in: 0,
+            network_out: 0,
+            network_in_speed: 10,
+            network_out_speed: 20,
+            online: true,
+            disks: vec![
+                DiskRecord {
+                    timestamp: 1700000000,
+                    mount_point: "/".to_string(),
+                    total: 100,
+                    used,
+                    ..Default::default()
+                },
+                DiskRecord {
+                    timestamp: 1700000000,
+                    mount_point: "/data".to_string(),
+                    total: 300,
+                    used: 0,
+                    ..Default::default()
+                },
+            ],
+        };
+        let o = Usage::from_records(&[record(10.0, 20), record(30.0, 60)]);
+        assert_eq!((o.points, o.cpu, o.memory, o.disk), (2, 20.0, 40.0, 15.0));
+        assert_eq!((o.get(Metric::NetIn), o.get(Metric::NetOut)), (10.0, 20.0));
+        assert_eq!(Usage::from_records(&[]), Usage::default());
+
+        let mut buf = Vec::new();
+        write_csv(&mut buf, columnar::Table::Disks, "h1", &[record(0.0, 5)]).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "h1,1700000000,\"/\",100,5\nh1,1700000000,\"/data\",300,0\n"
+        );
+        assert_eq!(duration("7d"), Ok(7 * 86400));
+        assert!(duration("7w").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+#![deny(warnings)]
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Cluster;
+use crate::metrics;
+use crate::G_STATS_MGR;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+static PUBLISHER: OnceCell<SyncSender<Value>> = OnceCell::new();
+
+// 节点间广播的上报数据
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    node: String,
+    stat: Value,
+}
+
+// 集群模式: 各实例通过 redis pub/sub 互相转发收到的上报, 每个实例都持有完整的主机状态,
+// 上报可以负载均衡到任意实例, 单个实例故障不影响其它实例的展示
+pub fn init(cfg: &'static Cluster) -> Result<()> {
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let client = redis::Client::open(cfg.redis_url.as_str())?;
+    eprintln!("✨ cluster mode enabled, node `{}` on channel `{}`", cfg.node_id, cfg.channel);
+
+    // publish thread
+    let (tx, rx) = sync_channel::<Value>(cfg.queue_size);
+    thread::spawn({
+        let client = client.clone();
+        move || {
+            let mut conn = None;
+            for stat in rx {
+                let payload = match serde_json::to_string(&Envelope {
+                    node: cfg.node_id.to_string(),
+                    stat,
+                }) {
+                    Ok(o) => o,
+                    Err(err) => {
+                        error!("cluster encode stat error => {:?}", err);
+                        continue;
+                    }
+                };
+                if conn.is_none() {
+                    conn = client.get_connection().map_err(|err| error!("cluster connect error => {:?}", err)).ok();
+                }
+                if let Some(c) = conn.as_mut() {
+                    if let Err(err) = redis::cmd("PUBLISH").arg(&cfg.channel).arg(payload).query::<i64>(c) {
+                        error!("cluster publish error => {:?}", err);
+                        metrics::inc("cluster_publish_failed");
+                        conn = None;
+                    } else {
+                        metrics::inc("cluster_published");
+                    }
+                }
+            }
+        }
+    });
+    if PUBLISHER.set(tx).is_err() {
+        error!("can't set cluster PUBLISHER");
+    }
+
+    // subscribe thread
+    thread::spawn(move || loop {
+        if let Err(err) = subscribe(&client, cfg) {
+            error!("cluster subscribe error => {:?}", err);
+        }
+        thread::sleep(RECONNECT_DELAY);
+    });
+
+    Ok(())
+}
+
+fn subscribe(client: &redis::Client, cfg: &Cluster) -> Result<()> {
+    let mut conn = client.get_connection()?;
+    let mut pubsub = conn.as_pubsub();
+    pubsub.subscribe(&cfg.channel)?;
+    loop {
+        let msg = pubsub.get_message()?;
+        let payload: String = msg.get_payload()?;
+        let envelope: Envelope = match serde_json::from_str(&payload) {
+            Ok(o) => o,
+            Err(err) => {
+                error!("cluster invalid message => {:?}", err);
+                continue;
+            }
+        };
+        // 自己发出的忽略
+        if envelope.node == cfg.node_id {
+            continue;
+        }
+        metrics::inc("cluster_received");
+        if let Some(mgr) = G_STATS_MGR.get() {
+            if let Err(err) = mgr.ingest(envelope.stat) {
+                warn!("cluster ingest stat from `{}` error => {:?}", envelope.node, err);
+            }
+        }
+    }
+}
+
+// 转发本实例收到的上报, 不阻塞上报路径
+pub fn publish(stat: &Value) {
+    if let Some(tx) = PUBLISHER.get() {
+        if let Err(TrySendError::Full(_)) = tx.try_send(stat.clone()) {
+            metrics::inc("cluster_publish_dropped");
+        }
+    }
+}
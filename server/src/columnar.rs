@@ -0,0 +1,188 @@
+#![deny(warnings)]
+use anyhow::{bail, Result};
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray, TimestampSecondArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use std::sync::Arc;
+
+use crate::db::HostStatRecord;
+
+pub const CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+
+// 历史数据的 arrow 表, 每行一个主机的一个时间点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    // cpu / 内存 / 网络
+    Stats,
+    // 每个挂载点一行
+    Disks,
+}
+
+impl Table {
+    pub fn parse(s: Option<&str>) -> Result<Self> {
+        Ok(match s.unwrap_or("stats") {
+            "stats" => Table::Stats,
+            "disks" => Table::Disks,
+            o => bail!("unknown table `{o}`, expected stats or disks"),
+        })
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        let ts = DataType::Timestamp(TimeUnit::Second, Some("UTC".into()));
+        let mut fields = vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("timestamp", ts, false),
+        ];
+        match self {
+            Table::Stats => {
+                fields.push(Field::new("cpu", DataType::Float64, false));
+                for name in [
+                    "memory_total",
+                    "memory_used",
+                    "network_in",
+                    "network_out",
+                    "network_in_speed",
+                    "network_out_speed",
+                ] {
+                    fields.push(Field::new(name, DataType::Int64, false));
+                }
+                fields.push(Field::new("online", DataType::Boolean, false));
+            }
+            Table::Disks => {
+                fields.push(Field::new("mount_point", DataType::Utf8, false));
+                fields.push(Field::new("total", DataType::Int64, false));
+                fields.push(Field::new("used", DataType::Int64, false));
+            }
+        }
+        Arc::new(Schema::new(fields))
+    }
+
+    pub fn batch(&self, name: &str, records: &[HostStatRecord]) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = match self {
+            Table::Stats => {
+                let ints =
+                    |f: fn(&HostStatRecord) -> i64| Arc::new(Int64Array::from_iter_values(records.iter().map(f)));
+                vec![
+                    Arc::new(StringArray::from_iter_values(records.iter().map(|_| name))),
+                    Arc::new(
+                        TimestampSecondArray::from_iter_values(records.iter().map(|o| o.timestamp))
+                            .with_timezone("UTC"),
+                    ),
+                    Arc::new(Float64Array::from_iter_values(records.iter().map(|o| o.cpu))),
+                    ints(|o| o.memory_total),
+                    ints(|o| o.memory_used),
+                    ints(|o| o.network_in),
+                    ints(|o| o.network_out),
+                    ints(|o| o.network_in_speed),
+                    ints(|o| o.network_out_speed),
+                    Arc::new(records.iter().map(|o| Some(o.online)).collect::<BooleanArray>()),
+                ]
+            }
+            Table::Disks => {
+                // 字符串列需要确定长度的迭代器
+                let disks = records.iter().flat_map(|o| o.disks.iter()).collect::<Vec<_>>();
+                vec![
+                    Arc::new(StringArray::from_iter_values(disks.iter().map(|_| name))),
+                    Arc::new(
+                        TimestampSecondArray::from_iter_values(disks.iter().map(|o| o.timestamp)).with_timezone("UTC"),
+                    ),
+                    Arc::new(StringArray::from_iter_values(
+                        disks.iter().map(|o| o.mount_point.as_str()),
+                    )),
+                    Arc::new(Int64Array::from_iter_values(disks.iter().map(|o| o.total))),
+                    Arc::new(Int64Array::from_iter_values(disks.iter().map(|o| o.used))),
+                ]
+            }
+        };
+        Ok(RecordBatch::try_new(self.schema(), columns)?)
+    }
+}
+
+// arrow ipc stream, 每台主机一个 record batch, 写入后取出已编码的字节分块发送
+pub struct Writer {
+    table: Table,
+    inner: StreamWriter<Vec<u8>>,
+}
+
+impl Writer {
+    pub fn new(table: Table) -> Result<Self> {
+        Ok(Self {
+            table,
+            inner: StreamWriter::try_new(Vec::new(), &table.schema())?,
+        })
+    }
+
+    // 已编码但未取出的字节
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(self.inner.get_mut())
+    }
+
+    pub fn write(&mut self, name: &str, records: &[HostStatRecord]) -> Result<Vec<u8>> {
+        let batch = self.table.batch(name, records)?;
+        if batch.num_rows() > 0 {
+            self.inner.write(&batch)?;
+        }
+        Ok(self.take())
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        self.inner.finish()?;
+        Ok(self.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DiskRecord;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_ipc::reader::StreamReader;
+
+    #[test]
+    fn test_writer() {
+        let record = |ts: i64| HostStatRecord {
+            timestamp: ts,
+            alias: "n1".to_string(),
+            cpu: 1.5,
+            memory_total: 100,
+            memory_used: 40,
+            network_in: 1,
+            network_out: 2,
+            network_in_speed: 3,
+            network_out_speed: 4,
+            online: true,
+            disks: vec![DiskRecord {
+                timestamp: ts,
+                mount_point: "/".to_string(),
+                total: 10,
+                used: 5,
+                ..Default::default()
+            }],
+        };
+        let records = vec![record(1700000000), record(1700000060)];
+        for (table, rows, cols) in [(Table::Stats, 2, 10), (Table::Disks, 2, 5)] {
+            let mut w = Writer::new(table).unwrap();
+            let mut buf = w.take();
+            buf.extend(w.write("h1", &records).unwrap());
+            buf.extend(w.write("h2", &[]).unwrap());
+            buf.extend(w.finish().unwrap());
+
+            let batches = StreamReader::try_new(buf.as_slice(), None)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            // 没有数据的主机不输出 batch
+            assert_eq!(batches.len(), 1);
+            assert_eq!((batches[0].num_rows(), batches[0].num_columns()), (rows, cols));
+            assert_eq!(batches[0].schema(), table.schema());
+            let col = batches[0].column_by_name(if table == Table::Stats { "memory_used" } else { "used" });
+            assert_eq!(
+                col.unwrap().as_primitive::<Int64Type>().value(1),
+                if table == Table::Stats { 40 } else { 5 }
+            );
+        }
+        assert!(Table::parse(Some("probes")).is_err());
+        assert_eq!(Table::parse(None).unwrap(), Table::Stats);
+    }
+}
@@ -0,0 +1,134 @@
+#![deny(warnings)]
+use anyhow::Result;
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+// compact.json 格式版本, 字段增删时递增
+const VERSION: u32 = 2;
+
+// 每台主机输出为按 FIELDS 顺序排列的数组, 客户端按 fields 表头取值
+const FIELDS: &[&str] = &[
+    "name",
+    "alias",
+    "type",
+    "location",
+    "region",
+    "zone",
+    "provider",
+    "online4",
+    "online6",
+    "uptime",
+    "load_1",
+    "load_5",
+    "load_15",
+    "ping_10010",
+    "ping_189",
+    "ping_10086",
+    "time_10010",
+    "time_189",
+    "time_10086",
+    "tcp_count",
+    "udp_count",
+    "process_count",
+    "thread_count",
+    "network_rx",
+    "network_tx",
+    "network_in",
+    "network_out",
+    "last_network_in",
+    "last_network_out",
+    "cpu",
+    "memory_total",
+    "memory_used",
+    "swap_total",
+    "swap_used",
+    "hdd_total",
+    "hdd_used",
+    "labels",
+    "custom",
+    "gid",
+    "latest_ts",
+    "si",
+    "disks",
+];
+
+const DISK_FIELDS: &[&str] = &["mount_point", "total", "used"];
+
+// 浮点数保留 2 位小数
+fn round(v: &Value) -> Value {
+    match v.as_f64() {
+        Some(f) if v.is_f64() => json!((f * 100.0).round() / 100.0),
+        _ => v.clone(),
+    }
+}
+
+// 由 stats.json 转换, 镜像模式下也可用
+pub fn render(stats_json: &[u8]) -> Result<Bytes> {
+    let stats: Value = serde_json::from_slice(stats_json)?;
+    let servers = stats["servers"]
+        .as_array()
+        .map(|servers| {
+            servers
+                .iter()
+                .map(|srv| {
+                    FIELDS
+                        .iter()
+                        .map(|&field| match field {
+                            "disks" => srv[field]
+                                .as_array()
+                                .map(|disks| {
+                                    disks
+                                        .iter()
+                                        .map(|disk| DISK_FIELDS.iter().map(|&k| disk[k].clone()).collect::<Vec<_>>())
+                                        .collect::<Vec<_>>()
+                                })
+                                .map(|o| json!(o))
+                                .unwrap_or_else(|| json!([])),
+                            _ => round(&srv[field]),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let resp = json!({
+        "v": VERSION,
+        "updated": stats["updated"],
+        "fields": FIELDS,
+        "disk_fields": DISK_FIELDS,
+        "servers": servers,
+    });
+    Ok(Bytes::from(serde_json::to_vec(&resp)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let srv = json!({
+            "name": "h1", "alias": "n1", "type": "kvm", "location": "🏠", "notify": true, "vnstat": false,
+            "online4": true, "online6": false, "uptime": "3 天", "load_1": 0.1, "load_5": 0.2, "load_15": 0.3,
+            "tcp_count": 10, "udp_count": 2, "process_count": 100, "thread_count": 200,
+            "network_rx": 100, "network_tx": 200, "network_in": 1000, "network_out": 2000,
+            "cpu": 12.3456, "memory_total": 1000, "memory_used": 500, "labels": "os=linux;",
+            "latest_ts": 1700000000u64, "weight": 10000,
+            "disks": [{"name": "sda", "mount_point": "/", "file_system": "ext4", "total": 100, "used": 50, "free": 50}],
+        });
+        let stats = json!({ "updated": 1700000001u64, "servers": [srv.clone(), srv] });
+        let raw = serde_json::to_vec(&stats).unwrap();
+        let compact: Value = serde_json::from_slice(&render(&raw).unwrap()).unwrap();
+
+        assert_eq!(compact["updated"], json!(1700000001u64));
+        let fields = compact["fields"].as_array().unwrap();
+        let row = compact["servers"][0].as_array().unwrap();
+        assert_eq!(fields.len(), row.len());
+        let get = |name: &str| &row[fields.iter().position(|o| o == name).unwrap()];
+        assert_eq!(get("name"), "h1");
+        assert_eq!(get("cpu"), &json!(12.35));
+        assert_eq!(get("ping_189"), &Value::Null);
+        assert_eq!(get("disks"), &json!([["/", 100, 50]]));
+    }
+}
@@ -0,0 +1,157 @@
+#![deny(warnings)]
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::db::HostStatRecord;
+
+// 一次最多对比的主机数
+pub const MAX_HOSTS: usize = 20;
+// 对齐后的最大点数
+const MAX_POINTS: i64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Cpu,
+    Memory,
+    Disk,
+    NetworkIn,
+    NetworkOut,
+}
+
+impl Metric {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "cpu" => Some(Metric::Cpu),
+            "memory" => Some(Metric::Memory),
+            "disk" => Some(Metric::Disk),
+            "network_in" => Some(Metric::NetworkIn),
+            "network_out" => Some(Metric::NetworkOut),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Metric::Cpu => "cpu",
+            Metric::Memory => "memory",
+            Metric::Disk => "disk",
+            Metric::NetworkIn => "network_in",
+            Metric::NetworkOut => "network_out",
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            Metric::NetworkIn | Metric::NetworkOut => "B/s",
+            _ => "%",
+        }
+    }
+
+    fn value(&self, o: &HostStatRecord) -> f64 {
+        let percent = |used: i64, total: i64| match total {
+            0 => 0.0,
+            _ => used as f64 * 100.0 / total as f64,
+        };
+        match self {
+            Metric::Cpu => o.cpu,
+            Metric::Memory => percent(o.memory_used, o.memory_total),
+            Metric::Disk => percent(o.disks.iter().map(|d| d.used).sum(), o.disks.iter().map(|d| d.total).sum()),
+            Metric::NetworkIn => o.network_in_speed as f64,
+            Metric::NetworkOut => o.network_out_speed as f64,
+        }
+    }
+}
+
+// 对齐步长取各主机所选聚合级别中最粗的一级, 且点数不超过 MAX_POINTS
+pub fn step(intervals: impl Iterator<Item = i64>, time_range: i64) -> i64 {
+    let coarsest = intervals.max().unwrap_or_default() * 60;
+    coarsest.max((time_range + MAX_POINTS - 1) / MAX_POINTS).max(1)
+}
+
+// 按 step 分桶取平均, 各主机共用同一组时间戳, 没有数据或离线的桶为 null
+pub fn align(
+    names: &[String],
+    mut records: HashMap<String, Vec<HostStatRecord>>,
+    metric: Metric,
+    (start, end): (i64, i64),
+    step: i64,
+) -> Value {
+    let first = start - start.rem_euclid(step);
+    let buckets = ((end - first) / step + 1) as usize;
+    let timestamps = (0..buckets).map(|i| first + i as i64 * step).collect::<Vec<_>>();
+
+    let series = names
+        .iter()
+        .filter_map(|name| {
+            let host = records.remove(name)?;
+            let mut sums = vec![(0.0, 0_u32); buckets];
+            for o in host.iter().filter(|o| o.online && o.timestamp >= first && o.timestamp <= end) {
+                let (sum, n) = &mut sums[((o.timestamp - first) / step) as usize];
+                *sum += metric.value(o);
+                *n += 1;
+            }
+            let values = sums
+                .into_iter()
+                .map(|(sum, n)| match n {
+                    0 => Value::Null,
+                    _ => json!((sum / n as f64 * 100.0).round() / 100.0),
+                })
+                .collect::<Vec<_>>();
+            Some(json!({
+                "name": name,
+                "alias": host.last().map(|o| o.alias.as_str()).unwrap_or(name),
+                "values": values,
+            }))
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "metric": metric.name(),
+        "unit": metric.unit(),
+        "start": start,
+        "end": end,
+        "step": step,
+        "timestamps": timestamps,
+        "series": series,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: i64, cpu: f64) -> HostStatRecord {
+        HostStatRecord {
+            timestamp,
+            alias: "n".to_string(),
+            cpu,
+            memory_total: 0,
+            memory_used: 0,
+            network_in: 0,
+            network_out: 0,
+            network_in_speed: 0,
+            network_out_speed: 0,
+            online: true,
+            disks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_align() {
+        assert_eq!(step([0, 0].into_iter(), 600), 1);
+        assert_eq!(step([0, 5].into_iter(), 86400), 300);
+        assert_eq!(step([5].into_iter(), 30 * 86400), 4320);
+
+        let records = HashMap::from([
+            ("h1".to_string(), vec![record(1000, 10.0), record(1030, 20.0), record(1130, 30.0)]),
+            ("h2".to_string(), vec![record(1070, 50.0)]),
+        ]);
+        let names = vec!["h2".to_string(), "h1".to_string(), "h9".to_string()];
+        let o = align(&names, records, Metric::Cpu, (1010, 1150), 60);
+        assert_eq!(o["timestamps"], json!([960, 1020, 1080, 1140]));
+        assert_eq!(o["series"].as_array().unwrap().len(), 2);
+        assert_eq!(o["series"][0]["name"], "h2");
+        assert_eq!(o["series"][0]["values"], json!([null, 50.0, null, null]));
+        assert_eq!(o["series"][1]["values"], json!([10.0, 20.0, 30.0, null]));
+    }
+}
@@ -2,12 +2,20 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
 use uuid::Uuid;
 
+use crate::alerts;
 use crate::notifier;
+use crate::db::{Pragmas, Profile, Resolution};
+use crate::conflict::OnConflict;
+use crate::integrity::OnCorruption;
+use crate::labels::{Labels, Selector};
+use crate::probe::ProbeMethod;
+use crate::queue::Overflow;
+use crate::traffic;
 
 fn default_as_true() -> bool {
     true
@@ -15,15 +23,51 @@ fn default_as_true() -> bool {
 fn default_grpc_addr() -> String {
     "0.0.0.0:9394".to_string()
 }
-fn default_http_addr() -> String {
-    "0.0.0.0:8080".to_string()
+fn default_http_addr() -> HttpAddr {
+    HttpAddr(vec![Listener {
+        addr: "0.0.0.0:8080".to_string(),
+        admin: true,
+    }])
 }
 fn default_workspace() -> String {
     "/opt/ServerStatus".to_string()
 }
+fn default_db_path() -> String {
+    "stats.db".to_string()
+}
 fn default_tls_dir() -> String {
     "tls".to_string()
 }
+fn default_refresh_interval() -> u64 {
+    500
+}
+fn default_retry_after() -> u64 {
+    5
+}
+fn default_queue_size() -> usize {
+    512
+}
+fn default_idle_interval() -> u32 {
+    10
+}
+fn default_active_interval() -> u32 {
+    1
+}
+fn default_cpu_threshold() -> f64 {
+    80.0
+}
+fn default_net_threshold() -> u64 {
+    10 * 1024 * 1024
+}
+fn default_flat_samples() -> u32 {
+    30
+}
+fn default_flat_cpu_delta() -> f64 {
+    5.0
+}
+fn default_flat_net_delta() -> u64 {
+    64 * 1024
+}
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Host {
@@ -33,6 +77,13 @@ pub struct Host {
     pub alias: String,
     #[serde(default = "Default::default")]
     pub location: String,
+    // 结构化位置, 用于按区域/可用区/服务商汇总
+    #[serde(default = "Default::default")]
+    pub region: String,
+    #[serde(default = "Default::default")]
+    pub zone: String,
+    #[serde(default = "Default::default")]
+    pub provider: String,
     #[serde(default = "Default::default")]
     pub r#type: String,
     #[serde(default = "u32::default")]
@@ -42,12 +93,15 @@ pub struct Host {
     #[serde(default = "bool::default")]
     pub disabled: bool,
     #[serde(default = "Default::default")]
-    pub labels: String,
+    pub labels: Labels,
 
     #[serde(skip_deserializing)]
     pub last_network_in: u64,
     #[serde(skip_deserializing)]
     pub last_network_out: u64,
+    // 连续时钟偏差的上报次数
+    #[serde(skip)]
+    pub skew_samples: u32,
 
     // user data
     #[serde(skip_serializing, skip_deserializing)]
@@ -58,6 +112,23 @@ pub struct Host {
     pub gid: String,
     #[serde(default = "Default::default")]
     pub latest_ts: u64,
+    // 可选 服务端探测地址, 为空时使用上报的 ip_info.query
+    #[serde(default = "Default::default")]
+    pub ipv4: String,
+    #[serde(default = "Default::default")]
+    pub ipv6: String,
+    // 数据精度策略, 对应 [resolution.<name>], 为空时使用分组的配置或 default
+    #[serde(default = "Default::default")]
+    pub resolution: String,
+    // 显示单位, auto 时使用 [site] 中的 units
+    #[serde(default = "Default::default")]
+    pub units: Units,
+    // 由客户端检查的进程名, systemd:<unit> 或 port:<port>[/udp], 不在运行/侦听时通知
+    #[serde(default = "Default::default")]
+    pub watch: Vec<String>,
+    // 由服务端检查证书过期时间的 https 地址, 如 example.com 或 https://example.com:8443
+    #[serde(default = "Default::default")]
+    pub certs: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -67,6 +138,12 @@ pub struct HostGroup {
     #[serde(default = "Default::default")]
     pub location: String,
     #[serde(default = "Default::default")]
+    pub region: String,
+    #[serde(default = "Default::default")]
+    pub zone: String,
+    #[serde(default = "Default::default")]
+    pub provider: String,
+    #[serde(default = "Default::default")]
     pub r#type: String,
     #[serde(default = "default_as_true")]
     pub notify: bool,
@@ -76,7 +153,23 @@ pub struct HostGroup {
     #[serde(default = "Default::default", skip_serializing)]
     pub weight: u64,
     #[serde(default = "Default::default")]
-    pub labels: String,
+    pub labels: Labels,
+    #[serde(default = "Default::default")]
+    pub resolution: String,
+    #[serde(default = "Default::default")]
+    pub units: Units,
+    // 分组配额: 所有成员本月流量(in+out)合计上限(GiB), 0 不检查
+    #[serde(default = "Default::default")]
+    pub max_traffic: u64,
+    // 分组配额: 最少在线成员数, 0 不检查
+    #[serde(default = "Default::default")]
+    pub min_online: usize,
+    // 组内主机的独立密码, 主机名 => 密码, 设置后该主机不能再用组密码上报
+    #[serde(default = "Default::default", skip_serializing)]
+    pub secrets: HashMap<String, String>,
+    // 组内主机由客户端检查的进程名, systemd:<unit> 或 port:<port>[/udp]
+    #[serde(default = "Default::default")]
+    pub watch: Vec<String>,
 }
 
 impl HostGroup {
@@ -86,46 +179,1177 @@ impl HostGroup {
             gid: self.gid.to_owned(),
             password: self.password.to_owned(),
             location: self.location.to_owned(),
+            region: self.region.to_owned(),
+            zone: self.zone.to_owned(),
+            provider: self.provider.to_owned(),
             r#type: self.r#type.to_owned(),
             monthstart: 1,
             notify: self.notify,
             pos: self.pos,
             weight: self.weight,
             labels: self.labels.to_owned(),
+            resolution: self.resolution.to_owned(),
+            units: self.units,
             ..Default::default()
         }
     }
 }
 
+// 上报数据入库队列
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Ingest {
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    // reject | block | drop_oldest | drop_newest | spill
+    #[serde(default = "Default::default")]
+    pub overflow: Overflow,
+    // reject 时建议客户端的重试等待时间(s)
+    #[serde(default = "default_retry_after")]
+    pub retry_after: u64,
+    // 溢出/退出时未处理数据的落盘文件, 为空则不落盘
+    #[serde(default = "Default::default")]
+    pub spill_path: String,
+    // 已接收的原始上报追加写入的文件, 用于回放复现问题, 为空则不录制
+    #[serde(default = "Default::default")]
+    pub record_path: String,
+    // /report 单独的 http 侦听地址, 设置后仪表盘的侦听地址不再接收上报, 为空则与仪表盘共用
+    #[serde(default = "Default::default")]
+    pub addr: String,
+    // 客户端时间与接收时间允许的偏差(s)
+    #[serde(default = "default_max_clock_skew")]
+    pub max_clock_skew: u64,
+    // 偏差超过 max_clock_skew 时使用接收时间作为 latest_ts
+    #[serde(default = "Default::default")]
+    pub fix_clock_skew: bool,
+    // 连续多少次上报偏差超过 max_clock_skew 时标记主机并通知一次, 0 不检测
+    #[serde(default = "default_clock_skew_samples")]
+    pub clock_skew_samples: u32,
+    // never | missing | always
+    #[serde(default = "Default::default")]
+    pub stamp: Stamp,
+    // latest_ts 超前接收时间多少秒时拒绝上报, 0 不检查
+    #[serde(default = "Default::default")]
+    pub max_future_ts: u64,
+    // off | mark | reject
+    #[serde(default = "Default::default")]
+    pub on_conflict: OnConflict,
+    // /report 请求体及 gRPC 上报消息的大小上限(bytes)
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    // 上报中文本字段的长度上限(字符), 超出截断, 主机名超长时拒绝
+    #[serde(default = "default_max_field_len")]
+    pub max_field_len: usize,
+    // 单次上报最多接收的磁盘数
+    #[serde(default = "default_max_disks")]
+    pub max_disks: usize,
+    // /api/ingest/alert 接收 Alertmanager webhook 的 Bearer 令牌, 为空则不接收
+    #[serde(default = "Default::default")]
+    pub alert_token: String,
+}
+
+// 是否使用服务端接收时间作为 latest_ts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stamp {
+    // 使用上报的 latest_ts
+    Never,
+    // 上报中没有 latest_ts 时使用接收时间
+    #[default]
+    Missing,
+    // 始终使用接收时间
+    Always,
+}
+
+impl Ingest {
+    // 返回入库使用的 latest_ts, 超前过多时拒绝
+    pub fn latest_ts(&self, latest_ts: u64, server_ts: u64) -> Result<u64> {
+        if self.stamp == Stamp::Always || (self.stamp == Stamp::Missing && latest_ts == 0) {
+            return Ok(server_ts);
+        }
+        if self.max_future_ts > 0 && latest_ts > server_ts + self.max_future_ts {
+            return Err(anyhow::anyhow!(
+                "latest_ts {} is {}s ahead of server time",
+                latest_ts,
+                latest_ts - server_ts
+            ));
+        }
+        // 客户端时钟偏差过大时使用接收时间, 避免一直显示离线
+        if self.fix_clock_skew && latest_ts.abs_diff(server_ts) > self.max_clock_skew {
+            return Ok(server_ts);
+        }
+        Ok(latest_ts)
+    }
+}
+
+impl Default for Ingest {
+    fn default() -> Self {
+        Self {
+            queue_size: default_queue_size(),
+            overflow: Overflow::default(),
+            retry_after: default_retry_after(),
+            spill_path: String::new(),
+            record_path: String::new(),
+            addr: String::new(),
+            max_clock_skew: default_max_clock_skew(),
+            fix_clock_skew: false,
+            clock_skew_samples: default_clock_skew_samples(),
+            stamp: Stamp::default(),
+            max_future_ts: 0,
+            on_conflict: OnConflict::default(),
+            max_body_size: default_max_body_size(),
+            max_field_len: default_max_field_len(),
+            max_disks: default_max_disks(),
+            alert_token: String::new(),
+        }
+    }
+}
+
+fn default_max_body_size() -> usize {
+    1 << 20
+}
+fn default_max_field_len() -> usize {
+    128
+}
+fn default_max_disks() -> usize {
+    64
+}
+
+fn default_max_clock_skew() -> u64 {
+    30
+}
+fn default_clock_skew_samples() -> u32 {
+    10
+}
+
+fn default_cluster_channel() -> String {
+    "serverstatus:stats".to_string()
+}
+fn default_cluster_queue_size() -> usize {
+    1024
+}
+fn default_lease_ttl() -> u64 {
+    30
+}
+
+// 集群模式, 通过 redis pub/sub 共享上报数据
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cluster {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    #[serde(default = "Default::default")]
+    pub redis_url: String,
+    #[serde(default = "default_cluster_channel")]
+    pub channel: String,
+    // 节点标识, 为空时启动时随机生成
+    #[serde(default = "Default::default")]
+    pub node_id: String,
+    // 待转发队列长度, 满了丢弃
+    #[serde(default = "default_cluster_queue_size")]
+    pub queue_size: usize,
+    // 多实例选主, 只有 leader 执行数据库维护任务和发送通知
+    #[serde(default = "Default::default")]
+    pub leader_election: bool,
+    #[serde(default = "default_lease_ttl")]
+    pub lease_ttl: u64,
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            redis_url: String::new(),
+            channel: default_cluster_channel(),
+            node_id: String::new(),
+            queue_size: default_cluster_queue_size(),
+            leader_election: false,
+            lease_ttl: default_lease_ttl(),
+        }
+    }
+}
+
+fn default_mirror_interval() -> u64 {
+    1
+}
+fn default_mirror_timeout() -> u64 {
+    5
+}
+
+// 只读镜像, 从主节点拉取数据, 只提供公开的展示页面和接口
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Mirror {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 主节点地址, 如 http://10.0.0.1:8080
+    #[serde(default = "Default::default")]
+    pub primary: String,
+    // 拉取间隔(s)
+    #[serde(default = "default_mirror_interval")]
+    pub interval: u64,
+    #[serde(default = "default_mirror_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for Mirror {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            primary: String::new(),
+            interval: default_mirror_interval(),
+            timeout: default_mirror_timeout(),
+        }
+    }
+}
+
+// 对外 http 请求使用的 dns, 都为空时使用系统配置
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Dns {
+    // ip 或 ip:port
+    #[serde(default = "Default::default")]
+    pub servers: Vec<String>,
+    // DNS over HTTPS, ip[:port]#tls_name
+    #[serde(default = "Default::default")]
+    pub doh: Vec<String>,
+}
+
+// stats.db
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Db {
+    // 启动时及每天执行 PRAGMA integrity_check
+    #[serde(default = "default_as_true")]
+    pub integrity_check: bool,
+    // recover | move | exit | ignore
+    #[serde(default = "Default::default")]
+    pub on_corruption: OnCorruption,
+    // 聚合/优化等后台任务连续失败多少次后告警, 0 不告警
+    #[serde(default = "default_task_alert_after")]
+    pub task_alert_after: u32,
+    // 历史查询超过该耗时(ms)时记录慢查询日志, 0 不记录
+    #[serde(default = "default_slow_query_ms")]
+    pub slow_query_ms: u64,
+    // 单次历史查询的时间预算(ms), 超过后返回已查询的主机及 cursor, 0 不限制
+    #[serde(default = "default_history_budget_ms")]
+    pub history_budget_ms: u64,
+    // 上下线及告警事件保留天数
+    #[serde(default = "default_event_retention_days")]
+    pub event_retention_days: i64,
+    // 只读连接数, 历史查询等使用只读连接, 不阻塞入库写入, 0 表示全部使用写连接
+    #[serde(default = "default_db_readers")]
+    pub readers: usize,
+    // default | small | large, 下面的单项配置优先
+    #[serde(default = "Default::default")]
+    pub profile: Profile,
+    #[serde(default = "Default::default")]
+    pub cache_size: Option<i64>,
+    #[serde(default = "Default::default")]
+    pub mmap_size: Option<i64>,
+    #[serde(default = "Default::default")]
+    pub busy_timeout: Option<u64>,
+    #[serde(default = "Default::default")]
+    pub wal_autocheckpoint: Option<u32>,
+}
+
+impl Db {
+    pub fn pragmas(&self) -> Pragmas {
+        let o = Pragmas::profile(self.profile);
+        Pragmas {
+            cache_size: self.cache_size.unwrap_or(o.cache_size),
+            mmap_size: self.mmap_size.unwrap_or(o.mmap_size),
+            busy_timeout: self.busy_timeout.unwrap_or(o.busy_timeout),
+            wal_autocheckpoint: self.wal_autocheckpoint.unwrap_or(o.wal_autocheckpoint),
+        }
+    }
+}
+
+fn default_db_readers() -> usize {
+    crate::db::DEFAULT_READERS
+}
+
+fn default_event_retention_days() -> i64 {
+    90
+}
+fn default_slow_query_ms() -> u64 {
+    1000
+}
+fn default_history_budget_ms() -> u64 {
+    5000
+}
+
+fn default_task_alert_after() -> u32 {
+    3
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self {
+            integrity_check: true,
+            on_corruption: OnCorruption::default(),
+            task_alert_after: default_task_alert_after(),
+            slow_query_ms: default_slow_query_ms(),
+            history_budget_ms: default_history_budget_ms(),
+            event_retention_days: default_event_retention_days(),
+            readers: default_db_readers(),
+            profile: Default::default(),
+            cache_size: None,
+            mmap_size: None,
+            busy_timeout: None,
+            wal_autocheckpoint: None,
+        }
+    }
+}
+
+// 分组主机过期(超过 group_gc 未上报)后的历史数据处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpireHistory {
+    #[default]
+    Keep,
+    // 聚合数据写入 archive.dir 后从数据库删除
+    Archive,
+    Purge,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct GroupExpire {
+    // 过期时通过已启用的通知方式告警
+    #[serde(default = "Default::default")]
+    pub notify: bool,
+    #[serde(default = "Default::default")]
+    pub history: ExpireHistory,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Archive {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // parquet 文件目录, 按 <主机>/<年-月> 分区
+    #[serde(default = "default_archive_dir")]
+    pub dir: String,
+    // 聚合数据保留在数据库中的天数, 更早的移入归档
+    #[serde(default = "default_archive_after_days")]
+    pub after_days: i64,
+}
+
+fn default_archive_dir() -> String {
+    "archive".to_string()
+}
+fn default_archive_after_days() -> i64 {
+    30
+}
+
+impl Default for Archive {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_archive_dir(),
+            after_days: default_archive_after_days(),
+        }
+    }
+}
+
+// 通知路由规则, 匹配 selector 的主机只通知 notifiers 中列出的实例
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NotifyRoute {
+    // 为空时匹配全部主机
+    #[serde(default = "Default::default")]
+    pub selector: String,
+    pub notifiers: Vec<String>,
+    // 生效时段, 如 "09:00-22:00", 结束早于开始时跨越午夜, 为空表示全天
+    #[serde(default = "Default::default")]
+    pub hours: String,
+    // 时段的时区, 如 Asia/Shanghai, 为空时使用服务器本地时区
+    #[serde(default = "Default::default")]
+    pub timezone: String,
+}
+
+// 告警规则检查的对象
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    // 逐台主机检查
+    #[default]
+    Host,
+    // 对 selector 选中的主机的汇总检查, 如离线数量, 平均 cpu
+    Group,
+}
+
+// 告警规则, expr 为表达式, 如 cpu > 90 && duration("5m"), 触发及恢复时各通知一次
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub expr: String,
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    #[serde(default = "Default::default")]
+    pub scope: Scope,
+    // 主机标签选择器, 为空时检查全部主机
+    #[serde(default = "Default::default")]
+    pub selector: String,
+    // jinja 模板, 可引用 rule 及 host(scope = host) 或 group(scope = group), 为空时使用默认内容
+    #[serde(default = "Default::default")]
+    pub message: String,
+}
+
+// 流量方向, in 为 network_rx, out 为 network_tx
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    // 出站流量突增常见于主机被入侵后对外攻击
+    #[default]
+    Out,
+}
+
+fn default_traffic_baseline() -> String {
+    "1h".to_string()
+}
+fn default_traffic_duration() -> String {
+    "60s".to_string()
+}
+
+// 流量突增告警, 超过 mbps 或基线的 factor 倍并持续 duration 时通知, 两者都设置时需同时满足
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrafficAlert {
+    pub name: String,
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    #[serde(default = "Default::default")]
+    pub direction: Direction,
+    // 绝对阈值, 0 不检查
+    #[serde(default = "Default::default")]
+    pub mbps: f64,
+    // 基线的倍数, 0 不检查
+    #[serde(default = "Default::default")]
+    pub factor: f64,
+    // 基线为这段时间内未超限时的平均速率
+    #[serde(default = "default_traffic_baseline")]
+    pub baseline: String,
+    #[serde(default = "default_traffic_duration")]
+    pub duration: String,
+    // 主机标签选择器, 为空时检查全部主机
+    #[serde(default = "Default::default")]
+    pub selector: String,
+}
+
+// 计划维护窗口, 发布在 /calendar.ics
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Maintenance {
+    pub title: String,
+    #[serde(default = "Default::default")]
+    pub description: String,
+    // RFC 3339, 如 2024-06-01T02:00:00+08:00
+    pub start: String,
+    pub end: String,
+    // 受影响的主机, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub hosts: Vec<String>,
+
+    #[serde(skip_deserializing)]
+    pub start_ts: i64,
+    #[serde(skip_deserializing)]
+    pub end_ts: i64,
+}
+
+fn default_graphql_max_depth() -> usize {
+    10
+}
+fn default_graphql_max_complexity() -> usize {
+    1000
+}
+
+// 每日汇总通知
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Digest {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 发送时间, 服务器本地时间 HH:MM
+    #[serde(default = "default_digest_at")]
+    pub at: String,
+    // 附带主机列表截图, tgbot / wechat 发送图片, 其它方式只发送文字
+    #[serde(default = "default_as_true")]
+    pub image: bool,
+}
+
+fn default_digest_at() -> String {
+    "09:00".to_string()
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            at: default_digest_at(),
+            image: true,
+        }
+    }
+}
+
+// 按日汇总的磁盘用量做线性拟合, 预测写满的天数
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiskForecast {
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    // 参与拟合的天数
+    #[serde(default = "default_forecast_window_days")]
+    pub window_days: i64,
+    // 至少有多少天的数据才预测
+    #[serde(default = "default_forecast_min_days")]
+    pub min_days: usize,
+    // 预测在该天数内写满时告警, 0 不告警
+    #[serde(default = "Default::default")]
+    pub alert_days: u64,
+}
+
+fn default_forecast_window_days() -> i64 {
+    30
+}
+fn default_forecast_min_days() -> usize {
+    7
+}
+
+impl Default for DiskForecast {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_days: default_forecast_window_days(),
+            min_days: default_forecast_min_days(),
+            alert_days: 0,
+        }
+    }
+}
+
+// 进程运行方式, 以 root 启动时侦听端口后降权
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RunAs {
+    #[serde(default = "Default::default")]
+    pub pid_file: String,
+    // 八进制, 如 "027", 为空时使用继承的 umask
+    #[serde(default = "Default::default")]
+    pub umask: String,
+    #[serde(default = "Default::default")]
+    pub user: String,
+    // 为空时使用 user 的主组
+    #[serde(default = "Default::default")]
+    pub group: String,
+}
+
+// 管理接口模拟主机下线, 用于验证通知路由
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Chaos {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 单次模拟下线的最长时间(s), 到期自动恢复
+    #[serde(default = "default_chaos_max_duration")]
+    pub max_duration: u64,
+}
+
+fn default_chaos_max_duration() -> u64 {
+    3600
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_duration: default_chaos_max_duration(),
+        }
+    }
+}
+
+// Grafana simple-json-datasource 接口 /grafana
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Grafana {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 不为空时需要 Authorization: Bearer {token}
+    #[serde(default = "Default::default")]
+    pub token: String,
+}
+
+// /graphql 接口
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GraphQL {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // GET /graphql 返回 playground 调试页面
+    #[serde(default = "default_as_true")]
+    pub playground: bool,
+    // 查询嵌套深度和复杂度限制
+    #[serde(default = "default_graphql_max_depth")]
+    pub max_depth: usize,
+    #[serde(default = "default_graphql_max_complexity")]
+    pub max_complexity: usize,
+}
+
+impl Default for GraphQL {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            playground: true,
+            max_depth: default_graphql_max_depth(),
+            max_complexity: default_graphql_max_complexity(),
+        }
+    }
+}
+
+fn default_frame_options() -> String {
+    "DENY".to_string()
+}
+fn default_frame_allow() -> Vec<String> {
+    vec!["/widget/".to_string()]
+}
+fn default_referrer_policy() -> String {
+    "strict-origin-when-cross-origin".to_string()
+}
+
+// http 访问日志, 每行一条 json
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AccessLog {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 追加写入的文件, 为空输出到 stdout
+    #[serde(default = "Default::default")]
+    pub path: String,
+}
+
+// 响应的安全头, 面板经常直接暴露在公网
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SecurityHeaders {
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    // Content-Security-Policy, 为空不设置, 自定义主题引用外部资源时需相应放开
+    #[serde(default = "Default::default")]
+    pub csp: String,
+    // X-Frame-Options, DENY | SAMEORIGIN, 为空不设置
+    #[serde(default = "default_frame_options")]
+    pub frame_options: String,
+    // 允许被 iframe 嵌入的路径前缀, 不设置 X-Frame-Options, 改用 frame_ancestors
+    #[serde(default = "default_frame_allow")]
+    pub frame_allow: Vec<String>,
+    // 允许嵌入的来源, 即 CSP frame-ancestors, 为空不限制
+    #[serde(default = "Default::default")]
+    pub frame_ancestors: String,
+    #[serde(default = "default_referrer_policy")]
+    pub referrer_policy: String,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            csp: String::new(),
+            frame_options: default_frame_options(),
+            frame_allow: default_frame_allow(),
+            frame_ancestors: String::new(),
+            referrer_policy: default_referrer_policy(),
+        }
+    }
+}
+
+// 流量/内存等的显示单位
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    // 按主机上报的 si 标记
+    #[default]
+    Auto,
+    // 1000 进制, KB MB GB
+    Si,
+    // 1024 进制, KiB MiB GiB
+    Iec,
+}
+
+// 站点信息, 注入到所有模板的 site 变量, 并通过 /config.pub.json 提供给前端
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Site {
+    #[serde(default = "Default::default")]
+    pub title: String,
+    #[serde(default = "Default::default")]
+    pub logo: String,
+    // 页脚, 允许 html
+    #[serde(default = "Default::default")]
+    pub footer: String,
+    // 统计代码, 原样插入页面
+    #[serde(default = "Default::default")]
+    pub analytics: String,
+    // 公告横幅, 为空不显示
+    #[serde(default = "Default::default")]
+    pub announcement: String,
+    #[serde(default = "Default::default")]
+    pub units: Units,
+    // 前端默认的刷新间隔(s)
+    #[serde(default = "default_site_refresh")]
+    pub refresh: u64,
+}
+
+fn default_site_refresh() -> u64 {
+    5
+}
+
+impl Units {
+    pub fn or(self, fallback: Units) -> Units {
+        match self {
+            Units::Auto => fallback,
+            o => o,
+        }
+    }
+
+    // reported 为主机上报的 si 标记
+    pub fn si(self, reported: bool) -> bool {
+        match self {
+            Units::Auto => reported,
+            Units::Si => true,
+            Units::Iec => false,
+        }
+    }
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            logo: String::new(),
+            footer: String::new(),
+            analytics: String::new(),
+            announcement: String::new(),
+            units: Units::default(),
+            refresh: default_site_refresh(),
+        }
+    }
+}
+
+fn default_probe_port() -> u16 {
+    22
+}
+fn default_probe_interval() -> u64 {
+    60
+}
+fn default_probe_timeout() -> u64 {
+    3
+}
+fn default_probe_concurrency() -> usize {
+    32
+}
+
+// 服务端主动探测主机 ipv4/ipv6 的可达性, 与客户端自报的 online4/online6 分开记录
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Probe {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    #[serde(default = "Default::default")]
+    pub method: ProbeMethod,
+    // tcp 探测端口
+    #[serde(default = "default_probe_port")]
+    pub port: u16,
+    // 探测间隔(s)
+    #[serde(default = "default_probe_interval")]
+    pub interval: u64,
+    #[serde(default = "default_probe_timeout")]
+    pub timeout: u64,
+    // 同时探测的地址数
+    #[serde(default = "default_probe_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for Probe {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: ProbeMethod::default(),
+            port: default_probe_port(),
+            interval: default_probe_interval(),
+            timeout: default_probe_timeout(),
+            concurrency: default_probe_concurrency(),
+        }
+    }
+}
+
+fn default_certs_interval() -> String {
+    "1d".to_string()
+}
+fn default_certs_timeout() -> u64 {
+    10
+}
+fn default_certs_warn_days() -> Vec<u32> {
+    vec![30, 7, 1]
+}
+
+// 服务端定期检查 https 证书的过期时间, 剩余天数依次达到 warn_days 中的值及过期时各通知一次
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Certs {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 检查间隔, 如 12h, 1d
+    #[serde(default = "default_certs_interval")]
+    pub interval: String,
+    // 单个地址的连接及握手超时(s)
+    #[serde(default = "default_certs_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_certs_warn_days")]
+    pub warn_days: Vec<u32>,
+    // 不属于某个主机的地址, 主机的地址在 [[hosts]] 的 certs 中配置
+    #[serde(default = "Default::default")]
+    pub endpoints: Vec<String>,
+}
+
+impl Default for Certs {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_certs_interval(),
+            timeout: default_certs_timeout(),
+            warn_days: default_certs_warn_days(),
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+fn default_domains_rdap_url() -> String {
+    "https://rdap.org/domain".to_string()
+}
+
+// 定期通过 RDAP 查询域名的注册到期时间, 查询失败时使用 WHOIS, 结果在 /json/domains.json 公开展示
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Domains {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    #[serde(default = "default_certs_interval")]
+    pub interval: String,
+    // 单个域名的查询超时(s)
+    #[serde(default = "default_certs_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_certs_warn_days")]
+    pub warn_days: Vec<u32>,
+    // RDAP 查询地址, 请求 {rdap_url}/{domain}
+    #[serde(default = "default_domains_rdap_url")]
+    pub rdap_url: String,
+    // RDAP 没有结果时通过 whois.iana.org 找到注册局的 WHOIS 服务器查询
+    #[serde(default = "default_as_true")]
+    pub whois: bool,
+    // RDAP 查询使用的代理, 为空时使用全局代理
+    #[serde(default = "Default::default")]
+    pub proxy: String,
+    #[serde(default = "Default::default")]
+    pub names: Vec<String>,
+}
+
+impl Default for Domains {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_certs_interval(),
+            timeout: default_certs_timeout(),
+            warn_days: default_certs_warn_days(),
+            rdap_url: default_domains_rdap_url(),
+            whois: true,
+            proxy: String::new(),
+            names: Vec::new(),
+        }
+    }
+}
+
+fn default_mesh_stale() -> u64 {
+    300
+}
+
+fn default_guard_max_failures() -> u32 {
+    10
+}
+fn default_guard_window() -> u64 {
+    60
+}
+fn default_guard_block() -> u64 {
+    600
+}
+
+// 上报认证(/report 及 gRPC)的暴力破解防护, 按来源 ip 计数
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthGuard {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // window 秒内认证失败 max_failures 次的来源封禁 block 秒
+    #[serde(default = "default_guard_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_guard_window")]
+    pub window: u64,
+    #[serde(default = "default_guard_block")]
+    pub block: u64,
+    // 封禁来源时通过已启用的通知方式告警
+    #[serde(default = "Default::default")]
+    pub notify: bool,
+    // 经反向代理时取来源 ip 的请求头, 如 x-real-ip, 为空使用连接的对端地址
+    #[serde(default = "Default::default")]
+    pub real_ip_header: String,
+}
+
+impl Default for AuthGuard {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_failures: default_guard_max_failures(),
+            window: default_guard_window(),
+            block: default_guard_block(),
+            notify: false,
+            real_ip_header: String::new(),
+        }
+    }
+}
+
+// 链路告警规则, from/to 为主机 location, * 匹配任意
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MeshRule {
+    pub from: String,
+    pub to: String,
+    // 平均延迟上限(ms), 0 不检查
+    #[serde(default = "Default::default")]
+    pub max_latency: u32,
+    // 丢包率上限(%), 0 不检查
+    #[serde(default = "Default::default")]
+    pub max_loss: u32,
+}
+
+impl MeshRule {
+    fn matches(pattern: &str, location: &str) -> bool {
+        pattern == "*" || pattern == location
+    }
+
+    pub fn applies(&self, from: &str, to: &str) -> bool {
+        Self::matches(&self.from, from) && Self::matches(&self.to, to)
+    }
+
+    pub fn degraded(&self, latency: u32, loss: u32) -> bool {
+        (self.max_latency > 0 && latency > self.max_latency) || (self.max_loss > 0 && loss > self.max_loss)
+    }
+}
+
+// 客户端之间互相探测的延迟矩阵
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Mesh {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 客户端 tcp 连接的端口
+    #[serde(default = "default_probe_port")]
+    pub port: u16,
+    // 每台主机探测的目标数, 0 为全部
+    #[serde(default = "Default::default")]
+    pub fanout: usize,
+    // 超过该时长(s)未更新的链路不再展示
+    #[serde(default = "default_mesh_stale")]
+    pub stale: u64,
+    #[serde(default = "Default::default")]
+    pub rules: Vec<MeshRule>,
+}
+
+impl Default for Mesh {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_probe_port(),
+            fanout: 0,
+            stale: default_mesh_stale(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+// 自适应上报间隔
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Adaptive {
+    #[serde(default = "Default::default")]
+    pub enabled: bool,
+    // 指标平稳时下发的上报间隔(s)
+    #[serde(default = "default_idle_interval")]
+    pub idle_interval: u32,
+    // 超过阈值时下发的上报间隔(s)
+    #[serde(default = "default_active_interval")]
+    pub active_interval: u32,
+    // cpu 使用率阈值 (%)
+    #[serde(default = "default_cpu_threshold")]
+    pub cpu_threshold: f64,
+    // 上/下行速率阈值 (bytes/s)
+    #[serde(default = "default_net_threshold")]
+    pub net_threshold: u64,
+    // 连续多少次平稳采样后降频
+    #[serde(default = "default_flat_samples")]
+    pub flat_samples: u32,
+    // 平稳判定: cpu 变化幅度 (%)
+    #[serde(default = "default_flat_cpu_delta")]
+    pub flat_cpu_delta: f64,
+    // 平稳判定: 网速变化幅度 (bytes/s)
+    #[serde(default = "default_flat_net_delta")]
+    pub flat_net_delta: u64,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_interval: default_idle_interval(),
+            active_interval: default_active_interval(),
+            cpu_threshold: default_cpu_threshold(),
+            net_threshold: default_net_threshold(),
+            flat_samples: default_flat_samples(),
+            flat_cpu_delta: default_flat_cpu_delta(),
+            flat_net_delta: default_flat_net_delta(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Listener {
+    pub addr: String,
+    // 是否提供 /admin 及 /api/admin 接口, 公开的侦听地址可关闭
+    #[serde(default = "default_as_true")]
+    pub admin: bool,
+}
+
+// http 侦听地址, 兼容单个地址 "0.0.0.0:8080", 也可以是列表
+// ["127.0.0.1:8081", { addr = "0.0.0.0:8080", admin = false }]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HttpAddr(pub Vec<Listener>);
+
+impl<'de> Deserialize<'de> for HttpAddr {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Item {
+            Str(String),
+            Listener(Listener),
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            One(Item),
+            Many(Vec<Item>),
+        }
+        let items = match Raw::deserialize(d)? {
+            Raw::One(o) => vec![o],
+            Raw::Many(v) => v,
+        };
+        if items.is_empty() {
+            return Err(serde::de::Error::custom("http_addr is empty"));
+        }
+        Ok(HttpAddr(
+            items
+                .into_iter()
+                .map(|o| match o {
+                    Item::Str(addr) => Listener { addr, admin: true },
+                    Item::Listener(o) => o,
+                })
+                .collect(),
+        ))
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default = "default_http_addr")]
-    pub http_addr: String,
+    pub http_addr: HttpAddr,
     #[serde(default = "default_grpc_addr")]
     pub grpc_addr: String,
     #[serde(default = "Default::default")]
     pub notify_interval: u64,
     #[serde(default = "Default::default")]
     pub offline_threshold: u64,
+    // stats.json 最小重建间隔(ms)
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+    // 通知只写入 log 通知方式(含渲染内容及路由结果), 不实际发送, 用于验证告警规则
+    #[serde(default = "Default::default")]
+    pub notify_dry_run: bool,
     #[serde(default = "Default::default")]
     pub grpc_tls: u32,
+    // 在 grpc_addr 上提供 History 历史数据查询服务(protobuf), 与 history.json 一样不需要认证
+    #[serde(default = "Default::default")]
+    pub grpc_history: bool,
     #[serde(default = "default_tls_dir")]
     pub tls_dir: String,
+    // sqlite 数据库文件, 只读根文件系统时指向挂载的可写目录, 损坏时移走的备份也在同一目录
+    #[serde(default = "default_db_path")]
+    pub db_path: String,
+    // 覆盖内置 detail/map/widget/client-init 模板的目录, 修改后自动重新加载
+    #[serde(default = "Default::default")]
+    pub templates_dir: String,
     // admin user & pass
     pub admin_user: Option<String>,
     pub admin_pass: Option<String>,
     pub jwt_secret: Option<String>,
 
+    // 对外 http 请求(通知, 镜像拉取等)的全局代理
+    #[serde(default = "Default::default")]
+    pub proxy: String,
+    #[serde(default = "Default::default")]
+    pub no_proxy: String,
+
+    #[serde(default = "Default::default")]
+    pub ingest: Ingest,
+    #[serde(default = "Default::default")]
+    pub auth_guard: AuthGuard,
+    #[serde(default = "Default::default")]
+    pub adaptive: Adaptive,
+    #[serde(default = "Default::default")]
+    pub cluster: Cluster,
+    #[serde(default = "Default::default")]
+    pub mirror: Mirror,
+    #[serde(default = "Default::default")]
+    pub dns: Dns,
+    #[serde(default = "Default::default")]
+    pub probe: Probe,
+    #[serde(default = "Default::default")]
+    pub certs: Certs,
+    #[serde(default = "Default::default")]
+    pub domains: Domains,
+    #[serde(default = "Default::default")]
+    pub mesh: Mesh,
+    #[serde(default = "Default::default")]
+    pub db: Db,
+    #[serde(default = "Default::default")]
+    pub archive: Archive,
+    #[serde(default = "Default::default")]
+    pub graphql: GraphQL,
+    #[serde(default = "Default::default")]
+    pub grafana: Grafana,
+    #[serde(default = "Default::default")]
+    pub security_headers: SecurityHeaders,
+    #[serde(default = "Default::default")]
+    pub access_log: AccessLog,
+    #[serde(default = "Default::default")]
+    pub site: Site,
+    #[serde(default = "Default::default")]
+    pub maintenance: Vec<Maintenance>,
+    #[serde(default = "Default::default")]
+    pub digest: Digest,
+    #[serde(default = "Default::default")]
+    pub disk_forecast: DiskForecast,
+    #[serde(default = "Default::default")]
+    pub chaos: Chaos,
+    #[serde(default = "Default::default")]
+    pub run_as: RunAs,
+    // 数据精度策略, name => 策略, default 为未指定策略的主机使用
+    #[serde(default = "Default::default")]
+    pub resolution: HashMap<String, Resolution>,
+
+    // 单实例的通知配置, 实例名默认为 kind
+    #[serde(default = "Default::default")]
+    pub tgbot: Option<toml::Table>,
+    #[serde(default = "Default::default")]
+    pub wechat: Option<toml::Table>,
+    #[serde(default = "Default::default")]
+    pub email: Option<toml::Table>,
     #[serde(default = "Default::default")]
-    pub tgbot: notifier::tgbot::Config,
+    pub log: Option<toml::Table>,
     #[serde(default = "Default::default")]
-    pub wechat: notifier::wechat::Config,
+    pub webhook: Option<toml::Table>,
     #[serde(default = "Default::default")]
-    pub email: notifier::email::Config,
+    pub alertmanager: Option<toml::Table>,
+    // 多实例的通知配置, kind 指定通知方式, name 供 notify_routes 引用
     #[serde(default = "Default::default")]
-    pub log: notifier::log::Config,
+    pub notifiers: Vec<toml::Table>,
     #[serde(default = "Default::default")]
-    pub webhook: notifier::webhook::Config,
+    pub notify_routes: Vec<NotifyRoute>,
+    #[serde(default = "Default::default")]
+    pub alert_rules: Vec<AlertRule>,
+    #[serde(default = "Default::default")]
+    pub traffic_alerts: Vec<TrafficAlert>,
 
     #[serde(default = "Default::default")]
     pub hosts: Vec<Host>,
@@ -133,6 +1357,8 @@ pub struct Config {
     pub hosts_group: Vec<HostGroup>,
     #[serde(default = "Default::default")]
     pub group_gc: u64,
+    #[serde(default = "Default::default")]
+    pub group_expire: GroupExpire,
 
     // deploy
     #[serde(default = "Default::default")]
@@ -148,15 +1374,65 @@ pub struct Config {
 }
 
 impl Config {
-    pub fn auth(&self, user: &str, pass: &str) -> bool {
+    // 仪表盘的地址, 由上报地址 server_url 去掉 /report 得到, 未配置时为空
+    pub fn public_url(&self) -> &str {
+        self.server_url.trim_end_matches('/').trim_end_matches("/report")
+    }
+
+    // 所有通知实例的配置, 补全 kind / name, [[notifiers]] 中未指定 enabled 时默认启用
+    pub fn notifier_tables(&self) -> Vec<toml::Table> {
+        let single = [
+            (notifier::tgbot::KIND, &self.tgbot),
+            (notifier::wechat::KIND, &self.wechat),
+            (notifier::email::KIND, &self.email),
+            (notifier::log::KIND, &self.log),
+            (notifier::webhook::KIND, &self.webhook),
+            (notifier::alertmanager::KIND, &self.alertmanager),
+        ]
+        .into_iter()
+        .filter_map(|(kind, t)| {
+            let mut t = t.clone()?;
+            t.insert("kind".into(), kind.into());
+            Some(t)
+        });
+
+        single
+            .chain(self.notifiers.iter().cloned())
+            .map(|mut t| {
+                let kind = notifier::kind_of(&t).to_string();
+                if notifier::name_of(&t).is_empty() {
+                    t.insert("name".into(), kind.into());
+                }
+                t.entry("enabled").or_insert(true.into());
+                t
+            })
+            .collect()
+    }
+
+    // secret 为 stats.db 中设置的独立密码, 优先于配置中的密码
+    pub fn auth(&self, user: &str, pass: &str, secret: Option<&str>) -> bool {
         if let Some(o) = self.hosts_map.get(user) {
-            return pass.eq(o.password.as_str());
+            return pass.eq(secret.unwrap_or(o.password.as_str()));
         }
         false
     }
+    // 组密码或组内任一主机的独立密码, 具体是哪台主机在上报时由 member_auth 校验
     pub fn group_auth(&self, gid: &str, pass: &str) -> bool {
         if let Some(o) = self.hosts_group_map.get(gid) {
-            return pass.eq(o.password.as_str());
+            return pass.eq(o.password.as_str()) || o.secrets.values().any(|s| pass.eq(s));
+        }
+        false
+    }
+    // 组内主机有独立密码时只接受该密码, 也不能冒用静态配置的主机名
+    pub fn member_auth(&self, gid: &str, name: &str, pass: &str, secret: Option<&str>) -> bool {
+        if self.hosts_map.contains_key(name) {
+            return false;
+        }
+        if let Some(o) = self.hosts_group_map.get(gid) {
+            let expect = secret
+                .or(o.secrets.get(name).map(String::as_str))
+                .unwrap_or(o.password.as_str());
+            return pass.eq(expect);
         }
         false
     }
@@ -167,6 +1443,18 @@ impl Config {
         false
     }
 
+    // 主机的数据精度策略: 主机配置 > 分组配置 > default
+    pub fn resolution(&self, name: &str, gid: &str) -> Resolution {
+        let host = self.hosts_map.get(name).map(|o| o.resolution.as_str());
+        let group = self.hosts_group_map.get(gid).map(|o| o.resolution.as_str());
+        [host, group, Some("default")]
+            .into_iter()
+            .flatten()
+            .find_map(|o| self.resolution.get(o))
+            .cloned()
+            .unwrap_or_default()
+    }
+
     pub fn to_json_value(&self) -> Result<Value> {
         serde_json::to_value(self).map_err(anyhow::Error::new)
     }
@@ -176,8 +1464,82 @@ impl Config {
     // }
 }
 
+// 90s 30m 2h 1d, 不带单位时为小时
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let (num, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let num = num.parse::<u64>().ok()?;
+    // 来自 url 参数及 bot 命令, 溢出时视为无效
+    match unit {
+        "s" => Some(num),
+        "m" => num.checked_mul(60),
+        "h" | "" => num.checked_mul(3600),
+        "d" => num.checked_mul(86400),
+        _ => None,
+    }
+}
+
+// 可以从文件读取的敏感配置, 任意层级的 xxx_file 的文件内容(去掉末尾换行)作为 xxx 的值
+// 如 jwt_secret_file = "/run/secrets/jwt_secret", [tgbot] bot_token_file = "...", [email] password_file = "..."
+const SECRET_KEYS: [&str; 7] = [
+    "admin_pass",
+    "jwt_secret",
+    "password",
+    "alert_token",
+    "bot_token",
+    "corp_secret",
+    "token",
+];
+// 值均为敏感内容的表, 其中任意的 xxx_file 均从文件读取
+// 如 hosts_group 的 secrets = { node1_file = "..." }, webhook/alertmanager 的 headers = { authorization_file = "..." }
+const SECRET_MAPS: [&str; 2] = ["secrets", "headers"];
+
+fn read_secret_file(file_key: &str, v: &toml::Value) -> Result<toml::Value> {
+    let path = v.as_str().ok_or_else(|| anyhow::anyhow!("`{file_key}` must be a path"))?;
+    let content = fs::read_to_string(path).map_err(|err| anyhow::anyhow!("read `{file_key}` {path} error: {err}"))?;
+    Ok(toml::Value::String(content.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn resolve_secret_map(table: &mut toml::Table) -> Result<()> {
+    let file_keys = table.keys().filter(|k| k.ends_with("_file")).cloned().collect::<Vec<_>>();
+    for file_key in file_keys {
+        let v = table.remove(&file_key).unwrap();
+        let key = file_key.trim_end_matches("_file").to_string();
+        table.insert(key, read_secret_file(&file_key, &v)?);
+    }
+    Ok(())
+}
+
+fn resolve_secret_files(table: &mut toml::Table) -> Result<()> {
+    for key in SECRET_KEYS {
+        let file_key = format!("{key}_file");
+        let Some(v) = table.remove(&file_key) else {
+            continue;
+        };
+        table.insert(key.to_string(), read_secret_file(&file_key, &v)?);
+    }
+    for (k, v) in table.iter_mut() {
+        match v {
+            toml::Value::Table(t) if SECRET_MAPS.contains(&k.as_str()) => resolve_secret_map(t)?,
+            toml::Value::Table(t) => resolve_secret_files(t)?,
+            toml::Value::Array(list) => {
+                for t in list.iter_mut().filter_map(toml::Value::as_table_mut) {
+                    resolve_secret_files(t)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse(content: &str) -> Result<Config> {
+    let mut table = toml::from_str::<toml::Table>(content)?;
+    resolve_secret_files(&mut table)?;
+    Ok(toml::Value::Table(table).try_into::<Config>()?)
+}
+
 pub fn from_str(content: &str) -> Option<Config> {
-    let mut o = toml::from_str::<Config>(content).unwrap();
+    let mut o = parse(content).unwrap();
     o.hosts_map = HashMap::new();
 
     for (idx, host) in o.hosts.iter_mut().enumerate() {
@@ -189,12 +1551,14 @@ pub fn from_str(content: &str) -> Option<Config> {
             host.monthstart = 1;
         }
         host.weight = 10000_u64 - idx as u64;
+        host.units = host.units.or(o.site.units);
         o.hosts_map.insert(host.name.to_owned(), host.clone());
     }
 
     for (idx, group) in o.hosts_group.iter_mut().enumerate() {
         group.pos = idx;
         group.weight = (10000 - (1 + idx) * 100) as u64;
+        group.units = group.units.or(o.site.units);
         o.hosts_group_map.insert(group.gid.to_owned(), group.clone());
     }
 
@@ -208,10 +1572,152 @@ pub fn from_str(content: &str) -> Option<Config> {
         o.group_gc = 30;
     }
 
+    // 降频后的上报间隔不能超过下线判定阈值
+    if o.adaptive.active_interval < 1 {
+        o.adaptive.active_interval = 1;
+    }
+    let max_idle_interval = (o.offline_threshold / 2) as u32;
+    if o.adaptive.idle_interval > max_idle_interval {
+        o.adaptive.idle_interval = max_idle_interval;
+    }
+
+    for (name, res) in o.resolution.iter_mut() {
+        res.intervals.retain(|&i| i > 0);
+        res.intervals.sort_unstable();
+        res.intervals.dedup();
+        if res.raw_retention_days < 1 {
+            res.raw_retention_days = 1;
+        }
+        if res.intervals.is_empty() {
+            eprintln!("❗resolution `{name}` has no aggregation interval, history only uses raw data");
+        }
+    }
+    for policy in o.hosts.iter().map(|h| &h.resolution).chain(o.hosts_group.iter().map(|g| &g.resolution)) {
+        if !policy.is_empty() && !o.resolution.contains_key(policy) {
+            eprintln!("❗resolution `{policy}` is not defined, use default");
+        }
+    }
+
+    o.maintenance.retain_mut(|m| {
+        let parse = |s: &str| chrono::DateTime::parse_from_rfc3339(s).map(|t| t.timestamp());
+        match (parse(&m.start), parse(&m.end)) {
+            (Ok(start), Ok(end)) if start < end => {
+                m.start_ts = start;
+                m.end_ts = end;
+                true
+            }
+            _ => {
+                eprintln!("❗maintenance `{}` has invalid start/end, ignored", m.title);
+                false
+            }
+        }
+    });
+
+    let mut names = HashSet::new();
+    for t in o.notifier_tables() {
+        let name = notifier::name_of(&t).to_string();
+        if let Err(err) = notifier::check(&t) {
+            eprintln!("❗notifier `{name}` is invalid: {err}");
+        }
+        let selector = t.get("selector").and_then(|v| v.as_str()).unwrap_or_default();
+        if let Err(err) = Selector::parse(selector) {
+            eprintln!("❗{name} selector is invalid, send all notifications: {err}");
+        }
+        if !names.insert(name.to_string()) {
+            eprintln!("❗notifier name `{name}` is duplicated");
+        }
+    }
+    for route in o.notify_routes.iter() {
+        if let Err(err) = Selector::parse(&route.selector) {
+            eprintln!("❗notify route selector `{}` is invalid, ignored: {err}", route.selector);
+        }
+        if let Err(err) = notifier::active_at(route, chrono::Utc::now()) {
+            eprintln!("❗notify route `{}` hours is invalid, always active: {err}", route.selector);
+        }
+        for name in route.notifiers.iter().filter(|o| !names.contains(*o)) {
+            eprintln!("❗notify route refers to unknown notifier `{name}`");
+        }
+    }
+
+    let mut names = HashSet::new();
+    o.alert_rules.retain(|rule| {
+        if !rule.enabled {
+            return false;
+        }
+        if !names.insert(rule.name.to_string()) {
+            eprintln!("❗alert rule `{}` is duplicated, ignored", rule.name);
+            return false;
+        }
+        match alerts::compile(rule) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("❗alert rule `{}` is invalid, ignored: {err}", rule.name);
+                false
+            }
+        }
+    });
+
+    let mut names = HashSet::new();
+    o.traffic_alerts.retain(|rule| {
+        if !rule.enabled {
+            return false;
+        }
+        if !names.insert(rule.name.to_string()) {
+            eprintln!("❗traffic alert `{}` is duplicated, ignored", rule.name);
+            return false;
+        }
+        match traffic::compile(rule) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("❗traffic alert `{}` is invalid, ignored: {err}", rule.name);
+                false
+            }
+        }
+    });
+
+    if parse_duration(&o.certs.interval).filter(|&o| o > 0).is_none() {
+        eprintln!("❗certs interval `{}` is invalid, use 1d", o.certs.interval);
+        o.certs.interval = default_certs_interval();
+    }
+    o.certs.warn_days.sort_unstable_by(|a, b| b.cmp(a));
+    o.certs.warn_days.dedup();
+    if parse_duration(&o.domains.interval).filter(|&o| o > 0).is_none() {
+        eprintln!("❗domains interval `{}` is invalid, use 1d", o.domains.interval);
+        o.domains.interval = default_certs_interval();
+    }
+    o.domains.warn_days.sort_unstable_by(|a, b| b.cmp(a));
+    o.domains.warn_days.dedup();
+    for name in o.domains.names.iter_mut() {
+        *name = name.trim().trim_end_matches('.').to_lowercase();
+    }
+    o.domains.names.retain(|name| {
+        let valid = name.contains('.') && name.split('.').all(|o| !o.is_empty()) && !name.contains(['/', ':', ' ']);
+        if !valid {
+            eprintln!("❗domain `{name}` is invalid, ignored");
+        }
+        valid
+    });
+    if o.db.mmap_size.is_some_and(|o| o < 0) {
+        eprintln!("❗db mmap_size `{}` is invalid, use the {:?} profile value", o.db.mmap_size.unwrap(), o.db.profile);
+        o.db.mmap_size = None;
+    }
+
+    // 没有落盘文件时 spill 会静默丢弃溢出的数据
+    if o.ingest.overflow == Overflow::Spill && o.ingest.spill_path.is_empty() {
+        eprintln!("❗ingest overflow `spill` requires spill_path");
+        return None;
+    }
+
+    if o.cluster.node_id.is_empty() {
+        o.cluster.node_id = Uuid::new_v4().to_string();
+    }
+
     if o.admin_user.is_none() || o.admin_user.as_ref()?.is_empty() {
         o.admin_user = Some("admin".to_string());
     }
-    if o.admin_pass.is_none() || o.admin_pass.as_ref()?.is_empty() {
+    // 从文件读取的密码不输出
+    let generated = o.admin_pass.as_ref().map_or(true, String::is_empty);
+    if generated {
         o.admin_pass = Some(Uuid::new_v4().to_string());
     }
     if o.jwt_secret.is_none() || o.jwt_secret.as_ref()?.is_empty() {
@@ -219,7 +1725,9 @@ pub fn from_str(content: &str) -> Option<Config> {
     }
 
     eprintln!("✨ admin_user: {}", o.admin_user.as_ref()?);
-    eprintln!("✨ admin_pass: {}", o.admin_pass.as_ref()?);
+    if generated {
+        eprintln!("✨ admin_pass: {}", o.admin_pass.as_ref()?);
+    }
 
     Some(o)
 }
@@ -232,6 +1740,15 @@ pub fn from_env() -> Option<Config> {
     )
 }
 
+// 只读取配置文件中的 db_path, 供不加载配置的子命令使用, 文件不存在时使用默认值
+pub fn db_path(cfg: &str) -> String {
+    fs::read_to_string(cfg)
+        .ok()
+        .and_then(|o| toml::from_str::<toml::Table>(&o).ok())
+        .and_then(|o| o.get("db_path").and_then(|v| v.as_str()).map(str::to_string))
+        .unwrap_or_else(default_db_path)
+}
+
 pub fn from_file(cfg: &str) -> Option<Config> {
     fs::read_to_string(cfg)
         .map(|contents| from_str(contents.as_str()))
@@ -240,7 +1757,122 @@ pub fn from_file(cfg: &str) -> Option<Config> {
 
 pub fn test_from_file(cfg: &str) -> Result<Config> {
     fs::read_to_string(cfg)
-        .map(|contents| toml::from_str::<Config>(&contents))
+        .map(|contents| parse(&contents))
         .unwrap()
-        .map_err(anyhow::Error::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration("2h"), Some(7200));
+        assert_eq!(parse_duration("1d"), Some(86400));
+        assert_eq!(parse_duration("3"), Some(10800));
+        assert_eq!(parse_duration("2w"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("999999999999999d"), None);
+    }
+
+    #[test]
+    fn test_http_addr() {
+        #[derive(Deserialize)]
+        struct T {
+            http_addr: HttpAddr,
+        }
+        let o = toml::from_str::<T>(r#"http_addr = "0.0.0.0:8080""#).unwrap();
+        assert_eq!(o.http_addr, default_http_addr());
+        let o = toml::from_str::<T>(r#"http_addr = ["127.0.0.1:8081", { addr = "[::]:8080", admin = false }]"#).unwrap();
+        assert_eq!(o.http_addr.0.len(), 2);
+        assert!(o.http_addr.0[0].admin && !o.http_addr.0[1].admin);
+        assert_eq!(o.http_addr.0[1].addr, "[::]:8080");
+        assert!(toml::from_str::<T>("http_addr = []").is_err());
+    }
+
+    #[test]
+    fn test_ingest_latest_ts() {
+        let now = 1_000_000;
+        let mut ingest = Ingest::default();
+        assert_eq!(ingest.latest_ts(0, now).unwrap(), now);
+        assert_eq!(ingest.latest_ts(now - 100, now).unwrap(), now - 100);
+
+        ingest.max_future_ts = 60;
+        assert!(ingest.latest_ts(now + 61, now).is_err());
+        assert_eq!(ingest.latest_ts(now + 60, now).unwrap(), now + 60);
+
+        ingest.fix_clock_skew = true;
+        assert_eq!(ingest.latest_ts(now - 100, now).unwrap(), now);
+        assert_eq!(ingest.latest_ts(now - 10, now).unwrap(), now - 10);
+
+        ingest.stamp = Stamp::Never;
+        assert_eq!(ingest.latest_ts(0, now).unwrap(), now);
+        ingest.fix_clock_skew = false;
+        assert_eq!(ingest.latest_ts(0, now).unwrap(), 0);
+
+        ingest.stamp = Stamp::Always;
+        assert_eq!(ingest.latest_ts(now + 3600, now).unwrap(), now);
+    }
+
+    #[test]
+    fn test_secret_files() {
+        let dir = std::env::temp_dir().join(format!("ssr-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("jwt"), "s3cret\n").unwrap();
+        fs::write(dir.join("p1"), "p1").unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        let content = format!(
+            r#"
+            jwt_secret_file = "{0}"
+            hosts = [{{name = "h1", password_file = "{1}"}}]
+            hosts_group = [{{gid = "g1", password = "pp", secrets = {{ n1_file = "{1}" }}}}]
+            [run_as]
+            pid_file = "ssr.pid"
+            [tgbot]
+            enabled = false
+            bot_token_file = "{0}"
+            [webhook]
+            enabled = false
+            receiver = [{{url = "http://x", username = "u", password_file = "{1}", headers = {{ authorization_file = "{0}" }}}}]
+            "#,
+            path("jwt"),
+            path("p1"),
+        );
+        let cfg = parse(&content).unwrap();
+        assert_eq!(cfg.jwt_secret.as_deref(), Some("s3cret"));
+        assert_eq!(cfg.hosts[0].password, "p1");
+        assert_eq!(cfg.tgbot.unwrap()["bot_token"].as_str(), Some("s3cret"));
+        assert_eq!(cfg.hosts_group[0].secrets["n1"], "p1");
+        let webhook = cfg.webhook.unwrap();
+        assert_eq!(webhook["receiver"][0]["password"].as_str(), Some("p1"));
+        assert_eq!(webhook["receiver"][0]["headers"]["authorization"].as_str(), Some("s3cret"));
+        // 不在 SECRET_KEYS 中的 xxx_file 保持原样
+        assert_eq!(cfg.run_as.pid_file, "ssr.pid");
+
+        assert!(parse(&format!(r#"admin_pass_file = "{}""#, path("missing"))).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_member_auth() {
+        let cfg = from_str(
+            r#"
+            hosts = [{name = "h1", password = "p1"}]
+            hosts_group = [{gid = "g1", password = "pp", secrets = { n1 = "s1" }}]
+            "#,
+        )
+        .unwrap();
+        assert!(cfg.auth("h1", "p1", None));
+        assert!(!cfg.auth("h1", "p1", Some("x1")) && cfg.auth("h1", "x1", Some("x1")));
+
+        assert!(cfg.group_auth("g1", "pp") && cfg.group_auth("g1", "s1"));
+        assert!(cfg.member_auth("g1", "n2", "pp", None));
+        // 有独立密码的主机不能再用组密码上报, 独立密码也不能冒充其它主机
+        assert!(!cfg.member_auth("g1", "n1", "pp", None) && cfg.member_auth("g1", "n1", "s1", None));
+        assert!(!cfg.member_auth("g1", "n2", "s1", None));
+        assert!(!cfg.member_auth("g1", "n2", "pp", Some("s2")) && cfg.member_auth("g1", "n2", "s2", Some("s2")));
+        assert!(!cfg.member_auth("g1", "h1", "pp", None));
+    }
 }
@@ -0,0 +1,123 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::payload::HostStat;
+
+// 同一主机名在窗口期内出现不同的机器指纹视为冲突(s)
+const WINDOW: u64 = 60;
+// 超过该时长没有再出现冲突时清除标记(s)
+const HOLD: u64 = 600;
+
+// 同名主机冲突时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    // 不检测
+    Off,
+    // 在 stats.json 中标记 conflict 并通知一次
+    #[default]
+    Mark,
+    // 标记并通知, 同时只接收最先上报的机器的数据
+    Reject,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Verdict {
+    // 是否来自当前的主人
+    pub owner: bool,
+    pub conflict: bool,
+    // 刚进入冲突状态时为涉及的指纹
+    pub detected: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    owner: String,
+    // 指纹 => 最近一次出现的时间
+    seen: HashMap<String, u64>,
+    conflict_ts: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Tracker {
+    hosts: HashMap<String, Entry>,
+}
+
+impl Tracker {
+    pub fn observe(&mut self, name: &str, fingerprint: &str, now: u64) -> Verdict {
+        let entry = self.hosts.entry(name.to_string()).or_default();
+        entry.seen.retain(|_, ts| *ts + WINDOW >= now);
+        // 主人长时间不上报时交给当前的机器
+        if !entry.seen.contains_key(&entry.owner) {
+            entry.owner = fingerprint.to_string();
+        }
+        entry.seen.insert(fingerprint.to_string(), now);
+
+        let mut detected = None;
+        if entry.seen.len() > 1 {
+            if entry.conflict_ts.is_none() {
+                let mut o = entry.seen.keys().cloned().collect::<Vec<_>>();
+                o.sort();
+                detected = Some(o);
+            }
+            entry.conflict_ts = Some(now);
+        } else if entry.conflict_ts.is_some_and(|ts| ts + HOLD < now) {
+            entry.conflict_ts = None;
+        }
+
+        Verdict {
+            owner: entry.owner == fingerprint,
+            conflict: entry.conflict_ts.is_some(),
+            detected,
+        }
+    }
+}
+
+static TRACKER: Lazy<Mutex<Tracker>> = Lazy::new(Default::default);
+
+// 机器指纹, 优先使用系统主机名, 没有时使用公网 ip
+pub fn fingerprint(stat: &HostStat) -> Option<String> {
+    if let Some(o) = stat.sys_info.as_ref().filter(|o| !o.host_name.is_empty()) {
+        return Some(format!("host:{}", o.host_name));
+    }
+    stat.ip_info
+        .as_ref()
+        .filter(|o| !o.query.is_empty())
+        .map(|o| format!("ip:{}", o.query))
+}
+
+pub fn observe(name: &str, fingerprint: &str, now: u64) -> Verdict {
+    TRACKER.lock().unwrap().observe(name, fingerprint, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe() {
+        let mut o = Tracker::default();
+        let v = o.observe("h1", "host:a", 100);
+        assert!(v.owner && !v.conflict && v.detected.is_none());
+        assert!(!o.observe("h1", "host:a", 101).conflict);
+
+        // 窗口期内出现另一台机器
+        let v = o.observe("h1", "host:b", 110);
+        assert!(!v.owner && v.conflict);
+        assert_eq!(v.detected, Some(vec!["host:a".to_string(), "host:b".to_string()]));
+        // 只通知一次
+        let v = o.observe("h1", "host:a", 111);
+        assert!(v.owner && v.conflict && v.detected.is_none());
+
+        // 另一台机器停止上报后, 保持标记直到 HOLD 过后
+        assert!(o.observe("h1", "host:a", 200).conflict);
+        assert!(!o.observe("h1", "host:a", 111 + HOLD + 1).conflict);
+
+        // 主人超过窗口期没有上报, 由新机器接管
+        assert!(o.observe("h1", "host:c", 111 + HOLD + 1 + WINDOW + 1).owner);
+        assert!(o.observe("h2", "host:a", 100).owner);
+    }
+}
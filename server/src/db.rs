@@ -1,41 +1,351 @@
 use anyhow::Result;
 use chrono::{Utc};
-use rusqlite::{params, Connection};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use rusqlite::{params, Connection, OpenFlags, Transaction};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::announce::{Announcement, Severity};
+use crate::integrity;
+use crate::labels::Labels;
+use crate::migrations;
+use crate::payload::{HostStat, ProbeResult};
+use stat_common::server_status::DiskInfo;
+
+// 聚合级别(分钟)
+pub const AGG_INTERVALS: [i64; 4] = [5, 15, 30, 60];
+
+// 历史查询与定时聚合的热点 sql, 依赖 migrations 中的覆盖索引, 改动后需通过 test_query_plans
+const HISTORY_RAW_STATS: &str = "SELECT timestamp, cpu_usage, memory_total, memory_used,
+        network_in, network_out, network_in_speed, network_out_speed, online
+    FROM stats
+    WHERE host_id = ? AND timestamp BETWEEN ? AND ?
+    ORDER BY timestamp ASC
+    LIMIT ?";
+const HISTORY_RAW_DISKS: &str = "SELECT timestamp, mount_point, disk_total, disk_used, inode_total, inode_used
+    FROM disk_stats
+    WHERE host_id = ? AND timestamp BETWEEN ? AND ?
+    ORDER BY timestamp ASC, mount_point ASC";
+const HISTORY_AGG_STATS: &str = "SELECT timestamp, cpu_usage, memory_total, memory_used,
+        network_in, network_out, network_in_speed, network_out_speed, online
+    FROM aggregated_stats
+    WHERE host_id = ? AND interval_minutes = ? AND timestamp BETWEEN ? AND ?
+    ORDER BY timestamp ASC
+    LIMIT ?";
+const HISTORY_AGG_DISKS: &str = "SELECT timestamp, mount_point, disk_total, disk_used, inode_total, inode_used
+    FROM aggregated_disk_stats
+    WHERE host_id = ? AND interval_minutes = ? AND timestamp BETWEEN ? AND ?
+    ORDER BY timestamp ASC, mount_point ASC";
+const HISTORY_RAW_PROBES: &str = "SELECT h.name, p.kind, p.timestamp, CASE WHEN p.ok THEN 100.0 ELSE 0.0 END, p.latency
+    FROM probe_stats p JOIN hosts h ON h.id = p.host_id
+    WHERE p.timestamp BETWEEN ? AND ?
+    ORDER BY p.timestamp ASC";
+const HISTORY_AGG_PROBES: &str = "SELECT h.name, p.kind, p.timestamp, p.success_rate, p.latency
+    FROM aggregated_probe_stats p JOIN hosts h ON h.id = p.host_id
+    WHERE p.interval_minutes = ? AND p.timestamp BETWEEN ? AND ?
+    ORDER BY p.timestamp ASC";
+const AGGREGATE_STATS: &str = "SELECT
+        AVG(cpu_usage) as avg_cpu,
+        AVG(memory_total) as avg_memory_total,
+        AVG(memory_used) as avg_memory_used,
+        MAX(network_in) as max_network_in,
+        MAX(network_out) as max_network_out,
+        AVG(network_in_speed) as avg_in_speed,
+        AVG(network_out_speed) as avg_out_speed,
+        MAX(online) as was_online
+    FROM stats
+    WHERE host_id = ? AND timestamp >= ? AND timestamp < ?";
+const AGGREGATE_DISKS: &str = "SELECT
+        mount_point,
+        AVG(disk_total) as avg_total,
+        AVG(disk_used) as avg_used,
+        AVG(inode_total) as avg_inode_total,
+        AVG(inode_used) as avg_inode_used
+    FROM disk_stats
+    WHERE host_id = ? AND timestamp >= ? AND timestamp < ?
+    GROUP BY mount_point";
+// 按天汇总, 从 ?3 所在的那天开始重新计算
+const DAILY_ROLLUP_STATS: &str = "INSERT INTO daily_stats (
+        host_id, day, samples, cpu_min, cpu_avg, cpu_max, memory_total, memory_min, memory_avg, memory_max,
+        network_in_min, network_in_avg, network_in_max, network_out_min, network_out_avg, network_out_max, online_ratio
+    )
+    SELECT host_id, timestamp / 86400 * 86400 AS d, COUNT(*),
+        MIN(cpu_usage), AVG(cpu_usage), MAX(cpu_usage), AVG(memory_total),
+        MIN(memory_used), AVG(memory_used), MAX(memory_used),
+        MIN(network_in_speed), AVG(network_in_speed), MAX(network_in_speed),
+        MIN(network_out_speed), AVG(network_out_speed), MAX(network_out_speed),
+        AVG(online)
+    FROM aggregated_stats
+    WHERE host_id = ?1 AND interval_minutes = ?2 AND timestamp >= ?3 / 86400 * 86400
+    GROUP BY d";
+const DAILY_ROLLUP_DISKS: &str = "INSERT INTO daily_disk_stats (
+        host_id, day, mount_point, disk_total, used_min, used_avg, used_max
+    )
+    SELECT host_id, timestamp / 86400 * 86400 AS d, mount_point,
+        AVG(disk_total), MIN(disk_used), AVG(disk_used), MAX(disk_used)
+    FROM aggregated_disk_stats
+    WHERE host_id = ?1 AND interval_minutes = ?2 AND timestamp >= ?3 / 86400 * 86400
+    GROUP BY d, mount_point";
+// 按 host_id 关联主机的表
+const HOST_TABLES: [&str; 10] = [
+    "stats",
+    "disk_stats",
+    "probe_stats",
+    "aggregated_stats",
+    "aggregated_disk_stats",
+    "aggregated_probe_stats",
+    "daily_stats",
+    "daily_disk_stats",
+    "last_network",
+    "disk_devices",
+];
+const LATEST_STATS: &str = "SELECT timestamp, cpu_usage, memory_total, memory_used, network_in, network_out
+    FROM stats
+    WHERE host_id = ?
+    ORDER BY timestamp DESC
+    LIMIT 1";
+const LATEST_DISKS: &str = "SELECT mount_point, disk_total, disk_used, inode_total, inode_used
+    FROM disk_stats
+    WHERE host_id = ? AND timestamp = ?
+    ORDER BY mount_point ASC";
+
+// (host_id, timestamp, interval_minutes, cpu, memory_total, memory_used, network_in, network_out, in_speed, out_speed, online)
+type AggregatedRow = (i64, i64, i64, f64, f64, f64, i64, i64, f64, f64, bool);
+// (host_id, timestamp, interval_minutes, mount_point, disk_total, disk_used, inode_total, inode_used)
+type AggregatedDiskRow = (i64, i64, i64, String, f64, f64, f64, f64);
+
+// 修正历史数据时的取值范围, key 为列名
+#[derive(Debug, Default, Deserialize)]
+pub struct Clamp {
+    #[serde(default)]
+    pub min: HashMap<String, f64>,
+    #[serde(default)]
+    pub max: HashMap<String, f64>,
+}
+
+// 主机的数据精度策略, 对应配置中的 [resolution.<name>]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Resolution {
+    // 原始数据保留天数
+    #[serde(default = "default_raw_retention_days")]
+    pub raw_retention_days: i64,
+    // 聚合级别(分钟)
+    #[serde(default = "default_intervals")]
+    pub intervals: Vec<i64>,
+    // 历史查询每台主机最多返回的点数, 0 表示按时间范围使用固定的聚合级别
+    #[serde(default)]
+    pub history_points: i64,
+}
+
+fn default_raw_retention_days() -> i64 {
+    1
+}
+fn default_intervals() -> Vec<i64> {
+    AGG_INTERVALS.to_vec()
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self {
+            raw_retention_days: default_raw_retention_days(),
+            intervals: default_intervals(),
+            history_points: 0,
+        }
+    }
+}
+
+impl Resolution {
+    // 历史查询使用的聚合级别, 0 表示原始数据
+    pub fn pick_interval(&self, time_range: i64) -> i64 {
+        let desired = history_interval(time_range);
+        let last = match self.intervals.last() {
+            Some(&o) if desired > 0 => o,
+            _ => return 0,
+        };
+        if self.history_points > 0 {
+            return self
+                .intervals
+                .iter()
+                .copied()
+                .find(|&i| time_range / (i * 60) <= self.history_points)
+                .unwrap_or(last);
+        }
+        // 没有对应级别时使用更粗的一级
+        self.intervals.iter().copied().find(|&i| i >= desired).unwrap_or(last)
+    }
+}
+
+// 默认的只读连接数
+pub const DEFAULT_READERS: usize = 4;
+// 每个连接缓存的预编译语句数
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+// SQLite 调优参数预设
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Default,
+    // 小内存 VPS, 尽量少占内存
+    Small,
+    // 内存充足的机器, 主机数多时减少读盘和 checkpoint 次数
+    Large,
+}
+
+// PRAGMA cache_size / mmap_size / busy_timeout / wal_autocheckpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pragmas {
+    // 正数为页数, 负数为 KiB
+    pub cache_size: i64,
+    // 字节, 0 关闭 mmap
+    pub mmap_size: i64,
+    // ms
+    pub busy_timeout: u64,
+    // WAL 达到多少页时自动 checkpoint, 0 关闭
+    pub wal_autocheckpoint: u32,
+}
+
+impl Pragmas {
+    pub fn profile(profile: Profile) -> Self {
+        match profile {
+            Profile::Default => Self {
+                cache_size: 1000,
+                mmap_size: 30000000000,
+                busy_timeout: 5000,
+                wal_autocheckpoint: 1000,
+            },
+            Profile::Small => Self {
+                cache_size: -4096,
+                mmap_size: 0,
+                busy_timeout: 5000,
+                wal_autocheckpoint: 500,
+            },
+            Profile::Large => Self {
+                cache_size: -262144,
+                mmap_size: 30000000000,
+                busy_timeout: 10000,
+                wal_autocheckpoint: 10000,
+            },
+        }
+    }
 
-use crate::payload::HostStat;
+    // 只读连接不需要 wal_autocheckpoint
+    fn apply(&self, conn: &Connection, writer: bool) -> Result<()> {
+        conn.execute_batch(&format!(
+            "PRAGMA cache_size = {}; PRAGMA temp_store = MEMORY; PRAGMA mmap_size = {};",
+            self.cache_size, self.mmap_size
+        ))?;
+        if writer {
+            conn.execute_batch(&format!("PRAGMA wal_autocheckpoint = {};", self.wal_autocheckpoint))?;
+        }
+        conn.busy_timeout(Duration::from_millis(self.busy_timeout))?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(())
+    }
+}
+
+impl Default for Pragmas {
+    fn default() -> Self {
+        Self::profile(Profile::Default)
+    }
+}
+
+// WAL 模式下的只读连接池, 历史查询等只读操作不与入库写入争用同一个连接
+struct Readers {
+    idle: Mutex<Vec<Connection>>,
+    available: Condvar,
+}
+
+// 只读连接, 没有只读连接池时使用写连接
+enum ReadConn<'a> {
+    Writer(MutexGuard<'a, Connection>),
+    Reader(&'a Readers, Option<Connection>),
+}
+
+impl Deref for ReadConn<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            ReadConn::Writer(conn) => conn,
+            ReadConn::Reader(_, conn) => conn.as_ref().unwrap(),
+        }
+    }
+}
+
+impl Drop for ReadConn<'_> {
+    fn drop(&mut self) {
+        if let ReadConn::Reader(readers, conn) = self {
+            readers.idle.lock().unwrap().extend(conn.take());
+            readers.available.notify_one();
+        }
+    }
+}
 
 pub struct Database {
     conn: Arc<Mutex<Connection>>,
+    readers: Option<Arc<Readers>>,
 }
 
 impl Database {
     pub fn new(db_path: &str) -> Result<Self> {
-        let path = Path::new(db_path);
-        let need_init = !path.exists();
+        Self::open(db_path, DEFAULT_READERS, Pragmas::default())
+    }
 
-        let conn = Connection::open(db_path)?;
+    // 一个写连接 + readers 个只读连接, 内存数据库或 readers 为 0 时只使用写连接
+    pub fn open(db_path: &str, readers: usize, pragmas: Pragmas) -> Result<Self> {
+        let mut conn = Connection::open(db_path)?;
 
         // 开启 WAL 模式和其他性能优化
         conn.execute_batch("
             PRAGMA journal_mode = WAL;
             PRAGMA synchronous = NORMAL;
-            PRAGMA cache_size = 1000;
-            PRAGMA temp_store = MEMORY;
-            PRAGMA mmap_size = 30000000000;
         ")?;
-
-        if need_init {
-            Self::init_db(&conn)?;
-        }
+        pragmas.apply(&conn, true)?;
+
+        // 按版本执行表结构变更
+        migrations::run(&mut conn)?;
+
+        let readers = match readers > 0 && !db_path.is_empty() && db_path != ":memory:" {
+            true => {
+                let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+                let idle = (0..readers)
+                    .map(|_| {
+                        let o = Connection::open_with_flags(db_path, flags)?;
+                        pragmas.apply(&o, false)?;
+                        Ok(o)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Some(Arc::new(Readers {
+                    idle: Mutex::new(idle),
+                    available: Condvar::new(),
+                }))
+            }
+            false => None,
+        };
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
+            readers,
         })
     }
 
+    // 取一个空闲的只读连接, 全部在使用时等待
+    fn reader(&self) -> ReadConn<'_> {
+        let Some(readers) = self.readers.as_deref() else {
+            return ReadConn::Writer(self.conn.lock().unwrap());
+        };
+        let mut idle = readers.idle.lock().unwrap();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return ReadConn::Reader(readers, Some(conn));
+            }
+            idle = readers.available.wait(idle).unwrap();
+        }
+    }
+
     // 在 Database 结构体的实现中添加以下方法
 
     // 更新主机的last_network数据
@@ -43,12 +353,12 @@ impl Database {
         let conn = self.conn.lock().unwrap();
 
         // 首先获取主机ID
-        let mut stmt = conn.prepare("SELECT id FROM hosts WHERE name = ?")?;
+        let mut stmt = conn.prepare_cached("SELECT id FROM hosts WHERE name = ?")?;
         let host_id: Option<i64> = stmt.query_row(params![host_name], |row| row.get(0)).ok();
 
         if let Some(id) = host_id {
             // 检查是否已有last_network记录
-            let mut check_stmt = conn.prepare("SELECT COUNT(*) FROM last_network WHERE host_id = ?")?;
+            let mut check_stmt = conn.prepare_cached("SELECT COUNT(*) FROM last_network WHERE host_id = ?")?;
             let count: i64 = check_stmt.query_row(params![id], |row| row.get(0))?;
 
             if count > 0 {
@@ -73,10 +383,10 @@ impl Database {
 
     // 获取所有主机的last_network数据
     pub fn get_last_network_data(&self) -> Result<Vec<(String, u64, u64)>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
         let mut result = Vec::new();
 
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT h.name, ln.network_in, ln.network_out
              FROM last_network ln
              JOIN hosts h ON ln.host_id = h.id"
@@ -97,138 +407,6 @@ impl Database {
         Ok(result)
     }
 
-    // 在init_db方法中添加last_network表的创建
-    fn init_db(conn: &Connection) -> Result<()> {
-        // 主机表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS hosts (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                alias TEXT,
-                UNIQUE(name)
-            )",
-            [],
-        )?;
-
-        // 简化的统计数据表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS stats (
-                id INTEGER PRIMARY KEY,
-                host_id INTEGER NOT NULL,
-                timestamp INTEGER NOT NULL,
-                cpu_usage REAL,
-                memory_total INTEGER,
-                memory_used INTEGER,
-                network_in INTEGER,
-                network_out INTEGER,
-                network_in_speed INTEGER,
-                network_out_speed INTEGER,
-                online BOOLEAN,
-                FOREIGN KEY (host_id) REFERENCES hosts(id)
-            )",
-            [],
-        )?;
-
-        // 磁盘数据表 - 每个磁盘单独记录
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS disk_stats (
-                id INTEGER PRIMARY KEY,
-                host_id INTEGER NOT NULL,
-                timestamp INTEGER NOT NULL,
-                mount_point TEXT NOT NULL,
-                disk_total INTEGER,
-                disk_used INTEGER,
-                FOREIGN KEY (host_id) REFERENCES hosts(id)
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS aggregated_stats (
-                id INTEGER PRIMARY KEY,
-                host_id INTEGER NOT NULL,
-                timestamp INTEGER NOT NULL,
-                interval_minutes INTEGER NOT NULL,
-                cpu_usage REAL,
-                memory_total INTEGER,
-                memory_used INTEGER,
-                network_in INTEGER,
-                network_out INTEGER,
-                network_in_speed INTEGER,
-                network_out_speed INTEGER,
-                online BOOLEAN,
-                FOREIGN KEY (host_id) REFERENCES hosts(id),
-                UNIQUE(host_id, timestamp, interval_minutes)
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS aggregated_disk_stats (
-                id INTEGER PRIMARY KEY,
-                host_id INTEGER NOT NULL,
-                timestamp INTEGER NOT NULL,
-                interval_minutes INTEGER NOT NULL,
-                mount_point TEXT NOT NULL,
-                disk_total INTEGER,
-                disk_used INTEGER,
-                FOREIGN KEY (host_id) REFERENCES hosts(id),
-                UNIQUE(host_id, timestamp, interval_minutes, mount_point)
-            )",
-            [],
-        )?;
-
-        // ... existing code ...
-
-        // 为聚合表添加索引
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_agg_stats_host_time ON aggregated_stats(host_id, timestamp, interval_minutes)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_agg_disk_stats_host_time ON aggregated_disk_stats(host_id, timestamp, interval_minutes)",
-            [],
-        )?;
-
-        // 优化索引 - 添加更多索引以加速查询
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_stats_host_time ON stats(host_id, timestamp)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_disk_stats_host_time ON disk_stats(host_id, timestamp)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_stats_timestamp ON stats(timestamp)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_disk_stats_timestamp ON disk_stats(timestamp)",
-            [],
-        )?;
-
-        // 添加last_network表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS last_network (
-                id INTEGER PRIMARY KEY,
-                host_id INTEGER NOT NULL,
-                network_in INTEGER NOT NULL,
-                network_out INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL,
-                FOREIGN KEY (host_id) REFERENCES hosts(id),
-                UNIQUE(host_id)
-            )",
-            [],
-        )?;
-
-        Ok(())
-    }
-
     // 在 save_stat 方法中
     // 修复 save_stat 方法中的事务处理
     pub fn save_stat(&self, stat: &HostStat) -> Result<()> {
@@ -262,19 +440,34 @@ impl Database {
 
         // 保存每个磁盘的数据 - 使用预处理语句
         if !stat.disks.is_empty() {
-            let mut disk_stmt = tx.prepare(
+            let mut disk_stmt = tx.prepare_cached(
                 "INSERT INTO disk_stats (
-                    host_id, timestamp, mount_point, disk_total, disk_used
-                ) VALUES (?, ?, ?, ?, ?)"
+                    host_id, timestamp, mount_point, disk_total, disk_used, inode_total, inode_used
+                ) VALUES (?, ?, ?, ?, ?, ?, ?)"
+            )?;
+            // 有设备标识的磁盘按设备记录, 挂载点变化后历史数据保持连续
+            let mut device_stmt = tx.prepare_cached(
+                "INSERT INTO disk_devices (host_id, device_id, series, mount_point, last_seen) VALUES (?1, ?2, ?3, ?3, ?4)
+                 ON CONFLICT(host_id, device_id) DO UPDATE SET mount_point = excluded.mount_point, last_seen = excluded.last_seen
+                 RETURNING series",
             )?;
 
             for disk in &stat.disks {
+                let series = match disk.device_id.is_empty() {
+                    true => disk.mount_point.to_string(),
+                    false => device_stmt.query_row(
+                        params![host_id, disk.device_id, disk.mount_point, stat.latest_ts],
+                        |row| row.get::<_, String>(0),
+                    )?,
+                };
                 disk_stmt.execute(params![
                     host_id,
                     stat.latest_ts,
-                    disk.mount_point,
+                    series,
                     disk.total,
-                    disk.used
+                    disk.used,
+                    disk.inode_total,
+                    disk.inode_used
                 ])?;
             }
         }
@@ -286,58 +479,149 @@ impl Database {
     }
 
     fn ensure_host_exists(&self, conn: &Connection, stat: &HostStat) -> Result<i64> {
-        let mut stmt = conn.prepare("SELECT id FROM hosts WHERE name = ?")?;
+        let mut stmt = conn.prepare_cached("SELECT id FROM hosts WHERE name = ?")?;
         let host_id: Option<i64> = stmt.query_row(params![stat.name], |row| row.get(0)).ok();
 
+        let labels = serde_json::to_string(&stat.labels)?;
         if let Some(id) = host_id {
-            // 更新别名
+            // 更新别名及标签
             if !stat.alias.is_empty() {
                 conn.execute(
-                    "UPDATE hosts SET alias = ? WHERE id = ?",
-                    params![stat.alias, id],
+                    "UPDATE hosts SET alias = ?, labels = ? WHERE id = ?",
+                    params![stat.alias, labels, id],
                 )?;
+            } else {
+                conn.execute("UPDATE hosts SET labels = ? WHERE id = ?", params![labels, id])?;
             }
             Ok(id)
         } else {
             conn.execute(
-                "INSERT INTO hosts (name, alias) VALUES (?, ?)",
-                params![stat.name, stat.alias],
+                "INSERT INTO hosts (name, alias, labels) VALUES (?, ?, ?)",
+                params![stat.name, stat.alias, labels],
             )?;
             Ok(conn.last_insert_rowid())
         }
     }
 
-    // 在 Database 实现中添加
-    pub fn get_stats_by_timerange(&self, start_time: i64, end_time: i64) -> Result<HashMap<String, Vec<HostStatRecord>>> {
+    // 保存一轮探测结果, (主机名, kind, 结果)
+    pub fn save_probe_results(&self, results: &[(String, &str, ProbeResult)]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut host_stmt = tx.prepare_cached("SELECT id FROM hosts WHERE name = ?")?;
+            let mut insert_stmt = tx.prepare_cached(
+                "INSERT INTO probe_stats (host_id, timestamp, kind, ok, latency) VALUES (?, ?, ?, ?, ?)"
+            )?;
+            for (name, kind, r) in results {
+                // 主机首次上报后才会写入 hosts 表
+                let host_id: Option<i64> = host_stmt.query_row(params![name], |row| row.get(0)).ok();
+                if let Some(host_id) = host_id {
+                    insert_stmt.execute(params![host_id, r.ts, kind, r.ok, r.latency])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // 探测结果按时间段聚合: 成功率(%) 与成功时的平均延迟(ms)
+    pub fn aggregate_probe_data(&self, interval_minutes: i64) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let mut result = HashMap::new();
+        let interval_seconds = interval_minutes * 60;
 
-        // 计算时间范围的长度（秒）
-        let time_range = end_time - start_time;
+        // 最后一个时间段可能不完整, 从它开始重新聚合
+        let start_time: i64 = conn
+            .query_row(
+                "SELECT MAX(timestamp) FROM aggregated_probe_stats WHERE interval_minutes = ?",
+                params![interval_minutes],
+                |row| row.get::<_, Option<i64>>(0),
+            )?
+            .unwrap_or(0);
+        let end_time = (Utc::now().timestamp() / interval_seconds) * interval_seconds;
+        if start_time >= end_time {
+            return Ok(());
+        }
+
+        Self::aggregate_probe_range(&conn, None, interval_minutes, start_time, end_time)?;
+        Ok(())
+    }
+
+    // host_id 为空时聚合所有主机
+    fn aggregate_probe_range(
+        conn: &Connection,
+        host_id: Option<i64>,
+        interval_minutes: i64,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<usize> {
+        Ok(conn.execute(
+            "INSERT OR REPLACE INTO aggregated_probe_stats (
+                host_id, timestamp, interval_minutes, kind, success_rate, latency
+            )
+            SELECT host_id, (timestamp / ?1) * ?1, ?2, kind,
+                   AVG(ok) * 100.0, AVG(CASE WHEN ok THEN latency END)
+            FROM probe_stats
+            WHERE timestamp >= ?3 AND timestamp < ?4 AND (?5 IS NULL OR host_id = ?5)
+            GROUP BY host_id, timestamp / ?1, kind",
+            params![interval_minutes * 60, interval_minutes, start_time, end_time, host_id],
+        )?)
+    }
 
-        // 根据时间范围选择合适的聚合级别
-        // 超过3天使用1小时聚合，超过1天使用30分钟聚合，超过12小时使用15分钟聚合，超过6小时使用5分钟聚合
-        let interval_minutes = if time_range > 3 * 24 * 3600 {
-            60 // 1小时
-        } else if time_range >= 24 * 3600 {
-            30 // 30分钟
-        } else if time_range >= 12 * 3600 {
-            15 // 15分钟
-        } else if time_range >= 1 * 3600 {
-            5  // 5分钟
+    // 主机名 => kind => 探测记录, 聚合级别与 get_stats_by_timerange 一致
+    pub fn get_probe_by_timerange(&self, start_time: i64, end_time: i64) -> Result<HashMap<String, HashMap<String, Vec<ProbeRecord>>>> {
+        let conn = self.reader();
+        let interval_minutes = history_interval(end_time - start_time);
+
+        let map_row = |row: &rusqlite::Row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                ProbeRecord {
+                    timestamp: row.get(2)?,
+                    success_rate: row.get::<_, Option<f64>>(3)?.unwrap_or(0.0),
+                    latency: row.get::<_, Option<f64>>(4)?.unwrap_or(0.0),
+                },
+            ))
+        };
+
+        let rows = if interval_minutes > 0 {
+            let mut stmt = conn.prepare_cached(HISTORY_AGG_PROBES)?;
+            let rows = stmt.query_map(params![interval_minutes, start_time, end_time], map_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
         } else {
-            0  // 使用原始数据
+            let mut stmt = conn.prepare_cached(HISTORY_RAW_PROBES)?;
+            let rows = stmt.query_map(params![start_time, end_time], map_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
         };
 
-        // 获取时间范围内的所有主机
-        let mut hosts_stmt = conn.prepare(
-            "SELECT DISTINCT h.id, h.name, h.alias
-             FROM hosts h
-             JOIN stats s ON h.id = s.host_id
-             WHERE s.timestamp BETWEEN ? AND ?"
-        )?;
+        let mut result: HashMap<String, HashMap<String, Vec<ProbeRecord>>> = HashMap::new();
+        for (name, kind, record) in rows {
+            result.entry(name).or_default().entry(kind).or_default().push(record);
+        }
+        Ok(result)
+    }
+
+    // 在 Database 实现中添加
+    // 按主机名顺序从 from(含) 开始查询, 超过 deadline 时返回已查询的主机及下一页的起始主机名
+    pub fn get_stats_by_timerange(
+        &self,
+        start_time: i64,
+        end_time: i64,
+        policy: impl Fn(&str) -> Resolution,
+        from: Option<&str>,
+        deadline: Option<Instant>,
+    ) -> Result<(HistoryRecords, Option<String>)> {
+        let conn = self.reader();
+        let mut result = HashMap::new();
+        let mut cursor = None;
+
+        // 计算时间范围的长度（秒）
+        let time_range = end_time - start_time;
+
+        // 获取所有主机, 原始数据过期后时间范围内可能只有聚合数据, 没有数据的主机结果为空
+        let mut hosts_stmt = conn.prepare_cached("SELECT id, name, alias FROM hosts WHERE name >= ?1 ORDER BY name")?;
 
-        let hosts = hosts_stmt.query_map(params![start_time, end_time], |row| {
+        let hosts = hosts_stmt.query_map([from.unwrap_or_default()], |row| {
             Ok((
                 row.get::<_, i64>(0)?,
                 row.get::<_, String>(1)?,
@@ -349,99 +633,77 @@ impl Database {
         // 最大数据点数量，默认600
     let max_points = 600;
 
-    for host_result in hosts {
+    for (i, host_result) in hosts.enumerate() {
         let (host_id, host_name, host_alias) = host_result?;
+        // 至少查询一台主机, 保证分页可以推进
+        if i > 0 && deadline.is_some_and(|o| Instant::now() >= o) {
+            cursor = Some(host_name);
+            break;
+        }
+
+        // 根据时间范围和主机的精度策略选择合适的聚合级别
+        let interval_minutes = policy(&host_name).pick_interval(time_range);
 
         // 如果使用聚合数据且聚合级别大于0
         if interval_minutes > 0 {
-            // 检查聚合表中是否有足够的数据
-            let mut count_agg_stmt = conn.prepare(
-                "SELECT COUNT(*) FROM aggregated_stats
-                 WHERE host_id = ? AND timestamp BETWEEN ? AND ? AND interval_minutes = ?"
-            )?;
+            // 使用聚合表查询, 没有数据时不返回该主机
+            let mut stats_stmt = conn.prepare_cached(HISTORY_AGG_STATS)?;
 
-            let agg_count: i64 = count_agg_stmt.query_row(
-                params![host_id, start_time, end_time, interval_minutes],
-                |row| row.get(0)
+            let stats = stats_stmt.query_map(
+                params![host_id, interval_minutes, start_time, end_time, max_points],
+                |row| {
+                    Ok(HostStatRecord {
+                        timestamp: row.get(0)?,
+                        cpu: row.get(1)?,
+                        memory_total: row.get::<_, f64>(2)? as i64,
+                        memory_used: row.get::<_, f64>(3)? as i64,
+                        network_in: row.get::<_, f64>(4)? as i64,
+                        network_out: row.get::<_, f64>(5)? as i64,
+                        network_in_speed: row.get::<_, f64>(6)? as i64,
+                        network_out_speed: row.get::<_, f64>(7)? as i64,
+                        online: row.get(8)?,
+                        alias: host_alias.clone(),
+                        disks: Vec::new(),
+                    })
+                }
             )?;
 
-            // 如果聚合表中有足够的数据
-            if agg_count > 0 {
-                // 使用聚合表查询
-                let mut stats_stmt = conn.prepare(
-                    "SELECT timestamp, cpu_usage, memory_total, memory_used,
-                            network_in, network_out, network_in_speed, network_out_speed, online
-                     FROM aggregated_stats
-                     WHERE host_id = ? AND timestamp BETWEEN ? AND ? AND interval_minutes = ?
-                     ORDER BY timestamp ASC
-                     LIMIT ?"
-                )?;
+            let mut host_stats = Vec::new();
+            for stat_result in stats {
+                host_stats.push(stat_result?);
+            }
 
-                let stats = stats_stmt.query_map(
-                    params![host_id, start_time, end_time, interval_minutes, max_points],
+            if let Some(last) = host_stats.last().map(|o| o.timestamp) {
+                // 一次取出返回的时间段内的聚合磁盘数据, 再按时间戳分配
+                let mut disks_stmt = conn.prepare_cached(HISTORY_AGG_DISKS)?;
+                let disks = disks_stmt.query_map(
+                    params![host_id, interval_minutes, start_time, last],
                     |row| {
-                        Ok(HostStatRecord {
+                        Ok(DiskRecord {
                             timestamp: row.get(0)?,
-                            cpu: row.get(1)?,
-                            memory_total: row.get::<_, f64>(2)? as i64,
-                            memory_used: row.get::<_, f64>(3)? as i64,
-                            network_in: row.get::<_, f64>(4)? as i64,
-                            network_out: row.get::<_, f64>(5)? as i64,
-                            network_in_speed: row.get::<_, f64>(6)? as i64,
-                            network_out_speed: row.get::<_, f64>(7)? as i64,
-                            online: row.get(8)?,
-                            alias: host_alias.clone(),
-                            disks: Vec::new(),
+                            mount_point: row.get(1)?,
+                            total: row.get::<_, f64>(2)? as i64,
+                            used: row.get::<_, f64>(3)? as i64,
+                            inode_total: row.get::<_, f64>(4)? as i64,
+                            inode_used: row.get::<_, f64>(5)? as i64,
                         })
                     }
                 )?;
 
-                let mut host_stats = Vec::new();
-                for stat_result in stats {
-                    host_stats.push(stat_result?);
+                let mut disk_map: HashMap<i64, Vec<DiskRecord>> = HashMap::new();
+                for disk_result in disks {
+                    let disk = disk_result?;
+                    disk_map.entry(disk.timestamp).or_default().push(disk);
                 }
-
-                // 如果有统计数据，获取聚合的磁盘数据
-                if !host_stats.is_empty() {
-                    for stat in &mut host_stats {
-                        // 从聚合磁盘表中获取对应时间戳的磁盘数据
-                        let mut disks_stmt = conn.prepare(
-                            "SELECT timestamp, mount_point, disk_total, disk_used
-                             FROM aggregated_disk_stats
-                             WHERE host_id = ? AND timestamp = ? AND interval_minutes = ?"
-                        )?;
-
-                        let disks = disks_stmt.query_map(
-                            params![host_id, stat.timestamp, interval_minutes],
-                            |row| {
-                                Ok(DiskRecord {
-                                    timestamp: row.get(0)?,
-                                    mount_point: row.get(1)?,
-                                    total: row.get::<_, f64>(2)? as i64,
-                                    used: row.get::<_, f64>(3)? as i64,
-                                })
-                            }
-                        )?;
-
-                        for disk_result in disks {
-                            stat.disks.push(disk_result?);
-                        }
-                    }
+                for stat in &mut host_stats {
+                    stat.disks = disk_map.remove(&stat.timestamp).unwrap_or_default();
                 }
 
                 result.insert(host_name, host_stats);
-                continue; // 已经处理完这个主机，继续下一个
             }
         }else{
                 // 使用原始查询 - 添加LIMIT以防止返回过多数据
-                let mut stats_stmt = conn.prepare(
-                    "SELECT timestamp, cpu_usage, memory_total, memory_used,
-                            network_in, network_out, network_in_speed, network_out_speed, online
-                     FROM stats
-                     WHERE host_id = ? AND timestamp BETWEEN ? AND ?
-                     ORDER BY timestamp ASC
-                     LIMIT ?"
-                )?;
+                let mut stats_stmt = conn.prepare_cached(HISTORY_RAW_STATS)?;
 
                 let stats = stats_stmt.query_map(params![host_id, start_time, end_time, max_points], |row| {
                     Ok(HostStatRecord {
@@ -465,38 +727,29 @@ impl Database {
                 }
 
                 // 如果有统计数据，获取磁盘数据
-                if !host_stats.is_empty() {
-                    // 获取该主机在时间范围内的所有磁盘数据
-                    let mut disks_stmt = conn.prepare(
-                        "SELECT timestamp, mount_point, disk_total, disk_used
-                         FROM disk_stats
-                         WHERE host_id = ? AND timestamp BETWEEN ? AND ?
-                         ORDER BY timestamp ASC, mount_point ASC"
-                    )?;
+                if let Some(last) = host_stats.last().map(|o| o.timestamp) {
+                    // 获取该主机在返回的时间段内的所有磁盘数据
+                    let mut disks_stmt = conn.prepare_cached(HISTORY_RAW_DISKS)?;
 
-                    let disks = disks_stmt.query_map(params![host_id, start_time, end_time], |row| {
+                    let disks = disks_stmt.query_map(params![host_id, start_time, last], |row| {
                         Ok(DiskRecord {
                             timestamp: row.get(0)?,
                             mount_point: row.get(1)?,
                             total: row.get(2)?,
                             used: row.get(3)?,
+                            inode_total: row.get(4)?,
+                            inode_used: row.get(5)?,
                         })
                     })?;
 
-                    // 将磁盘数据添加到对应的统计记录中
-                    let mut disk_records = Vec::new();
+                    // 按时间戳将磁盘数据分配到对应的统计记录
+                    let mut disk_map: HashMap<i64, Vec<DiskRecord>> = HashMap::new();
                     for disk_result in disks {
-                        disk_records.push(disk_result?);
+                        let disk = disk_result?;
+                        disk_map.entry(disk.timestamp).or_default().push(disk);
                     }
-
-                    // 按时间戳将磁盘数据分配到对应的统计记录
                     for stat in &mut host_stats {
-                        let stat_disks: Vec<_> = disk_records.iter()
-                            .filter(|disk| disk.timestamp == stat.timestamp)
-                            .cloned()
-                            .collect();
-
-                        stat.disks = stat_disks;
+                        stat.disks = disk_map.remove(&stat.timestamp).unwrap_or_default();
                     }
                 }
 
@@ -505,26 +758,35 @@ impl Database {
         }
     }
 
-        Ok(result)
+        Ok((result, cursor))
     }
 
-    pub fn aggregate_data(&self, interval_minutes: i64) -> Result<()> {
+    // 聚合使用该级别的主机
+    pub fn aggregate_data(&self, interval_minutes: i64, hosts: &[i64]) -> Result<()> {
+        if hosts.is_empty() {
+            return Ok(());
+        }
         let mut conn = self.conn.lock().unwrap();
+        let host_ids = hosts.iter().map(|o| o.to_string()).collect::<Vec<_>>().join(",");
 
         // 获取最新的聚合时间戳 - 使用conn查询
         let last_agg_time: Option<i64> = {
-            let mut stmt = conn.prepare(
-                "SELECT MAX(timestamp) FROM aggregated_stats WHERE interval_minutes = ?"
-            )?;
-            stmt.query_row(params![interval_minutes], |row| row.get(0)).ok()
+            let mut stmt = conn.prepare(&format!(
+                "SELECT MAX(timestamp) FROM aggregated_stats WHERE interval_minutes = ? AND host_id IN ({host_ids})"
+            ))?;
+            stmt.query_row(params![interval_minutes], |row| row.get(0)).ok().flatten()
         };
 
         // 如果没有聚合记录，从最早的数据开始 - 使用conn查询
         let start_time = if let Some(time) = last_agg_time {
             time
         } else {
-            let mut stmt = conn.prepare("SELECT min(timestamp) FROM stats limit 1")?;
-            stmt.query_row([], |row| row.get::<_, i64>(0)).unwrap_or(0)
+            let mut stmt = conn.prepare(&format!("SELECT min(timestamp) FROM stats WHERE host_id IN ({host_ids})"))?;
+            match stmt.query_row([], |row| row.get::<_, Option<i64>>(0))? {
+                Some(time) => time,
+                // 还没有原始数据, 不能从 0 开始逐段扫描
+                None => return Ok(()),
+            }
         };
 
         // 计算当前时间对齐到interval_minutes的时间点
@@ -537,44 +799,37 @@ impl Database {
             return Ok(());
         }
 
-        // 获取所有主机 - 使用conn查询
-        let hosts: Vec<(i64, String)> = {
-            let mut hosts_stmt = conn.prepare("SELECT id, name FROM hosts")?;
-            let hosts_iter = hosts_stmt.query_map([], |row| {
-                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
-            })?;
+        // 第一阶段：收集所有需要聚合的数据
+        let (aggregated_data, aggregated_disk_data) =
+            Self::collect_aggregates(&conn, hosts, interval_minutes, start_time, end_time)?;
 
-            let mut result = Vec::new();
-            for host_result in hosts_iter {
-                result.push(host_result?);
-            }
-            result
-        };
+        // 第二阶段：开始事务并写入所有聚合数据
+        let tx = conn.transaction()?;
+        Self::write_aggregates(&tx, aggregated_data, aggregated_disk_data)?;
+        tx.commit()?;
+        Ok(())
+    }
 
-        // 第一阶段：收集所有需要聚合的数据
+    // 按 interval_minutes 分段聚合 [start_time, end_time) 内的原始数据, 没有数据的时间段不产生记录
+    fn collect_aggregates(
+        conn: &Connection,
+        hosts: &[i64],
+        interval_minutes: i64,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<(Vec<AggregatedRow>, Vec<AggregatedDiskRow>)> {
+        let interval_seconds = interval_minutes * 60;
         let mut aggregated_data = Vec::new();
         let mut aggregated_disk_data = Vec::new();
 
-        for (host_id, _host_name) in hosts {
+        for &host_id in hosts {
             let mut current_time = start_time;
             while current_time < end_time {
                 let period_end = current_time + interval_seconds;
 
                 // 聚合主机统计数据 - 使用conn查询
                 let row_opt = {
-                    let mut agg_stmt = conn.prepare(
-                        "SELECT
-                            AVG(cpu_usage) as avg_cpu,
-                            AVG(memory_total) as avg_memory_total,
-                            AVG(memory_used) as avg_memory_used,
-                            MAX(network_in) as max_network_in,
-                            MAX(network_out) as max_network_out,
-                            AVG(network_in_speed) as avg_in_speed,
-                            AVG(network_out_speed) as avg_out_speed,
-                            MAX(online) as was_online
-                         FROM stats
-                         WHERE host_id = ? AND timestamp >= ? AND timestamp < ?"
-                    )?;
+                    let mut agg_stmt = conn.prepare_cached(AGGREGATE_STATS)?;
 
                     agg_stmt.query_row(params![host_id, current_time, period_end], |row| {
                         Ok((
@@ -608,26 +863,20 @@ impl Database {
                     }
 
                     // 聚合磁盘数据 - 使用conn查询
-                    let mut disk_stmt = conn.prepare(
-                        "SELECT
-                            mount_point,
-                            AVG(disk_total) as avg_total,
-                            AVG(disk_used) as avg_used
-                         FROM disk_stats
-                         WHERE host_id = ? AND timestamp >= ? AND timestamp < ?
-                         GROUP BY mount_point"
-                    )?;
+                    let mut disk_stmt = conn.prepare_cached(AGGREGATE_DISKS)?;
 
                     let disks = disk_stmt.query_map(params![host_id, current_time, period_end], |row| {
                         Ok((
                             row.get::<_, String>(0)?,
                             row.get::<_, f64>(1)?,
                             row.get::<_, f64>(2)?,
+                            row.get::<_, f64>(3)?,
+                            row.get::<_, f64>(4)?,
                         ))
                     })?;
 
                     for disk_result in disks {
-                        let (mount_point, total, used) = disk_result?;
+                        let (mount_point, total, used, inode_total, inode_used) = disk_result?;
                         aggregated_disk_data.push((
                             host_id,
                             current_time,
@@ -635,6 +884,8 @@ impl Database {
                             mount_point,
                             total,
                             used,
+                            inode_total,
+                            inode_used,
                         ));
                     }
                 }
@@ -643,9 +894,14 @@ impl Database {
             }
         }
 
-        // 第二阶段：开始事务并写入所有聚合数据
-        let tx = conn.transaction()?;
+        Ok((aggregated_data, aggregated_disk_data))
+    }
 
+    fn write_aggregates(
+        tx: &Transaction,
+        aggregated_data: Vec<AggregatedRow>,
+        aggregated_disk_data: Vec<AggregatedDiskRow>,
+    ) -> Result<()> {
         // 写入主机聚合数据
         for (host_id, timestamp, interval, cpu, mem_total, mem_used, net_in, net_out, in_speed, out_speed, online) in aggregated_data {
             tx.execute(
@@ -671,73 +927,631 @@ impl Database {
         }
 
         // 写入磁盘聚合数据
-        for (host_id, timestamp, interval, mount_point, total, used) in aggregated_disk_data {
+        for (host_id, timestamp, interval, mount_point, total, used, inode_total, inode_used) in aggregated_disk_data {
             tx.execute(
                 "INSERT OR REPLACE INTO aggregated_disk_stats (
-                    host_id, timestamp, interval_minutes, mount_point, disk_total, disk_used
-                ) VALUES (?, ?, ?, ?, ?, ?)",
+                    host_id, timestamp, interval_minutes, mount_point, disk_total, disk_used, inode_total, inode_used
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
                 params![
                     host_id,
                     timestamp,
                     interval,
                     mount_point,
                     total,
-                    used
+                    used,
+                    inode_total,
+                    inode_used
                 ],
             )?;
         }
+        Ok(())
+    }
+    // 用 interval_minutes 级别的聚合数据重新计算 since 所在的那天及之后的日汇总, 返回写入的记录数
+    fn rollup_daily(conn: &Connection, host_id: i64, interval_minutes: i64, since: i64) -> Result<usize> {
+        let day = since / 86400 * 86400;
+        for table in ["daily_stats", "daily_disk_stats"] {
+            conn.execute(
+                &format!("DELETE FROM {table} WHERE host_id = ? AND day >= ?"),
+                params![host_id, day],
+            )?;
+        }
+        let mut rows = conn.prepare_cached(DAILY_ROLLUP_STATS)?.execute(params![host_id, interval_minutes, day])?;
+        rows += conn.prepare_cached(DAILY_ROLLUP_DISKS)?.execute(params![host_id, interval_minutes, day])?;
+        Ok(rows)
+    }
+
+    // 主机 since 之后的日汇总
+    pub fn daily_stats(&self, host: &str, since: i64) -> Result<(Vec<DailyRecord>, Vec<DailyDiskRecord>)> {
+        let conn = self.reader();
+        let host_id = Self::host_id(&conn, host)?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT day, samples, cpu_min, cpu_avg, cpu_max, memory_total, memory_min, memory_avg, memory_max,
+                network_in_min, network_in_avg, network_in_max, network_out_min, network_out_avg, network_out_max,
+                online_ratio
+            FROM daily_stats WHERE host_id = ? AND day >= ? ORDER BY day",
+        )?;
+        let stats = stmt
+            .query_map(params![host_id, since], |row| {
+                Ok(DailyRecord {
+                    day: row.get(0)?,
+                    samples: row.get(1)?,
+                    cpu: [row.get(2)?, row.get(3)?, row.get(4)?],
+                    memory_total: row.get(5)?,
+                    memory_used: [row.get(6)?, row.get(7)?, row.get(8)?],
+                    network_in_speed: [row.get(9)?, row.get(10)?, row.get(11)?],
+                    network_out_speed: [row.get(12)?, row.get(13)?, row.get(14)?],
+                    online_ratio: row.get(15)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT day, mount_point, disk_total, used_min, used_avg, used_max
+            FROM daily_disk_stats WHERE host_id = ? AND day >= ? ORDER BY mount_point, day",
+        )?;
+        let disks = stmt
+            .query_map(params![host_id, since], |row| {
+                Ok(DailyDiskRecord {
+                    day: row.get(0)?,
+                    mount_point: row.get(1)?,
+                    total: row.get(2)?,
+                    used: [row.get(3)?, row.get(4)?, row.get(5)?],
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((stats, disks))
+    }
+
+    fn host_id(conn: &Connection, name: &str) -> Result<i64> {
+        conn.query_row("SELECT id FROM hosts WHERE name = ?", params![name], |row| row.get(0))
+            .map_err(|_| anyhow::anyhow!("Host not found: {}", name))
+    }
+
+    // 删除主机在 [start_time, end_time] 内的原始数据, 以及完全落在该范围内的聚合数据, 再重新聚合受影响的时间段
+    pub fn delete_history(&self, host: &str, start_time: i64, end_time: i64) -> Result<usize> {
+        let deleted = {
+            let mut conn = self.conn.lock().unwrap();
+            let host_id = Self::host_id(&conn, host)?;
+            let tx = conn.transaction()?;
+            let mut deleted = 0;
+            for table in ["stats", "disk_stats", "probe_stats"] {
+                deleted += tx.execute(
+                    &format!("DELETE FROM {table} WHERE host_id = ? AND timestamp BETWEEN ? AND ?"),
+                    params![host_id, start_time, end_time],
+                )?;
+            }
+            for table in ["aggregated_stats", "aggregated_disk_stats", "aggregated_probe_stats"] {
+                deleted += tx.execute(
+                    &format!(
+                        "DELETE FROM {table}
+                         WHERE host_id = ? AND timestamp >= ? AND timestamp + interval_minutes * 60 <= ?"
+                    ),
+                    params![host_id, start_time, end_time + 1],
+                )?;
+            }
+            tx.commit()?;
+            deleted
+        };
+        self.recompute_aggregates(Some(host), start_time, end_time)?;
+        Ok(deleted)
+    }
+
+    // 把主机在 [start_time, end_time] 内的指标限制在 [min, max], 原始表和聚合表都会修改, 再重新聚合受影响的时间段
+    pub fn clamp_history(&self, host: &str, start_time: i64, end_time: i64, clamp: &Clamp) -> Result<usize> {
+        let updated = {
+            let mut conn = self.conn.lock().unwrap();
+            let host_id = Self::host_id(&conn, host)?;
+            let tx = conn.transaction()?;
+            let mut updated = 0;
+            for (limits, op) in [(&clamp.min, "<"), (&clamp.max, ">")] {
+                for (column, value) in limits {
+                    let tables: &[&str] = match column.as_str() {
+                        "cpu_usage" | "memory_used" | "network_in" | "network_out" | "network_in_speed"
+                        | "network_out_speed" => &["stats", "aggregated_stats"],
+                        "disk_used" => &["disk_stats", "aggregated_disk_stats"],
+                        _ => return Err(anyhow::anyhow!("can't clamp column `{}`", column)),
+                    };
+                    for table in tables {
+                        updated += tx.execute(
+                            &format!(
+                                "UPDATE {table} SET {column} = ?1
+                                 WHERE host_id = ?2 AND timestamp BETWEEN ?3 AND ?4 AND {column} {op} ?1"
+                            ),
+                            params![value, host_id, start_time, end_time],
+                        )?;
+                    }
+                }
+            }
+            tx.commit()?;
+            updated
+        };
+        self.recompute_aggregates(Some(host), start_time, end_time)?;
+        Ok(updated)
+    }
 
+    // 按原始数据重建 [start_time, end_time] 覆盖到的聚合时间段, host 为空时处理所有主机
+    // 原始数据不完整(已被清理)的时间段保持不变, 返回重建的记录数
+    pub fn recompute_aggregates(&self, host: Option<&str>, start_time: i64, end_time: i64) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let hosts: Vec<i64> = match host {
+            Some(name) => vec![Self::host_id(&conn, name)?],
+            None => {
+                let mut stmt = conn.prepare_cached("SELECT id FROM hosts")?;
+                let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            }
+        };
+        let now = Utc::now().timestamp();
+
+        let tx = conn.transaction()?;
+        let mut rebuilt = 0;
+        for &host_id in &hosts {
+            let raw_min = |table: &str| -> Result<Option<i64>> {
+                Ok(tx.query_row(
+                    &format!("SELECT MIN(timestamp) FROM {table} WHERE host_id = ?"),
+                    params![host_id],
+                    |row| row.get(0),
+                )?)
+            };
+            let stats_min = raw_min("stats")?;
+            let probe_min = raw_min("probe_stats")?;
+
+            // 保持主机现有的聚合级别(由精度策略决定), 还没有聚合数据时使用默认级别
+            let stats_intervals = {
+                let mut stmt = tx.prepare_cached("SELECT DISTINCT interval_minutes FROM aggregated_stats WHERE host_id = ?")?;
+                let rows = stmt.query_map(params![host_id], |row| row.get::<_, i64>(0))?;
+                let intervals = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+                if intervals.is_empty() {
+                    AGG_INTERVALS.to_vec()
+                } else {
+                    intervals
+                }
+            };
+            let mut intervals = stats_intervals.iter().copied().chain(AGG_INTERVALS).collect::<Vec<_>>();
+            intervals.sort_unstable();
+            intervals.dedup();
+
+            for interval_minutes in intervals {
+                let interval_seconds = interval_minutes * 60;
+                // 只处理已结束的时间段, 进行中的由定时聚合负责
+                let end = ((end_time + interval_seconds) / interval_seconds * interval_seconds)
+                    .min(now / interval_seconds * interval_seconds);
+                let window = |raw_min: i64| {
+                    let start = start_time / interval_seconds * interval_seconds;
+                    if start < raw_min {
+                        (raw_min + interval_seconds - 1) / interval_seconds * interval_seconds
+                    } else {
+                        start
+                    }
+                };
+
+                if let Some(start) = stats_min
+                    .filter(|_| stats_intervals.contains(&interval_minutes))
+                    .map(window)
+                    .filter(|&o| o < end)
+                {
+                    for table in ["aggregated_stats", "aggregated_disk_stats"] {
+                        tx.execute(
+                            &format!(
+                                "DELETE FROM {table}
+                                 WHERE host_id = ? AND interval_minutes = ? AND timestamp >= ? AND timestamp < ?"
+                            ),
+                            params![host_id, interval_minutes, start, end],
+                        )?;
+                    }
+                    let (data, disk_data) = Self::collect_aggregates(&tx, &[host_id], interval_minutes, start, end)?;
+                    rebuilt += data.len() + disk_data.len();
+                    Self::write_aggregates(&tx, data, disk_data)?;
+                }
+
+                if let Some(start) = probe_min
+                    .filter(|_| AGG_INTERVALS.contains(&interval_minutes))
+                    .map(window)
+                    .filter(|&o| o < end)
+                {
+                    tx.execute(
+                        "DELETE FROM aggregated_probe_stats
+                         WHERE host_id = ? AND interval_minutes = ? AND timestamp >= ? AND timestamp < ?",
+                        params![host_id, interval_minutes, start, end],
+                    )?;
+                    rebuilt += Self::aggregate_probe_range(&tx, Some(host_id), interval_minutes, start, end)?;
+                }
+            }
+
+            // 受影响的日汇总
+            if let (Some(raw_min), Some(&interval)) = (stats_min, stats_intervals.iter().min()) {
+                if start_time.max(raw_min) <= end_time {
+                    rebuilt += Self::rollup_daily(&tx, host_id, interval, start_time.max(raw_min))?;
+                }
+            }
+        }
         tx.commit()?;
-        Ok(())
+        Ok(rebuilt)
     }
-    // 添加清理旧数据的方法
-    pub fn cleanup_old_data(&self, retention_days: i64) -> Result<usize> {
-        let mut conn = self.conn.lock().unwrap();  // 修改这里，添加 mut 关键字
+
+    // 按主机的精度策略清理过期的原始数据
+    pub fn cleanup_old_data(&self, policy: impl Fn(&str) -> Resolution) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
         let now = Utc::now().timestamp();
-        let cutoff_time = now - (retention_days * 24 * 60 * 60);
+        let hosts = Self::hosts(&conn)?;
 
         let tx = conn.transaction()?;
+        let mut deleted = 0;
+        for (host_id, name) in hosts {
+            let cutoff_time = now - (policy(&name).raw_retention_days * 24 * 60 * 60);
+            // 删除旧的统计数据, 磁盘数据, 探测数据
+            for table in ["stats", "disk_stats", "probe_stats"] {
+                deleted += tx.execute(
+                    &format!("DELETE FROM {table} WHERE host_id = ? AND timestamp < ?"),
+                    params![host_id, cutoff_time],
+                )?;
+            }
+        }
+        tx.commit()?;
 
-        // 删除旧的统计数据
-        let stats_deleted = tx.execute(
-            "DELETE FROM stats WHERE timestamp < ?",
-            params![cutoff_time],
-        )?;
+        Ok(deleted)
+    }
+
+    pub fn save_event(&self, ts: i64, kind: &str, name: &str, alias: &str, message: &str, private: bool) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.prepare_cached("INSERT INTO events (ts, kind, name, alias, message, private) VALUES (?, ?, ?, ?, ?, ?)")?
+            .execute(params![ts, kind, name, alias, message, private])?;
+        Ok(())
+    }
 
-        // 删除旧的磁盘数据
-        let disks_deleted = tx.execute(
-            "DELETE FROM disk_stats WHERE timestamp < ?",
-            params![cutoff_time],
+    // since 之后的事件, 新的在前, admin 为 false 时不包含只在管理接口展示的事件及隐藏主机的事件
+    pub fn recent_events(&self, since: i64, limit: usize, admin: bool) -> Result<Vec<EventRecord>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, ts, kind, name, alias, message FROM events WHERE ts >= ?
+             AND (? OR (private = 0 AND name NOT IN (SELECT name FROM hosts WHERE hidden = 1)))
+             ORDER BY ts DESC, id DESC LIMIT ?",
         )?;
+        let rows = stmt.query_map(params![since, admin, limit as i64], |row| {
+            Ok(EventRecord {
+                id: row.get(0)?,
+                ts: row.get(1)?,
+                kind: row.get(2)?,
+                name: row.get(3)?,
+                alias: row.get(4)?,
+                message: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
 
+    pub fn delete_events_before(&self, ts: i64) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM events WHERE ts < ?", params![ts])?)
+    }
+
+    // 删除主机的全部数据及主机记录, 返回删除的行数
+    pub fn purge_host(&self, name: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let host_id = Self::host_id(&conn, name)?;
+        let tx = conn.transaction()?;
+        let mut deleted = 0;
+        for table in HOST_TABLES {
+            deleted += tx.execute(&format!("DELETE FROM {table} WHERE host_id = ?"), params![host_id])?;
+        }
+        tx.execute("DELETE FROM hosts WHERE id = ?", params![host_id])?;
         tx.commit()?;
+        Ok(deleted)
+    }
+
+    // 重命名主机, 新名称已存在时把数据合并过去, 时间点重复的聚合数据及 last_network 以新名称的为准
+    // 返回 (是否合并, 迁移的行数)
+    pub fn rename_host(&self, from: &str, to: &str) -> Result<(bool, usize)> {
+        let mut conn = self.conn.lock().unwrap();
+        let from_id = Self::host_id(&conn, from)?;
+        let to_id = Self::host_id(&conn, to).ok();
+        let tx = conn.transaction()?;
+        let mut moved = 0;
+        match to_id {
+            None => {
+                tx.execute("UPDATE hosts SET name = ? WHERE id = ?", params![to, from_id])?;
+            }
+            Some(to_id) => {
+                for table in HOST_TABLES {
+                    moved += tx.execute(
+                        &format!("UPDATE OR IGNORE {table} SET host_id = ? WHERE host_id = ?"),
+                        params![to_id, from_id],
+                    )?;
+                    tx.execute(&format!("DELETE FROM {table} WHERE host_id = ?"), params![from_id])?;
+                }
+                tx.execute("DELETE FROM hosts WHERE id = ?", params![from_id])?;
+            }
+        }
+        tx.execute("UPDATE events SET name = ? WHERE name = ?", params![to, from])?;
+        tx.commit()?;
+        Ok((to_id.is_some(), moved))
+    }
+
+    // 主机名 => 最近一次入库时的标签
+    pub fn host_labels(&self) -> Result<HashMap<String, Labels>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT name, labels FROM hosts")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        let mut o = HashMap::new();
+        for row in rows {
+            let (name, labels) = row?;
+            o.insert(name, serde_json::from_str(&labels).unwrap_or_default());
+        }
+        Ok(o)
+    }
+
+    // 隐藏的主机名
+    pub fn hidden_hosts(&self) -> Result<HashSet<String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT name FROM hosts WHERE hidden = 1")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<HashSet<_>>>()?)
+    }
+
+    // 还没有上报过的主机也可以先隐藏
+    pub fn set_hidden(&self, name: &str, hidden: bool) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO hosts (name, alias, hidden) VALUES (?, '', ?)
+             ON CONFLICT(name) DO UPDATE SET hidden = excluded.hidden",
+            params![name, hidden],
+        )?;
+        Ok(())
+    }
+
+    // 主机名 => 独立的上报密码
+    pub fn host_secrets(&self) -> Result<HashMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT name, secret FROM hosts WHERE secret != ''")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+    }
+
+    // 可以在主机首次上报前设置, 为空时清除
+    pub fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO hosts (name, alias, secret) VALUES (?, '', ?)
+             ON CONFLICT(name) DO UPDATE SET secret = excluded.secret",
+            params![name, secret],
+        )?;
+        Ok(())
+    }
+
+    // 所有主机的磁盘设备及对应的历史数据名称
+    pub fn disk_devices(&self) -> Result<Vec<DiskDevice>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT h.name, d.device_id, d.series, d.mount_point, d.last_seen
+             FROM disk_devices d JOIN hosts h ON h.id = d.host_id ORDER BY h.name, d.series",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(DiskDevice {
+                name: row.get(0)?,
+                device_id: row.get(1)?,
+                series: row.get(2)?,
+                mount_point: row.get(3)?,
+                last_seen: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    // 把设备之后的数据记到 series 下, 如换盘后新设备沿用原来的挂载点, 已有的历史数据不变
+    pub fn set_disk_series(&self, name: &str, device_id: &str, series: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let host_id = Self::host_id(&conn, name)?;
+        conn.execute(
+            "INSERT INTO disk_devices (host_id, device_id, series, mount_point, last_seen) VALUES (?1, ?2, ?3, '', 0)
+             ON CONFLICT(host_id, device_id) DO UPDATE SET series = excluded.series",
+            params![host_id, device_id, series],
+        )?;
+        Ok(())
+    }
+
+    // 令牌摘要 => 主机名
+    pub fn host_tokens(&self) -> Result<HashMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT token, name FROM hosts WHERE token != ''")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+    }
+
+    // digest 为空时吊销
+    pub fn set_token(&self, name: &str, digest: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO hosts (name, alias, token) VALUES (?, '', ?)
+             ON CONFLICT(name) DO UPDATE SET token = excluded.token",
+            params![name, digest],
+        )?;
+        Ok(())
+    }
+
+    pub fn announcements(&self) -> Result<Vec<Announcement>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT id, message, severity, start_ts, end_ts FROM announcements ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Announcement {
+                id: row.get(0)?,
+                message: row.get(1)?,
+                severity: Severity::parse(&row.get::<_, String>(2)?),
+                start: row.get(3)?,
+                end: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    // id 为 0 时新建, 返回公告 id, 要更新的公告不存在时返回 None
+    pub fn save_announcement(&self, o: &Announcement) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        if o.id == 0 {
+            conn.execute(
+                "INSERT INTO announcements (message, severity, start_ts, end_ts, updated_at) VALUES (?, ?, ?, ?, ?)",
+                params![o.message, o.severity.as_str(), o.start, o.end, now],
+            )?;
+            return Ok(Some(conn.last_insert_rowid()));
+        }
+        let n = conn.execute(
+            "UPDATE announcements SET message = ?, severity = ?, start_ts = ?, end_ts = ?, updated_at = ? WHERE id = ?",
+            params![o.message, o.severity.as_str(), o.start, o.end, now, o.id],
+        )?;
+        Ok((n > 0).then_some(o.id))
+    }
+
+    pub fn delete_announcement(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM announcements WHERE id = ?", params![id])? > 0)
+    }
+
+    // 每台主机最近一次入库的数据, 用于重启后恢复主机列表
+    pub fn latest_stats(&self) -> Result<Vec<HostStat>> {
+        let conn = self.reader();
+        let mut stats = Vec::new();
+        let mut stmt = conn.prepare_cached(LATEST_STATS)?;
+        let mut disk_stmt = conn.prepare_cached(LATEST_DISKS)?;
+        for (host_id, name) in Self::hosts(&conn)? {
+            let row = stmt.query_row(params![host_id], |row| {
+                Ok(HostStat {
+                    name: name.to_string(),
+                    latest_ts: row.get::<_, i64>(0)? as u64,
+                    cpu: row.get(1)?,
+                    memory_total: row.get::<_, i64>(2)? as u64,
+                    memory_used: row.get::<_, i64>(3)? as u64,
+                    network_in: row.get::<_, i64>(4)? as u64,
+                    network_out: row.get::<_, i64>(5)? as u64,
+                    ..Default::default()
+                })
+            });
+            let mut stat = match row {
+                Ok(o) => o,
+                Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let disks = disk_stmt.query_map(params![host_id, stat.latest_ts as i64], |row| {
+                Ok(DiskInfo {
+                    mount_point: row.get(0)?,
+                    total: row.get::<_, i64>(1)? as u64,
+                    used: row.get::<_, i64>(2)? as u64,
+                    inode_total: row.get::<_, i64>(3)? as u64,
+                    inode_used: row.get::<_, i64>(4)? as u64,
+                    ..Default::default()
+                })
+            })?;
+            for disk in disks {
+                let mut disk = disk?;
+                disk.free = disk.total.saturating_sub(disk.used);
+                stat.hdd_total += disk.total;
+                stat.hdd_used += disk.used;
+                stat.disks.push(disk);
+            }
+            stats.push(stat);
+        }
+        Ok(stats)
+    }
 
-        Ok(stats_deleted + disks_deleted)
+    pub fn list_hosts(&self) -> Result<Vec<(i64, String)>> {
+        Self::hosts(&self.reader())
     }
 
-    pub fn run_scheduled_aggregation(&self) -> Result<()> {
-        // 执行5分钟聚合
-        self.aggregate_data(5)?;
+    // 归档用: 按 sql 读出主机 cutoff 之前的数据, sql 参数为 (host_id, cutoff)
+    pub fn query_before<T>(
+        &self,
+        sql: &str,
+        host_id: i64,
+        cutoff: i64,
+        f: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(sql)?;
+        let rows = stmt.query_map(params![host_id, cutoff], f)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn delete_before(&self, table: &str, host_id: i64, cutoff: i64) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            &format!("DELETE FROM {table} WHERE host_id = ? AND timestamp < ?"),
+            params![host_id, cutoff],
+        )?)
+    }
 
-        // 执行15分钟聚合
-        self.aggregate_data(15)?;
+    fn hosts(conn: &Connection) -> Result<Vec<(i64, String)>> {
+        let mut stmt = conn.prepare_cached("SELECT id, name FROM hosts")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
 
-        // 执行30分钟聚合
-        self.aggregate_data(30)?;
+    pub fn run_scheduled_aggregation(&self, policy: impl Fn(&str) -> Resolution) -> Result<()> {
+        // 按主机的精度策略分组, 每个聚合级别只处理使用该级别的主机
+        let hosts = Self::hosts(&self.conn.lock().unwrap())?;
+        let mut levels: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+        // host_id => 最细的聚合级别, 用于日汇总
+        let mut finest = Vec::new();
+        for (host_id, name) in hosts {
+            let intervals = policy(&name).intervals;
+            if let Some(&interval) = intervals.iter().min() {
+                finest.push((host_id, interval));
+            }
+            for interval in intervals {
+                levels.entry(interval).or_default().push(host_id);
+            }
+        }
+        for (interval, hosts) in levels {
+            self.aggregate_data(interval, &hosts)?;
+        }
 
-        // 执行60分钟聚合
-        self.aggregate_data(60)?;
+        // 日汇总, 从最近一天(可能还未结束)开始
+        for (host_id, interval) in finest {
+            let conn = self.conn.lock().unwrap();
+            let since: Option<i64> =
+                conn.query_row("SELECT MAX(day) FROM daily_stats WHERE host_id = ?", params![host_id], |row| row.get(0))?;
+            Self::rollup_daily(&conn, host_id, interval, since.unwrap_or(0))?;
+        }
 
-        
+        // 探测数据聚合
+        for interval in AGG_INTERVALS {
+            self.aggregate_probe_data(interval)?;
+        }
 
         Ok(())
     }
+    // 获取或续约租约, 返回是否为持有者
+    pub fn try_acquire_lease(&self, name: &str, holder: &str, ttl: u64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        let changed = conn.execute(
+            "INSERT INTO leader_lease (name, holder, expires_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET holder = excluded.holder, expires_at = excluded.expires_at
+             WHERE leader_lease.holder = excluded.holder OR leader_lease.expires_at < ?4",
+            params![name, holder, now + ttl as i64, now],
+        )?;
+        Ok(changed == 1)
+    }
+
+    // 获取写锁后回滚, 检查数据库可写(未只读挂载/磁盘未满/未被其它进程长时间锁住)
+    pub fn check_writable(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")?;
+        Ok(())
+    }
+
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        integrity::check(&conn)
+    }
+
     // 添加数据库优化方法
-    pub fn optimize(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();  // 修改这里，添加 mut 关键字
+    pub fn optimize(&self, policy: impl Fn(&str) -> Resolution) -> Result<()> {
+        // cleanup_old_data 内部会加锁, 需在持有连接锁之前调用
+        self.cleanup_old_data(policy)?;
+        self.vacuum()
+    }
+
+    pub fn vacuum(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
 
-        self.cleanup_old_data(1)?;
-        
         // 运行VACUUM来整理数据库文件
         conn.execute_batch("VACUUM")?;
 
@@ -746,16 +1560,103 @@ impl Database {
 
         Ok(())
     }
+
+    // 按名称排序的主机及最近一次数据的时间, 供命令行查看
+    pub fn host_infos(&self) -> Result<Vec<HostInfo>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT h.name, IFNULL(h.alias, ''), h.hidden, h.labels, h.token != '',
+                MAX(IFNULL((SELECT MAX(timestamp) FROM stats WHERE host_id = h.id), 0),
+                    IFNULL((SELECT MAX(timestamp) FROM aggregated_stats WHERE host_id = h.id), 0))
+            FROM hosts h ORDER BY h.name",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(HostInfo {
+                name: row.get(0)?,
+                alias: row.get(1)?,
+                hidden: row.get(2)?,
+                labels: serde_json::from_str(&row.get::<_, String>(3)?).unwrap_or_default(),
+                token: row.get(4)?,
+                last_seen: Some(row.get::<_, i64>(5)?).filter(|&o| o > 0),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+// 根据时间范围选择合适的聚合级别
+// 超过3天使用1小时聚合，超过1天使用30分钟聚合，超过12小时使用15分钟聚合，超过1小时使用5分钟聚合
+fn history_interval(time_range: i64) -> i64 {
+    if time_range > 3 * 24 * 3600 {
+        60 // 1小时
+    } else if time_range >= 24 * 3600 {
+        30 // 30分钟
+    } else if time_range >= 12 * 3600 {
+        15 // 15分钟
+    } else if time_range >= 3600 {
+        5  // 5分钟
+    } else {
+        0  // 使用原始数据
+    }
 }
 
 #[derive(Debug, Clone)]
+pub struct HostInfo {
+    pub name: String,
+    pub alias: String,
+    pub hidden: bool,
+    pub labels: Labels,
+    // 是否签发了上报 token
+    pub token: bool,
+    // 原始或聚合数据中最近的时间戳
+    pub last_seen: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRecord {
+    pub id: i64,
+    pub ts: i64,
+    // NodeUp | NodeDown | Alert
+    pub kind: String,
+    pub name: String,
+    pub alias: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeRecord {
+    pub timestamp: i64,
+    // 成功率 %
+    pub success_rate: f64,
+    // ms
+    pub latency: f64,
+}
+
+// 磁盘设备与历史数据名称的对应
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskDevice {
+    pub name: String,
+    pub device_id: String,
+    pub series: String,
+    // 最近一次上报时的挂载点
+    pub mount_point: String,
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct DiskRecord {
     pub timestamp: i64,  // 添加 timestamp 字段
     pub mount_point: String,
     pub total: i64,
     pub used: i64,
+    // 0: 未上报
+    pub inode_total: i64,
+    pub inode_used: i64,
 }
 
+// 主机名 => 按时间排序的记录
+pub type HistoryRecords = HashMap<String, Vec<HostStatRecord>>;
+
 #[derive(Debug, Clone)]
 pub struct HostStatRecord {
     pub timestamp: i64,
@@ -770,3 +1671,405 @@ pub struct HostStatRecord {
     pub online: bool,
     pub disks: Vec<DiskRecord>,
 }
+
+// 日汇总, [min, avg, max]
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyRecord {
+    pub day: i64,
+    pub samples: i64,
+    pub cpu: [f64; 3],
+    pub memory_total: f64,
+    pub memory_used: [f64; 3],
+    pub network_in_speed: [f64; 3],
+    pub network_out_speed: [f64; 3],
+    pub online_ratio: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyDiskRecord {
+    pub day: i64,
+    pub mount_point: String,
+    pub total: f64,
+    pub used: [f64; 3],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_interval() {
+        let hour = 3600;
+        let default = Resolution::default();
+        assert_eq!(default.pick_interval(600), 0);
+        assert_eq!(default.pick_interval(2 * hour), 5);
+        assert_eq!(default.pick_interval(24 * hour), 30);
+        assert_eq!(default.pick_interval(7 * 24 * hour), 60);
+
+        // 没有对应级别时使用更粗的一级
+        let bulk = Resolution {
+            intervals: vec![60],
+            ..Default::default()
+        };
+        assert_eq!(bulk.pick_interval(600), 0);
+        assert_eq!(bulk.pick_interval(2 * hour), 60);
+
+        let vip = Resolution {
+            intervals: vec![1, 5, 15, 30, 60],
+            history_points: 720,
+            ..Default::default()
+        };
+        assert_eq!(vip.pick_interval(2 * hour), 1);
+        assert_eq!(vip.pick_interval(24 * hour), 5);
+        assert_eq!(vip.pick_interval(30 * 24 * hour), 60);
+
+        let raw_only = Resolution {
+            intervals: vec![],
+            ..Default::default()
+        };
+        assert_eq!(raw_only.pick_interval(7 * 24 * hour), 0);
+    }
+
+    #[test]
+    fn test_purge_host() {
+        let dir = std::env::temp_dir().join(format!("purge-{}.db", std::process::id()));
+        let db = Database::new(dir.to_str().unwrap()).unwrap();
+        for name in ["h1", "h2"] {
+            let stat = HostStat {
+                name: name.to_string(),
+                latest_ts: 1700000000,
+                ..Default::default()
+            };
+            db.save_stat(&stat).unwrap();
+            db.update_last_network(name, 1, 1).unwrap();
+        }
+        assert_eq!(db.purge_host("h1").unwrap(), 2);
+        assert_eq!(db.list_hosts().unwrap().into_iter().map(|o| o.1).collect::<Vec<_>>(), ["h2"]);
+        assert!(db.purge_host("h1").is_err());
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", dir.display()));
+        }
+    }
+
+    #[test]
+    fn test_latest_stats() {
+        let dir = std::env::temp_dir().join(format!("latest-{}.db", std::process::id()));
+        let db = Database::new(dir.to_str().unwrap()).unwrap();
+        for ts in [1700000000, 1700000010] {
+            let stat = HostStat {
+                name: "h1".to_string(),
+                latest_ts: ts,
+                cpu: ts as f64 - 1700000000.0,
+                disks: vec![DiskInfo {
+                    mount_point: "/".to_string(),
+                    total: 100,
+                    used: 40,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            db.save_stat(&stat).unwrap();
+        }
+        let stats = db.latest_stats().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].latest_ts, stats[0].cpu), (1700000010, 10.0));
+        assert_eq!((stats[0].hdd_total, stats[0].hdd_used, stats[0].disks[0].free), (100, 40, 60));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", dir.display()));
+        }
+    }
+
+    #[test]
+    fn test_history_page() {
+        let db = Database::new(":memory:").unwrap();
+        for name in ["h3", "h1", "h2"] {
+            db.save_stat(&HostStat {
+                name: name.to_string(),
+                latest_ts: 1700000000,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        let page = |from: Option<&str>, deadline: Option<Instant>| {
+            let (records, cursor) = db
+                .get_stats_by_timerange(1699999000, 1700001000, |_| Resolution::default(), from, deadline)
+                .unwrap();
+            let mut names = records.into_keys().collect::<Vec<_>>();
+            names.sort();
+            (names, cursor)
+        };
+        assert_eq!(page(None, None), (vec!["h1".to_string(), "h2".to_string(), "h3".to_string()], None));
+        // 超时后每页至少一台主机
+        let expired = Some(Instant::now());
+        assert_eq!(page(None, expired), (vec!["h1".to_string()], Some("h2".to_string())));
+        assert_eq!(page(Some("h2"), expired), (vec!["h2".to_string()], Some("h3".to_string())));
+        assert_eq!(page(Some("h3"), expired), (vec!["h3".to_string()], None));
+    }
+
+    #[test]
+    fn test_rename_host() {
+        let db = Database::new(":memory:").unwrap();
+        let save = |name: &str, ts: u64| {
+            db.save_stat(&HostStat {
+                name: name.to_string(),
+                latest_ts: ts,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        save("old", 1700000000);
+        save("old", 1700000010);
+        db.update_last_network("old", 1, 1).unwrap();
+        assert_eq!(db.rename_host("old", "mid").unwrap(), (false, 0));
+        assert_eq!(db.list_hosts().unwrap().into_iter().map(|o| o.1).collect::<Vec<_>>(), ["mid"]);
+
+        save("new", 1700000020);
+        db.update_last_network("new", 2, 2).unwrap();
+        // 2 条 stats, last_network 以 new 的为准
+        assert_eq!(db.rename_host("mid", "new").unwrap(), (true, 2));
+        assert_eq!(db.list_hosts().unwrap().into_iter().map(|o| o.1).collect::<Vec<_>>(), ["new"]);
+        assert_eq!(db.get_last_network_data().unwrap(), [("new".to_string(), 2, 2)]);
+        assert!(db.rename_host("mid", "new").is_err());
+    }
+
+    #[test]
+    fn test_set_hidden() {
+        let db = Database::new(":memory:").unwrap();
+        db.set_hidden("h1", true).unwrap();
+        db.save_stat(&HostStat {
+            name: "h1".to_string(),
+            alias: "n1".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(db.hidden_hosts().unwrap(), HashSet::from(["h1".to_string()]));
+        db.set_hidden("h1", false).unwrap();
+        assert!(db.hidden_hosts().unwrap().is_empty());
+        assert_eq!(db.list_hosts().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_disk_devices() {
+        let db = Database::new(":memory:").unwrap();
+        let save = |ts: u64, device_id: &str, mount_point: &str| {
+            let disk = DiskInfo {
+                mount_point: mount_point.to_string(),
+                device_id: device_id.to_string(),
+                total: 100,
+                ..Default::default()
+            };
+            db.save_stat(&HostStat {
+                name: "h1".to_string(),
+                latest_ts: ts,
+                disks: vec![disk],
+                ..Default::default()
+            })
+            .unwrap();
+        };
+        let series = |ts: u64| -> String {
+            let conn = db.conn.lock().unwrap();
+            conn.query_row("SELECT mount_point FROM disk_stats WHERE timestamp = ?", [ts], |row| row.get(0))
+                .unwrap()
+        };
+        // 改挂载点后仍记在原来的名称下, 没有设备标识的按挂载点
+        save(1700000000, "u1", "/data");
+        save(1700000010, "u1", "/mnt/data");
+        save(1700000020, "", "/tmp");
+        assert_eq!((series(1700000010), series(1700000020)), ("/data".to_string(), "/tmp".to_string()));
+        // 换盘后新设备沿用原来的名称
+        db.set_disk_series("h1", "u2", "/data").unwrap();
+        save(1700000030, "u2", "/new");
+        assert_eq!(series(1700000030), "/data");
+        let devices = db.disk_devices().unwrap();
+        assert_eq!(
+            devices.iter().map(|o| (o.device_id.as_str(), o.series.as_str(), o.mount_point.as_str())).collect::<Vec<_>>(),
+            [("u1", "/data", "/mnt/data"), ("u2", "/data", "/new")]
+        );
+        assert!(db.set_disk_series("h2", "u3", "/data").is_err());
+    }
+
+    #[test]
+    fn test_disk_inodes() {
+        let db = Database::new(":memory:").unwrap();
+        let stat = HostStat {
+            name: "h1".to_string(),
+            latest_ts: 1700000000,
+            disks: vec![
+                DiskInfo {
+                    mount_point: "/".to_string(),
+                    total: 100,
+                    inode_total: 1000,
+                    inode_used: 950,
+                    ..Default::default()
+                },
+                DiskInfo {
+                    mount_point: "/data".to_string(),
+                    total: 100,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(stat.max_inode_percent(), 95.0);
+        db.save_stat(&stat).unwrap();
+
+        let (records, _) = db
+            .get_stats_by_timerange(1699999000, 1700001000, |_| Resolution::default(), None, None)
+            .unwrap();
+        let disks = &records["h1"][0].disks;
+        assert_eq!((disks[0].inode_total, disks[0].inode_used), (1000, 950));
+        assert_eq!((disks[1].inode_total, disks[1].inode_used), (0, 0));
+        assert_eq!(db.latest_stats().unwrap()[0].disks[0].inode_used, 950);
+
+        let conn = db.conn.lock().unwrap();
+        let (_, disks) = Database::collect_aggregates(&conn, &[1], 5, 1699999800, 1700000100).unwrap();
+        assert_eq!((disks[0].6, disks[0].7), (1000.0, 950.0));
+    }
+
+    #[test]
+    fn test_readers() {
+        let dir = std::env::temp_dir().join(format!("readers-{}.db", std::process::id()));
+        let db = Arc::new(Database::open(dir.to_str().unwrap(), 2, Pragmas::profile(Profile::Small)).unwrap());
+        db.set_hidden("h1", true).unwrap();
+        let pragma = |conn: &Connection, name: &str| conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(pragma(&db.reader(), "cache_size"), -4096);
+        assert_eq!(pragma(&db.conn.lock().unwrap(), "wal_autocheckpoint"), 500);
+        assert_eq!(db.hidden_hosts().unwrap().len(), 1);
+
+        // 写连接被占用时只读查询不等待
+        let writer = db.conn.lock().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let db2 = db.clone();
+        std::thread::spawn(move || {
+            // 同时使用两个只读连接
+            let reader = db2.reader();
+            let n = db2.hidden_hosts().map(|o| o.len()).ok();
+            drop(reader);
+            tx.send(n).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap(), Some(1));
+        drop(writer);
+        assert_eq!(db.readers.as_ref().unwrap().idle.lock().unwrap().len(), 2);
+        assert!(Database::open(":memory:", 2, Pragmas::default()).unwrap().readers.is_none());
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", dir.display()));
+        }
+    }
+
+    #[test]
+    fn test_announcements() {
+        let db = Database::new(":memory:").unwrap();
+        let mut o = Announcement {
+            message: "maintenance tonight 02:00 UTC".to_string(),
+            severity: Severity::Warning,
+            start: 100,
+            ..Default::default()
+        };
+        o.id = db.save_announcement(&o).unwrap().unwrap();
+        o.end = 200;
+        assert_eq!(db.save_announcement(&o).unwrap(), Some(o.id));
+        assert_eq!(db.announcements().unwrap(), vec![o.clone()]);
+        assert_eq!(db.save_announcement(&Announcement { id: 99, ..o.clone() }).unwrap(), None);
+        assert!(db.delete_announcement(o.id).unwrap());
+        assert!(db.announcements().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_daily_rollup() {
+        let db = Database::new(":memory:").unwrap();
+        db.save_stat(&HostStat {
+            name: "h1".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let conn = db.conn.lock().unwrap();
+        // 两天, 每天 3 个 5 分钟的聚合点
+        for (ts, cpu, used) in [(0, 10.0, 100.0), (300, 20.0, 200.0), (600, 30.0, 300.0), (86400, 50.0, 500.0), (86700, 70.0, 700.0), (87000, 60.0, 600.0)] {
+            conn.execute(
+                "INSERT INTO aggregated_stats (host_id, timestamp, interval_minutes, cpu_usage, memory_total, memory_used,
+                    network_in, network_out, network_in_speed, network_out_speed, online)
+                VALUES (1, ?, 5, ?, 1000, ?, 0, 0, 0, 0, ?)",
+                params![ts, cpu, used, ts != 300],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO aggregated_disk_stats (host_id, timestamp, interval_minutes, mount_point, disk_total, disk_used)
+                VALUES (1, ?, 5, '/', 1000, ?)",
+                params![ts, used],
+            )
+            .unwrap();
+        }
+        assert_eq!(Database::rollup_daily(&conn, 1, 5, 0).unwrap(), 4);
+        // 只重新计算第二天
+        assert_eq!(Database::rollup_daily(&conn, 1, 5, 86400 + 3600).unwrap(), 2);
+        drop(conn);
+
+        let (stats, disks) = db.daily_stats("h1", 0).unwrap();
+        assert_eq!(stats.iter().map(|o| (o.day, o.samples)).collect::<Vec<_>>(), [(0, 3), (86400, 3)]);
+        assert_eq!(stats[0].cpu, [10.0, 20.0, 30.0]);
+        assert_eq!(stats[1].memory_used, [500.0, 600.0, 700.0]);
+        assert!((stats[0].online_ratio - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(disks[1].used, [500.0, 600.0, 700.0]);
+        assert_eq!(db.daily_stats("h1", 86400).unwrap().0.len(), 1);
+    }
+
+    // 热点 sql 必须走覆盖索引, 不能退化成全表扫描
+    #[test]
+    fn test_query_plans() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::run(&mut conn).unwrap();
+        for (sql, index) in [
+            (HISTORY_RAW_STATS, "idx_stats_host_time_cover"),
+            (HISTORY_RAW_DISKS, "idx_disk_stats_host_time_cover"),
+            (HISTORY_AGG_STATS, "idx_agg_stats_interval_host_time"),
+            (HISTORY_AGG_DISKS, "idx_agg_disk_stats_interval_host_time"),
+            (HISTORY_RAW_PROBES, "idx_probe_stats_time_cover"),
+            (HISTORY_AGG_PROBES, "idx_agg_probe_stats_interval_time"),
+            (AGGREGATE_STATS, "idx_stats_host_time_cover"),
+            (AGGREGATE_DISKS, "idx_disk_stats_host_time_cover"),
+            (LATEST_STATS, "idx_stats_host_time_cover"),
+            (LATEST_DISKS, "idx_disk_stats_host_time_cover"),
+            (DAILY_ROLLUP_STATS, "idx_agg_stats_interval_host_time"),
+            (DAILY_ROLLUP_DISKS, "idx_agg_disk_stats_interval_host_time"),
+        ] {
+            let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {sql}")).unwrap();
+            // 参数不影响执行计划, 全部绑定 NULL
+            let nulls = vec![rusqlite::types::Null; stmt.parameter_count()];
+            let plan = stmt
+                .query_map(rusqlite::params_from_iter(nulls), |row| row.get::<_, String>(3))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap();
+            let scans = plan
+                .iter()
+                .filter(|o| o.starts_with("SCAN") && !o.contains("USING INTEGER PRIMARY KEY"))
+                .collect::<Vec<_>>();
+            assert!(scans.is_empty(), "{sql}: {plan:?}");
+            assert!(
+                plan.iter().any(|o| o.contains(&format!("COVERING INDEX {index} "))),
+                "{sql}: {plan:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_private_events() {
+        let db = Database::new(":memory:").unwrap();
+        db.save_event(1, "NodeDown", "h1", "n1", "offline", false).unwrap();
+        db.save_event(2, "Alert", "", "", "auth failures from 203.0.113.1", true).unwrap();
+        db.save_event(3, "NodeUp", "h2", "n2", "back online", false).unwrap();
+        let kinds = |admin| {
+            db.recent_events(0, 10, admin)
+                .unwrap()
+                .into_iter()
+                .map(|o| o.kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(false), ["NodeUp", "NodeDown"]);
+        assert_eq!(kinds(true), ["NodeUp", "Alert", "NodeDown"]);
+        // 隐藏主机的事件只在管理接口展示
+        db.set_hidden("h2", true).unwrap();
+        assert_eq!(kinds(false), ["NodeDown"]);
+        assert_eq!(kinds(true), ["NodeUp", "Alert", "NodeDown"]);
+    }
+}
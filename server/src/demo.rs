@@ -0,0 +1,352 @@
+#![deny(warnings)]
+// --demo 模式, 生成虚拟主机并在进程内持续上报, 不需要客户端, 供主题及前端开发使用
+use anyhow::Result;
+use serde_json::{json, Value};
+use stat_common::server_status::{IpInfo, SysInfo};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::auth::Reporter;
+use crate::config::{Config, Host};
+use crate::labels::Labels;
+use crate::G_STATS_MGR;
+
+// location, region, provider, country, city, lat, lon
+const SITES: [(&str, &str, &str, &str, &str, f64, f64); 8] = [
+    ("hk", "asia", "aws", "Hong Kong", "Hong Kong", 22.32, 114.17),
+    ("jp", "asia", "vultr", "Japan", "Tokyo", 35.69, 139.69),
+    ("sg", "asia", "digitalocean", "Singapore", "Singapore", 1.35, 103.82),
+    ("us", "america", "aws", "United States", "Los Angeles", 34.05, -118.24),
+    ("us", "america", "linode", "United States", "New York", 40.71, -74.01),
+    ("de", "europe", "hetzner", "Germany", "Falkenstein", 50.48, 12.37),
+    ("gb", "europe", "ovh", "United Kingdom", "London", 51.51, -0.13),
+    ("cn", "asia", "aliyun", "China", "Shanghai", 31.23, 121.47),
+];
+const OS_LIST: [(&str, &str); 6] = [
+    ("debian", "Debian GNU/Linux 12"),
+    ("ubuntu", "Ubuntu 22.04.4 LTS"),
+    ("centos", "CentOS Stream 9"),
+    ("alpine", "Alpine Linux v3.19"),
+    ("arch", "Arch Linux"),
+    ("freebsd", "FreeBSD 14.0-RELEASE"),
+];
+const GB: f64 = (1_u64 << 30) as f64;
+
+// 线性同余, 同一主机名每次生成相同的主机
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    // [0, 1)
+    fn f64(&mut self) -> f64 {
+        self.next() as f64 / (1_u64 << 31) as f64
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    // 近似正态分布, 均值 0 方差 1
+    fn normal(&mut self) -> f64 {
+        (0..12).map(|_| self.f64()).sum::<f64>() - 6.0
+    }
+}
+
+// 一台虚拟主机, 指标围绕各自的基准值随机游走, 网络流量有昼夜起伏, 磁盘缓慢增长
+pub struct FakeHost {
+    pub name: String,
+    rng: Rng,
+    site: usize,
+    os: usize,
+    cpu_num: u32,
+    cpu_base: f64,
+    cpu: f64,
+    load: [f64; 3],
+    memory_total: f64,
+    memory: f64,
+    disk_total: f64,
+    disk: f64,
+    // 网络基准速率(bytes/s)
+    network_base: f64,
+    network_in: u64,
+    network_out: u64,
+    boot_ts: u64,
+    last_ts: u64,
+    // 每 period 秒中有 duration 秒离线, period 为 0 时不离线
+    outage: (u64, u64, u64),
+}
+
+impl FakeHost {
+    pub fn new(name: &str) -> Self {
+        let mut rng = Rng(name
+            .bytes()
+            .fold(7_u64, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u64)));
+        let cpu_num = 1 << rng.below(4);
+        let cpu_base = 3.0 + rng.f64() * 40.0;
+        let memory_total = (cpu_num * 2) as f64 * GB;
+        let disk_total = (20 + 20 * rng.below(10)) as f64 * GB;
+        let outage = match rng.below(3) {
+            0 => (0, 0, 0),
+            _ => {
+                let period = 1200 + rng.below(2400);
+                (period, 60 + rng.below(180), rng.below(period))
+            }
+        };
+        Self {
+            name: name.to_string(),
+            site: rng.below(SITES.len() as u64) as usize,
+            os: rng.below(OS_LIST.len() as u64) as usize,
+            cpu_num,
+            cpu_base,
+            cpu: cpu_base,
+            load: [0.0; 3],
+            memory_total,
+            memory: memory_total * (0.2 + rng.f64() * 0.5),
+            disk_total,
+            disk: disk_total * (0.1 + rng.f64() * 0.6),
+            network_base: 1024.0 * (8 + rng.below(2048)) as f64,
+            network_in: rng.below(1 << 40),
+            network_out: rng.below(1 << 40),
+            boot_ts: 0,
+            last_ts: 0,
+            outage,
+            rng,
+        }
+    }
+
+    pub fn is_down(&self, now: u64) -> bool {
+        let (period, duration, offset) = self.outage;
+        period > 0 && (now + offset) % period < duration
+    }
+
+    pub fn host(&self, idx: usize) -> Host {
+        let (location, region, provider, ..) = SITES[self.site];
+        let (os, _) = OS_LIST[self.os];
+        let spec = format!(
+            "{}C/{}G/{}G",
+            self.cpu_num,
+            self.cpu_num * 2,
+            (self.disk_total / GB) as u64
+        );
+        Host {
+            name: self.name.to_string(),
+            password: self.name.to_string(),
+            alias: format!("{}-{}", location.to_uppercase(), idx + 1),
+            location: location.to_string(),
+            region: region.to_string(),
+            zone: format!("{location}-{}", 1 + idx % 3),
+            provider: provider.to_string(),
+            r#type: ["kvm", "lxc", "openvz"][idx % 3].to_string(),
+            labels: Labels::parse(&format!("os={os};spec={spec};env={};", ["prod", "staging"][idx % 2])),
+            notify: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn sample(&mut self, now: u64) -> Value {
+        let elapsed = match self.last_ts {
+            0 => 1,
+            ts => now.saturating_sub(ts).clamp(1, 60),
+        };
+        // 离线后重新上报视为重启
+        if self.boot_ts == 0 {
+            self.boot_ts = now.saturating_sub(86400 * self.rng.below(90));
+        } else if elapsed >= 60 {
+            self.boot_ts = now;
+        }
+        self.last_ts = now;
+
+        // 向基准值回归, 偶尔出现尖峰
+        self.cpu += (self.cpu_base - self.cpu) * 0.1 + self.rng.normal() * 3.0;
+        if self.rng.below(200) == 0 {
+            self.cpu += 40.0;
+        }
+        self.cpu = self.cpu.clamp(0.5, 100.0);
+        let load = self.cpu / 100.0 * self.cpu_num as f64;
+        for (i, o) in self.load.iter_mut().enumerate() {
+            *o += (load - *o) / [1.0, 5.0, 15.0][i];
+        }
+        let base = self.memory_total * 0.45;
+        self.memory = (self.memory + (base - self.memory) * 0.02 + self.rng.normal() * 0.005 * self.memory_total)
+            .clamp(0.1 * self.memory_total, 0.95 * self.memory_total);
+        // 写满前清理一次
+        self.disk += self.rng.f64() * 64.0 * 1024.0 * elapsed as f64;
+        if self.disk > 0.9 * self.disk_total {
+            self.disk = 0.4 * self.disk_total;
+        }
+
+        let hour = (now % 86400) as f64 / 3600.0;
+        let diurnal = 1.0 + 0.6 * (hour / 24.0 * 2.0 * PI).sin();
+        let mut rate = |o: f64| (o * diurnal * (1.0 + self.rng.normal() * 0.2)).max(0.0) as u64;
+        let (rx, tx) = (rate(self.network_base), rate(self.network_base * 0.6));
+        self.network_in += rx * elapsed;
+        self.network_out += tx * elapsed;
+
+        let (location, _, _, country, city, lat, lon) = SITES[self.site];
+        let (os, os_release) = OS_LIST[self.os];
+        let ip_info = IpInfo {
+            query: format!("203.0.113.{}", 1 + self.site * 16 + self.os),
+            source: "demo".to_string(),
+            country: country.to_string(),
+            city: city.to_string(),
+            isp: SITES[self.site].2.to_string(),
+            lat,
+            lon,
+            ..Default::default()
+        };
+        let sys_info = SysInfo {
+            name: "stat_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os_name: os.to_string(),
+            os_arch: "x86_64".to_string(),
+            os_family: "unix".to_string(),
+            os_release: os_release.to_string(),
+            cpu_num: self.cpu_num,
+            host_name: format!("{location}-{}", self.name),
+            ..Default::default()
+        };
+        let process = 80 + self.rng.below(120);
+        json!({
+            "name": self.name,
+            "uptime": now - self.boot_ts,
+            "load_1": self.load[0],
+            "load_5": self.load[1],
+            "load_15": self.load[2],
+            "ping_10010": self.rng.below(3) as f64,
+            "ping_189": self.rng.below(3) as f64,
+            "ping_10086": self.rng.below(3) as f64,
+            "time_10010": 20 + self.rng.below(40),
+            "time_189": 20 + self.rng.below(40),
+            "time_10086": 20 + self.rng.below(40),
+            "tcp": 20 + self.rng.below(200),
+            "udp": 5 + self.rng.below(20),
+            "process": process,
+            "thread": process * 3,
+            "network_rx": rx,
+            "network_tx": tx,
+            "network_in": self.network_in,
+            "network_out": self.network_out,
+            "cpu": (self.cpu * 10.0).round() / 10.0,
+            "memory_total": self.memory_total as u64,
+            "memory_used": self.memory as u64,
+            "swap_total": (GB / 2.0) as u64,
+            "swap_used": self.rng.below(1 << 26),
+            "hdd_total": self.disk_total as u64,
+            "hdd_used": self.disk as u64,
+            "latest_ts": now,
+            "disks": [{
+                "name": "vda1",
+                "mount_point": "/",
+                "file_system": "ext4",
+                "total": self.disk_total as u64,
+                "used": self.disk as u64,
+                "free": (self.disk_total - self.disk) as u64,
+            }],
+            "ip_info": ip_info,
+            "sys_info": sys_info,
+        })
+    }
+}
+
+fn names(n: usize) -> Vec<String> {
+    (1..=n).map(|i| format!("demo-{i:02}")).collect()
+}
+
+// 用虚拟主机替换配置中的主机, 通知只演练, 关闭会访问外部的功能
+// 数据写入临时目录, 不影响现有的 stats.db
+pub fn setup(cfg: &mut Config, n: usize) -> Result<PathBuf> {
+    if !cfg.templates_dir.is_empty() {
+        cfg.templates_dir = fs::canonicalize(&cfg.templates_dir)?.to_string_lossy().to_string();
+    }
+    let dir = std::env::temp_dir().join("ss-demo");
+    fs::create_dir_all(&dir)?;
+    std::env::set_current_dir(&dir)?;
+    cfg.db_path = "stats.db".to_string();
+
+    cfg.hosts = names(n)
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            let mut host = FakeHost::new(name).host(idx);
+            host.pos = idx;
+            host.monthstart = 1;
+            host.weight = 10000_u64 - idx as u64;
+            host.units = cfg.site.units;
+            host
+        })
+        .collect();
+    cfg.hosts_map = cfg.hosts.iter().map(|o| (o.name.to_string(), o.clone())).collect();
+    cfg.hosts_group.clear();
+    cfg.hosts_group_map.clear();
+    cfg.notify_dry_run = true;
+    cfg.mirror.enabled = false;
+    cfg.cluster.enabled = false;
+    cfg.probe.enabled = false;
+    Ok(dir)
+}
+
+// 每秒为每台主机上报一次, 离线时段内不上报
+pub fn run(n: usize) {
+    let mut hosts = names(n).iter().map(|o| FakeHost::new(o)).collect::<Vec<_>>();
+    thread::spawn(move || loop {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mgr = G_STATS_MGR.get().unwrap();
+        for host in hosts.iter_mut().filter(|o| !o.is_down(now)) {
+            let reporter = Reporter::Host(host.name.to_string());
+            if let Err(err) = mgr.report(host.sample(now), &reporter) {
+                error!("demo report {} error => {:?}", host.name, err);
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_host() {
+        let mut o = FakeHost::new("demo-01");
+        assert_eq!(
+            o.host(0).labels.to_string(),
+            FakeHost::new("demo-01").host(0).labels.to_string()
+        );
+
+        let now = 1_700_000_000;
+        let mut last = 0;
+        for i in 0..600 {
+            let v = o.sample(now + i);
+            let cpu = v["cpu"].as_f64().unwrap();
+            assert!((0.5..=100.0).contains(&cpu));
+            assert!(v["memory_used"].as_u64().unwrap() < v["memory_total"].as_u64().unwrap());
+            assert!(v["hdd_used"].as_u64().unwrap() < v["hdd_total"].as_u64().unwrap());
+            let network_in = v["network_in"].as_u64().unwrap();
+            assert!(network_in >= last);
+            last = network_in;
+        }
+
+        // 离线时段
+        let mut o = (1..100)
+            .map(|i| FakeHost::new(&format!("demo-{i:02}")))
+            .find(|o| o.outage.0 > 0)
+            .unwrap();
+        let (period, duration, offset) = o.outage;
+        let down = now + period - (now + offset) % period;
+        assert!(o.is_down(down) && o.is_down(down + duration - 1) && !o.is_down(down + duration));
+        let uptime = |o: &mut FakeHost, now| o.sample(now)["uptime"].as_u64().unwrap();
+        uptime(&mut o, down - 1);
+        assert_eq!(uptime(&mut o, down + duration), 0);
+        assert_eq!(uptime(&mut o, down + duration + 5), 5);
+    }
+}
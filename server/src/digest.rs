@@ -0,0 +1,140 @@
+#![deny(warnings)]
+use chrono::{Duration as ChronoDuration, Local, NaiveTime};
+use std::time::Duration;
+
+use crate::config::Digest;
+use crate::db::EventRecord;
+use crate::payload::HostStat;
+use crate::{events, leader, notifier, snapshot, G_STATS_MGR};
+
+// 内存/磁盘使用率超过该值时列出
+const HIGH_USAGE: f64 = 80.0;
+
+fn pct(used: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => used as f64 * 100.0 / total as f64,
+    }
+}
+
+pub fn text(date: &str, servers: &[HostStat], events: &[EventRecord]) -> String {
+    let online = servers.iter().filter(|o| o.online4 || o.online6).count();
+    let mut lines = vec![
+        format!("📊 ServerStatus daily digest {date}"),
+        format!("🟢 online {online}/{}", servers.len()),
+    ];
+    let offline = servers
+        .iter()
+        .filter(|o| !(o.online4 || o.online6))
+        .map(|o| o.alias.as_str())
+        .collect::<Vec<_>>();
+    if !offline.is_empty() {
+        lines.push(format!("🔴 offline: {}", offline.join(", ")));
+    }
+    for o in servers.iter().filter(|o| o.online4 || o.online6) {
+        let mut high = vec![];
+        if o.cpu >= HIGH_USAGE {
+            high.push(format!("cpu {:.0}%", o.cpu));
+        }
+        let mem = pct(o.memory_used, o.memory_total);
+        if mem >= HIGH_USAGE {
+            high.push(format!("mem {mem:.0}%"));
+        }
+        let hdd = pct(o.hdd_used, o.hdd_total);
+        if hdd >= HIGH_USAGE {
+            high.push(format!("disk {hdd:.0}%"));
+        }
+        if !high.is_empty() {
+            lines.push(format!("⚠️ {}: {}", o.alias, high.join(", ")));
+        }
+    }
+    let count = |kind: &str| events.iter().filter(|e| e.kind == kind).count();
+    lines.push(format!(
+        "📉 last 24h: {} offline, {} recovered, {} alerts",
+        count("NodeDown"),
+        count("NodeUp"),
+        count("Alert")
+    ));
+    lines.join("\n")
+}
+
+pub fn send(cfg: &Digest) {
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    let now = Local::now();
+    let date = now.format("%Y-%m-%d").to_string();
+    let events = events::recent_all(now.timestamp() - 86400, 10000);
+    let caption = text(&date, &stats.servers, &events);
+    let png = match cfg.image {
+        true => snapshot::render(&format!("ServerStatus {date}"), &stats.servers)
+            .map_err(|err| error!("render snapshot error => {:?}", err))
+            .ok(),
+        false => None,
+    };
+    notifier::send_image(&caption, png);
+}
+
+// 距离下一个 at 时刻的时长
+fn next_delay(at: NaiveTime) -> Duration {
+    let now = Local::now();
+    let today = now.date_naive().and_time(at);
+    let next = match today > now.naive_local() {
+        true => today,
+        false => today + ChronoDuration::days(1),
+    };
+    (next - now.naive_local()).to_std().unwrap_or_default()
+}
+
+pub async fn run(cfg: &'static Digest) {
+    let Ok(at) = NaiveTime::parse_from_str(&cfg.at, "%H:%M") else {
+        return error!("invalid digest.at `{}`, expect HH:MM", cfg.at);
+    };
+    loop {
+        tokio::time::sleep(next_delay(at)).await;
+        // 多实例时只由 notify leader 发送
+        if !leader::is_notify_leader() {
+            continue;
+        }
+        if let Err(err) = tokio::task::spawn_blocking(|| send(cfg)).await {
+            error!("send digest error => {:?}", err);
+        }
+        // 避免同一分钟内重复发送
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        let servers = vec![
+            HostStat {
+                alias: "n1".to_string(),
+                online4: true,
+                cpu: 12.0,
+                memory_total: 100,
+                memory_used: 90,
+                ..Default::default()
+            },
+            HostStat {
+                alias: "n2".to_string(),
+                ..Default::default()
+            },
+        ];
+        let event = |kind: &str| EventRecord {
+            id: 0,
+            ts: 0,
+            kind: kind.to_string(),
+            name: String::new(),
+            alias: String::new(),
+            message: String::new(),
+        };
+        let events = vec![event("NodeDown"), event("NodeDown"), event("NodeUp")];
+        assert_eq!(
+            text("2024-06-01", &servers, &events),
+            "📊 ServerStatus daily digest 2024-06-01\n🟢 online 1/2\n🔴 offline: n2\n⚠️ n1: mem 90%\n📉 last 24h: 2 offline, 1 recovered, 0 alerts"
+        );
+        assert!(next_delay(NaiveTime::from_hms_opt(9, 0, 0).unwrap()) <= Duration::from_secs(86400));
+    }
+}
@@ -0,0 +1,304 @@
+#![deny(warnings)]
+// 域名注册到期检查, 优先使用 RDAP, 没有结果时通过 WHOIS 查询, 提醒规则与证书检查相同
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::certs;
+use crate::config::{self, Domains};
+use crate::{notifier, outbound, tasks};
+
+const IANA_WHOIS: &str = "whois.iana.org";
+// whois 响应的最大字节数
+const WHOIS_MAX_SIZE: u64 = 64 * 1024;
+// 按顺序查找到期时间所在的行, 各注册局的 WHOIS 格式不同
+const EXPIRY_KEYS: [&str; 8] = [
+    "registry expiry date",
+    "registrar registration expiration date",
+    "expiration date",
+    "expiry date",
+    "expiration time",
+    "expires on",
+    "paid-till",
+    "expires",
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DomainStatus {
+    pub domain: String,
+    pub registrar: String,
+    pub expires: i64,
+    pub days_left: i64,
+    pub checked_at: i64,
+    // rdap | whois
+    pub source: String,
+    pub error: Option<String>,
+}
+
+static RESULTS: Lazy<Mutex<Vec<DomainStatus>>> = Lazy::new(Default::default);
+// 域名 => 已通知的级别
+static LEVELS: Lazy<Mutex<HashMap<String, usize>>> = Lazy::new(Default::default);
+
+pub fn list() -> Vec<DomainStatus> {
+    RESULTS.lock().unwrap().clone()
+}
+
+fn parse_date(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Some(t.timestamp());
+    }
+    for fmt in ["%Y-%m-%dT%H:%M:%S%.fZ", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(s.trim_end_matches(" UTC"), fmt) {
+            return Some(t.and_utc().timestamp());
+        }
+    }
+    let day = s.split_whitespace().next()?;
+    ["%Y-%m-%d", "%Y.%m.%d", "%Y/%m/%d", "%d-%b-%Y", "%d.%m.%Y"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(day, fmt).ok())
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|t| t.and_utc().timestamp())
+}
+
+// RDAP 响应中的 (到期时间, 注册商)
+fn parse_rdap(v: &Value) -> Option<(i64, String)> {
+    let expires = v["events"]
+        .as_array()?
+        .iter()
+        .find(|e| e["eventAction"] == "expiration")
+        .and_then(|e| e["eventDate"].as_str())
+        .and_then(parse_date)?;
+    let registrar = v["entities"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|e| e["roles"].as_array().is_some_and(|o| o.iter().any(|r| r == "registrar")))
+        .and_then(|e| e["vcardArray"][1].as_array())
+        .and_then(|props| props.iter().find(|p| p[0] == "fn"))
+        .and_then(|p| p[3].as_str())
+        .unwrap_or_default();
+    Some((expires, registrar.to_string()))
+}
+
+fn whois_field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(k, v)| k.trim().eq_ignore_ascii_case(key) && !v.trim().is_empty())
+        .map(|(_, v)| v.trim())
+}
+
+// WHOIS 响应中的 (到期时间, 注册商)
+fn parse_whois(text: &str) -> Option<(i64, String)> {
+    let expires = EXPIRY_KEYS
+        .iter()
+        .find_map(|k| whois_field(text, k).and_then(parse_date))?;
+    let registrar = ["registrar", "sponsoring registrar"]
+        .iter()
+        .find_map(|k| whois_field(text, k))
+        .unwrap_or_default();
+    Some((expires, registrar.to_string()))
+}
+
+async fn rdap(client: &reqwest::Client, cfg: &Domains, domain: &str) -> Result<(i64, String)> {
+    let url = format!("{}/{domain}", cfg.rdap_url.trim_end_matches('/'));
+    let resp = client
+        .get(&url)
+        .timeout(Duration::from_secs(cfg.timeout.max(1)))
+        .header(reqwest::header::ACCEPT, "application/rdap+json")
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(anyhow!("rdap {url} {status}"));
+    }
+    let v = resp.json::<Value>().await?;
+    parse_rdap(&v).ok_or_else(|| anyhow!("no expiration in rdap response"))
+}
+
+fn whois_query(server: &str, query: &str, timeout: Duration) -> Result<String> {
+    let addr = (server, 43)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("can't resolve {server}"))?;
+    let mut sock = TcpStream::connect_timeout(&addr, timeout)?;
+    sock.set_read_timeout(Some(timeout))?;
+    sock.set_write_timeout(Some(timeout))?;
+    sock.write_all(format!("{query}\r\n").as_bytes())?;
+    // 服务器来自 IANA 的 refer 字段, 超出部分丢弃
+    let mut buf = Vec::new();
+    sock.take(WHOIS_MAX_SIZE).read_to_end(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+fn whois(cfg: &Domains, domain: &str) -> Result<(i64, String)> {
+    let timeout = Duration::from_secs(cfg.timeout.max(1));
+    let tld = domain.rsplit('.').next().unwrap_or(domain);
+    let iana = whois_query(IANA_WHOIS, tld, timeout)?;
+    let server = whois_field(&iana, "refer")
+        .or_else(|| whois_field(&iana, "whois"))
+        .ok_or_else(|| anyhow!("no whois server for .{tld}"))?;
+    let text = whois_query(server, domain, timeout)?;
+    parse_whois(&text).ok_or_else(|| anyhow!("no expiration in whois response from {server}"))
+}
+
+fn check(rt: &Runtime, client: &reqwest::Client, cfg: &Domains, domain: &str) -> DomainStatus {
+    let now = Utc::now().timestamp();
+    let mut status = DomainStatus {
+        domain: domain.to_string(),
+        checked_at: now,
+        ..Default::default()
+    };
+    let result = match rt.block_on(rdap(client, cfg, domain)) {
+        Ok(o) => Ok((o, "rdap")),
+        Err(err) if cfg.whois => {
+            debug!("{domain} rdap error => {:?}, try whois", err);
+            whois(cfg, domain)
+                .map(|o| (o, "whois"))
+                .map_err(|e| anyhow!("{err}; whois: {e}"))
+        }
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(((expires, registrar), source)) => {
+            status.registrar = registrar;
+            status.expires = expires;
+            status.days_left = (expires - now).div_euclid(86400);
+            status.source = source.to_string();
+        }
+        Err(err) => status.error = Some(err.to_string()),
+    }
+    status
+}
+
+fn message(status: &DomainStatus, last: usize, level: usize) -> Option<String> {
+    let date = Utc
+        .timestamp_opt(status.expires, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let domain = &status.domain;
+    if level > last {
+        return Some(match status.expires <= status.checked_at {
+            true => format!("❗domain {domain} expired at {date}"),
+            false => format!("⚠️ domain {domain} expires in {} days at {date}", status.days_left),
+        });
+    }
+    (level == 0 && last > 0).then(|| format!("✅ domain {domain} renewed, expires at {date}"))
+}
+
+fn check_all(rt: &Runtime, client: &reqwest::Client, cfg: &Domains) -> Result<()> {
+    let mut results = cfg
+        .names
+        .iter()
+        .map(|domain| check(rt, client, cfg, domain))
+        .collect::<Vec<_>>();
+
+    let mut msgs = Vec::new();
+    {
+        let mut levels = LEVELS.lock().unwrap();
+        levels.retain(|k, _| cfg.names.contains(k));
+        for status in results.iter().filter(|o| o.error.is_none()) {
+            let level = certs::level(&cfg.warn_days, status.days_left, status.expires <= status.checked_at);
+            let last = levels.insert(status.domain.to_string(), level).unwrap_or(0);
+            msgs.extend(message(status, last, level));
+        }
+    }
+    for msg in msgs {
+        notifier::alert(&msg);
+    }
+
+    results.sort_by_key(|o| (o.error.is_none(), o.days_left));
+    let failed = results.iter().filter_map(|o| o.error.as_ref().map(|e| (o, e))).collect::<Vec<_>>();
+    let err = failed
+        .first()
+        .map(|(o, e)| anyhow!("{} of {} failed, {}: {e}", failed.len(), cfg.names.len(), o.domain));
+    *RESULTS.lock().unwrap() = results;
+    match err {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+pub fn run(cfg: &'static Domains) {
+    let interval = config::parse_duration(&cfg.interval).unwrap_or(86400);
+    thread::spawn(move || {
+        // RDAP 使用异步的 reqwest, 在本线程的 runtime 中执行
+        let rt = match Builder::new_current_thread().enable_all().build() {
+            Ok(o) => o,
+            Err(err) => {
+                error!("create domains runtime error => {:?}", err);
+                return;
+            }
+        };
+        let client = rt.block_on(async { outbound::http_client(&cfg.proxy) });
+        loop {
+            let _ = tasks::run("domain_check", || check_all(&rt, &client, cfg));
+            thread::sleep(Duration::from_secs(interval));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let ts = 1755057600; // 2025-08-13T04:00:00Z
+        assert_eq!(parse_date("2025-08-13T04:00:00Z"), Some(ts));
+        assert_eq!(parse_date("2025-08-13T04:00:00.0Z"), Some(ts));
+        assert_eq!(parse_date("2025-08-13 04:00:00 UTC"), Some(ts));
+        assert_eq!(parse_date("2025-08-13"), Some(ts - 4 * 3600));
+        assert_eq!(parse_date("13-Aug-2025"), Some(ts - 4 * 3600));
+        assert_eq!(parse_date("2025.08.13 04:00:00"), Some(ts - 4 * 3600));
+        assert_eq!(parse_date("soon"), None);
+
+        let v = json!({
+            "objectClassName": "domain",
+            "ldhName": "EXAMPLE.COM",
+            "events": [
+                { "eventAction": "registration", "eventDate": "1995-08-14T04:00:00Z" },
+                { "eventAction": "expiration", "eventDate": "2025-08-13T04:00:00Z" }
+            ],
+            "entities": [{
+                "roles": ["registrar"],
+                "vcardArray": ["vcard", [["version", {}, "text", "4.0"], ["fn", {}, "text", "RESERVED-IANA"]]]
+            }]
+        });
+        assert_eq!(parse_rdap(&v), Some((ts, "RESERVED-IANA".to_string())));
+        assert_eq!(parse_rdap(&json!({ "events": [] })), None);
+
+        let text = "Domain Name: EXAMPLE.COM\r\nRegistrar: RESERVED-IANA\r\nCreation Date: 1995-08-14T04:00:00Z\r\n\
+                    Registry Expiry Date: 2025-08-13T04:00:00Z\r\n";
+        assert_eq!(parse_whois(text), Some((ts, "RESERVED-IANA".to_string())));
+        assert_eq!(parse_whois("domain: example.ru\npaid-till: 2025-08-13T04:00:00Z\n").unwrap().0, ts);
+        assert_eq!(whois_field("refer:        whois.verisign-grs.com\n", "refer"), Some("whois.verisign-grs.com"));
+
+        let mut status = DomainStatus {
+            domain: "example.com".to_string(),
+            expires: ts,
+            days_left: 6,
+            checked_at: ts - 6 * 86400 - 60,
+            ..Default::default()
+        };
+        assert_eq!(
+            message(&status, 1, 2).unwrap(),
+            "⚠️ domain example.com expires in 6 days at 2025-08-13"
+        );
+        assert_eq!(message(&status, 2, 2), None);
+        status.checked_at = ts;
+        assert_eq!(message(&status, 2, 3).unwrap(), "❗domain example.com expired at 2025-08-13");
+        assert_eq!(message(&status, 3, 0).unwrap(), "✅ domain example.com renewed, expires at 2025-08-13");
+    }
+}
@@ -0,0 +1,91 @@
+#![deny(warnings)]
+use anyhow::Result;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use serde::Serialize;
+
+// 按 Accept 协商的响应格式, 结构与 json 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl Format {
+    // 取 Accept 中第一个支持的类型, 不处理 q 权重
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        accept
+            .split(',')
+            .find_map(|o| match o.split(';').next().unwrap_or_default().trim() {
+                "application/json" => Some(Format::Json),
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Some(Format::MsgPack),
+                "application/cbor" => Some(Format::Cbor),
+                _ => None,
+            })
+            .unwrap_or(Format::Json)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::MsgPack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, v: &T) -> Result<Vec<u8>> {
+        Ok(match self {
+            Format::Json => serde_json::to_vec(v)?,
+            // 以 map 形式输出字段名, 与 json 结构一致
+            Format::MsgPack => rmp_serde::to_vec_named(v)?,
+            Format::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(v, &mut buf)?;
+                buf
+            }
+        })
+    }
+
+    // 已经渲染好的 json 转为当前格式
+    pub fn transcode(&self, json: &[u8]) -> Result<Bytes> {
+        match self {
+            Format::Json => Ok(Bytes::copy_from_slice(json)),
+            _ => Ok(Bytes::from(self.encode(&serde_json::from_slice::<serde_json::Value>(json)?)?)),
+        }
+    }
+
+    pub fn response(&self, body: impl Into<Bytes>) -> Response {
+        let mut resp = ([(header::CONTENT_TYPE, self.content_type())], body.into()).into_response();
+        resp.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_format() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Format::from_headers(&headers), Format::Json);
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/html, application/msgpack;q=0.9, */*"));
+        assert_eq!(Format::from_headers(&headers), Format::MsgPack);
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/cbor"));
+        assert_eq!(Format::from_headers(&headers), Format::Cbor);
+
+        let v = json!({ "updated": 1, "servers": [{ "name": "h1", "cpu": 1.5, "disks": [] }] });
+        let raw = serde_json::to_vec(&v).unwrap();
+        let msgpack = Format::MsgPack.transcode(&raw).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&msgpack).unwrap(), v);
+        let cbor = Format::Cbor.transcode(&raw).unwrap();
+        assert_eq!(ciborium::from_reader::<Value, _>(&cbor[..]).unwrap(), v);
+    }
+}
@@ -0,0 +1,67 @@
+#![deny(warnings)]
+use once_cell::sync::OnceCell;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::db::{Database, EventRecord};
+use crate::live;
+use crate::notifier::Event;
+use crate::payload::HostStat;
+
+// 主机上下线及告警事件, 保存在 events 表, 供 graphql / feed.xml 查询
+// 告警的内容可能包含来源 IP/错误信息等, 记录为 private, 只在管理接口展示
+static DB: OnceCell<Arc<Database>> = OnceCell::new();
+
+pub fn init(db: Arc<Database>) {
+    let _ = DB.set(db);
+}
+
+fn save(kind: &str, name: &str, alias: &str, message: &str, private: bool) {
+    live::publish("event", json!({ "kind": kind, "name": name, "alias": alias, "message": message }));
+    let Some(db) = DB.get() else {
+        return;
+    };
+    let ts = chrono::Utc::now().timestamp();
+    if let Err(err) = db.save_event(ts, kind, name, alias, message, private) {
+        error!("save event {} {} error => {:?}", kind, name, err);
+    }
+}
+
+pub fn record(kind: &Event, stat: &HostStat) {
+    let (kind, message) = match kind {
+        Event::NodeUp => ("NodeUp", "back online"),
+        Event::NodeDown => ("NodeDown", "offline"),
+        Event::Custom => return,
+    };
+    save(kind, &stat.name, &stat.alias, message, false);
+}
+
+// 主机的告警规则触发及恢复
+pub fn host_alert(stat: &HostStat, msg: &str) {
+    save("Alert", &stat.name, &stat.alias, msg, true);
+}
+
+// 与主机无关的告警, 如任务失败/时钟偏差
+pub fn alert(msg: &str) {
+    save("Alert", "", "", msg, true);
+}
+
+fn query(since: i64, limit: usize, admin: bool) -> Vec<EventRecord> {
+    let Some(db) = DB.get() else {
+        return vec![];
+    };
+    db.recent_events(since, limit, admin).unwrap_or_else(|err| {
+        error!("query events error => {:?}", err);
+        vec![]
+    })
+}
+
+// since 之后公开的事件(未隐藏主机的上下线), 新的在前, 最多 limit 条
+pub fn recent(since: i64, limit: usize) -> Vec<EventRecord> {
+    query(since, limit, false)
+}
+
+// 包含告警及隐藏主机在内的全部事件, 只用于管理接口及内部的通知
+pub fn recent_all(since: i64, limit: usize) -> Vec<EventRecord> {
+    query(since, limit, true)
+}
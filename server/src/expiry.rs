@@ -0,0 +1,96 @@
+#![deny(warnings)]
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, ExpireHistory};
+use crate::db::Database;
+use crate::payload::HostStat;
+use crate::{archive, leader, notifier};
+
+// 保留最近过期的主机数
+const RECENT: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiredHost {
+    pub name: String,
+    pub alias: String,
+    pub gid: String,
+    pub last_seen: u64,
+    pub expired_at: u64,
+    pub history: ExpireHistory,
+    // 历史数据处理的行数或错误
+    pub result: String,
+}
+
+static EXPIRED: Lazy<Mutex<VecDeque<ExpiredHost>>> = Lazy::new(Default::default);
+
+pub fn recent() -> Vec<ExpiredHost> {
+    EXPIRED.lock().unwrap().iter().rev().cloned().collect()
+}
+
+fn handle_history(cfg: &Config, db: &Database, o: &HostStat, now: u64) -> Result<usize> {
+    match cfg.group_expire.history {
+        ExpireHistory::Keep => Ok(0),
+        ExpireHistory::Purge => db.purge_host(&o.name),
+        ExpireHistory::Archive => {
+            // 还未聚合的原始数据先聚合, 再整体归档
+            let raw_days = cfg.resolution(&o.name, &o.gid).raw_retention_days.max(1);
+            db.recompute_aggregates(Some(&o.name), now as i64 - raw_days * 86400, now as i64)?;
+            let archived = archive::host(&cfg.archive, db, &o.name)?;
+            Ok(archived + db.purge_host(&o.name)?)
+        }
+    }
+}
+
+// group_gc 移除的主机, 在后台线程中通知及处理历史数据
+pub fn expired(cfg: &'static Config, db: Arc<Database>, hosts: Vec<HostStat>) {
+    if hosts.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        // 多实例共用数据库时只由 leader 处理
+        let leader = leader::is_notify_leader();
+        for o in hosts {
+            let result = match leader {
+                true => match handle_history(cfg, &db, &o, now) {
+                    Ok(n) => format!("{n} rows"),
+                    Err(err) => {
+                        error!("{} expire history error => {:?}", o.name, err);
+                        err.to_string()
+                    }
+                },
+                false => "skipped, not leader".to_string(),
+            };
+            if cfg.group_expire.notify {
+                notifier::alert(&format!(
+                    "⌛ {} ({}) in group {} expired, last seen {}s ago",
+                    o.alias,
+                    o.name,
+                    o.gid,
+                    now.saturating_sub(o.latest_ts)
+                ));
+            }
+            info!("{} expired, history {:?} => {}", o.name, cfg.group_expire.history, result);
+
+            let mut expired = EXPIRED.lock().unwrap();
+            if expired.len() >= RECENT {
+                expired.pop_front();
+            }
+            expired.push_back(ExpiredHost {
+                name: o.name.to_string(),
+                alias: o.alias.to_string(),
+                gid: o.gid.to_string(),
+                last_seen: o.latest_ts,
+                expired_at: now,
+                history: cfg.group_expire.history,
+                result,
+            });
+        }
+    });
+}
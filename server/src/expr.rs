@@ -0,0 +1,528 @@
+#![deny(warnings)]
+// 告警规则表达式, 如 cpu > 90 && duration("5m"), (memory_used / memory_total) > 0.95, avg(cpu, "10m") > 80
+// 运算符: || && ! > >= < <= == != + - * /, 函数: duration(d) 条件持续时间, avg/min/max(指标, d) 最近 d 内的采样
+use anyhow::{anyhow, Result};
+
+use crate::config::parse_duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agg {
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Or,
+    And,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Bool(bool),
+    Metric(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    // 不含 duration 的部分持续成立的秒数
+    Duration(u64),
+    Window(Agg, String, u64),
+}
+
+// 表达式求值时需要的数据, 由告警模块按主机提供
+pub trait Env {
+    fn metric(&self, name: &str) -> f64;
+    fn window(&self, agg: Agg, name: &str, secs: u64) -> f64;
+    fn duration(&self, secs: u64) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Num,
+    Bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+const OPS: [&str; 15] = [
+    "||", "&&", ">=", "<=", "==", "!=", ">", "<", "!", "+", "-", "*", "/", "%", "=",
+];
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Num(n) => format!("`{n}`"),
+        Tok::Str(s) => format!("\"{s}\""),
+        Tok::Ident(s) => format!("`{s}`"),
+        Tok::Op(s) => format!("`{s}`"),
+        Tok::LParen => "`(`".to_string(),
+        Tok::RParen => "`)`".to_string(),
+        Tok::Comma => "`,`".to_string(),
+        Tok::End => "end of expression".to_string(),
+    }
+}
+
+// (token, 列号), 列号从 1 开始
+fn lex(src: &str) -> Result<Vec<(Tok, usize)>> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut toks = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let col = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|o| o.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let s = chars[start..i].iter().collect::<String>();
+            let n = s
+                .parse::<f64>()
+                .map_err(|_| anyhow!("invalid number `{s}` at column {col}"))?;
+            toks.push((Tok::Num(n), col));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            toks.push((Tok::Ident(chars[start..i].iter().collect()), col));
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|&o| o == c)
+                .ok_or_else(|| anyhow!("unterminated string at column {col}"))?;
+            toks.push((Tok::Str(chars[i + 1..i + 1 + end].iter().collect()), col));
+            i += end + 2;
+            continue;
+        }
+        let tok = match c {
+            '(' => Some(Tok::LParen),
+            ')' => Some(Tok::RParen),
+            ',' => Some(Tok::Comma),
+            _ => None,
+        };
+        if let Some(tok) = tok {
+            toks.push((tok, col));
+            i += 1;
+            continue;
+        }
+        let rest = chars[i..].iter().take(2).collect::<String>();
+        match OPS.iter().find(|o| rest.starts_with(*o)) {
+            Some(&"%") => return Err(anyhow!("`%` is not supported at column {col}")),
+            Some(&"=") => return Err(anyhow!("unexpected `=` at column {col}, use `==` to compare")),
+            Some(op) => {
+                toks.push((Tok::Op(op), col));
+                i += op.len();
+            }
+            None => return Err(anyhow!("unexpected character `{c}` at column {col}")),
+        }
+    }
+    toks.push((Tok::End, chars.len() + 1));
+    Ok(toks)
+}
+
+struct Parser<'a> {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    is_metric: &'a dyn Fn(&str) -> bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos].0
+    }
+
+    fn col(&self) -> usize {
+        self.toks[self.pos].1
+    }
+
+    fn next(&mut self) -> (Tok, usize) {
+        let o = self.toks[self.pos].clone();
+        if self.pos + 1 < self.toks.len() {
+            self.pos += 1;
+        }
+        o
+    }
+
+    fn expect(&mut self, tok: Tok) -> Result<()> {
+        let (got, col) = self.next();
+        match got == tok {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "expected {} but found {} at column {col}",
+                describe(&tok),
+                describe(&got)
+            )),
+        }
+    }
+
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<(&'static str, usize)> {
+        match self.peek() {
+            Tok::Op(op) if ops.contains(op) => {
+                let (op, col) = (*op, self.col());
+                self.next();
+                Some((op, col))
+            }
+            _ => None,
+        }
+    }
+
+    fn operand(&self, (expr, t): (Expr, Type), want: Type, op: &str, col: usize) -> Result<Expr> {
+        match (t, want) {
+            (Type::Num, Type::Bool) => Err(anyhow!("`{op}` expects conditions but found a number at column {col}")),
+            (Type::Bool, Type::Num) => Err(anyhow!("`{op}` expects numbers but found a condition at column {col}")),
+            _ => Ok(expr),
+        }
+    }
+
+    // 同一优先级的左结合二元运算
+    fn binary(
+        &mut self,
+        ops: &[&'static str],
+        operand: Type,
+        result: Type,
+        next: fn(&mut Self) -> Result<(Expr, Type)>,
+    ) -> Result<(Expr, Type)> {
+        let mut lhs = next(self)?;
+        while let Some((op, col)) = self.eat_op(ops) {
+            let rhs = next(self)?;
+            let l = self.operand(lhs, operand, op, col)?;
+            let r = self.operand(rhs, operand, op, col)?;
+            lhs = (Expr::Binary(op_of(op), Box::new(l), Box::new(r)), result);
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<(Expr, Type)> {
+        self.binary(&["||"], Type::Bool, Type::Bool, Self::and)
+    }
+
+    fn and(&mut self) -> Result<(Expr, Type)> {
+        self.binary(&["&&"], Type::Bool, Type::Bool, Self::cmp)
+    }
+
+    fn cmp(&mut self) -> Result<(Expr, Type)> {
+        let lhs = self.add()?;
+        let Some((op, col)) = self.eat_op(&[">", ">=", "<", "<=", "==", "!="]) else {
+            return Ok(lhs);
+        };
+        let rhs = self.add()?;
+        let l = self.operand(lhs, Type::Num, op, col)?;
+        let r = self.operand(rhs, Type::Num, op, col)?;
+        if let Tok::Op(next) = self.peek() {
+            if [">", ">=", "<", "<=", "==", "!="].contains(next) {
+                return Err(anyhow!(
+                    "comparisons can't be chained at column {}, use `&&`",
+                    self.col()
+                ));
+            }
+        }
+        Ok((Expr::Binary(op_of(op), Box::new(l), Box::new(r)), Type::Bool))
+    }
+
+    fn add(&mut self) -> Result<(Expr, Type)> {
+        self.binary(&["+", "-"], Type::Num, Type::Num, Self::mul)
+    }
+
+    fn mul(&mut self) -> Result<(Expr, Type)> {
+        self.binary(&["*", "/"], Type::Num, Type::Num, Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<(Expr, Type)> {
+        if let Some((op, col)) = self.eat_op(&["!", "-"]) {
+            let o = self.unary()?;
+            return match op {
+                "!" => Ok((Expr::Not(Box::new(self.operand(o, Type::Bool, op, col)?)), Type::Bool)),
+                _ => Ok((Expr::Neg(Box::new(self.operand(o, Type::Num, op, col)?)), Type::Num)),
+            };
+        }
+        self.primary()
+    }
+
+    fn duration_arg(&mut self, func: &str) -> Result<u64> {
+        let (tok, col) = self.next();
+        let secs = match &tok {
+            Tok::Str(s) => parse_duration(s).filter(|&o| o > 0),
+            _ => None,
+        };
+        secs.ok_or_else(|| {
+            anyhow!(
+                "{func}() expects a duration like \"5m\" but found {} at column {col}",
+                describe(&tok)
+            )
+        })
+    }
+
+    fn call(&mut self, func: &str, col: usize) -> Result<(Expr, Type)> {
+        self.expect(Tok::LParen)?;
+        let o = match func {
+            "duration" => (Expr::Duration(self.duration_arg(func)?), Type::Bool),
+            "avg" | "min" | "max" => {
+                let (tok, col) = self.next();
+                let name = match tok {
+                    Tok::Ident(name) if (self.is_metric)(&name) => name,
+                    Tok::Ident(name) => return Err(anyhow!("unknown metric `{name}` at column {col}")),
+                    tok => {
+                        return Err(anyhow!(
+                            "{func}() expects a metric but found {} at column {col}",
+                            describe(&tok)
+                        ))
+                    }
+                };
+                self.expect(Tok::Comma)?;
+                let agg = match func {
+                    "avg" => Agg::Avg,
+                    "min" => Agg::Min,
+                    _ => Agg::Max,
+                };
+                (Expr::Window(agg, name, self.duration_arg(func)?), Type::Num)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "unknown function `{func}` at column {col}, expected duration/avg/min/max"
+                ))
+            }
+        };
+        self.expect(Tok::RParen)?;
+        Ok(o)
+    }
+
+    fn primary(&mut self) -> Result<(Expr, Type)> {
+        let (tok, col) = self.next();
+        match tok {
+            Tok::Num(n) => Ok((Expr::Num(n), Type::Num)),
+            Tok::Ident(name) if *self.peek() == Tok::LParen => self.call(&name, col),
+            Tok::Ident(name) if name == "true" || name == "false" => Ok((Expr::Bool(name == "true"), Type::Bool)),
+            Tok::Ident(name) if (self.is_metric)(&name) => Ok((Expr::Metric(name), Type::Num)),
+            Tok::Ident(name) => Err(anyhow!("unknown metric `{name}` at column {col}")),
+            Tok::LParen => {
+                let o = self.or()?;
+                self.expect(Tok::RParen)?;
+                Ok(o)
+            }
+            Tok::Str(s) => Err(anyhow!(
+                "unexpected string \"{s}\" at column {col}, strings are only used as function arguments"
+            )),
+            tok => Err(anyhow!("expected a value but found {} at column {col}", describe(&tok))),
+        }
+    }
+}
+
+fn op_of(op: &str) -> Op {
+    match op {
+        "||" => Op::Or,
+        "&&" => Op::And,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "+" => Op::Add,
+        "-" => Op::Sub,
+        "*" => Op::Mul,
+        _ => Op::Div,
+    }
+}
+
+impl Expr {
+    // 解析并检查类型, 结果必须是条件, is_metric 判断指标名是否有效
+    pub fn parse(src: &str, is_metric: &dyn Fn(&str) -> bool) -> Result<Expr> {
+        let mut p = Parser {
+            toks: lex(src)?,
+            pos: 0,
+            is_metric,
+        };
+        if *p.peek() == Tok::End {
+            return Err(anyhow!("expression is empty"));
+        }
+        let (expr, t) = p.or()?;
+        if *p.peek() != Tok::End {
+            return Err(anyhow!("unexpected {} at column {}", describe(p.peek()), p.col()));
+        }
+        if t != Type::Bool {
+            return Err(anyhow!("expression must be a condition, e.g. `cpu > 90`"));
+        }
+        Ok(expr)
+    }
+
+    // 引用的指标
+    pub fn metrics(&self, o: &mut Vec<String>) {
+        match self {
+            Expr::Metric(name) | Expr::Window(_, name, _) if !o.contains(name) => o.push(name.to_string()),
+            Expr::Not(e) | Expr::Neg(e) => e.metrics(o),
+            Expr::Binary(_, l, r) => {
+                l.metrics(o);
+                r.metrics(o);
+            }
+            _ => {}
+        }
+    }
+
+    // avg/min/max 需要保留的最长采样时间(s)
+    pub fn window(&self) -> u64 {
+        match self {
+            Expr::Window(_, _, secs) => *secs,
+            Expr::Not(e) | Expr::Neg(e) => e.window(),
+            Expr::Binary(_, l, r) => l.window().max(r.window()),
+            _ => 0,
+        }
+    }
+
+    pub fn num(&self, env: &dyn Env) -> f64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Metric(name) => env.metric(name),
+            Expr::Window(agg, name, secs) => env.window(*agg, name, *secs),
+            Expr::Neg(e) => -e.num(env),
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (l.num(env), r.num(env));
+                match op {
+                    Op::Add => l + r,
+                    Op::Sub => l - r,
+                    Op::Mul => l * r,
+                    _ => l / r,
+                }
+            }
+            _ => f64::NAN,
+        }
+    }
+
+    pub fn test(&self, env: &dyn Env) -> bool {
+        match self {
+            Expr::Bool(b) => *b,
+            Expr::Duration(secs) => env.duration(*secs),
+            Expr::Not(e) => !e.test(env),
+            Expr::Binary(Op::Or, l, r) => l.test(env) || r.test(env),
+            Expr::Binary(Op::And, l, r) => l.test(env) && r.test(env),
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (l.num(env), r.num(env));
+                match op {
+                    Op::Gt => l > r,
+                    Op::Ge => l >= r,
+                    Op::Lt => l < r,
+                    Op::Le => l <= r,
+                    Op::Eq => l == r,
+                    _ => l != r,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Host(bool);
+
+    impl Env for Host {
+        fn metric(&self, name: &str) -> f64 {
+            match name {
+                "cpu" => 95.0,
+                "memory_used" => 960.0,
+                _ => 1000.0,
+            }
+        }
+        fn window(&self, agg: Agg, _name: &str, _secs: u64) -> f64 {
+            match agg {
+                Agg::Avg => 85.0,
+                Agg::Min => 70.0,
+                Agg::Max => 99.0,
+            }
+        }
+        fn duration(&self, _secs: u64) -> bool {
+            self.0
+        }
+    }
+
+    fn parse(src: &str) -> Result<Expr> {
+        Expr::parse(src, &|o| ["cpu", "memory_used", "memory_total"].contains(&o))
+    }
+
+    fn err(src: &str) -> String {
+        parse(src).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_expr() {
+        let e = parse(r#"cpu > 90 && duration("5m")"#).unwrap();
+        assert!(e.test(&Host(true)) && !e.test(&Host(false)));
+        assert!(parse("(memory_used / memory_total) > 0.95").unwrap().test(&Host(false)));
+        assert!(parse("memory_used / memory_total * 100 >= 96 || false")
+            .unwrap()
+            .test(&Host(false)));
+        assert!(
+            parse("avg(cpu, '10m') > 80 && min(cpu, \"1h\") < 80 && !(max(cpu, \"1m\") < 99)")
+                .unwrap()
+                .test(&Host(false))
+        );
+        assert!(parse("-cpu + 100 == 5").unwrap().test(&Host(false)));
+
+        let e = parse(r#"avg(cpu, "10m") > 80 && memory_used > 0 || max(cpu, "1h") > 0"#).unwrap();
+        assert_eq!(e.window(), 3600);
+        let mut metrics = Vec::new();
+        e.metrics(&mut metrics);
+        assert_eq!(metrics, ["cpu", "memory_used"]);
+    }
+
+    #[test]
+    fn test_expr_errors() {
+        assert_eq!(err("cpux > 90"), "unknown metric `cpux` at column 1");
+        assert_eq!(
+            err("cpu > 90 &&"),
+            "expected a value but found end of expression at column 12"
+        );
+        assert_eq!(err("cpu = 90"), "unexpected `=` at column 5, use `==` to compare");
+        assert_eq!(
+            err("cpu > 90 && duration(5)"),
+            "duration() expects a duration like \"5m\" but found `5` at column 22"
+        );
+        assert_eq!(
+            err("cpu > 90 && (cpu < 99"),
+            "expected `)` but found end of expression at column 22"
+        );
+        assert_eq!(err("cpu + 1"), "expression must be a condition, e.g. `cpu > 90`");
+        assert_eq!(
+            err("cpu && 1"),
+            "`&&` expects conditions but found a number at column 5"
+        );
+        assert_eq!(
+            err("rate(cpu) > 1"),
+            "unknown function `rate` at column 1, expected duration/avg/min/max"
+        );
+        assert_eq!(err("1 < cpu < 2"), "comparisons can't be chained at column 9, use `&&`");
+        assert_eq!(err("cpu > 90 cpu"), "unexpected `cpu` at column 10");
+        assert_eq!(err(""), "expression is empty");
+    }
+}
@@ -0,0 +1,97 @@
+#![deny(warnings)]
+use chrono::{TimeZone, Utc};
+
+use crate::db::EventRecord;
+
+// feed.xml 中的事件条数
+pub const ITEMS: usize = 50;
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn rfc2822(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0).single().unwrap_or_default().to_rfc2822()
+}
+
+fn title(e: &EventRecord) -> String {
+    let name = if e.alias.is_empty() { &e.name } else { &e.alias };
+    match e.kind.as_str() {
+        "NodeDown" => format!("🔴 {name} {}", e.message),
+        "NodeUp" => format!("🟢 {name} {}", e.message),
+        _ => format!("❗ {}", e.message.lines().next().unwrap_or_default()),
+    }
+}
+
+// RSS 2.0, site 为站点地址, 如 https://status.example.com
+pub fn render(site: &str, events: &[EventRecord]) -> String {
+    let site = escape(site.trim_end_matches('/'));
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#);
+    xml.push_str("<title>ServerStatus incidents</title>");
+    xml.push_str(&format!("<link>{site}/</link>"));
+    xml.push_str(&format!(r#"<atom:link href="{site}/feed.xml" rel="self" type="application/rss+xml"/>"#));
+    xml.push_str("<description>Server offline/recovery events</description>");
+    if let Some(e) = events.first() {
+        xml.push_str(&format!("<lastBuildDate>{}</lastBuildDate>", rfc2822(e.ts)));
+    }
+    for e in events {
+        xml.push_str("<item>");
+        xml.push_str(&format!("<title>{}</title>", escape(&title(e))));
+        xml.push_str(&format!("<link>{site}/</link>"));
+        xml.push_str(&format!("<description>{}</description>", escape(&e.message)));
+        xml.push_str(&format!("<category>{}</category>", escape(&e.kind)));
+        xml.push_str(&format!(r#"<guid isPermaLink="false">{}-{}</guid>"#, e.ts, e.id));
+        xml.push_str(&format!("<pubDate>{}</pubDate>", rfc2822(e.ts)));
+        xml.push_str("</item>");
+    }
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: i64, kind: &str, alias: &str, message: &str) -> EventRecord {
+        EventRecord {
+            id,
+            ts: 1700000000 + id,
+            kind: kind.to_string(),
+            name: "h1".to_string(),
+            alias: alias.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let events = vec![
+            event(3, "Alert", "", "task <archive> failed & retry"),
+            event(2, "NodeUp", "n1", "back online"),
+            event(1, "NodeDown", "", "offline"),
+        ];
+        let xml = render("https://example.com/", &events);
+        assert!(xml.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0""#));
+        assert!(xml.ends_with("</channel></rss>"));
+        assert_eq!(xml.matches("<item>").count(), 3);
+        assert!(xml.contains("<link>https://example.com/</link>"));
+        assert!(xml.contains("<title>❗ task &lt;archive&gt; failed &amp; retry</title>"));
+        assert!(xml.contains("<title>🟢 n1 back online</title>"));
+        assert!(xml.contains("<title>🔴 h1 offline</title>"));
+        assert!(xml.contains(r#"<guid isPermaLink="false">1700000003-3</guid>"#));
+        assert!(xml.contains("<pubDate>Tue, 14 Nov 2023 22:13:23 +0000</pubDate>"));
+        assert!(!xml.contains("<archive>"));
+    }
+}
@@ -0,0 +1,177 @@
+#![deny(warnings)]
+use anyhow::Result;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use stat_common::utils::bytes2human;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+
+use crate::config::DiskForecast;
+use crate::db::{DailyDiskRecord, Database};
+use crate::notifier;
+
+// 主机名 => 各挂载点的预测, 由聚合任务更新
+static FORECASTS: Lazy<RwLock<HashMap<String, Vec<Forecast>>>> = Lazy::new(Default::default);
+// 已告警的 (主机, 挂载点)
+static ALERTED: Lazy<Mutex<HashSet<(String, String)>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Forecast {
+    pub mount_point: String,
+    pub total: u64,
+    pub used: u64,
+    // 每天增长(bytes), 按拟合的斜率
+    pub growth_per_day: f64,
+    // 预计写满的天数, 用量不增长时为 None
+    pub days_until_full: Option<f64>,
+}
+
+// 最小二乘拟合 y = a + b * x, 返回 (a, b)
+fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|o| o.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|o| o.1).sum::<f64>() / n;
+    let sxx = points.iter().map(|o| (o.0 - mean_x).powi(2)).sum::<f64>();
+    if sxx == 0.0 {
+        return None;
+    }
+    let sxy = points.iter().map(|o| (o.0 - mean_x) * (o.1 - mean_y)).sum::<f64>();
+    let b = sxy / sxx;
+    Some((mean_y - b * mean_x, b))
+}
+
+// 同一挂载点按天排序的记录, today 为当天 0 点(s)
+fn forecast(records: &[&DailyDiskRecord], today: i64, min_days: usize) -> Option<Forecast> {
+    let latest = records.last()?;
+    if records.len() < min_days {
+        return None;
+    }
+    let points = records
+        .iter()
+        .map(|o| ((o.day / 86400) as f64, o.used[1]))
+        .collect::<Vec<_>>();
+    let (a, b) = fit(&points)?;
+    let days_until_full = match b > 0.0 {
+        true => Some(((latest.total - a) / b - (today / 86400) as f64).max(0.0)),
+        false => None,
+    };
+    Some(Forecast {
+        mount_point: latest.mount_point.to_string(),
+        total: latest.total as u64,
+        used: latest.used[1] as u64,
+        growth_per_day: b,
+        days_until_full,
+    })
+}
+
+pub fn get(name: &str) -> Vec<Forecast> {
+    FORECASTS.read().unwrap().get(name).cloned().unwrap_or_default()
+}
+
+// 在聚合任务中调用, 重新计算所有主机的预测, 进入及离开 alert_days 时各告警一次
+pub fn refresh(cfg: &DiskForecast, db: &Database) -> Result<()> {
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let today = Utc::now().timestamp() / 86400 * 86400;
+    let since = today - (cfg.window_days - 1) * 86400;
+    let mut forecasts = HashMap::new();
+    for (_, name) in db.list_hosts()? {
+        let (_, disks) = db.daily_stats(&name, since)?;
+        let mut mounts: BTreeMap<&str, Vec<&DailyDiskRecord>> = BTreeMap::new();
+        for o in &disks {
+            mounts.entry(&o.mount_point).or_default().push(o);
+        }
+        let o = mounts
+            .values()
+            .filter_map(|records| forecast(records, today, cfg.min_days))
+            .collect::<Vec<_>>();
+        if !o.is_empty() {
+            forecasts.insert(name, o);
+        }
+    }
+
+    if cfg.alert_days > 0 {
+        let mut msgs = Vec::new();
+        let mut alerted = ALERTED.lock().unwrap();
+        let mut current = HashSet::new();
+        for (name, o) in forecasts.iter() {
+            for f in o {
+                let Some(days) = f.days_until_full.filter(|&d| d <= cfg.alert_days as f64) else {
+                    continue;
+                };
+                let key = (name.to_string(), f.mount_point.to_string());
+                if !alerted.contains(&key) {
+                    msgs.push(format!(
+                        "❗{} {} is predicted to be full in {:.1} days (+{}/day, {} / {})",
+                        name,
+                        f.mount_point,
+                        days,
+                        bytes2human(f.growth_per_day as u64, 1, false),
+                        bytes2human(f.used, 1, false),
+                        bytes2human(f.total, 1, false)
+                    ));
+                }
+                current.insert(key);
+            }
+        }
+        for (name, mount_point) in alerted.difference(&current) {
+            msgs.push(format!(
+                "✅ {name} {mount_point} is no longer predicted to be full soon"
+            ));
+        }
+        *alerted = current;
+        drop(alerted);
+        for msg in msgs {
+            notifier::alert(&msg);
+        }
+    }
+
+    *FORECASTS.write().unwrap() = forecasts;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forecast() {
+        let (a, b) = fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]).unwrap();
+        assert!((a - 1.0).abs() < 1e-9 && (b - 2.0).abs() < 1e-9);
+        assert!(fit(&[(1.0, 1.0), (1.0, 2.0)]).is_none());
+
+        // 每天增长 10, 第 9 天用到 190, 容量 300
+        let records = (0..10)
+            .map(|i| DailyDiskRecord {
+                day: i * 86400,
+                mount_point: "/".to_string(),
+                total: 300.0,
+                used: [0.0, 100.0 + 10.0 * i as f64, 0.0],
+            })
+            .collect::<Vec<_>>();
+        let refs = records.iter().collect::<Vec<_>>();
+        let o = forecast(&refs, 9 * 86400, 7).unwrap();
+        assert_eq!((o.total, o.used), (300, 190));
+        assert!((o.growth_per_day - 10.0).abs() < 1e-9);
+        assert!((o.days_until_full.unwrap() - 11.0).abs() < 1e-9);
+        assert!(forecast(&refs, 9 * 86400, 11).is_none());
+
+        // 用量下降
+        let records = records
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, o)| DailyDiskRecord {
+                day: i as i64 * 86400,
+                ..o.clone()
+            })
+            .collect::<Vec<_>>();
+        let refs = records.iter().collect::<Vec<_>>();
+        assert_eq!(forecast(&refs, 9 * 86400, 7).unwrap().days_until_full, None);
+    }
+}
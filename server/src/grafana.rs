@@ -0,0 +1,266 @@
+#![deny(warnings)]
+// Grafana simple-json-datasource 接口, 数据源 URL 填 http://<server>/grafana
+// 指标名为 <主机名>.<cpu|memory|disk|network_in|network_out>, 主机名为 * 时查询全部主机, 注释为上下线及告警事件
+// 只在 admin 侦听地址提供, 配置了 token 时需要 Authorization: Bearer {grafana.token}
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::alertmanager::authorized;
+use crate::compare::Metric;
+use crate::events;
+use crate::{G_CONFIG, G_STATS_MGR};
+
+const METRICS: [&str; 5] = ["cpu", "memory", "disk", "network_in", "network_out"];
+// 一次最多返回的注释数
+const MAX_ANNOTATIONS: usize = 1000;
+// 查询的最大时间范围, 超出时只返回最近的部分, 与 /chart 相同
+const MAX_RANGE: i64 = 30 * 86400;
+
+#[derive(Debug, Default, Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Search {
+    #[serde(default = "Default::default")]
+    target: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    #[serde(default = "Default::default")]
+    target: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryReq {
+    range: Range,
+    #[serde(default = "Default::default")]
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnnotationReq {
+    range: Range,
+    #[serde(default = "Default::default")]
+    annotation: Value,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/grafana", get(health))
+        .route("/grafana/", get(health))
+        .route("/grafana/search", post(search))
+        .route("/grafana/query", post(query))
+        .route("/grafana/annotations", post(annotations))
+        .route_layer(middleware::from_fn(require_token))
+}
+
+async fn require_token(req: Request, next: Next) -> Response {
+    let token = &G_CONFIG.get().unwrap().grafana.token;
+    if !token.is_empty() && !authorized(req.headers(), token) {
+        return (StatusCode::UNAUTHORIZED, "invalid token").into_response();
+    }
+    next.run(req).await
+}
+
+// 测试数据源连接
+async fn health() -> &'static str {
+    "ok"
+}
+
+fn parse_time(s: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(s).ok().map(|o| o.timestamp())
+}
+
+fn parse_range(range: &Range) -> Option<(i64, i64)> {
+    match (parse_time(&range.from), parse_time(&range.to)) {
+        (Some(from), Some(to)) if from < to => Some((from.max(to - MAX_RANGE), to)),
+        _ => None,
+    }
+}
+
+// h1.cpu => (h1, cpu), 主机名可以包含 .
+fn parse_target(target: &str) -> Option<(&str, Metric)> {
+    let (name, metric) = target.rsplit_once('.')?;
+    Some((name, Metric::parse(metric)?)).filter(|o| !o.0.is_empty())
+}
+
+// 包含 keyword 的指标名
+fn search_targets(names: &[String], keyword: &str) -> Vec<String> {
+    names
+        .iter()
+        .flat_map(|name| METRICS.iter().map(move |metric| format!("{name}.{metric}")))
+        .filter(|o| o.contains(keyword))
+        .collect()
+}
+
+// 不包含隐藏的主机
+fn host_names() -> Vec<String> {
+    let mut names = G_STATS_MGR
+        .get()
+        .unwrap()
+        .get_stats()
+        .servers
+        .iter()
+        .map(|o| o.name.to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+async fn search(body: Option<Json<Search>>) -> Json<Vec<String>> {
+    let req = body.map(|o| o.0).unwrap_or_default();
+    Json(search_targets(&host_names(), &req.target))
+}
+
+// compare::align 的结果 => [{ target, datapoints: [[value, ms]] }], 没有数据的点为 null
+fn datapoints(aligned: &Value, metric: &str) -> Vec<Value> {
+    let timestamps = aligned["timestamps"].as_array().cloned().unwrap_or_default();
+    aligned["series"]
+        .as_array()
+        .map(|series| {
+            series
+                .iter()
+                .map(|o| {
+                    let values = o["values"].as_array().cloned().unwrap_or_default();
+                    let points = values
+                        .into_iter()
+                        .zip(timestamps.iter())
+                        .map(|(v, ts)| json!([v, ts.as_i64().unwrap_or_default() * 1000]))
+                        .collect::<Vec<_>>();
+                    json!({ "target": format!("{}.{metric}", o["name"].as_str().unwrap_or_default()), "datapoints": points })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn query(Json(req): Json<QueryReq>) -> Response {
+    let Some((start, end)) = parse_range(&req.range) else {
+        return (StatusCode::BAD_REQUEST, "invalid range").into_response();
+    };
+    let hosts = host_names();
+    let mut queries = Vec::new();
+    for o in req.targets.iter().filter(|o| !o.target.is_empty()) {
+        let Some((name, metric)) = parse_target(&o.target).filter(|o| o.0 == "*" || hosts.iter().any(|h| h == o.0)) else {
+            return (StatusCode::BAD_REQUEST, format!("unknown target `{}`", o.target)).into_response();
+        };
+        let names = match name {
+            "*" => hosts.clone(),
+            _ => vec![name.to_string()],
+        };
+        let metric_name = o.target.rsplit_once('.').map(|o| o.1.to_string()).unwrap_or_default();
+        queries.push((names, metric, metric_name));
+    }
+
+    let result = tokio::task::spawn_blocking(move || {
+        let mgr = G_STATS_MGR.get().unwrap();
+        let mut result = Vec::new();
+        for (names, metric, metric_name) in queries {
+            let aligned = mgr.compare(&names, metric, start, end)?;
+            result.extend(datapoints(&aligned, &metric_name));
+        }
+        anyhow::Ok(result)
+    })
+    .await;
+    match result {
+        Ok(Ok(o)) => Json(o).into_response(),
+        Ok(Err(err)) => {
+            error!("grafana query error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(err) => {
+            error!("grafana query error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 注释的 query 为逗号分隔的事件类型(NodeUp/NodeDown/Alert)或主机名, 为空时返回全部事件
+async fn annotations(Json(req): Json<AnnotationReq>) -> Response {
+    let Some((start, end)) = parse_range(&req.range) else {
+        return (StatusCode::BAD_REQUEST, "invalid range").into_response();
+    };
+    let filter = req.annotation["query"]
+        .as_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let events = match tokio::task::spawn_blocking(move || events::recent_all(start, MAX_ANNOTATIONS)).await {
+        Ok(o) => o,
+        Err(err) => {
+            error!("grafana annotations error => {:?}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let result = events
+        .iter()
+        .filter(|o| o.ts <= end)
+        .filter(|o| filter.is_empty() || filter.iter().any(|f| f == &o.kind || f == &o.name))
+        .map(|o| {
+            let title = match o.alias.is_empty() {
+                true => o.kind.to_string(),
+                false => format!("{} {}", o.alias, o.kind),
+            };
+            json!({
+                "annotation": req.annotation,
+                "time": o.ts * 1000,
+                "title": title,
+                "text": o.message,
+                "tags": [o.kind.as_str(), o.name.as_str()],
+            })
+        })
+        .collect::<Vec<_>>();
+    Json(result).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets() {
+        assert_eq!(parse_target("h1.cpu"), Some(("h1", Metric::Cpu)));
+        assert_eq!(parse_target("web.example.com.network_in"), Some(("web.example.com", Metric::NetworkIn)));
+        assert_eq!(parse_target("*.disk"), Some(("*", Metric::Disk)));
+        assert_eq!(parse_target("h1.load"), None);
+        assert_eq!(parse_target(".cpu"), None);
+        assert_eq!(parse_time("2016-10-31T06:33:44.866Z"), Some(1477895624));
+        let range = |from: &str, to: &str| Range {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        let to = parse_time("2024-03-01T00:00:00Z").unwrap();
+        assert_eq!(parse_range(&range("2024-02-29T00:00:00Z", "2024-03-01T00:00:00Z")), Some((to - 86400, to)));
+        assert_eq!(parse_range(&range("2000-01-01T00:00:00Z", "2024-03-01T00:00:00Z")), Some((to - MAX_RANGE, to)));
+        assert_eq!(parse_range(&range("2024-03-01T00:00:00Z", "2024-03-01T00:00:00Z")), None);
+
+        let names = vec!["h1".to_string(), "h2".to_string()];
+        assert_eq!(search_targets(&names, "").len(), 10);
+        assert_eq!(search_targets(&names, "h2.net"), ["h2.network_in", "h2.network_out"]);
+
+        let aligned = json!({
+            "timestamps": [1700000000, 1700000060],
+            "series": [{ "name": "h1", "values": [12.5, null] }],
+        });
+        assert_eq!(
+            datapoints(&aligned, "cpu"),
+            [json!({ "target": "h1.cpu", "datapoints": [[12.5, 1700000000000_i64], [null, 1700000060000_i64]] })]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+#![deny(warnings)]
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Result, Schema, SimpleObject};
+use axum::response::{Html, IntoResponse};
+use axum::Json;
+use once_cell::sync::OnceCell;
+use stat_common::server_status::DiskInfo;
+use std::collections::HashMap;
+
+use crate::compare;
+use crate::config::GraphQL;
+use crate::db::{DiskRecord, EventRecord, HostStatRecord, ProbeRecord};
+use crate::events;
+use crate::metrics;
+use crate::payload::{HostStat, ProbeResult};
+use crate::G_STATS_MGR;
+
+pub type StatusSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+// history 最大的时间范围, 超出时只返回最近的部分, 与 /chart 相同
+const MAX_RANGE: i64 = 30 * 86400;
+// events 一次最多返回的条数
+const MAX_EVENTS: usize = 1000;
+
+static SCHEMA: OnceCell<StatusSchema> = OnceCell::new();
+
+pub fn init(cfg: &GraphQL) {
+    let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(cfg.max_depth)
+        .limit_complexity(cfg.max_complexity)
+        .finish();
+    let _ = SCHEMA.set(schema);
+}
+
+pub async fn handler(Json(req): Json<async_graphql::Request>) -> Json<async_graphql::Response> {
+    metrics::inc("graphql_requests");
+    Json(SCHEMA.get().unwrap().execute(req).await)
+}
+
+pub async fn playground() -> impl IntoResponse {
+    Html(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+}
+
+#[derive(SimpleObject)]
+pub struct Disk {
+    name: String,
+    mount_point: String,
+    file_system: String,
+    total: u64,
+    used: u64,
+    free: u64,
+    inode_total: u64,
+    inode_used: u64,
+}
+
+impl From<&DiskInfo> for Disk {
+    fn from(o: &DiskInfo) -> Self {
+        Self {
+            name: o.name.to_string(),
+            mount_point: o.mount_point.to_string(),
+            file_system: o.file_system.to_string(),
+            total: o.total,
+            used: o.used,
+            free: o.free,
+            inode_total: o.inode_total,
+            inode_used: o.inode_used,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Probe {
+    ok: bool,
+    // ms
+    latency: u32,
+    ts: u64,
+}
+
+impl From<ProbeResult> for Probe {
+    fn from(o: ProbeResult) -> Self {
+        Self {
+            ok: o.ok,
+            latency: o.latency,
+            ts: o.ts,
+        }
+    }
+}
+
+// 实时状态, 字段与 stats.json 对应
+#[derive(SimpleObject)]
+pub struct Server {
+    name: String,
+    alias: String,
+    #[graphql(name = "type")]
+    host_type: String,
+    location: String,
+    region: String,
+    zone: String,
+    provider: String,
+    gid: String,
+    labels: String,
+    online4: bool,
+    online6: bool,
+    uptime: u64,
+    load_1: f64,
+    load_5: f64,
+    load_15: f64,
+    cpu: f64,
+    memory_total: u64,
+    memory_used: u64,
+    swap_total: u64,
+    swap_used: u64,
+    hdd_total: u64,
+    hdd_used: u64,
+    network_rx: u64,
+    network_tx: u64,
+    network_in: u64,
+    network_out: u64,
+    last_network_in: u64,
+    last_network_out: u64,
+    tcp_count: u32,
+    udp_count: u32,
+    process_count: u32,
+    thread_count: u32,
+    latest_ts: u64,
+    clock_skew: i64,
+    disks: Vec<Disk>,
+    probe4: Option<Probe>,
+    probe6: Option<Probe>,
+}
+
+impl From<&HostStat> for Server {
+    fn from(o: &HostStat) -> Self {
+        Self {
+            name: o.name.to_string(),
+            alias: o.alias.to_string(),
+            host_type: o.host_type.to_string(),
+            location: o.location.to_string(),
+            region: o.region.to_string(),
+            zone: o.zone.to_string(),
+            provider: o.provider.to_string(),
+            gid: o.gid.to_string(),
+            labels: o.labels.to_string(),
+            online4: o.online4,
+            online6: o.online6,
+            uptime: o.uptime,
+            load_1: o.load_1,
+            load_5: o.load_5,
+            load_15: o.load_15,
+            cpu: o.cpu,
+            memory_total: o.memory_total,
+            memory_used: o.memory_used,
+            swap_total: o.swap_total,
+            swap_used: o.swap_used,
+            hdd_total: o.hdd_total,
+            hdd_used: o.hdd_used,
+            network_rx: o.network_rx,
+            network_tx: o.network_tx,
+            network_in: o.network_in,
+            network_out: o.network_out,
+            last_network_in: o.last_network_in,
+            last_network_out: o.last_network_out,
+            tcp_count: o.tcp_count,
+            udp_count: o.udp_count,
+            process_count: o.process_count,
+            thread_count: o.thread_count,
+            latest_ts: o.latest_ts,
+            clock_skew: o.clock_skew,
+            disks: o.disks.iter().map(Disk::from).collect(),
+            probe4: o.probe4.map(Probe::from),
+            probe6: o.probe6.map(Probe::from),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DiskPoint {
+    mount_point: String,
+    total: i64,
+    used: i64,
+    inode_total: i64,
+    inode_used: i64,
+}
+
+impl From<DiskRecord> for DiskPoint {
+    fn from(o: DiskRecord) -> Self {
+        Self {
+            mount_point: o.mount_point,
+            total: o.total,
+            used: o.used,
+            inode_total: o.inode_total,
+            inode_used: o.inode_used,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Point {
+    timestamp: i64,
+    cpu: f64,
+    memory_total: i64,
+    memory_used: i64,
+    network_in: i64,
+    network_out: i64,
+    network_in_speed: i64,
+    network_out_speed: i64,
+    online: bool,
+    disks: Vec<DiskPoint>,
+}
+
+impl From<HostStatRecord> for Point {
+    fn from(o: HostStatRecord) -> Self {
+        Self {
+            timestamp: o.timestamp,
+            cpu: o.cpu,
+            memory_total: o.memory_total,
+            memory_used: o.memory_used,
+            network_in: o.network_in,
+            network_out: o.network_out,
+            network_in_speed: o.network_in_speed,
+            network_out_speed: o.network_out_speed,
+            online: o.online,
+            disks: o.disks.into_iter().map(DiskPoint::from).collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct ProbePoint {
+    timestamp: i64,
+    // %
+    success_rate: f64,
+    // ms
+    latency: f64,
+}
+
+impl From<ProbeRecord> for ProbePoint {
+    fn from(o: ProbeRecord) -> Self {
+        Self {
+            timestamp: o.timestamp,
+            success_rate: o.success_rate,
+            latency: o.latency,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct ProbeSeries {
+    // probe4 | probe6
+    kind: String,
+    points: Vec<ProbePoint>,
+}
+
+#[derive(SimpleObject)]
+pub struct History {
+    name: String,
+    alias: String,
+    points: Vec<Point>,
+    probes: Vec<ProbeSeries>,
+}
+
+#[derive(SimpleObject)]
+pub struct Event {
+    ts: i64,
+    // NodeUp | NodeDown | Alert
+    kind: String,
+    name: String,
+    alias: String,
+    message: String,
+}
+
+impl From<EventRecord> for Event {
+    fn from(o: EventRecord) -> Self {
+        Self {
+            ts: o.ts,
+            kind: o.kind,
+            name: o.name,
+            alias: o.alias,
+            message: o.message,
+        }
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    // 实时状态, 可按主机名或分组过滤
+    async fn servers(&self, names: Option<Vec<String>>, gid: Option<String>) -> Vec<Server> {
+        G_STATS_MGR
+            .get()
+            .unwrap()
+            .get_stats()
+            .servers
+            .iter()
+            .filter(|o| names.as_ref().map_or(true, |v| v.contains(&o.name)))
+            .filter(|o| gid.as_ref().map_or(true, |v| v == &o.gid))
+            .map(Server::from)
+            .collect()
+    }
+
+    async fn server(&self, name: String) -> Option<Server> {
+        let stats = G_STATS_MGR.get().unwrap().get_stats();
+        stats.servers.iter().find(|o| o.name == name).map(Server::from)
+    }
+
+    // 历史数据, 默认最近 10 分钟, 最多 30 天, 数据精度与 history.json 相同
+    // 指定 names 时只查询这些主机, 最多 compare::MAX_HOSTS 台
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        start: Option<i64>,
+        end: Option<i64>,
+        names: Option<Vec<String>>,
+    ) -> Result<Vec<History>> {
+        let end = end.unwrap_or_else(|| chrono::Utc::now().timestamp());
+        let start = start.unwrap_or(end - 600).max(end - MAX_RANGE);
+        if start >= end {
+            return Ok(vec![]);
+        }
+        if names.as_ref().is_some_and(|o| o.len() > compare::MAX_HOSTS) {
+            return Err(format!("names must select at most {} hosts", compare::MAX_HOSTS).into());
+        }
+        // 没有查询 probes 时不读探测数据
+        let with_probes = ctx.look_ahead().field("probes").exists();
+        let (stats, mut probes) = tokio::task::spawn_blocking(move || {
+            let mgr = G_STATS_MGR.get().unwrap();
+            let probes = match with_probes {
+                true => mgr.probe_records(start, end)?,
+                false => Default::default(),
+            };
+            let stats = match names {
+                Some(names) => names
+                    .into_iter()
+                    .map(|name| {
+                        let records = mgr.host_records(&name, start, end)?;
+                        Ok((name, records))
+                    })
+                    .collect::<anyhow::Result<HashMap<_, _>>>()?,
+                None => mgr.history_records(start, end)?,
+            };
+            anyhow::Ok((stats, probes))
+        })
+        .await??;
+
+        let mut result = stats
+            .into_iter()
+            .filter(|(_, records)| !records.is_empty())
+            .map(|(name, records)| {
+                let mut series = probes
+                    .remove(&name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(kind, points)| ProbeSeries {
+                        kind,
+                        points: points.into_iter().map(ProbePoint::from).collect(),
+                    })
+                    .collect::<Vec<_>>();
+                series.sort_by(|a, b| a.kind.cmp(&b.kind));
+                History {
+                    alias: records.last().map(|o| o.alias.to_string()).unwrap_or_default(),
+                    name,
+                    points: records.into_iter().map(Point::from).collect(),
+                    probes: series,
+                }
+            })
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(result)
+    }
+
+    // 最近的上下线事件, 新的在前, 最多 1000 条, 告警只在管理接口展示
+    async fn events(&self, since: Option<i64>, #[graphql(default = 100)] limit: usize) -> Vec<Event> {
+        events::recent(since.unwrap_or(0), limit.min(MAX_EVENTS)).into_iter().map(Event::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_schema() {
+        init(&GraphQL::default());
+        let schema = SCHEMA.get().unwrap();
+        let resp = schema.execute("{ events(limit: 5) { ts kind name } }").await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        assert_eq!(resp.data.into_json().unwrap()["events"], serde_json::json!([]));
+
+        // 一次查询的主机数有上限
+        let names = (0..=compare::MAX_HOSTS).map(|i| format!("\"h{i}\"")).collect::<Vec<_>>().join(",");
+        let resp = schema.execute(format!("{{ history(names: [{names}]) {{ name }} }}")).await;
+        assert!(!resp.errors.is_empty());
+
+        // 超过深度限制
+        let resp = schema
+            .execute("{ __schema { types { fields { type { ofType { ofType { ofType { ofType { ofType { ofType { ofType { name } } } } } } } } } } } }")
+            .await;
+        assert!(!resp.errors.is_empty());
+    }
+}
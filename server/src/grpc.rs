@@ -1,16 +1,30 @@
 // #![allow(unused)]
 use anyhow::Result;
+use futures_util::Stream;
+use std::pin::Pin;
 use std::str::FromStr;
+use tokio::net::TcpListener;
 use tonic::{
-    transport::{Certificate, Identity, Server, ServerTlsConfig},
+    codec::CompressionEncoding,
+    service::interceptor::InterceptedService,
+    transport::{server::TcpIncoming, Certificate, Identity, Server, ServerTlsConfig},
     Request, Response, Status,
 };
 
 use stat_common::server_status;
+use stat_common::server_status::history_server::{History, HistoryServer};
 use stat_common::server_status::server_status_server::{ServerStatus, ServerStatusServer};
-use stat_common::server_status::StatRequest;
+use stat_common::server_status::{HistoryDisk, HistoryPoint, HistoryRequest, HostHistory, StatRequest};
 
+use crate::auth::{self, Forbidden, Reporter};
 use crate::config::Config;
+use crate::db::HostStatRecord;
+use crate::guard;
+use crate::http;
+use crate::labels::Selector;
+use crate::net;
+use crate::payload::ReportAck;
+use crate::queue::Busy;
 use crate::G_CONFIG;
 use crate::G_STATS_MGR;
 
@@ -20,11 +34,35 @@ pub struct ServerStatusSrv {}
 #[tonic::async_trait]
 impl ServerStatus for ServerStatusSrv {
     async fn report(&self, request: Request<StatRequest>) -> Result<Response<server_status::Response>, Status> {
+        let mut ack = ReportAck::default();
+        let reporter = request
+            .extensions()
+            .get::<Reporter>()
+            .cloned()
+            .ok_or_else(|| Status::unauthenticated("invalid user/group && pass"))?;
         if let Some(mgr) = G_STATS_MGR.get() {
             match serde_json::to_value(request.get_ref()) {
-                Ok(v) => {
-                    let _ = mgr.report(v);
-                }
+                // overflow = "block" 时队列满会等待, 不能占用 runtime 的工作线程
+                Ok(v) => match tokio::task::spawn_blocking(move || mgr.report(v, &reporter))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|o| o)
+                {
+                    Ok(o) => ack = o,
+                    Err(err) => {
+                        if let Some(busy) = err.downcast_ref::<Busy>() {
+                            let mut status = Status::resource_exhausted(busy.to_string());
+                            if let Ok(v) = busy.retry_after.to_string().parse() {
+                                status.metadata_mut().insert("retry-after", v);
+                            }
+                            return Err(status);
+                        }
+                        if let Some(forbidden) = err.downcast_ref::<Forbidden>() {
+                            return Err(Status::permission_denied(forbidden.to_string()));
+                        }
+                        return Err(Status::invalid_argument(err.to_string()));
+                    }
+                },
                 Err(err) => {
                     error!("serde_json::to_value err => {:?}", err);
                 }
@@ -34,11 +72,113 @@ impl ServerStatus for ServerStatusSrv {
         Ok(Response::new(server_status::Response {
             code: 0,
             message: "ok".to_string(),
+            interval: ack.interval,
+            server_ts: ack.server_ts,
+            skew: ack.skew,
+            mesh: ack.mesh,
+            watch: ack.watch,
         }))
     }
 }
 
-fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
+// 历史数据查询, 与 history.json 的数据及聚合级别相同, 逐台主机以 protobuf 输出
+#[derive(Default)]
+pub struct HistorySrv {}
+
+type HistoryStream = Pin<Box<dyn Stream<Item = Result<HostHistory, Status>> + Send>>;
+
+fn host_history(name: String, si: bool, interval: i64, records: Vec<HostStatRecord>) -> HostHistory {
+    HostHistory {
+        alias: records.last().map(|o| o.alias.to_string()).unwrap_or_default(),
+        name,
+        si,
+        interval: interval as u32,
+        points: records
+            .into_iter()
+            .map(|o| HistoryPoint {
+                timestamp: o.timestamp,
+                cpu: o.cpu,
+                memory_total: o.memory_total,
+                memory_used: o.memory_used,
+                network_in: o.network_in,
+                network_out: o.network_out,
+                network_in_speed: o.network_in_speed,
+                network_out_speed: o.network_out_speed,
+                online: o.online,
+                disks: o
+                    .disks
+                    .into_iter()
+                    .map(|d| HistoryDisk {
+                        mount_point: d.mount_point,
+                        total: d.total,
+                        used: d.used,
+                        inode_total: d.inode_total,
+                        inode_used: d.inode_used,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+#[tonic::async_trait]
+impl History for HistorySrv {
+    type QueryStream = HistoryStream;
+
+    async fn query(&self, request: Request<HistoryRequest>) -> Result<Response<Self::QueryStream>, Status> {
+        let req = request.into_inner();
+        let mgr = G_STATS_MGR.get().ok_or_else(|| Status::unavailable("not ready"))?;
+        let selector = match req.label.is_empty() {
+            true => None,
+            false => Some(Selector::parse(&req.label).map_err(Status::invalid_argument)?),
+        };
+        let end_time = match req.end_time {
+            0 => chrono::Utc::now().timestamp(),
+            o => o,
+        };
+        let start_time = match req.start_time {
+            0 => end_time - 600,
+            o => o,
+        };
+
+        let (tx, rx) = tokio::sync::mpsc::channel(http::HISTORY_STREAM_BUFFER);
+        http::spawn_history(move || {
+            let names = selector.map(|o| mgr.select(&o));
+            let policy = mgr.resolution_policy();
+            let from = Some(req.cursor.as_str()).filter(|o| !o.is_empty());
+            let result = mgr.stream_records(start_time, end_time, from, 0, |name, records| {
+                if names.as_ref().is_some_and(|names| !names.contains(&name)) {
+                    return true;
+                }
+                let interval = policy(&name).pick_interval(end_time - start_time);
+                let si = mgr.si(&name);
+                tx.blocking_send(Ok(host_history(name, si, interval, records))).is_ok()
+            });
+            if let Err(err) = result {
+                error!("Failed to stream grpc history: {}", err);
+                let _ = tx.blocking_send(Err(Status::internal(err.to_string())));
+            }
+        });
+        let stream = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|o| (o, rx)) });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn check_auth(mut req: Request<()>) -> Result<Request<()>, Status> {
+    let real_ip = req
+        .metadata()
+        .get(guard::real_ip_header())
+        .and_then(|v| v.to_str().ok());
+    let src = guard::source(req.remote_addr().map(|o| o.ip()), real_ip);
+    if let Some(remaining) = guard::check(&src) {
+        let mut status = Status::resource_exhausted("too many failed authentications");
+        if let Ok(v) = remaining.to_string().parse() {
+            status.metadata_mut().insert("retry-after", v);
+        }
+        return Err(status);
+    }
+
     let mut group_auth = false;
     req.metadata().get("ssr-auth").map(|v| {
         v.to_str().map(|s| {
@@ -48,18 +188,29 @@ fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
 
     match req.metadata().get("authorization") {
         Some(token) => {
-            let tuple = token.to_str().unwrap_or("").split("@_@").collect::<Vec<_>>();
+            let token = token.to_str().unwrap_or("");
+            // 主机令牌 authorization: Bearer ssr_xxx, 与 http 上报共用
+            if let Some(token) = token.strip_prefix("Bearer ") {
+                if let Some(reporter) = auth::check_token(token.trim()) {
+                    req.extensions_mut().insert(reporter);
+                    return Ok(req);
+                }
+                guard::failed(&src, auth::TOKEN_USER, false);
+                return Err(Status::unauthenticated("invalid token"));
+            }
+
+            // 兼容 user@_@pass
+            let tuple = token.split("@_@").collect::<Vec<_>>();
 
             if tuple.len() == 2 {
-                if let Some(cfg) = G_CONFIG.get() {
-                    if group_auth {
-                        if cfg.group_auth(tuple[0], tuple[1]) {
-                            return Ok(req);
-                        }
-                    } else if cfg.auth(tuple[0], tuple[1]) {
-                        return Ok(req);
-                    }
+                if let Some(reporter) = G_CONFIG
+                    .get()
+                    .and_then(|cfg| auth::check(cfg, tuple[0], tuple[1], group_auth))
+                {
+                    req.extensions_mut().insert(reporter);
+                    return Ok(req);
                 }
+                guard::failed(&src, tuple[0], group_auth);
             }
 
             Err(Status::unauthenticated("invalid user/group && pass"))
@@ -69,10 +220,24 @@ fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
     }
 }
 
-pub async fn serv_grpc(cfg: &Config) -> anyhow::Result<()> {
-    let sock_addr = cfg.grpc_addr.parse().unwrap();
+fn incoming(listener: TcpListener) -> anyhow::Result<TcpIncoming> {
+    TcpIncoming::from_listener(listener, true, None).map_err(|e| anyhow::anyhow!(e))
+}
+
+// listener 由 main 在降权前创建
+pub async fn serv_grpc(cfg: &Config, listener: TcpListener) -> anyhow::Result<()> {
+    let sock_addr = net::parse_addr(&cfg.grpc_addr)?;
     let sss = ServerStatusSrv::default();
-    let svc = ServerStatusServer::with_interceptor(sss, check_auth);
+    let svc = InterceptedService::new(
+        ServerStatusServer::new(sss).max_decoding_message_size(cfg.ingest.max_body_size),
+        check_auth,
+    );
+    // 不需要认证, 与 history.json 一样公开
+    let history = cfg.grpc_history.then(|| {
+        HistoryServer::new(HistorySrv::default())
+            .send_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Gzip)
+    });
 
     if cfg.grpc_tls > 0 {
         let mut proto = " + TLS";
@@ -92,7 +257,8 @@ pub async fn serv_grpc(cfg: &Config) -> anyhow::Result<()> {
         Server::builder()
             .tls_config(tls)?
             .add_service(svc)
-            .serve(sock_addr)
+            .add_optional_service(history)
+            .serve_with_incoming(incoming(listener)?)
             .await
             .map_err(anyhow::Error::new)
     } else {
@@ -100,8 +266,46 @@ pub async fn serv_grpc(cfg: &Config) -> anyhow::Result<()> {
         Server::builder()
             .accept_http1(true)
             .add_service(svc)
-            .serve(sock_addr)
+            .add_optional_service(history)
+            .serve_with_incoming(incoming(listener)?)
             .await
             .map_err(anyhow::Error::new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DiskRecord;
+
+    #[test]
+    fn test_host_history() {
+        let record = |timestamp, alias: &str| HostStatRecord {
+            timestamp,
+            alias: alias.to_string(),
+            cpu: 12.5,
+            memory_total: 1024,
+            memory_used: 512,
+            network_in: 0,
+            network_out: 0,
+            network_in_speed: 0,
+            network_out_speed: 0,
+            online: true,
+            disks: vec![DiskRecord {
+                timestamp,
+                mount_point: "/".to_string(),
+                total: 100,
+                used: 40,
+                inode_total: 1000,
+                inode_used: 10,
+            }],
+        };
+        let o = host_history("h1".to_string(), true, 5, vec![record(1700000000, "old"), record(1700000300, "web")]);
+        assert_eq!((o.name.as_str(), o.alias.as_str(), o.si, o.interval), ("h1", "web", true, 5));
+        assert_eq!(o.points.len(), 2);
+        assert_eq!(o.points[1].timestamp, 1700000300);
+        assert_eq!(o.points[1].disks[0].used, 40);
+        assert_eq!(o.points[1].disks[0].inode_used, 10);
+        assert!(host_history("h2".to_string(), false, 0, Vec::new()).alias.is_empty());
+    }
+}
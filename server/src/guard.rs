@@ -0,0 +1,211 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::AuthGuard;
+use crate::metrics;
+use crate::notifier;
+use crate::G_CONFIG;
+
+// 来源数超过该值时清理过期的记录
+const GC_SOURCES: usize = 1024;
+// 每个来源最多记录的尝试用户名
+const MAX_USERS: usize = 8;
+
+#[derive(Debug, Default)]
+struct Entry {
+    window_start: u64,
+    failures: u32,
+    blocked_until: u64,
+    // 尝试过的用户名/gid
+    users: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Guard {
+    sources: HashMap<String, Entry>,
+    // 配置中存在的 uid/gid => 累计失败次数
+    users: HashMap<String, u64>,
+}
+
+impl Guard {
+    // 封禁中时返回剩余秒数
+    pub fn blocked(&self, src: &str, now: u64) -> Option<u64> {
+        self.sources
+            .get(src)
+            .filter(|o| o.blocked_until > now)
+            .map(|o| o.blocked_until - now)
+    }
+
+    // 记录一次认证失败, 刚被封禁时返回尝试过的用户名
+    pub fn failure(&mut self, cfg: &AuthGuard, src: &str, user: &str, known: bool, now: u64) -> Option<Vec<String>> {
+        if known {
+            *self.users.entry(user.to_string()).or_default() += 1;
+        }
+        if self.sources.len() > GC_SOURCES {
+            self.sources
+                .retain(|_, o| o.blocked_until > now || o.window_start + cfg.window > now);
+        }
+
+        let entry = self.sources.entry(src.to_string()).or_default();
+        if entry.blocked_until > now {
+            return None;
+        }
+        if entry.window_start + cfg.window <= now {
+            entry.window_start = now;
+            entry.failures = 0;
+            entry.users.clear();
+        }
+        entry.failures += 1;
+        if entry.users.len() < MAX_USERS {
+            entry.users.insert(user.to_string());
+        }
+        if cfg.max_failures == 0 || entry.failures < cfg.max_failures {
+            return None;
+        }
+        entry.blocked_until = now + cfg.block;
+        entry.failures = 0;
+        Some(std::mem::take(&mut entry.users).into_iter().collect())
+    }
+
+    pub fn to_json(&self, now: u64) -> Value {
+        let mut blocked = self
+            .sources
+            .iter()
+            .filter(|(_, o)| o.blocked_until > now)
+            .map(|(src, o)| (src, o.blocked_until - now))
+            .collect::<Vec<_>>();
+        blocked.sort();
+        let blocked = blocked
+            .into_iter()
+            .map(|(src, remaining)| json!({ "source": src, "remaining": remaining }))
+            .collect::<Vec<_>>();
+        json!({
+            "blocked": blocked,
+            "failures": self.users,
+        })
+    }
+}
+
+static GUARD: Lazy<Mutex<Guard>> = Lazy::new(Default::default);
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+// 来源标识, 配置了 real_ip_header 时优先使用该请求头, x-forwarded-for 取第一个
+pub fn source(peer: Option<IpAddr>, real_ip: Option<&str>) -> String {
+    real_ip
+        .and_then(|o| o.split(',').next())
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(str::to_string)
+        .or_else(|| peer.map(|o| o.to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub fn real_ip_header() -> &'static str {
+    G_CONFIG
+        .get()
+        .map(|o| o.auth_guard.real_ip_header.as_str())
+        .unwrap_or_default()
+}
+
+// 封禁中的来源返回剩余秒数
+pub fn check(src: &str) -> Option<u64> {
+    let cfg = &G_CONFIG.get()?.auth_guard;
+    if !cfg.enabled {
+        return None;
+    }
+    let remaining = GUARD.lock().unwrap().blocked(src, now());
+    if remaining.is_some() {
+        metrics::inc("auth_blocked_requests");
+    }
+    remaining
+}
+
+// 认证失败, 达到阈值时封禁来源并按配置通知
+pub fn failed(src: &str, user: &str, group: bool) {
+    metrics::inc("auth_failed");
+    let Some(cfg) = G_CONFIG.get() else {
+        return;
+    };
+    let known = match group {
+        true => cfg.hosts_group_map.contains_key(user),
+        false => cfg.hosts_map.contains_key(user),
+    };
+    if !known {
+        metrics::inc("auth_failed_unknown_user");
+    }
+    if !cfg.auth_guard.enabled {
+        return;
+    }
+
+    let now = now();
+    let mut guard = GUARD.lock().unwrap();
+    let users = guard.failure(&cfg.auth_guard, src, user, known, now);
+    let blocked = guard.sources.values().filter(|o| o.blocked_until > now).count();
+    drop(guard);
+    metrics::gauge("auth_blocked_sources", blocked as u64);
+
+    if let Some(users) = users {
+        metrics::inc("auth_blocks");
+        let msg = format!(
+            "🚫 {} blocked for {}s after {} failed report authentications ({})",
+            src,
+            cfg.auth_guard.block,
+            cfg.auth_guard.max_failures,
+            users.join(", ")
+        );
+        warn!("{}", msg);
+        if cfg.auth_guard.notify {
+            thread::spawn(move || notifier::alert(&msg));
+        }
+    }
+}
+
+pub fn to_json() -> Value {
+    GUARD.lock().unwrap().to_json(now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure() {
+        let cfg = AuthGuard {
+            enabled: true,
+            max_failures: 3,
+            window: 60,
+            block: 600,
+            ..Default::default()
+        };
+        let mut o = Guard::default();
+        assert!(o.failure(&cfg, "1.1.1.1", "h1", true, 100).is_none());
+        assert!(o.failure(&cfg, "1.1.1.1", "x", false, 110).is_none());
+        // 窗口期外的失败重新计数
+        assert!(o.failure(&cfg, "1.1.1.1", "h1", true, 170).is_none());
+        assert!(o.failure(&cfg, "1.1.1.1", "h2", true, 171).is_none());
+        assert_eq!(
+            o.failure(&cfg, "1.1.1.1", "h1", true, 172),
+            Some(vec!["h1".to_string(), "h2".to_string()])
+        );
+        assert_eq!(o.blocked("1.1.1.1", 172), Some(600));
+        assert!(o.blocked("2.2.2.2", 172).is_none());
+        // 封禁期间不再重复通知
+        assert!(o.failure(&cfg, "1.1.1.1", "h1", true, 173).is_none());
+        assert!(o.blocked("1.1.1.1", 772).is_none());
+        assert_eq!(o.users.get("h1"), Some(&4));
+        assert!(!o.users.contains_key("x"));
+
+        assert_eq!(source(Some([10, 0, 0, 1].into()), None), "10.0.0.1");
+        assert_eq!(source(Some([10, 0, 0, 1].into()), Some("1.2.3.4, 10.0.0.1")), "1.2.3.4");
+        assert_eq!(source(None, Some(" ")), "unknown");
+    }
+}
@@ -0,0 +1,151 @@
+#![deny(warnings)]
+// 联调工具: 临时目录中的 stats.db + 完整的 http 路由 + 模拟客户端, 上报走真实的 /report
+// 开启 harness feature 后可通过 `stat_server harness` 子命令供主题/工具开发者对接真实数据
+// 端到端测试见 tests/harness.rs, 直接启动编译好的 stat_server
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use reqwest::StatusCode;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::db::Database;
+use crate::demo::FakeHost;
+use crate::notifier::{self, Event, Notifier, NOTIFIER_HANDLE};
+use crate::payload::HostStat;
+use crate::{config, http, stats, tasks, G_CONFIG, G_STATS_MGR};
+
+// 全局配置只能设置一次, 同一进程只启动一个
+static HARNESS: OnceCell<Harness> = OnceCell::new();
+
+pub const DEFAULT_CONFIG: &str = r#"
+http_addr = "127.0.0.1:0"
+offline_threshold = 30
+admin_user = "admin"
+admin_pass = "pass"
+hosts = [
+  {name = "h1", password = "p1", alias = "n1", location = "🏠", type = "kvm", labels = "os=debian;env=prod;"},
+  {name = "h2", password = "p2", alias = "n2", location = "🏢", type = "kvm", region = "asia", provider = "aws"},
+  {name = "h3", password = "p3", alias = "n3", location = "us", type = "kvm", notify = false},
+]
+"#;
+
+// 只打印通知内容, 不实际发送
+struct Echo;
+
+impl Notifier for Echo {
+    fn kind(&self) -> &'static str {
+        "harness"
+    }
+
+    fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
+        self.send_notify(format!("{:?} {}", e, stat.name))
+    }
+
+    fn send_notify(&self, content: String) -> Result<()> {
+        eprintln!("🔔 harness notify => {content}");
+        Ok(())
+    }
+}
+
+pub struct Harness {
+    pub addr: SocketAddr,
+    rt: Runtime,
+    client: reqwest::Client,
+}
+
+// 以 config 启动服务端, 再次调用返回已启动的实例
+pub fn start(config: &str) -> Result<&'static Harness> {
+    HARNESS.get_or_try_init(|| {
+        let dir = std::env::temp_dir().join(format!("ss-harness-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut cfg = config::from_str(config).ok_or_else(|| anyhow!("invalid config"))?;
+        cfg.db_path = dir.join("stats.db").to_string_lossy().to_string();
+        G_CONFIG.set(cfg).map_err(|_| anyhow!("G_CONFIG already set"))?;
+        let cfg = G_CONFIG.get().unwrap();
+        http::init_jinja_tpl()?;
+
+        let rt = Builder::new_multi_thread().worker_threads(2).enable_all().build()?;
+        *NOTIFIER_HANDLE.lock().unwrap() = Some(rt.handle().clone());
+        let notifies: Arc<Mutex<Vec<Box<dyn Notifier + Send>>>> = Arc::new(Mutex::new(vec![Box::new(Echo)]));
+        notifier::set_notifiers(notifies.clone());
+
+        let mut mgr = stats::StatsMgr::new(Arc::new(Database::new(&cfg.db_path)?));
+        mgr.init(cfg, notifies)?;
+        G_STATS_MGR.set(mgr).map_err(|_| anyhow!("G_STATS_MGR already set"))?;
+        tasks::init(cfg.db.task_alert_after);
+        let history_runtime = Builder::new_multi_thread().worker_threads(2).enable_all().build()?;
+        http::init_history_runtime(history_runtime).map_err(|_| anyhow!("history runtime already set"))?;
+
+        let listener = rt.block_on(tokio::net::TcpListener::bind(&cfg.http_addr.0[0].addr))?;
+        let addr = listener.local_addr()?;
+        rt.spawn(async move {
+            let app = crate::create_app_router(true).into_make_service_with_connect_info::<SocketAddr>();
+            axum::serve(listener, app).await.unwrap();
+        });
+        eprintln!("✨ harness listening on http://{addr}, workdir {}", dir.display());
+
+        Ok(Harness {
+            addr,
+            rt,
+            client: reqwest::Client::new(),
+        })
+    })
+}
+
+impl Harness {
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn agent(&self, name: &str, password: &str) -> Agent<'_> {
+        Agent {
+            harness: self,
+            name: name.to_string(),
+            password: password.to_string(),
+            host: FakeHost::new(name),
+        }
+    }
+}
+
+// 模拟客户端, 上报内容与 --demo 的虚拟主机相同
+pub struct Agent<'a> {
+    harness: &'a Harness,
+    pub name: String,
+    pub password: String,
+    host: FakeHost,
+}
+
+impl Agent<'_> {
+    pub fn report(&mut self) -> Result<StatusCode> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let data = self.host.sample(now);
+        let h = self.harness;
+        h.rt.block_on(async {
+            let resp = h
+                .client
+                .post(h.url("/report"))
+                .basic_auth(&self.name, Some(&self.password))
+                .json(&data)
+                .send()
+                .await?;
+            Ok(resp.status())
+        })
+    }
+}
+
+// `stat_server harness`, 按 interval 为配置中的每台主机持续上报
+pub fn serve(config: &str, interval: u64) -> Result<()> {
+    let h = start(config)?;
+    let hosts = G_CONFIG.get().unwrap().hosts.clone();
+    let mut agents = hosts.iter().map(|o| h.agent(&o.name, &o.password)).collect::<Vec<_>>();
+    loop {
+        for agent in agents.iter_mut() {
+            if let Err(err) = agent.report() {
+                error!("harness report {} error => {:?}", agent.name, err);
+            }
+        }
+        std::thread::sleep(Duration::from_secs(interval.max(1)));
+    }
+}
@@ -0,0 +1,131 @@
+#![deny(warnings)]
+// /healthz 存活检查, /readyz 就绪检查, 供容器编排及外部监控使用, 不需要认证
+use axum::{http::StatusCode, Json};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::tasks::{self, TaskStatus};
+use crate::G_STATS_MGR;
+
+// 入库队列超过容量的该比例时视为饱和
+const QUEUE_SATURATED: f64 = 0.9;
+// 写锁被长时间占用时视为不可写
+const DB_TIMEOUT: Duration = Duration::from_secs(3);
+// 数据库检查需要写锁, 结果缓存一段时间, 避免频繁探测与入库争锁
+const DB_CHECK_TTL: Duration = Duration::from_secs(5);
+
+// 最近一次数据库检查的时间及结果
+static DB_CHECK: Lazy<tokio::sync::Mutex<Option<(Instant, Value)>>> = Lazy::new(Default::default);
+// 超时后后台的检查仍在执行, 结束前不再发起新的检查
+static DB_CHECKING: AtomicBool = AtomicBool::new(false);
+
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok", "version": env!("APP_VERSION") }))
+}
+
+fn queue_check(depth: Option<(usize, usize)>) -> Value {
+    match depth {
+        Some((len, capacity)) => json!({
+            "ok": (len as f64) < capacity as f64 * QUEUE_SATURATED,
+            "depth": len,
+            "capacity": capacity,
+        }),
+        None => json!({ "ok": true }),
+    }
+}
+
+// 从未运行(刚启动或非 leader)视为正常, 最近一次失败则不正常
+fn task_check(status: Option<TaskStatus>) -> Value {
+    match status {
+        Some(o) => json!({
+            "ok": o.consecutive_failures == 0,
+            "consecutive_failures": o.consecutive_failures,
+            "last_success": o.last_success,
+        }),
+        None => json!({ "ok": true }),
+    }
+}
+
+fn summarize(checks: Value) -> (StatusCode, Value) {
+    let ok = checks
+        .as_object()
+        .map(|o| o.values().all(|v| v["ok"] == true))
+        .unwrap_or(false);
+    let status = match ok {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let resp = json!({ "status": if ok { "ok" } else { "fail" }, "checks": checks });
+    (status, resp)
+}
+
+async fn db_check() -> Value {
+    let mut cache = DB_CHECK.lock().await;
+    if let Some((ts, o)) = cache.as_ref() {
+        if ts.elapsed() < DB_CHECK_TTL {
+            return o.clone();
+        }
+    }
+    let o = if DB_CHECKING.swap(true, Ordering::SeqCst) {
+        json!({ "ok": false, "error": "timeout" })
+    } else {
+        let db = G_STATS_MGR.get().unwrap().db();
+        let writable = tokio::task::spawn_blocking(move || {
+            let r = db.check_writable();
+            DB_CHECKING.store(false, Ordering::SeqCst);
+            r
+        });
+        match tokio::time::timeout(DB_TIMEOUT, writable).await {
+            Ok(Ok(Ok(()))) => json!({ "ok": true }),
+            Ok(Ok(Err(e))) => {
+                warn!("readyz db check error => {:?}", e);
+                json!({ "ok": false, "error": "not writable" })
+            }
+            Ok(Err(e)) => {
+                warn!("readyz db check error => {:?}", e);
+                json!({ "ok": false, "error": "not writable" })
+            }
+            Err(_) => json!({ "ok": false, "error": "timeout" }),
+        }
+    };
+    *cache = Some((Instant::now(), o.clone()));
+    o
+}
+
+pub async fn readyz() -> (StatusCode, Json<Value>) {
+    let mgr = G_STATS_MGR.get().unwrap();
+    let checks = json!({
+        "db": db_check().await,
+        "aggregation": task_check(tasks::get("aggregation")),
+        "ingest_queue": queue_check(mgr.queue_depth()),
+    });
+    let (status, resp) = summarize(checks);
+    (status, Json(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready() {
+        assert_eq!(queue_check(Some((10, 100)))["ok"], true);
+        assert_eq!(queue_check(Some((90, 100)))["ok"], false);
+        let failed = TaskStatus {
+            consecutive_failures: 1,
+            last_error: Some("disk I/O error".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(task_check(Some(failed.clone()))["ok"], false);
+        assert_eq!(task_check(None)["ok"], true);
+
+        let (status, resp) = summarize(json!({ "db": { "ok": true }, "ingest_queue": queue_check(None) }));
+        assert_eq!((status, resp["status"].as_str()), (StatusCode::OK, Some("ok")));
+        let (status, resp) = summarize(json!({ "db": { "ok": true }, "aggregation": task_check(Some(failed)) }));
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        // 错误详情只记录在日志中, 不对外暴露
+        assert!(resp["checks"]["aggregation"].get("last_error").is_none());
+    }
+}
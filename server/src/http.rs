@@ -1,10 +1,9 @@
-use crate::assets::Asset;
 use tokio::task::JoinHandle;
 use once_cell::sync::OnceCell;
 use tokio::runtime::Runtime;
 use axum::extract::{Path, Query};
 use axum::{
-    body::Bytes,
+    body::{Body, Bytes},
     http::{header, header::HeaderMap, StatusCode, Uri},
     response::{IntoResponse, Response},
     Json,
@@ -12,29 +11,125 @@ use axum::{
 use minijinja::context;
 use prettytable::Table;
 use prost::Message;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fmt::Write as _;
 
-use stat_common::{server_status::StatRequest, utils::bytes2human};
+use stat_common::{
+    server_status::{DiskInfo, StatRequest},
+    utils::bytes2human,
+};
 
+use crate::alerts;
+use crate::announce::Announcement;
 use crate::auth;
+use crate::calendar;
+use crate::certs;
+use crate::chart;
+use crate::columnar;
+use crate::compare;
+use crate::config;
+use crate::domains;
+use crate::db::Clamp;
+use crate::encoding::Format;
+use crate::events;
+use crate::expiry;
+use crate::feed;
+use crate::guard;
 use crate::jinja;
-use crate::jwt;
+use crate::labels::Selector;
+use crate::mesh;
+use crate::metrics;
+use crate::mirror;
+use crate::net;
+use crate::notifier;
+use crate::payload::StatsResp;
+use crate::queue::Busy;
+use crate::summary;
+use crate::tasks;
+use crate::templates;
 use crate::G_CONFIG;
 use crate::G_STATS_MGR;
 
 const KIND: &str = "http";
 
+// ?label=env:prod,provider:aws 标签选择器, 未指定时为 None
+#[allow(clippy::result_large_err)]
+fn label_selector(params: &HashMap<String, String>) -> Result<Option<Selector>, Response> {
+    match params.get("label").map(|s| Selector::parse(s)) {
+        Some(Ok(o)) if !o.is_empty() => Ok(Some(o)),
+        Some(Err(err)) => Err((StatusCode::BAD_REQUEST, err).into_response()),
+        _ => Ok(None),
+    }
+}
+
 // 新的接口：只返回实时数据，不需要参数
-pub async fn get_stats_json() -> impl IntoResponse {
-    // 获取当前状态
-    let current_stats = G_STATS_MGR.get().unwrap().get_stats_json();
-    
-    (
-        [(header::CONTENT_TYPE, "application/json")],
-        current_stats,
-    )
+pub async fn get_stats_json(headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Response {
+    let format = Format::from_headers(&headers);
+    let selector = match label_selector(&params) {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    // 按标签筛选时单独序列化
+    if let Some(selector) = selector {
+        let stats = G_STATS_MGR.get().unwrap().get_stats();
+        let resp = StatsResp {
+            updated: stats.updated,
+            servers: stats.servers.iter().filter(|o| selector.matches(|k| o.label(k))).cloned().collect(),
+            announcements: stats.announcements.clone(),
+        };
+        return match format.encode(&resp) {
+            Ok(body) => format.response(body),
+            Err(err) => {
+                error!("encode stats error => {:?}", err);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+    // 获取当前状态, 预先渲染好的 Bytes, 无需拷贝
+    match G_STATS_MGR.get().unwrap().get_stats_as(format) {
+        Ok(body) => format.response(body),
+        Err(err) => {
+            error!("encode stats error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 精简格式, 字段名只在表头中出现一次, 适合移动端/低带宽轮询
+pub async fn get_compact_json() -> Response {
+    match G_STATS_MGR.get().unwrap().get_compact_json() {
+        Ok(body) => ([(header::CONTENT_TYPE, "application/json")], body).into_response(),
+        Err(err) => {
+            error!("render compact json error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 按 region/zone 及 provider 分组的汇总 /json/summary.json
+pub async fn get_summary_json(headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Response {
+    let format = Format::from_headers(&headers);
+    let selector = match label_selector(&params) {
+        Ok(o) => o.unwrap_or_default(),
+        Err(resp) => return resp,
+    };
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    let servers = stats
+        .servers
+        .iter()
+        .filter(|o| selector.matches(|k| o.label(k)))
+        .cloned()
+        .collect::<Vec<_>>();
+    let si = G_CONFIG.get().unwrap().site.units.si(false);
+    match format.encode(&summary::summary(&servers, si)) {
+        Ok(body) => format.response(body),
+        Err(err) => {
+            error!("encode summary error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
 }
 
 // 添加全局变量存储历史数据处理线程池
@@ -45,80 +140,253 @@ pub fn init_history_runtime(runtime: Runtime) -> Result<(), Runtime> {
     HISTORY_RUNTIME.set(runtime)
 }
 
+// grpc 历史查询同样使用专用线程池
+pub fn spawn_history(f: impl FnOnce() + Send + 'static) {
+    HISTORY_RUNTIME.get().unwrap().spawn_blocking(f);
+}
+
 // 在历史数据查询函数中使用专用线程池
-pub async fn get_history_stats(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+pub async fn get_history_stats(uri: Uri, headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Response {
+    let format = Format::from_headers(&headers);
+    let cfg = G_CONFIG.get().unwrap();
+    if cfg.mirror.enabled {
+        let body = match mirror::history(&cfg.mirror, uri.query()).await {
+            Ok(body) => format.transcode(&body),
+            Err(err) => {
+                error!("mirror history error => {:?}", err);
+                format
+                    .encode(&json!({ "error": "Failed to get stats from primary", "code": 502 }))
+                    .map(Bytes::from)
+            }
+        };
+        return match body {
+            Ok(body) => format.response(body),
+            Err(_) => StatusCode::BAD_GATEWAY.into_response(),
+        };
+    }
+
+    let selector = match label_selector(&params) {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    // arrow ipc stream, 供 pandas / polars 等直接读取
+    if params.get("format").is_some_and(|o| o == "arrow") {
+        return match columnar::Table::parse(params.get("table").map(String::as_str)) {
+            Ok(table) => stream_arrow(params, selector, table),
+            Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+        };
+    }
+    // json 逐台主机分块输出, 其它格式需要完整的结果再编码
+    if format == Format::Json {
+        return stream_history(params, selector);
+    }
     let params_clone = params.clone();
     
     // 使用专用线程池处理历史数据查询
-    let handle: JoinHandle<([(header::HeaderName, &'static str); 1], String)> = 
+    let handle: JoinHandle<Value> = 
         HISTORY_RUNTIME.get().unwrap().spawn(async move {
-            let now = chrono::Utc::now().timestamp();
-            let start_time = params_clone
-                .get("start_time")
-                .and_then(|s| s.parse::<i64>().ok())
-                .unwrap_or(now - 600);
-            
-            let end_time = params_clone
-                .get("end_time")
-                .and_then(|s| s.parse::<i64>().ok())
-                .unwrap_or(now);
-            
-            match G_STATS_MGR.get().unwrap().get_stats_by_timerange(start_time, end_time) {
-                Ok(stats) => (
-                    [(header::CONTENT_TYPE, "application/json")],
-                    serde_json::to_string(&stats).unwrap_or_else(|_| "{}".to_string()),
-                ),
+            let (start_time, end_time) = history_range(&params_clone);
+            let mgr = G_STATS_MGR.get().unwrap();
+            let cursor = params_clone.get("cursor").map(String::as_str);
+            match mgr.get_stats_by_timerange(start_time, end_time, cursor, true) {
+                Ok(mut stats) => {
+                    if let (Some(selector), Some(servers)) = (selector, stats["servers"].as_array_mut()) {
+                        let names = mgr.select(&selector);
+                        servers.retain(|o| o["name"].as_str().is_some_and(|name| names.contains(name)));
+                    }
+                    stats
+                }
                 Err(e) => {
                     error!("Failed to get stats by timerange: {}", e);
-                    (
-                        [(header::CONTENT_TYPE, "application/json")],
-                        json!({
-                            "error": format!("Failed to get stats: {}", e),
-                            "code": 500
-                        }).to_string(),
-                    )
+                    json!({
+                        "error": format!("Failed to get stats: {}", e),
+                        "code": 500
+                    })
                 }
             }
         });
     
-    match handle.await {
-        Ok(response) => response,
+    let resp = match handle.await {
+        Ok(resp) => resp,
         Err(e) => {
             error!("Thread error: {:?}", e);
-            (
-                [(header::CONTENT_TYPE, "application/json")],
-                json!({
-                    "error": "Internal server error",
-                    "code": 500
-                }).to_string(),
-            )
+            json!({
+                "error": "Internal server error",
+                "code": 500
+            })
+        }
+    };
+    match format.encode(&resp) {
+        Ok(body) => {
+            metrics::observe_bytes("history_json_bytes", body.len() as u64);
+            format.response(body)
+        }
+        Err(err) => {
+            error!("encode history error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
 
-#[allow(unused)]
+// start_time 默认 10 分钟前, end_time 默认当前时间
+fn history_range(params: &HashMap<String, String>) -> (i64, i64) {
+    let now = chrono::Utc::now().timestamp();
+    let parse = |key: &str| params.get(key).and_then(|s| s.parse::<i64>().ok());
+    (parse("start_time").unwrap_or(now - 600), parse("end_time").unwrap_or(now))
+}
+
+// 历史数据流中最多缓存的主机数, 客户端读取慢时查询线程等待
+pub const HISTORY_STREAM_BUFFER: usize = 4;
+
+// 以 chunked 编码逐台主机输出 history.json, 峰值内存与主机数及时间范围无关
+fn stream_history(params: HashMap<String, String>, selector: Option<Selector>) -> Response {
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(HISTORY_STREAM_BUFFER);
+    HISTORY_RUNTIME.get().unwrap().spawn_blocking(move || {
+        let (start_time, end_time) = history_range(&params);
+        let mgr = G_STATS_MGR.get().unwrap();
+        let names = selector.map(|o| mgr.select(&o));
+        let head = format!(r#"{{"updated":{},"servers":["#, chrono::Utc::now().timestamp());
+        let mut size = head.len();
+        if tx.blocking_send(Ok(head.into())).is_err() {
+            return;
+        }
+
+        let mut first = true;
+        let result = mgr.stream_history(start_time, end_time, params.get("cursor").map(String::as_str), |host| {
+            let name = host["name"].as_str().unwrap_or_default();
+            if names.as_ref().is_some_and(|names| !names.contains(name)) {
+                return true;
+            }
+            let mut chunk = match first {
+                true => Vec::new(),
+                false => vec![b','],
+            };
+            first = false;
+            if let Err(err) = serde_json::to_writer(&mut chunk, &host) {
+                error!("encode history of {} error => {:?}", name, err);
+                return false;
+            }
+            size += chunk.len();
+            tx.blocking_send(Ok(chunk.into())).is_ok()
+        });
+
+        let tail = match result {
+            Ok(cursor) => {
+                let mut tail = json!({ "truncated": cursor.is_some() });
+                if let Some(cursor) = cursor {
+                    tail["cursor"] = cursor.into();
+                }
+                // 与开头的对象合并: {"truncated":..} => ],"truncated":..}
+                format!("],{}", &tail.to_string()[1..])
+            }
+            Err(err) => {
+                // 已经开始输出, 中断连接让客户端得到不完整的响应
+                error!("Failed to stream stats by timerange: {}", err);
+                let _ = tx.blocking_send(Err(std::io::Error::other(err.to_string())));
+                return;
+            }
+        };
+        size += tail.len();
+        if tx.blocking_send(Ok(tail.into())).is_ok() {
+            metrics::observe_bytes("history_json_bytes", size as u64);
+        }
+    });
+
+    stream_response(rx, Format::Json.content_type())
+}
+
+// 以 arrow ipc stream 逐台主机输出历史数据, 流中无法附带 cursor, 不限制查询耗时
+fn stream_arrow(params: HashMap<String, String>, selector: Option<Selector>, table: columnar::Table) -> Response {
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(HISTORY_STREAM_BUFFER);
+    HISTORY_RUNTIME.get().unwrap().spawn_blocking(move || {
+        let (start_time, end_time) = history_range(&params);
+        let mgr = G_STATS_MGR.get().unwrap();
+        let names = selector.map(|o| mgr.select(&o));
+        let mut writer = match columnar::Writer::new(table) {
+            Ok(o) => o,
+            Err(err) => {
+                error!("create arrow writer error => {:?}", err);
+                let _ = tx.blocking_send(Err(std::io::Error::other(err.to_string())));
+                return;
+            }
+        };
+        if tx.blocking_send(Ok(writer.take().into())).is_err() {
+            return;
+        }
+
+        let mut size = 0;
+        let from = params.get("cursor").map(String::as_str);
+        let result = mgr.stream_records(start_time, end_time, from, 0, |name, records| {
+            if names.as_ref().is_some_and(|names| !names.contains(&name)) {
+                return true;
+            }
+            match writer.write(&name, &records) {
+                Ok(chunk) => {
+                    size += chunk.len();
+                    tx.blocking_send(Ok(chunk.into())).is_ok()
+                }
+                Err(err) => {
+                    error!("encode arrow history of {} error => {:?}", name, err);
+                    false
+                }
+            }
+        });
+        match result.and_then(|_| writer.finish()) {
+            Ok(tail) => {
+                size += tail.len();
+                if tx.blocking_send(Ok(tail.into())).is_ok() {
+                    metrics::observe_bytes("history_arrow_bytes", size as u64);
+                }
+            }
+            Err(err) => {
+                error!("Failed to stream arrow history: {}", err);
+                let _ = tx.blocking_send(Err(std::io::Error::other(err.to_string())));
+            }
+        }
+    });
+    stream_response(rx, columnar::CONTENT_TYPE)
+}
+
+fn stream_response(rx: tokio::sync::mpsc::Receiver<std::io::Result<Bytes>>, content_type: &'static str) -> Response {
+    let body = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|o| (o, rx)) });
+    ([(header::CONTENT_TYPE, content_type), (header::VARY, "accept")], Body::from_stream(body)).into_response()
+}
+
+// 前端主题使用的站点信息及启用的功能, 不含任何敏感配置
 pub async fn get_site_config_json() -> impl IntoResponse {
-    // TODO
-    ([(header::CONTENT_TYPE, "application/json")], "{}")
+    let cfg = G_CONFIG.get().unwrap();
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    // 未单独指定策略的主机的历史精度, 聚合数据不过期, 开启归档后更早的数据从归档读取
+    let policy = cfg.resolution("", "");
+    Json(json!({
+        "site": &cfg.site,
+        "announcements": stats.announcements,
+        "features": {
+            "mirror": cfg.mirror.enabled,
+            "history": {
+                "raw_retention_days": policy.raw_retention_days,
+                "intervals": policy.intervals,
+                "archive": cfg.archive.enabled,
+                "trends": true,
+            },
+            "websocket": false,
+            "graphql": cfg.graphql.enabled,
+            "probe": cfg.probe.enabled,
+            "mesh": cfg.mesh.enabled,
+            "domains": cfg.domains.enabled,
+            "public_hosts": stats.servers.len(),
+        },
+    }))
 }
 
-pub async fn admin_api(_claims: jwt::Claims, Path(path): Path<String>, Query(params): Query<HashMap<String, String>>) -> Json<Value> {
+pub async fn admin_api(Path(path): Path<String>, Query(params): Query<HashMap<String, String>>) -> Json<Value> {
     match path.as_str() {
         "stats.json" => {
             // 检查是否有时间范围参数
             if params.contains_key("start_time") || params.contains_key("end_time") {
-                let now = chrono::Utc::now().timestamp();
-                let start_time = params
-                    .get("start_time")
-                    .and_then(|s| s.parse::<i64>().ok())
-                    .unwrap_or(now - 600); // 默认10分钟前
-                
-                let end_time = params
-                    .get("end_time")
-                    .and_then(|s| s.parse::<i64>().ok())
-                    .unwrap_or(now);
-                
-                match G_STATS_MGR.get().unwrap().get_stats_by_timerange(start_time, end_time) {
+                let (start_time, end_time) = history_range(&params);
+                let cursor = params.get("cursor").map(String::as_str);
+                match G_STATS_MGR.get().unwrap().get_stats_by_timerange(start_time, end_time, cursor, false) {
                     Ok(stats) => return Json(stats),
                     Err(e) => {
                         error!("Failed to get stats by timerange: {}", e);
@@ -137,6 +405,70 @@ pub async fn admin_api(_claims: jwt::Claims, Path(path): Path<String>, Query(par
             let resp = G_CONFIG.get().unwrap().to_json_value().unwrap();
             return Json(resp);
         }
+        "metrics.json" => {
+            return Json(metrics::snapshot());
+        }
+        "tasks" => {
+            return Json(tasks::snapshot());
+        }
+        // 最近因 group_gc 过期的分组主机
+        "expired.json" => {
+            return Json(json!(expiry::recent()));
+        }
+        // https 证书的剩余天数, 按剩余天数排序, 检查失败的在前
+        "certs.json" => return Json(json!(certs::list())),
+        // 磁盘设备(UUID)与历史数据中磁盘名称的对应
+        "disks.json" => {
+            return match G_STATS_MGR.get().unwrap().db().disk_devices() {
+                Ok(o) => Json(json!(o)),
+                Err(e) => Json(json!({ "error": e.to_string(), "code": 500 })),
+            };
+        }
+        "notifiers.json" => {
+            return Json(json!(notifier::list()));
+        }
+        // 上报认证失败封禁中的来源及各 uid/gid 的累计失败次数
+        "auth_guard.json" => {
+            return Json(guard::to_json());
+        }
+        // 包含服务告警在内的全部事件, ?since=<unix 时间>&limit=100
+        "events.json" => {
+            let since = params.get("since").and_then(|s| s.parse::<i64>().ok()).unwrap_or(0);
+            let limit = params.get("limit").and_then(|s| s.parse::<usize>().ok()).unwrap_or(100).min(1000);
+            return Json(json!(events::recent_all(since, limit)));
+        }
+        "announcements.json" => return Json(json!(G_STATS_MGR.get().unwrap().announcements())),
+        // 静默中的主机, until 为 0 表示已确认
+        "silences.json" => {
+            let o = notifier::silence::list()
+                .into_iter()
+                .map(|(name, until)| json!({ "name": name, "until": until }))
+                .collect::<Vec<_>>();
+            return Json(json!(o));
+        }
+        "alerts.json" => return Json(json!(alerts::list())),
+        // 各通知方式的主机选择器及告警模板
+        "rules.json" => {
+            let o = G_CONFIG
+                .get()
+                .unwrap()
+                .notifier_tables()
+                .iter()
+                .map(|t| {
+                    let get = |key: &str| t.get(key).cloned().unwrap_or_else(|| "".into());
+                    json!({
+                        "name": notifier::name_of(t),
+                        "kind": notifier::kind_of(t),
+                        "enabled": get("enabled"),
+                        "selector": get("selector"),
+                        "online_tpl": get("online_tpl"),
+                        "offline_tpl": get("offline_tpl"),
+                        "custom_tpl": get("custom_tpl"),
+                    })
+                })
+                .collect::<Vec<_>>();
+            return Json(json!(o));
+        }
         _ => {
             //
         }
@@ -145,19 +477,426 @@ pub async fn admin_api(_claims: jwt::Claims, Path(path): Path<String>, Query(par
     Json(json!({ "code": 0, "message": "ok" }))
 }
 
-pub fn init_jinja_tpl() -> Result<(), anyhow::Error> {
-    let detail_data = Asset::get("/jinja/detail.jinja.html").expect("detail.jinja.html not found");
-    let detail_html: String = String::from_utf8(detail_data.data.into()).unwrap();
-    jinja::add_template(KIND, "detail", detail_html);
+// 运行时通过单个通知方式发送测试消息, 返回发送结果
+pub async fn admin_notifier_test(Path(kind): Path<String>) -> (StatusCode, Json<Value>) {
+    let result = tokio::task::spawn_blocking(move || notifier::test(&kind).map(|o| (kind, o))).await;
+
+    match result {
+        Ok(Some((kind, Ok(resp)))) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "notifier": kind, "result": resp })),
+        ),
+        Ok(Some((kind, Err(e)))) => {
+            error!("{} test error => {:?}", kind, e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({ "code": 502, "message": format!("{e:#}"), "notifier": kind })),
+            )
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "code": 404, "message": "notifier not found or not enabled" })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostHidden {
+    pub hidden: bool,
+}
+
+// 隐藏/取消隐藏主机, 照常接收上报及记录历史
+pub async fn admin_host_hidden(
+    Path(name): Path<String>,
+    Json(req): Json<HostHidden>,
+) -> (StatusCode, Json<Value>) {
+    let mgr = G_STATS_MGR.get().unwrap();
+    let known = G_CONFIG.get().unwrap().hosts_map.contains_key(&name)
+        || mgr.get_all_stats().servers.iter().any(|o| o.name == name);
+    if !known {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "code": 404, "message": "host not found" })),
+        );
+    }
+
+    match tokio::task::spawn_blocking(move || mgr.set_hidden(&name, req.hidden).map(|_| name)).await {
+        Ok(Ok(name)) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": name, "hidden": req.hidden })),
+        ),
+        Ok(Err(e)) => {
+            error!("set hidden error => {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "code": 500, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostSecret {
+    #[serde(default)]
+    pub secret: String,
+}
+
+// 设置主机的独立上报密码, 可以在主机首次上报前设置, 为空时清除
+pub async fn admin_host_secret(
+    Path(name): Path<String>,
+    Json(req): Json<HostSecret>,
+) -> (StatusCode, Json<Value>) {
+    let mgr = G_STATS_MGR.get().unwrap();
+    match tokio::task::spawn_blocking(move || mgr.set_secret(&name, &req.secret).map(|_| (name, req.secret))).await {
+        Ok(Ok((name, secret))) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": name, "secret": !secret.is_empty() })),
+        ),
+        Ok(Err(e)) => {
+            error!("set secret error => {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "code": 500, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HostToken {
+    #[serde(default)]
+    pub revoke: bool,
+}
+
+// 签发主机的上报令牌(只返回这一次), 或 {"revoke": true} 吊销
+pub async fn admin_host_token(
+    Path(name): Path<String>,
+    req: Option<Json<HostToken>>,
+) -> (StatusCode, Json<Value>) {
+    let mgr = G_STATS_MGR.get().unwrap();
+    let revoke = req.map(|o| o.revoke).unwrap_or_default();
+    let result = tokio::task::spawn_blocking(move || match revoke {
+        true => mgr.revoke_token(&name).map(|_| (name, None)),
+        false => mgr.issue_token(&name).map(|o| (name, Some(o))),
+    })
+    .await;
+    match result {
+        Ok(Ok((name, token))) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": name, "token": token })),
+        ),
+        Ok(Err(e)) => {
+            error!("host token error => {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "code": 500, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnouncementReq {
+    #[serde(flatten)]
+    pub announcement: Announcement,
+    #[serde(default)]
+    pub delete: bool,
+}
+
+// 新建(id 为 0)/更新/删除站点公告
+pub async fn admin_announcement(Json(req): Json<AnnouncementReq>) -> (StatusCode, Json<Value>) {
+    let mgr = G_STATS_MGR.get().unwrap();
+    let o = req.announcement;
+    if req.delete {
+        return match tokio::task::spawn_blocking(move || mgr.delete_announcement(o.id)).await {
+            Ok(Ok(true)) => (StatusCode::OK, Json(json!({ "code": 0, "message": "ok", "id": o.id }))),
+            Ok(Ok(false)) => (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "code": 404, "message": "announcement not found" })),
+            ),
+            Ok(Err(e)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "code": 500, "message": e.to_string() })),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "code": 500, "message": e.to_string() })),
+            ),
+        };
+    }
+    if let Err(e) = o.validate() {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "code": 400, "message": e })));
+    }
+
+    match tokio::task::spawn_blocking(move || mgr.save_announcement(o)).await {
+        Ok(Ok(Some(o))) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "announcement": o })),
+        ),
+        Ok(Ok(None)) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "code": 404, "message": "announcement not found" })),
+        ),
+        Ok(Err(e)) => {
+            error!("save announcement error => {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "code": 500, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HostSilence {
+    // 90s 30m 2h 1d
+    #[serde(default)]
+    pub duration: String,
+    #[serde(default)]
+    pub ack: bool,
+    #[serde(default)]
+    pub unsilence: bool,
+}
+
+// 静默主机的通知一段时间, 或确认当前告警直到下一次上下线, 只在当前实例内存中生效
+pub async fn admin_host_silence(Path(name): Path<String>, Json(req): Json<HostSilence>) -> (StatusCode, Json<Value>) {
+    if req.unsilence {
+        let removed = notifier::silence::unsilence(&name);
+        return (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": name, "removed": removed })),
+        );
+    }
+    if req.ack {
+        notifier::silence::ack(&name);
+        return (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": name, "until": 0 })),
+        );
+    }
+    match config::parse_duration(&req.duration) {
+        Some(secs) if secs > 0 => {
+            let until = notifier::silence::silence(&name, secs);
+            (
+                StatusCode::OK,
+                Json(json!({ "code": 0, "message": "ok", "name": name, "until": until })),
+            )
+        }
+        _ => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "code": 400, "message": "invalid duration, e.g. 30m 2h 1d" })),
+        ),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HostChaos {
+    #[serde(default)]
+    pub offline: bool,
+    // 10m 2h, 为空时使用 max_duration
+    #[serde(default)]
+    pub duration: String,
+}
+
+// 模拟主机下线或恢复, 只修改内存中的状态, 需在配置中开启 [chaos]
+pub async fn admin_host_chaos(Path(name): Path<String>, Json(req): Json<HostChaos>) -> (StatusCode, Json<Value>) {
+    let cfg = &G_CONFIG.get().unwrap().chaos;
+    if !cfg.enabled {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "code": 403, "message": "chaos is disabled, see [chaos] in config" })),
+        );
+    }
+    let until = match req.offline {
+        true => {
+            let secs = match req.duration.as_str() {
+                "" => cfg.max_duration,
+                o => match config::parse_duration(o) {
+                    Some(secs) if secs > 0 => secs.min(cfg.max_duration),
+                    _ => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            Json(json!({ "code": 400, "message": "invalid duration, e.g. 10m 2h" })),
+                        )
+                    }
+                },
+            };
+            Some(chrono::Utc::now().timestamp() as u64 + secs)
+        }
+        false => None,
+    };
+    match G_STATS_MGR.get().unwrap().chaos(&name, until) {
+        true => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": name, "until": until.unwrap_or_default() })),
+        ),
+        false => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "code": 404, "message": "host not found" })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostRename {
+    pub to: String,
+}
 
-    let map_data = Asset::get("/jinja/map.jinja.html").expect("map.jinja.html not found");
-    let map_html: String = String::from_utf8(map_data.data.into()).unwrap();
-    jinja::add_template(KIND, "map", map_html);
+// 重命名主机, 新名称已存在时合并历史数据
+pub async fn admin_host_rename(
+    Path(name): Path<String>,
+    Json(req): Json<HostRename>,
+) -> (StatusCode, Json<Value>) {
+    if req.to.is_empty() || req.to == name {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "code": 400, "message": "invalid new name" })),
+        );
+    }
 
-    let client_init_sh = Asset::get("/jinja/client-init.jinja.sh").expect("client-init.jinja.sh not found");
-    let client_init_sh_s: String = String::from_utf8(client_init_sh.data.into()).unwrap();
-    jinja::add_template(KIND, "client-init", client_init_sh_s);
-    Ok(())
+    let mgr = G_STATS_MGR.get().unwrap();
+    let to = req.to.to_string();
+    match tokio::task::spawn_blocking(move || mgr.rename_host(&name, &to)).await {
+        Ok(Ok((merged, moved))) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "name": req.to, "merged": merged, "moved": moved })),
+        ),
+        Ok(Err(e)) => {
+            error!("rename host error => {:?}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "code": 400, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiskSeries {
+    pub device_id: String,
+    pub series: String,
+}
+
+// 把磁盘设备之后的数据记到 series 下, 如换盘后新设备沿用原来挂载点的历史数据
+pub async fn admin_host_disk(
+    Path(name): Path<String>,
+    Json(req): Json<DiskSeries>,
+) -> (StatusCode, Json<Value>) {
+    if req.device_id.is_empty() || req.series.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "code": 400, "message": "device_id and series are required" })),
+        );
+    }
+
+    let db = G_STATS_MGR.get().unwrap().db();
+    match tokio::task::spawn_blocking(move || db.set_disk_series(&name, &req.device_id, &req.series)).await {
+        Ok(Ok(())) => (StatusCode::OK, Json(json!({ "code": 0, "message": "ok" }))),
+        Ok(Err(e)) => {
+            error!("set disk series error => {:?}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "code": 400, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryFix {
+    // recompute 时为空表示所有主机
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub start_time: i64,
+    #[serde(default = "now_ts")]
+    pub end_time: i64,
+    // clamp: {"max": {"network_in_speed": 125000000}}
+    #[serde(flatten)]
+    pub clamp: Clamp,
+}
+
+fn now_ts() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+// 删除或修正异常的历史数据, 重建聚合数据: /api/admin/history/delete || clamp || recompute
+pub async fn admin_history(Path(action): Path<String>, Json(req): Json<HistoryFix>) -> (StatusCode, Json<Value>) {
+    if (req.host.is_empty() && action != "recompute") || req.start_time > req.end_time {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "code": 400, "message": "invalid host or time range" })),
+        );
+    }
+
+    let db = G_STATS_MGR.get().unwrap().db();
+    let result = tokio::task::spawn_blocking(move || match action.as_str() {
+        "delete" => db.delete_history(&req.host, req.start_time, req.end_time),
+        "clamp" => db.clamp_history(&req.host, req.start_time, req.end_time, &req.clamp),
+        "recompute" => {
+            let host = Some(req.host.as_str()).filter(|o| !o.is_empty());
+            db.recompute_aggregates(host, req.start_time, req.end_time)
+        }
+        _ => Err(anyhow::anyhow!("unknown action `{}`", action)),
+    })
+    .await;
+
+    match result {
+        Ok(Ok(affected)) => (
+            StatusCode::OK,
+            Json(json!({ "code": 0, "message": "ok", "affected": affected })),
+        ),
+        Ok(Err(e)) => {
+            error!("fix history error => {:?}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "code": 400, "message": e.to_string() })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "code": 500, "message": e.to_string() })),
+        ),
+    }
+}
+
+pub fn init_jinja_tpl() -> Result<(), anyhow::Error> {
+    let cfg = G_CONFIG.get().unwrap();
+    jinja::add_global("site", minijinja::Value::from_serialize(&cfg.site));
+    templates::init(KIND, &cfg.templates_dir)
+}
+
+// 双引号内原样展开也不会被 shell 解释的字符
+fn is_shell_safe(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_alphanumeric() || ":/._-,@%*=+[]~".contains(c))
 }
 
 pub async fn init_client(uri: Uri, req_header: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Response {
@@ -177,11 +916,10 @@ pub async fn init_client(uri: Uri, req_header: HeaderMap, Query(params): Query<H
     // auth
     let mut auth_ok = false;
     if let Some(cfg) = G_CONFIG.get() {
-        if gid.is_empty() {
-            auth_ok = cfg.auth(uid, pass)
-        } else {
-            auth_ok = cfg.group_auth(gid, pass)
-        }
+        auth_ok = match gid.is_empty() {
+            true => auth::check(cfg, uid, pass, false).is_some(),
+            false => auth::check(cfg, gid, pass, true).is_some_and(|o| o.allows(cfg, alias, gid)),
+        };
     }
     if !auth_ok {
         return (StatusCode::UNAUTHORIZED, StatusCode::UNAUTHORIZED.to_string()).into_response();
@@ -213,13 +951,13 @@ pub async fn init_client(uri: Uri, req_header: HeaderMap, Query(params): Query<H
         req_header.get("Host").map(|v| {
             v.to_str().map(|host| {
                 debug!("Http Host => {}", host);
-                domain = host.to_string();
+                domain = net::normalize_host(host);
             })
         });
         req_header.get("x-forwarded-host").map(|v| {
             v.to_str().map(|host| {
                 debug!("x-forwarded-host => {}", host);
-                domain = host.to_string();
+                domain = net::normalize_host(host);
             })
         });
         server_url = format!("{scheme}://{domain}/report");
@@ -321,6 +1059,25 @@ pub async fn init_client(uri: Uri, req_header: HeaderMap, Query(params): Query<H
         let _ = write!(client_opts, r#" --ip-source "{ip_source}""#);
     }
 
+    // 客户端在代理后面, 这几个参数会写入安装脚本, 只接受合法的 url/主机列表/路径
+    let proxy = params.get("proxy").unwrap_or(&invalid);
+    let no_proxy = params.get("no-proxy").unwrap_or(&invalid);
+    let ca_cert = params.get("ca-cert").unwrap_or(&invalid);
+    let proxy_ok = proxy.is_empty()
+        || url::Url::parse(proxy).is_ok_and(|o| matches!(o.scheme(), "http" | "https" | "socks5" | "socks5h"));
+    if !proxy_ok || ![proxy, no_proxy, ca_cert].iter().all(|o| is_shell_safe(o)) {
+        return (StatusCode::BAD_REQUEST, "invalid proxy, no-proxy or ca-cert").into_response();
+    }
+    if !proxy.is_empty() {
+        let _ = write!(client_opts, r#" --proxy "{proxy}""#);
+    }
+    if !no_proxy.is_empty() {
+        let _ = write!(client_opts, r#" --no-proxy "{no_proxy}""#);
+    }
+    if !ca_cert.is_empty() {
+        let _ = write!(client_opts, r#" --ca-cert "{ca_cert}""#);
+    }
+
     jinja::render_template(
         KIND,
         "client-init",
@@ -329,7 +1086,7 @@ pub async fn init_client(uri: Uri, req_header: HeaderMap, Query(params): Query<H
             vnstat => vnstat, weight => weight, cn => cn,
             domain => domain, scheme => scheme,
             server_url => server_url, workspace => workspace,
-            client_opts => client_opts,
+            client_opts => client_opts, proxy => proxy,
             pkg_version => env!("CARGO_PKG_VERSION"),
         ),
         false,
@@ -358,9 +1115,11 @@ pub async fn init_client(uri: Uri, req_header: HeaderMap, Query(params): Query<H
 }
 
 async fn render_jinja_ht_tpl(tag: &'static str) -> Response {
-    let o = G_STATS_MGR.get().unwrap().get_all_info().unwrap();
+    let mgr = G_STATS_MGR.get().unwrap();
+    let o = mgr.get_all_info().unwrap();
+    let summary = summary::summary(&mgr.get_stats().servers, G_CONFIG.get().unwrap().site.units.si(false));
 
-    jinja::render_template(KIND, tag, context!(resp => &o), false)
+    jinja::render_template(KIND, tag, context!(resp => &o, summary => &summary), false)
         .map(|contents| {
             //
             ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], contents).into_response()
@@ -374,6 +1133,338 @@ async fn render_jinja_ht_tpl(tag: &'static str) -> Response {
         )
 }
 
+// 单台主机的小卡片, 用于 iframe 嵌入, 页面内定时拉取 stats.json 刷新
+pub async fn get_widget(Path(host): Path<String>, Query(params): Query<HashMap<String, String>>) -> Response {
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    let stat = match stats.servers.iter().find(|o| o.name == host) {
+        Some(o) => o,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    // 刷新间隔(s)
+    let refresh = params
+        .get("refresh")
+        .and_then(|o| o.parse::<u64>().ok())
+        .unwrap_or(G_CONFIG.get().unwrap().site.refresh)
+        .clamp(1, 3600);
+
+    jinja::render_template(KIND, "widget", context!(host => stat, refresh => refresh), false)
+        .map(|contents| ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], contents).into_response())
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+fn percent(used: u64, total: u64) -> String {
+    match total {
+        0 => "-".to_string(),
+        _ => format!("{:.0}%", used as f64 * 100.0 / total as f64),
+    }
+}
+
+// 已用/总数 (百分比), 没有上报 inode 时为 -
+fn inode_usage(disk: &DiskInfo) -> String {
+    match disk.inode_total {
+        0 => "-".to_string(),
+        total => format!("{}/{} ({})", disk.inode_used, total, percent(disk.inode_used, total)),
+    }
+}
+
+// 主机间延迟矩阵 /json/mesh.json
+pub async fn get_mesh() -> Response {
+    let cfg = &G_CONFIG.get().unwrap().mesh;
+    if !cfg.enabled {
+        return (StatusCode::NOT_FOUND, "mesh is disabled").into_response();
+    }
+    Json(mesh::matrix(cfg.stale)).into_response()
+}
+
+// 域名注册到期时间 /json/domains.json, 按剩余天数排序, 查询失败的在前
+pub async fn get_domains() -> Response {
+    if !G_CONFIG.get().unwrap().domains.enabled {
+        return (StatusCode::NOT_FOUND, "domains is disabled").into_response();
+    }
+    Json(domains::list()).into_response()
+}
+
+// 多台主机同一指标的对齐序列 /json/compare.json?hosts=h1,h2&metric=cpu&start_time=&end_time=
+pub async fn get_compare(headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Response {
+    let format = Format::from_headers(&headers);
+    let mut names = params
+        .get("hosts")
+        .map(|s| s.split(',').map(str::trim).filter(|o| !o.is_empty()).map(str::to_string).collect::<Vec<_>>())
+        .unwrap_or_default();
+    // 未指定 hosts 时按标签选择
+    if names.is_empty() {
+        match label_selector(&params) {
+            Ok(Some(selector)) => {
+                names = G_STATS_MGR.get().unwrap().select(&selector).into_iter().collect();
+                names.sort();
+            }
+            Ok(None) => {}
+            Err(resp) => return resp,
+        }
+    }
+    if names.is_empty() || names.len() > compare::MAX_HOSTS {
+        let msg = format!("hosts or label must select 1 to {} hosts", compare::MAX_HOSTS);
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+    let Some(metric) = compare::Metric::parse(params.get("metric").map(String::as_str).unwrap_or("cpu")) else {
+        return (StatusCode::BAD_REQUEST, "metric must be one of cpu, memory, disk, network_in, network_out").into_response();
+    };
+    let now = chrono::Utc::now().timestamp();
+    let end_time = params.get("end_time").and_then(|s| s.parse::<i64>().ok()).unwrap_or(now);
+    let start_time = params
+        .get("start_time")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(end_time - 600);
+    if start_time >= end_time {
+        return (StatusCode::BAD_REQUEST, "start_time must be less than end_time").into_response();
+    }
+
+    let handle: JoinHandle<anyhow::Result<Value>> = HISTORY_RUNTIME
+        .get()
+        .unwrap()
+        .spawn(async move { G_STATS_MGR.get().unwrap().compare(&names, metric, start_time, end_time) });
+    let resp = match handle.await {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(err)) => {
+            error!("compare error => {:?}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+        }
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    match format.encode(&resp) {
+        Ok(body) => format.response(body),
+        Err(err) => {
+            error!("encode compare error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 按天汇总的长期趋势 /api/trends?host=h1&days=365, 每天一个点, 各指标为 [min, avg, max]
+pub async fn get_trends(headers: HeaderMap, Query(params): Query<HashMap<String, String>>) -> Response {
+    let format = Format::from_headers(&headers);
+    let Some(host) = params.get("host").cloned() else {
+        return (StatusCode::BAD_REQUEST, "host is required").into_response();
+    };
+    let days = params
+        .get("days")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(365)
+        .clamp(1, 3650);
+
+    let handle: JoinHandle<anyhow::Result<Option<Value>>> = HISTORY_RUNTIME
+        .get()
+        .unwrap()
+        .spawn(async move { G_STATS_MGR.get().unwrap().trends(&host, days) });
+    let resp = match handle.await {
+        Ok(Ok(Some(resp))) => resp,
+        Ok(Ok(None)) => return StatusCode::NOT_FOUND.into_response(),
+        Ok(Err(err)) => {
+            error!("trends error => {:?}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+        }
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    match format.encode(&resp) {
+        Ok(body) => format.response(body),
+        Err(err) => {
+            error!("encode trends error => {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 历史数据折线图 /chart/{host}/{metric}.png?range=24h&width=800&height=300
+pub async fn get_chart(Path((host, file)): Path<(String, String)>, Query(params): Query<HashMap<String, String>>) -> Response {
+    let Some(metric) = file.strip_suffix(".png").and_then(chart::Metric::parse) else {
+        return (StatusCode::NOT_FOUND, "metric must be one of cpu, memory, disk, network").into_response();
+    };
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    let Some(alias) = stats.servers.iter().find(|o| o.name == host).map(|o| o.alias.to_string()) else {
+        return (StatusCode::NOT_FOUND, StatusCode::NOT_FOUND.to_string()).into_response();
+    };
+    let range_str = params.get("range").map(String::as_str).unwrap_or("24h");
+    let Some(range) = config::parse_duration(range_str) else {
+        return (StatusCode::BAD_REQUEST, "invalid range, e.g. 30m 24h 7d").into_response();
+    };
+    let range = range.clamp(600, 30 * 86400) as i64;
+    let size = |k: &str, default: usize, max: usize| {
+        params.get(k).and_then(|s| s.parse::<usize>().ok()).unwrap_or(default).clamp(300, max)
+    };
+    let (width, height) = (size("width", 800, 2000), size("height", 300, 1000));
+    // 点阵字体只有 ascii
+    let name = if alias.is_ascii() { alias } else { host.to_string() };
+    let title = chart::title(&name, metric, range_str);
+
+    let handle: JoinHandle<anyhow::Result<Vec<u8>>> = HISTORY_RUNTIME.get().unwrap().spawn(async move {
+        let end = chrono::Utc::now().timestamp();
+        let start = end - range;
+        let records = G_STATS_MGR.get().unwrap().host_records(&host, start, end)?;
+        chart::render(&title, metric, &records, (start, end), (width, height))
+    });
+    match handle.await {
+        Ok(Ok(png)) => (
+            [(header::CONTENT_TYPE, "image/png"), (header::CACHE_CONTROL, "max-age=60")],
+            png,
+        )
+            .into_response(),
+        Ok(Err(err)) => {
+            error!("render chart error => {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// 维护窗口及续费日期的 iCal 订阅
+pub async fn get_calendar() -> Response {
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    let maintenance = G_CONFIG.get().map(|cfg| cfg.maintenance.as_slice()).unwrap_or_default();
+    let mut events = calendar::maintenance_events(maintenance, &stats.servers);
+    events.extend(calendar::renewal_events(&stats.servers));
+    (
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar::render(&events, chrono::Utc::now().timestamp()),
+    )
+        .into_response()
+}
+
+// 上下线及告警事件的 RSS
+pub async fn get_feed(req_header: HeaderMap) -> Response {
+    let header_value = |name: &str| req_header.get(name).and_then(|v| v.to_str().ok());
+    let scheme = header_value("x-forwarded-proto").unwrap_or("http");
+    let domain = header_value("x-forwarded-host")
+        .or_else(|| header_value("Host"))
+        .map(net::normalize_host)
+        .unwrap_or_else(|| "localhost".to_string());
+    let site = format!("{scheme}://{domain}");
+
+    match tokio::task::spawn_blocking(|| events::recent(0, feed::ITEMS)).await {
+        Ok(events) => (
+            [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+            feed::render(&site, &events),
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+fn txt_response(table: Table) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], table.to_string()).into_response()
+}
+
+// 纯文本的主机列表, 适合 `watch curl` 及小尺寸屏幕, 只包含公开的字段
+pub async fn get_txt(Query(params): Query<HashMap<String, String>>) -> Response {
+    let selector = match label_selector(&params) {
+        Ok(o) => o.unwrap_or_default(),
+        Err(resp) => return resp,
+    };
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Name", "Loc", "Status", "Uptime", "Load", "CPU", "RAM", "Disk", "Net ↓|↑", "Month ↓|↑"]);
+    for host in stats.servers.iter().filter(|o| selector.matches(|k| o.label(k))) {
+        let online = host.online4 || host.online6;
+        table.add_row(row![
+            host.alias,
+            host.location,
+            if online { "up" } else { "down" },
+            host.uptime_str,
+            format!("{:.2}", host.load_1),
+            format!("{:.0}%", host.cpu),
+            percent(host.memory_used, host.memory_total),
+            percent(host.hdd_used, host.hdd_total),
+            format!(
+                "{}|{}",
+                bytes2human(host.network_rx, 1, host.si),
+                bytes2human(host.network_tx, 1, host.si)
+            ),
+            format!(
+                "{}|{}",
+                bytes2human(host.network_in.saturating_sub(host.last_network_in), 1, host.si),
+                bytes2human(host.network_out.saturating_sub(host.last_network_out), 1, host.si)
+            ),
+        ]);
+    }
+    txt_response(table)
+}
+
+pub async fn get_txt_host(Path(name): Path<String>) -> Response {
+    let stats = G_STATS_MGR.get().unwrap().get_stats();
+    let host = match stats.servers.iter().find(|o| o.name == name) {
+        Some(o) => o,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    // memory: KiB, hdd: MiB
+    let unit: u64 = if host.si { 1000 } else { 1024 };
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP);
+    table.add_row(row!["Name", host.alias]);
+    table.add_row(row!["Location", host.location]);
+    table.add_row(row!["Status", if host.online4 || host.online6 { "up" } else { "down" }]);
+    table.add_row(row!["Uptime", host.uptime_str]);
+    table.add_row(row!["Load", format!("{:.2} {:.2} {:.2}", host.load_1, host.load_5, host.load_15)]);
+    table.add_row(row!["CPU", format!("{:.1}%", host.cpu)]);
+    table.add_row(row![
+        "RAM",
+        format!(
+            "{} / {} ({})",
+            bytes2human(host.memory_used * unit, 1, host.si),
+            bytes2human(host.memory_total * unit, 1, host.si),
+            percent(host.memory_used, host.memory_total)
+        )
+    ]);
+    table.add_row(row![
+        "Swap",
+        format!(
+            "{} / {}",
+            bytes2human(host.swap_used * unit, 1, host.si),
+            bytes2human(host.swap_total * unit, 1, host.si)
+        )
+    ]);
+    table.add_row(row![
+        "Disk",
+        format!(
+            "{} / {} ({})",
+            bytes2human(host.hdd_used * unit * unit, 1, host.si),
+            bytes2human(host.hdd_total * unit * unit, 1, host.si),
+            percent(host.hdd_used, host.hdd_total)
+        )
+    ]);
+    for disk in &host.disks {
+        table.add_row(row![
+            format!("  {}", disk.mount_point),
+            format!(
+                "{} / {} ({})",
+                bytes2human(disk.used, 1, host.si),
+                bytes2human(disk.total, 1, host.si),
+                percent(disk.used, disk.total)
+            )
+        ]);
+    }
+    table.add_row(row![
+        "Net",
+        format!(
+            "↓{}/s ↑{}/s",
+            bytes2human(host.network_rx, 1, host.si),
+            bytes2human(host.network_tx, 1, host.si)
+        )
+    ]);
+    table.add_row(row![
+        "Month",
+        format!(
+            "↓{} ↑{}",
+            bytes2human(host.network_in.saturating_sub(host.last_network_in), 1, host.si),
+            bytes2human(host.network_out.saturating_sub(host.last_network_out), 1, host.si)
+        )
+    ]);
+    table.add_row(row!["TCP/UDP", format!("{}/{}", host.tcp_count, host.udp_count)]);
+    table.add_row(row!["Proc/Thread", format!("{}/{}", host.process_count, host.thread_count)]);
+    txt_response(table)
+}
+
 pub async fn get_map(
     // _claims: jwt::Claims
     _auth: auth::AdminAuth,
@@ -385,8 +1476,8 @@ pub async fn get_detail(
     // _claims: jwt::Claims
     _auth: auth::AdminAuth,
 ) -> Response {
-    let resp = G_STATS_MGR.get().unwrap().get_stats();
-    let o = resp.lock().unwrap();
+    // 管理员可以看到隐藏的主机
+    let o = G_STATS_MGR.get().unwrap().get_all_stats();
 
     let mut table = Table::new();
     table.set_titles(row![
@@ -423,7 +1514,7 @@ pub async fn get_detail(
         let mut di = String::new();
         if !host.disks.is_empty() {
             let mut t = Table::new();
-            t.set_titles(row!["名称", "挂载点", "类型", "总容量", "已用", "可用"]);
+            t.set_titles(row!["名称", "挂载点", "类型", "总容量", "已用", "可用", "Inode"]);
             
             // 先显示普通文件系统
             let normal_disks: Vec<_> = host.disks.iter()
@@ -438,6 +1529,7 @@ pub async fn get_detail(
                     bytes2human(disk.total, 2, host.si),
                     bytes2human(disk.used, 2, host.si),
                     bytes2human(disk.free, 2, host.si),
+                    inode_usage(disk),
                 ]);
             }
             
@@ -447,7 +1539,7 @@ pub async fn get_detail(
                 .collect();
             
             if !zfs_pools.is_empty() {
-                t.add_row(row!["--- ZFS 存储池 ---", "---", "---", "---", "---", "---"]);
+                t.add_row(row!["--- ZFS 存储池 ---", "---", "---", "---", "---", "---", "---"]);
                 
                 for pool in zfs_pools {
                     let usage_percent = if pool.total > 0 {
@@ -466,6 +1558,7 @@ pub async fn get_detail(
                             usage_percent
                         ),
                         bytes2human(pool.free, 2, host.si),
+                        "-",
                     ]);
                 }
             }
@@ -540,7 +1633,7 @@ pub async fn get_detail(
 }
 
 // report
-pub async fn report(_auth: auth::HostAuth, req_header: HeaderMap, body: Bytes) -> impl IntoResponse {
+pub async fn report(auth::HostAuth(reporter): auth::HostAuth, req_header: HeaderMap, body: Bytes) -> Response {
     let mut json_data: Option<serde_json::Value> = None;
 
     let content_type_header = req_header.get(header::CONTENT_TYPE);
@@ -567,20 +1660,32 @@ pub async fn report(_auth: auth::HostAuth, req_header: HeaderMap, body: Bytes) -
                 }
             }
         } else {
-            return StatusCode::UNSUPPORTED_MEDIA_TYPE;
+            return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
         }
     }
 
     if json_data.is_none() {
         error!("{}", "Invalid json data!");
-        return StatusCode::BAD_REQUEST;
+        return StatusCode::BAD_REQUEST.into_response();
     }
 
     if let Some(mgr) = G_STATS_MGR.get() {
-        if mgr.report(json_data.unwrap()).is_err() {
-            return StatusCode::BAD_REQUEST;
-        }
+        // overflow = "block" 时队列满会等待, 不能占用 runtime 的工作线程
+        let data = json_data.unwrap();
+        let result = tokio::task::spawn_blocking(move || mgr.report(data, &reporter)).await;
+        return match result.map_err(anyhow::Error::from).and_then(|o| o) {
+            Ok(ack) => Json(ack).into_response(),
+            Err(err) => match err.downcast_ref::<Busy>() {
+                Some(busy) => (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, busy.retry_after.to_string())],
+                )
+                    .into_response(),
+                None if err.is::<auth::Forbidden>() => StatusCode::FORBIDDEN.into_response(),
+                None => StatusCode::BAD_REQUEST.into_response(),
+            },
+        };
     }
 
-    StatusCode::OK
+    StatusCode::OK.into_response()
 }
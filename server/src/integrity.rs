@@ -0,0 +1,233 @@
+#![deny(warnings)]
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection, ErrorCode, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::config::Db;
+use crate::metrics;
+use crate::migrations;
+
+// 数据库损坏时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnCorruption {
+    // 尽量把可读的数据导入新库, 损坏的库改名保留
+    #[default]
+    Recover,
+    // 损坏的库改名保留, 使用空库启动
+    Move,
+    // 退出, 由运维人员处理
+    Exit,
+    // 只记录日志
+    Ignore,
+}
+
+// PRAGMA integrity_check, 返回发现的问题, 为空表示正常
+pub fn check(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    let problems = rows
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|o| o != "ok")
+        .collect::<Vec<_>>();
+    Ok(problems)
+}
+
+fn check_file(path: &str) -> Result<Vec<String>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    check(&conn)
+}
+
+// 只有 SQLITE_CORRUPT/SQLITE_NOTADB 表示文件损坏, 忙/无权限/只读挂载等错误不能按损坏处理
+fn is_corruption(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _)) if matches!(e.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+    )
+}
+
+// 启动时检查, 有损坏并已处理时返回需要通知运维的消息
+pub fn check_on_startup(path: &str, cfg: &Db) -> Result<Option<String>> {
+    if !cfg.integrity_check || !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    // 文件头损坏时 open/prepare 本身就会失败, 同样视为损坏, 其它错误启动失败
+    let problems = match check_file(path) {
+        Ok(o) if o.is_empty() => return Ok(None),
+        Ok(o) => o,
+        Err(err) if is_corruption(&err) => vec![err.to_string()],
+        Err(err) => return Err(err.context(format!("{path} integrity check error"))),
+    };
+    metrics::inc("db_integrity_failed");
+    error!("{} integrity check failed => {:?}", path, problems);
+    let summary = problems.iter().take(5).cloned().collect::<Vec<_>>().join("; ");
+
+    let msg = match cfg.on_corruption {
+        OnCorruption::Ignore => format!("❗{path} is corrupted, ignored: {summary}"),
+        OnCorruption::Exit => return Err(anyhow::anyhow!("{} is corrupted: {}", path, summary)),
+        OnCorruption::Move => {
+            let backup = move_aside(path)?;
+            format!("❗{path} is corrupted and moved to {backup}, start with an empty database: {summary}")
+        }
+        OnCorruption::Recover => {
+            let recovered = format!("{path}.recovered");
+            let rows = salvage(path, &recovered)?;
+            let backup = move_aside(path)?;
+            fs::rename(&recovered, path)?;
+            metrics::add("db_recovered_rows", rows as u64);
+            format!("❗{path} is corrupted and moved to {backup}, {rows} rows recovered: {summary}")
+        }
+    };
+    eprintln!("{msg}");
+    Ok(Some(msg))
+}
+
+// 连同 -wal/-shm 一起改名
+fn move_aside(path: &str) -> Result<String> {
+    let backup = format!("{}.corrupt-{}", path, Utc::now().timestamp());
+    for suffix in ["", "-wal", "-shm"] {
+        let src = format!("{path}{suffix}");
+        if Path::new(&src).exists() {
+            fs::rename(&src, format!("{backup}{suffix}"))?;
+        }
+    }
+    Ok(backup)
+}
+
+// 逐表把能读出的数据复制到新库, 整表复制失败时按 rowid 逐行复制
+fn salvage(src: &str, dst: &str) -> Result<usize> {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{dst}{suffix}"));
+    }
+    let mut conn = Connection::open(dst)?;
+    // 临时文件, 完成前不会替换原库, 不需要每次提交都落盘
+    conn.execute_batch("PRAGMA synchronous = OFF; PRAGMA journal_mode = MEMORY;")?;
+    migrations::run(&mut conn)?;
+    if let Err(err) = conn.execute("ATTACH DATABASE ? AS old", params![src]) {
+        error!("attach corrupted database error => {:?}", err);
+        return Ok(0);
+    }
+
+    let tables = {
+        let mut stmt = conn.prepare(
+            "SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'schema_version'",
+        )?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let mut total = 0;
+    for table in tables {
+        let n = match salvage_table(&conn, &table) {
+            Ok(n) => n,
+            Err(err) => {
+                error!("salvage table `{}` error => {:?}", table, err);
+                0
+            }
+        };
+        eprintln!("✨ salvage table `{table}`, {n} rows");
+        total += n;
+    }
+    conn.execute_batch("DETACH DATABASE old")?;
+    Ok(total)
+}
+
+fn salvage_table(conn: &Connection, table: &str) -> Result<usize> {
+    // 只复制新旧表都有的列
+    let columns = |schema: &str| -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(&format!("PRAGMA {schema}.table_info({table})"))?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    };
+    let old_columns = columns("old")?;
+    let cols = columns("main")?
+        .into_iter()
+        .filter(|o| old_columns.contains(o))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if cols.is_empty() {
+        return Ok(0);
+    }
+
+    let bulk = format!("INSERT OR IGNORE INTO main.{table} ({cols}) SELECT {cols} FROM old.{table}");
+    if let Ok(n) = conn.execute(&bulk, []) {
+        return Ok(n);
+    }
+
+    // 逐行复制, 读到损坏的页时停止, sqlite 遇到损坏会回滚整个事务, 所以每行单独提交
+    let mut copied = 0;
+    let mut insert = conn.prepare(&format!(
+        "INSERT OR IGNORE INTO main.{table} ({cols}) SELECT {cols} FROM old.{table} WHERE rowid = ?"
+    ))?;
+    let mut stmt = conn.prepare(&format!("SELECT rowid FROM old.{table}"))?;
+    let mut rows = stmt.query([])?;
+    loop {
+        match rows.next() {
+            Ok(Some(row)) => {
+                let rowid: i64 = row.get(0)?;
+                if let Ok(n) = insert.execute(params![rowid]) {
+                    copied += n;
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                error!("read `{}` error => {:?}", table, err);
+                break;
+            }
+        }
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recover() {
+        let dir = std::env::temp_dir().join(format!("ss-integrity-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stats.db").to_string_lossy().to_string();
+        {
+            let mut conn = Connection::open(&path).unwrap();
+            migrations::run(&mut conn).unwrap();
+            conn.execute("INSERT INTO hosts (name, alias) VALUES ('h1', 'n1')", []).unwrap();
+            assert!(check(&conn).unwrap().is_empty());
+        }
+        let cfg = Db::default();
+        assert!(check_on_startup(&path, &cfg).unwrap().is_none());
+
+        // 破坏文件头
+        let mut data = fs::read(&path).unwrap();
+        data[..16].copy_from_slice(b"not a sqlite db!");
+        fs::write(&path, data).unwrap();
+
+        let msg = check_on_startup(&path, &cfg).unwrap();
+        assert!(msg.is_some());
+        assert!(check_file(&path).unwrap().is_empty());
+        let backups = fs::read_dir(&dir)
+            .unwrap()
+            .filter(|o| o.as_ref().unwrap().file_name().to_string_lossy().contains(".corrupt-"))
+            .count();
+        assert_eq!(backups, 1);
+
+        let exit = Db {
+            on_corruption: OnCorruption::Exit,
+            ..Default::default()
+        };
+        fs::write(&path, b"garbage").unwrap();
+        assert!(check_on_startup(&path, &exit).is_err());
+
+        // 打不开不等于损坏, 不能被移走
+        let dir_path = dir.to_string_lossy().to_string();
+        assert!(check_on_startup(&dir_path, &cfg).is_err());
+        assert!(dir.is_dir());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
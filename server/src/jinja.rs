@@ -1,9 +1,23 @@
 use anyhow::Result;
-use minijinja::{value::Value, Environment};
+use minijinja::{value::Value, AutoEscape, Environment};
 use once_cell::sync::Lazy;
+use stat_common::utils::bytes2human;
+use std::collections::HashSet;
 use std::sync::Mutex;
 
-pub static JINJA_ENV: Lazy<Mutex<Environment>> = Lazy::new(|| Mutex::new(Environment::new()));
+// 按 html 自动转义的模板, 客户端上报的 alias/location 等字段不能注入脚本
+static HTML_TEMPLATES: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+pub static JINJA_ENV: Lazy<Mutex<Environment>> = Lazy::new(|| {
+    let mut env = Environment::new();
+    env.set_auto_escape_callback(|name| match HTML_TEMPLATES.lock().unwrap().contains(name) {
+        true => AutoEscape::Html,
+        false => AutoEscape::None,
+    });
+    // 字节数转可读的值, si 一般传 host.si, {{ host.network_in|human(host.si) }}
+    env.add_filter("human", |v: f64, si: Option<bool>| bytes2human(v as u64, 1, si.unwrap_or(false)));
+    Mutex::new(env)
+});
 
 pub fn add_template<K, T, S>(kind: K, tag: T, tpl: S)
 where
@@ -19,6 +33,21 @@ where
         .unwrap();
 }
 
+// 用户提供的模板, 语法错误时返回错误, 保留已有的同名模板
+pub fn try_add_template(kind: &str, tag: &str, tpl: String, html: bool) -> Result<()> {
+    let name = format!("{kind}.{tag}");
+    if html {
+        HTML_TEMPLATES.lock().unwrap().insert(name.clone());
+    }
+    JINJA_ENV.lock().unwrap().add_template_owned(name, tpl)?;
+    Ok(())
+}
+
+// 所有模板共享的变量, 如 site
+pub fn add_global(name: &'static str, value: Value) {
+    JINJA_ENV.lock().unwrap().add_global(name, value);
+}
+
 pub fn render_template<'a>(kind: &'a str, tag: &'a str, ctx: Value, trim: bool) -> Result<String> {
     let name = format!("{kind}.{tag}");
     Ok(JINJA_ENV
@@ -48,3 +77,45 @@ pub fn render_template<'a>(kind: &'a str, tag: &'a str, ctx: Value, trim: bool)
             Ok("".to_string())
         })?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Asset;
+    use minijinja::context;
+    use serde_json::json;
+
+    #[test]
+    fn test_html_escape() {
+        for tag in ["detail", "map", "widget"] {
+            let data = Asset::get(&format!("/jinja/{tag}.jinja.html")).unwrap();
+            try_add_template("test", tag, String::from_utf8(data.data.into()).unwrap(), true).unwrap();
+        }
+        let evil = "</script><script>alert(1)</script>`${alert(2)}`";
+        let host = json!({
+            "name": "h1",
+            "alias": evil,
+            "location": evil,
+            "ip_info": { "lat": 1.0, "lon": 2.0, "city": evil },
+        });
+        add_global("site", Value::from_serialize(json!({ "title": "t", "announcement": evil })));
+
+        let detail = render_template("test", "detail", context!(pretty_content => evil), false).unwrap();
+        assert!(detail.contains("&lt;&#x2f;script&gt;") && !detail.contains("<script>"));
+        assert!(detail.contains("<title>t</title>"));
+
+        let summary = json!({ "regions": [{ "name": evil, "online": 1, "total": 1, "children": [] }] });
+        let map = render_template("test", "map", context!(resp => json!({ "servers": [host] }), summary), false).unwrap();
+        assert!(map.contains(r#"bindPopup("\u003cpre\u003e"#) && !map.contains("<script>alert"));
+
+        let widget = render_template("test", "widget", context!(host, refresh => 5), false).unwrap();
+        assert!(!widget.contains("<script>alert"));
+    }
+
+    #[test]
+    fn test_human_filter() {
+        add_template("test", "human", "{{ v|human }} {{ v|human(true) }}");
+        let o = render_template("test", "human", context!(v => 1000000), false).unwrap();
+        assert_eq!(o, "976.6K 1.0M");
+    }
+}
@@ -48,6 +48,11 @@ pub async fn authorize(Json(payload): Json<AuthPayload>) -> Result<Json<AuthBody
     Ok(Json(AuthBody::new(token)))
 }
 
+// 不能设置请求头的场景(如浏览器的 WebSocket)通过查询参数传递 token
+pub fn verify(token: &str) -> bool {
+    decode::<Claims>(token, &KEYS.decoding, &Validation::default()).is_ok()
+}
+
 pub struct Keys {
     pub encoding: EncodingKey,
     pub decoding: DecodingKey,
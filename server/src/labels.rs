@@ -0,0 +1,147 @@
+#![deny(warnings)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// 主机标签, 配置中可以写成 "os=centos;ndd=2022/11/25;" 或 { os = "centos", ndd = "2022/11/25" }
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize)]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    pub fn parse(s: &str) -> Self {
+        Self(
+            s.split(';')
+                .filter_map(|kv| kv.split_once('='))
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .filter(|(k, _)| !k.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    // stats.json 等对外格式仍输出 ; 分隔的字符串, 兼容现有主题
+    pub fn serialize_str<S: Serializer>(labels: &Labels, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(labels)
+    }
+}
+
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, v) in self.0.iter() {
+            write!(f, "{k}={v};")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for Labels {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Str(String),
+            Map(BTreeMap<String, String>),
+        }
+        Ok(match Raw::deserialize(d)? {
+            Raw::Str(s) => Labels::parse(&s),
+            Raw::Map(m) => Labels(m),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    // key:value
+    Eq(String, String),
+    // key!:value
+    Ne(String, String),
+    // key, 存在且不为空
+    Exists(String),
+}
+
+// 标签选择器, 逗号分隔的条件全部满足才匹配, 如 env:prod,provider:aws,db,region!:eu
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector(Vec<Term>);
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut terms = Vec::new();
+        for term in s.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            let term = match term.split_once(':') {
+                Some((k, v)) => match k.strip_suffix('!') {
+                    Some(k) => Term::Ne(k.trim().to_string(), v.trim().to_string()),
+                    None => Term::Eq(k.trim().to_string(), v.trim().to_string()),
+                },
+                None => Term::Exists(term.to_string()),
+            };
+            match &term {
+                Term::Eq(k, _) | Term::Ne(k, _) | Term::Exists(k) if k.is_empty() => {
+                    return Err(format!("invalid selector term `{s}`, expect key:value"));
+                }
+                _ => terms.push(term),
+            }
+        }
+        Ok(Self(terms))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // get 按 key 取值, 主机的内置字段与标签见 HostStat::label
+    pub fn matches<'a>(&self, get: impl Fn(&str) -> Option<&'a str>) -> bool {
+        self.0.iter().all(|term| match term {
+            Term::Eq(k, v) => get(k) == Some(v.as_str()),
+            Term::Ne(k, v) => get(k) != Some(v.as_str()),
+            Term::Exists(k) => get(k).is_some_and(|o| !o.is_empty()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let labels = Labels::parse("os=centos; ndd=2022/11/25;spec=2C/4G/60G;bad;");
+        assert_eq!(labels.iter().count(), 3);
+        assert_eq!(labels.get("ndd"), Some("2022/11/25"));
+        assert_eq!(labels.to_string(), "ndd=2022/11/25;os=centos;spec=2C/4G/60G;");
+
+        #[derive(Deserialize)]
+        struct Host {
+            labels: Labels,
+        }
+        let a: Host = toml::from_str(r#"labels = "env=prod;""#).unwrap();
+        let b: Host = toml::from_str(r#"labels = { env = "prod" }"#).unwrap();
+        assert_eq!(a.labels, b.labels);
+    }
+
+    #[test]
+    fn test_selector() {
+        let labels = Labels::parse("env=prod;provider=aws;db=;");
+        let get = |k: &str| labels.get(k);
+        assert!(Selector::parse("").unwrap().matches(get));
+        assert!(Selector::parse("env:prod,provider:aws").unwrap().matches(get));
+        assert!(!Selector::parse("env:prod,provider:gcp").unwrap().matches(get));
+        assert!(Selector::parse("env!:dev, provider").unwrap().matches(get));
+        assert!(!Selector::parse("db").unwrap().matches(get));
+        assert!(Selector::parse(":prod").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+#![deny(warnings)]
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Cluster;
+use crate::db::Database;
+use crate::metrics;
+
+// 数据库维护任务(聚合, 清理, optimize), 同一个数据库只能有一个实例执行
+const MAINTENANCE_LEASE: &str = "maintenance";
+// 通知发送, 整个集群只能有一个实例执行
+const NOTIFY_LEASE: &str = "notify";
+
+// 未开启选主时单实例运行, 默认就是 leader
+static MAINTENANCE: AtomicBool = AtomicBool::new(true);
+static NOTIFY: AtomicBool = AtomicBool::new(true);
+
+pub fn is_maintenance_leader() -> bool {
+    MAINTENANCE.load(Ordering::Relaxed)
+}
+
+pub fn is_notify_leader() -> bool {
+    NOTIFY.load(Ordering::Relaxed)
+}
+
+fn update(flag: &AtomicBool, name: &str, leader: bool) {
+    if flag.swap(leader, Ordering::Relaxed) != leader {
+        if leader {
+            eprintln!("✨ become `{name}` leader");
+        } else {
+            eprintln!("✨ lost `{name}` leadership");
+        }
+        metrics::inc("leader_changed");
+    }
+}
+
+// 基于租约选主: 持有者定期续约, 租约过期后其它实例才能接手
+pub fn init(cfg: &'static Cluster, db: Arc<Database>) -> Result<()> {
+    if !cfg.leader_election {
+        return Ok(());
+    }
+    MAINTENANCE.store(false, Ordering::Relaxed);
+    NOTIFY.store(false, Ordering::Relaxed);
+
+    // 集群模式下各实例数据库独立, 通知的租约放在 redis 中
+    let redis_client = if cfg.enabled {
+        Some(redis::Client::open(cfg.redis_url.as_str())?)
+    } else {
+        None
+    };
+
+    let ttl = cfg.lease_ttl.max(3);
+    thread::spawn(move || {
+        let mut redis_conn = None;
+        loop {
+            match db.try_acquire_lease(MAINTENANCE_LEASE, &cfg.node_id, ttl) {
+                Ok(leader) => update(&MAINTENANCE, MAINTENANCE_LEASE, leader),
+                Err(err) => {
+                    error!("acquire `{MAINTENANCE_LEASE}` lease error => {:?}", err);
+                    update(&MAINTENANCE, MAINTENANCE_LEASE, false);
+                }
+            }
+
+            let notify = match redis_client.as_ref() {
+                Some(client) => {
+                    if redis_conn.is_none() {
+                        redis_conn = client.get_connection().ok();
+                    }
+                    match redis_conn.as_mut() {
+                        Some(conn) => try_acquire_redis_lease(conn, cfg, ttl).map_err(|err| {
+                            redis_conn = None;
+                            anyhow::Error::new(err)
+                        }),
+                        None => Err(anyhow::anyhow!("can't connect to {}", cfg.redis_url)),
+                    }
+                }
+                None => db.try_acquire_lease(NOTIFY_LEASE, &cfg.node_id, ttl),
+            };
+            match notify {
+                Ok(leader) => update(&NOTIFY, NOTIFY_LEASE, leader),
+                Err(err) => {
+                    error!("acquire `{NOTIFY_LEASE}` lease error => {:?}", err);
+                    update(&NOTIFY, NOTIFY_LEASE, false);
+                }
+            }
+
+            thread::sleep(Duration::from_secs(ttl / 3));
+        }
+    });
+
+    Ok(())
+}
+
+fn try_acquire_redis_lease(conn: &mut redis::Connection, cfg: &Cluster, ttl: u64) -> redis::RedisResult<bool> {
+    let key = format!("{}:lease:{NOTIFY_LEASE}", cfg.channel);
+    // 已是持有者则续约, 否则仅在租约不存在时抢占
+    let script = redis::Script::new(
+        r"
+        local v = redis.call('GET', KEYS[1])
+        if v == ARGV[1] then
+            redis.call('EXPIRE', KEYS[1], ARGV[2])
+            return 1
+        end
+        if v == false then
+            redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+            return 1
+        end
+        return 0
+        ",
+    );
+    let acquired: i64 = script.key(key).arg(&cfg.node_id).arg(ttl).invoke(conn)?;
+    Ok(acquired == 1)
+}
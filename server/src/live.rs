@@ -0,0 +1,108 @@
+#![deny(warnings)]
+// 告警事件实时推送, 管理页面通过 /ws/events 订阅, 显示实时的事件流
+// 推送: 主机上下线及服务告警(event), 静默/确认(silence), 通知发送结果(delivery)
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::jwt;
+
+// 慢的订阅者最多落后的消息数, 超过后跳过并通知客户端
+const CAPACITY: usize = 256;
+
+static TX: Lazy<broadcast::Sender<String>> = Lazy::new(|| broadcast::channel(CAPACITY).0);
+
+fn message(kind: &str, mut data: Value) -> String {
+    data["type"] = kind.into();
+    data["ts"] = chrono::Utc::now().timestamp().into();
+    data.to_string()
+}
+
+// 没有订阅者时直接丢弃
+pub fn publish(kind: &str, data: Value) {
+    if TX.receiver_count() > 0 {
+        let _ = TX.send(message(kind, data));
+    }
+}
+
+// 通知实际发送的结果, 由各通知方式的发送任务调用
+pub fn delivered<E: std::fmt::Display>(notifier: &str, result: Result<String, E>) {
+    let data = match result {
+        Ok(resp) => json!({ "notifier": notifier, "ok": true, "response": resp }),
+        Err(err) => json!({ "notifier": notifier, "ok": false, "error": err.to_string() }),
+    };
+    publish("delivery", data);
+}
+
+// Authorization: Bearer xxx 或 ?token=xxx
+fn token<'a>(headers: &'a HeaderMap, params: &'a HashMap<String, String>) -> Option<&'a str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|o| o.to_str().ok())
+        .and_then(|o| o.strip_prefix("Bearer "))
+        .or(params.get("token").map(String::as_str))
+}
+
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if !token(&headers, &params).is_some_and(jwt::verify) {
+        return (StatusCode::FORBIDDEN, "Invalid token").into_response();
+    }
+    ws.on_upgrade(serve)
+}
+
+async fn serve(mut socket: WebSocket) {
+    let mut rx = TX.subscribe();
+    loop {
+        tokio::select! {
+            msg = rx.recv() => {
+                let text = match msg {
+                    Ok(o) => o,
+                    Err(RecvError::Lagged(n)) => message("lagged", json!({ "skipped": n })),
+                    Err(RecvError::Closed) => return,
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+            }
+            // 只接收 ping/close, 客户端断开后退出
+            msg = socket.recv() => {
+                if !matches!(msg, Some(Ok(_))) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live() {
+        let mut headers = HeaderMap::new();
+        let mut params = HashMap::new();
+        assert_eq!(token(&headers, &params), None);
+        params.insert("token".to_string(), "q".to_string());
+        assert_eq!(token(&headers, &params), Some("q"));
+        headers.insert(header::AUTHORIZATION, "Bearer h".parse().unwrap());
+        assert_eq!(token(&headers, &params), Some("h"));
+
+        let o: Value = serde_json::from_str(&message("event", json!({ "kind": "NodeDown", "name": "h1" }))).unwrap();
+        assert_eq!((o["type"].as_str(), o["name"].as_str()), (Some("event"), Some("h1")));
+        assert!(o["ts"].as_i64().unwrap() > 0);
+    }
+}
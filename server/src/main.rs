@@ -6,8 +6,9 @@ extern crate pretty_env_logger;
 #[macro_use]
 extern crate prettytable;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use once_cell::sync::OnceCell;
+use std::net::SocketAddr;
 use std::process;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -21,6 +22,7 @@ use tokio::runtime::Builder;
 use tokio::time;
 
 use axum::{
+    extract::DefaultBodyLimit,
     http::{Method, Uri},
     response::IntoResponse,
     routing::{get, post},
@@ -28,17 +30,75 @@ use axum::{
 };
 use tower_http::cors::{Any, CorsLayer};
 
+mod access;
+mod adaptive;
+mod admin;
+mod alertmanager;
+mod alerts;
+mod announce;
+mod archive;
 mod assets;
 mod auth;
+mod calendar;
+mod certs;
+mod chaos;
+mod chart;
+mod cli;
+mod cluster;
+mod columnar;
+mod compact;
+mod conflict;
+mod compare;
 mod config;
+mod events;
+mod expr;
+mod expiry;
+mod feed;
+mod forecast;
+mod grafana;
+mod graphql;
 mod grpc;
+mod guard;
+#[cfg(feature = "harness")]
+mod harness;
+mod health;
 mod http;
+mod integrity;
 mod jinja;
 mod jwt;
+mod labels;
+mod leader;
+mod live;
+mod mesh;
+mod metrics;
+mod migrations;
+mod mirror;
+mod net;
 mod notifier;
+mod outbound;
 mod payload;
+mod probe;
+mod queue;
+mod quota;
+mod recorder;
+mod render;
+mod runas;
+mod sanitize;
+mod security;
+mod shard;
+mod snapshot;
 mod stats;
+mod summary;
+mod tasks;
+mod telegraf;
+mod templates;
+mod traffic;
+mod watchdog;
 mod db;
+mod domains;
+mod demo;
+mod digest;
+mod encoding;
 
 static G_CONFIG: OnceCell<crate::config::Config> = OnceCell::new();
 static G_STATS_MGR: OnceCell<crate::stats::StatsMgr> = OnceCell::new();
@@ -48,33 +108,177 @@ static G_STATS_MGR: OnceCell<crate::stats::StatsMgr> = OnceCell::new();
 struct Args {
     #[arg(short, long, default_value = "config.toml")]
     config: String,
+    #[arg(
+        long = "workdir",
+        value_name = "DIR",
+        help = "change to this directory first, relative paths (config, db_path, tls_dir, ...) are resolved from it"
+    )]
+    workdir: Option<String>,
     #[arg(short = 't', long, help = "config test, default:false")]
     config_test: bool,
     #[arg(long = "notify-test", help = "notify test, default:false")]
     notify_test: bool,
+    #[arg(long = "notify-dry-run", help = "log notifications instead of sending them, default:false")]
+    notify_dry_run: bool,
     #[arg(long = "cloud", help = "cloud mode, load cfg from env var: SRV_CONF")]
     cloud: bool,
+    #[arg(
+        long = "demo",
+        value_name = "HOSTS",
+        num_args = 0..=1,
+        default_missing_value = "12",
+        help = "demo mode, serve fake hosts without agents, data in a temp dir"
+    )]
+    demo: Option<usize>,
+    #[arg(long = "replay", value_name = "FILE", help = "replay reports recorded by ingest.record_path")]
+    replay: Option<String>,
+    #[arg(long = "replay-speed", default_value = "1", help = "replay speed, 10 means 10x faster")]
+    replay_speed: f64,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn create_app_router() -> Router {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// rebuild aggregated stats from raw data, then exit
+    Recompute {
+        #[arg(long, help = "host name, default: all hosts")]
+        host: Option<String>,
+        #[arg(long = "start-time", default_value = "0", help = "unix timestamp")]
+        start_time: i64,
+        #[arg(long = "end-time", help = "unix timestamp, default: now")]
+        end_time: Option<i64>,
+    },
+    /// offline queries on stats.db
+    Stats {
+        #[command(subcommand)]
+        command: cli::StatsCommand,
+    },
+    /// stats.db maintenance
+    Db {
+        #[command(subcommand)]
+        command: cli::DbCommand,
+    },
+    /// hosts stored in stats.db
+    Host {
+        #[command(subcommand)]
+        command: cli::HostCommand,
+    },
+    /// serve with a temp stats.db and fake agents reporting for every host in the config
+    #[cfg(feature = "harness")]
+    Harness {
+        #[arg(long, default_value = "5", help = "report interval(s)")]
+        interval: u64,
+    },
+}
+
+// 镜像模式只提供公开的页面和接口
+fn create_mirror_router() -> Router {
+    let cors_layer = CorsLayer::new().allow_methods([Method::GET]).allow_origin(Any);
+
+    Router::new()
+        .route("/json/stats.json", get(http::get_stats_json))
+        .route("/json/compact.json", get(http::get_compact_json))
+        .route("/json/history.json", get(http::get_history_stats))
+        .route("/config.pub.json", get(http::get_site_config_json))
+        .route("/healthz", get(health::healthz))
+        .route("/", get(assets::index_handler))
+        .fallback(fallback)
+        .layer(cors_layer)
+}
+
+// 上报及外部告警接口, 设置 ingest.addr 时单独侦听, 便于只对公网开放上报
+fn create_ingest_router() -> Router {
+    let cfg = G_CONFIG.get().unwrap();
+    Router::new()
+        .route(
+            "/report",
+            post(http::report).layer(DefaultBodyLimit::max(cfg.ingest.max_body_size)),
+        )
+        .route(
+            "/report/telegraf",
+            post(telegraf::handler).layer(DefaultBodyLimit::max(cfg.ingest.max_body_size)),
+        )
+        .route("/api/ingest/alert", post(alertmanager::handler)) // Alertmanager webhook, Authorization: Bearer {ingest.alert_token}
+}
+
+// admin 为 false 时不提供管理页面及接口, 用于公开的侦听地址
+fn create_app_router(admin: bool) -> Router {
+    let cfg = G_CONFIG.get().unwrap();
     let cors_layer = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST])
         .allow_origin(Any);
 
-    Router::new()
-        .route("/report", post(http::report))
+    let mut router = Router::new()
         .route("/json/stats.json", get(http::get_stats_json)) // 兼容就旧主题
+        .route("/json/compact.json", get(http::get_compact_json))
         .route("/json/history.json", get(http::get_history_stats)) // 兼容就旧主题
-        // .route("/config.pub.json", get(http::get_site_config_json)) // TODO
-        .route("/api/admin/authorize", post(jwt::authorize))
-        .route("/api/admin/:path", get(http::admin_api)) // stats.json || config.json
-        // .route("/admin", get(assets::admin_index_handler))
+        .route("/json/summary.json", get(http::get_summary_json))
+        .route("/json/compare.json", get(http::get_compare))
+        .route("/json/mesh.json", get(http::get_mesh))
+        .route("/json/domains.json", get(http::get_domains))
+        .route("/api/trends", get(http::get_trends)) // ?host=h1&days=365
+        .route("/config.pub.json", get(http::get_site_config_json))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
         .route("/detail", get(http::get_detail))
         .route("/map", get(http::get_map))
+        .route("/widget/:host", get(http::get_widget))
+        .route("/txt", get(http::get_txt))
+        .route("/txt/:host", get(http::get_txt_host))
+        .route("/feed.xml", get(http::get_feed))
+        .route("/calendar.ics", get(http::get_calendar))
+        .route("/chart/:host/:metric", get(http::get_chart))
         .route("/i", get(http::init_client))
-        .route("/", get(assets::index_handler))
-        .fallback(fallback)
-        .layer(cors_layer)
+        .route("/", get(assets::index_handler));
+    if admin {
+        router = router.merge(admin::router());
+    }
+    if cfg.ingest.addr.is_empty() {
+        router = router.merge(create_ingest_router());
+    }
+
+    // 可查询全部主机的历史数据, 不在公开的侦听地址提供
+    if admin && cfg.grafana.enabled {
+        router = router.merge(grafana::router());
+    }
+
+    if cfg.graphql.enabled {
+        graphql::init(&cfg.graphql);
+        let mut route = post(graphql::handler);
+        if cfg.graphql.playground {
+            route = route.get(graphql::playground);
+        }
+        router = router.route("/graphql", route);
+    }
+
+    router = router.fallback(fallback).layer(cors_layer);
+    if cfg.security_headers.enabled {
+        router = router.layer(axum::middleware::from_fn(security::headers));
+    }
+    router
+}
+
+// 每个侦听地址一个 axum::serve, 全部退出后返回
+async fn serve(servers: Vec<(String, TcpListener, Router)>) {
+    let mut handles = Vec::new();
+    for (name, listener, router) in servers {
+        eprintln!("🚀 listening on {name}");
+        // 上报认证的暴力破解防护需要对端地址
+        let app = router
+            .layer(axum::middleware::from_fn(access::track))
+            .into_make_service_with_connect_info::<SocketAddr>();
+        handles.push(tokio::spawn(async move {
+            axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()).await
+        }));
+    }
+    for handle in handles {
+        match handle.await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("http server error => {:?}", err),
+            Err(err) => error!("http server error => {:?}", err),
+        }
+    }
 }
 
 async fn fallback(uri: Uri) -> impl IntoResponse {
@@ -112,6 +316,10 @@ async fn main() -> Result<(), anyhow::Error> {
     let args = Args::parse();
 
     eprintln!("✨ {} {}", env!("CARGO_BIN_NAME"), env!("APP_VERSION"));
+    if let Some(dir) = &args.workdir {
+        std::env::set_current_dir(dir).map_err(|err| anyhow::anyhow!("can't change to workdir `{dir}`: {err}"))?;
+        eprintln!("✨ workdir `{dir}`");
+    }
 
     // config test
     if args.config_test {
@@ -121,8 +329,23 @@ async fn main() -> Result<(), anyhow::Error> {
         process::exit(0);
     }
 
+    #[cfg(feature = "harness")]
+    if let Some(Command::Harness { interval }) = &args.command {
+        // 没有配置文件时使用内置的 3 台主机
+        let config = std::fs::read_to_string(&args.config).unwrap_or_else(|_| harness::DEFAULT_CONFIG.to_string());
+        let interval = *interval;
+        tokio::task::spawn_blocking(move || harness::serve(&config, interval)).await??;
+        process::exit(0);
+    }
+
+    // 离线重建聚合数据及查询维护 stats.db, 不需要加载配置
+    if let Some(command) = &args.command {
+        cli::run(command, &config::db_path(&args.config))?;
+        process::exit(0);
+    }
+
     // config load
-    if let Some(cfg) = if args.cloud {
+    if let Some(mut cfg) = if args.cloud {
         // export SRV_CONF=$(cat config.toml)
         // echo "$SRV_CONF"
         eprintln!("✨ run in cloud mode, load config from env");
@@ -131,6 +354,18 @@ async fn main() -> Result<(), anyhow::Error> {
         eprintln!("✨ run in normal mode, load conf from local file `{}", &args.config);
         config::from_file(&args.config)
     } {
+        if let Some(n) = args.demo {
+            let dir = demo::setup(&mut cfg, n)?;
+            eprintln!("✨ demo mode, {n} fake hosts, data in {}", dir.display());
+        }
+        // 回放的上报不再录制
+        if args.replay.is_some() {
+            cfg.ingest.record_path.clear();
+        }
+        cfg.notify_dry_run |= args.notify_dry_run;
+        if cfg.notify_dry_run {
+            eprintln!("✨ notify dry-run enabled, notifications will only be logged");
+        }
         debug!("{}", serde_json::to_string_pretty(&cfg).unwrap());
         G_CONFIG.set(cfg).unwrap();
     } else {
@@ -141,36 +376,20 @@ async fn main() -> Result<(), anyhow::Error> {
     // init tpl
     http::init_jinja_tpl().unwrap();
 
+    outbound::init_resolver(&G_CONFIG.get().unwrap().dns)?;
+    access::init(&G_CONFIG.get().unwrap().access_log)?;
+
     // init notifier
     *notifier::NOTIFIER_HANDLE.lock().unwrap() = Some(Handle::current());
     let cfg = G_CONFIG.get().unwrap();
-    let notifies: Arc<Mutex<Vec<Box<dyn notifier::Notifier + Send>>>> = Arc::new(Mutex::new(Vec::new()));
-    if cfg.tgbot.enabled {
-        let o = Box::new(notifier::tgbot::TGBot::new(&cfg.tgbot));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.wechat.enabled {
-        let o = Box::new(notifier::wechat::WeChat::new(&cfg.wechat));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.email.enabled {
-        let o = Box::new(notifier::email::Email::new(&cfg.email));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.log.enabled {
-        let o = Box::new(notifier::log::Log::new(&cfg.log));
-        notifies.lock().unwrap().push(o);
-    }
-    if cfg.webhook.enabled {
-        let o = Box::new(notifier::webhook::Webhook::new(&cfg.webhook));
-        notifies.lock().unwrap().push(o);
-    }
+    let notifies: Arc<Mutex<Vec<Box<dyn notifier::Notifier + Send>>>> =
+        Arc::new(Mutex::new(notifier::build(cfg.notifier_tables())));
     // init notifier end
 
     // notify test
     if args.notify_test {
         for notifier in &*notifies.lock().unwrap() {
-            eprintln!("send test message to {}", notifier.kind());
+            eprintln!("send test message to {}", notifier.name());
             notifier.notify_test().unwrap();
         }
         thread::sleep(Duration::from_millis(7000)); // TODO: wait
@@ -178,23 +397,93 @@ async fn main() -> Result<(), anyhow::Error> {
         process::exit(0);
     }
 
+    notifier::set_notifiers(notifies.clone());
+
+    // 先侦听端口再降权, 之后创建的数据库等文件属于降权后的用户
+    let http_listeners = cfg
+        .http_addr
+        .0
+        .iter()
+        .map(|o| Ok((o.clone(), net::bind(&o.addr)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let ingest_listener = match cfg.mirror.enabled || cfg.ingest.addr.is_empty() {
+        true => None,
+        false => Some(net::bind(&cfg.ingest.addr)?),
+    };
+    let grpc_listener = match cfg.mirror.enabled {
+        true => None,
+        false => Some(net::bind(&cfg.grpc_addr)?),
+    };
+    runas::apply(&cfg.run_as)?;
+
+    // 打开数据库前检查完整性
+    if let Some(msg) = integrity::check_on_startup(&cfg.db_path, &cfg.db)? {
+        notifier::alert(&msg);
+    }
+
+    // 整个进程共用一个数据库实例
+    let db = Arc::new(db::Database::open(&cfg.db_path, cfg.db.readers, cfg.db.pragmas())?);
+
     // init mgr
-    let mut mgr = crate::stats::StatsMgr::new();
-    mgr.init(G_CONFIG.get().unwrap(), notifies)?;
+    let mut mgr = crate::stats::StatsMgr::new(db.clone());
+    if !cfg.mirror.enabled {
+        mgr.init(G_CONFIG.get().unwrap(), notifies)?;
+    }
     if G_STATS_MGR.set(mgr).is_err() {
         error!("can't set G_STATS_MGR");
         process::exit(1);
     }
-    let db = Arc::new(db::Database::new("stats.db")?);
+    notifier::start();
+    if cfg.digest.enabled {
+        tokio::spawn(digest::run(&cfg.digest));
+    }
+
+    // 镜像模式不接收上报, 不执行数据库任务
+    if cfg.mirror.enabled {
+        tokio::spawn(mirror::run(&cfg.mirror));
+
+        let servers = http_listeners
+            .into_iter()
+            .map(|(o, listener)| (format!("http://{}", o.addr), listener, create_mirror_router()))
+            .collect();
+        serve(servers).await;
+        runas::remove_pid_file(&cfg.run_as);
+        return Ok(());
+    }
+
+    cluster::init(&cfg.cluster)?;
+    alerts::init(cfg)?;
+    traffic::init(cfg)?;
+    tasks::init(cfg.db.task_alert_after);
+    if let Some(n) = args.demo {
+        demo::run(n);
+    }
+    recorder::init(&cfg.ingest.record_path)?;
+    if let Some(path) = &args.replay {
+        recorder::replay(path, args.replay_speed)?;
+    }
+    leader::init(&cfg.cluster, db.clone())?;
+    if cfg.probe.enabled {
+        probe::run(&cfg.probe, db.clone());
+    }
+    if cfg.certs.enabled {
+        certs::run(&cfg.certs);
+    }
+    if cfg.domains.enabled {
+        domains::run(&cfg.domains);
+    }
 
     let db_clone = db.clone();
     tokio::spawn(async move {
         let mut interval = time::interval(Duration::from_secs(300)); // 每5分钟执行一次
         loop {
             interval.tick().await;
-            if let Err(e) = db_clone.run_scheduled_aggregation() {
-                eprintln!("Error running data aggregation: {}", e);
+            if !leader::is_maintenance_leader() {
+                continue;
             }
+            let policy = G_STATS_MGR.get().unwrap().resolution_policy();
+            let _ = tasks::run("aggregation", || db_clone.run_scheduled_aggregation(policy));
+            let _ = tasks::run("disk_forecast", || forecast::refresh(&cfg.disk_forecast, &db_clone));
         }
     });
 
@@ -203,18 +492,38 @@ async fn main() -> Result<(), anyhow::Error> {
         let mut interval = time::interval(Duration::from_secs(24*60*60)); // 每天执行一次
         loop {
             interval.tick().await;
-            if let Err(e) = db_clone2.optimize() {
-                eprintln!("Error running data optimize: {}", e);
+            if !leader::is_maintenance_leader() {
+                continue;
+            }
+            if cfg.db.integrity_check {
+                let checked = tasks::run("integrity_check", || {
+                    let problems = db_clone2.integrity_check()?;
+                    if problems.is_empty() {
+                        return Ok(());
+                    }
+                    metrics::inc("db_integrity_failed");
+                    let msg = format!("❗{} integrity check failed, handled by on_corruption on next start: {}", cfg.db_path, problems.join("; "));
+                    notifier::alert(&msg);
+                    Err(anyhow::anyhow!(msg))
+                });
+                // 损坏时不再执行 VACUUM 等操作
+                if checked.is_err() {
+                    continue;
+                }
             }
+            // 先归档再 VACUUM, 释放归档数据占用的空间
+            if cfg.archive.enabled {
+                let _ = tasks::run("archive", || archive::run(&cfg.archive, &db_clone2));
+            }
+            let _ = tasks::run("events_cleanup", || {
+                let before = chrono::Utc::now().timestamp() - cfg.db.event_retention_days * 24 * 3600;
+                db_clone2.delete_events_before(before).map(|_| ())
+            });
+            let policy = G_STATS_MGR.get().unwrap().resolution_policy();
+            let _ = tasks::run("optimize", || db_clone2.optimize(policy));
         }
     });
 
-    // serv grpc
-    tokio::spawn(async move { grpc::serv_grpc(cfg).await });
-
-    let http_addr = cfg.http_addr.to_string();
-    eprintln!("🚀 listening on http://{http_addr}");
-
     // 创建专用于处理历史数据的线程池
     let history_runtime = Builder::new_multi_thread()
         .worker_threads(4)  // 可以根据需要调整线程数
@@ -229,6 +538,9 @@ async fn main() -> Result<(), anyhow::Error> {
         process::exit(1);
     }
 
+    // serv grpc, History.Query 使用历史数据线程池, 需在其初始化之后
+    tokio::spawn(async move { grpc::serv_grpc(cfg, grpc_listener.unwrap()).await });
+
     // 注意：这里有重复的代码，需要删除下面的重复部分
     // serv grpc
     // tokio::spawn(async move { grpc::serv_grpc(cfg).await });
@@ -237,11 +549,28 @@ async fn main() -> Result<(), anyhow::Error> {
     // eprintln!("🚀 listening on http://{http_addr}");
     // 重复代码结束
 
-    let listener = TcpListener::bind(&http_addr).await.unwrap();
-    axum::serve(listener, create_app_router())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+    let mut servers = http_listeners
+        .into_iter()
+        .map(|(o, listener)| {
+            let name = match o.admin {
+                true => format!("http://{}", o.addr),
+                false => format!("http://{} (admin disabled)", o.addr),
+            };
+            (name, listener, create_app_router(o.admin))
+        })
+        .collect::<Vec<_>>();
+    if let Some(listener) = ingest_listener {
+        servers.push((format!("http://{} (ingest)", cfg.ingest.addr), listener, create_ingest_router()));
+    }
+    serve(servers).await;
+
+    // 未入库的数据落盘, 下次启动回放
+    match G_STATS_MGR.get().unwrap().persist_queue() {
+        Ok(n) if n > 0 => eprintln!("✨ {n} pending stats saved to spill file"),
+        Ok(_) => {}
+        Err(err) => error!("persist stat queue error => {:?}", err),
+    }
+    runas::remove_pid_file(&cfg.run_as);
 
     Ok(())
 }
@@ -0,0 +1,176 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+use stat_common::server_status::{MeshResult, MeshTarget};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, MeshRule};
+use crate::notifier;
+use crate::payload::HostStat;
+use crate::probe;
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    latency: u32,
+    loss: u32,
+    ts: u64,
+}
+
+// from => to => 最近一次上报的链路质量
+static LINKS: Lazy<RwLock<HashMap<String, HashMap<String, Link>>>> = Lazy::new(Default::default);
+// 已告警的劣化链路 (from, to)
+static DEGRADED: Lazy<Mutex<HashSet<(String, String)>>> = Lazy::new(Default::default);
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+// 按主机名排序后从自身之后循环取 fanout 个, 目标分散且在主机不变时保持稳定
+fn pick(names: &[&str], name: &str, fanout: usize) -> Vec<usize> {
+    let pos = names.iter().position(|&o| o == name).map(|i| i + 1).unwrap_or_default();
+    let n = names.len();
+    let fanout = if fanout == 0 { n } else { fanout };
+    (0..n).map(|i| (pos + i) % n).filter(|&i| names[i] != name).take(fanout).collect()
+}
+
+// 下发给 name 的探测目标, 地址同服务端探测, 优先 hosts 中配置的 ipv4/ipv6
+pub fn targets(cfg: &Config, servers: &[HostStat], name: &str) -> Vec<MeshTarget> {
+    let mut candidates = servers
+        .iter()
+        .filter(|o| !o.disabled && (o.online4 || o.online6))
+        .filter_map(|o| {
+            let (ipv4, ipv6) = cfg
+                .hosts_map
+                .get(&o.name)
+                .map(|h| (h.ipv4.as_str(), h.ipv6.as_str()))
+                .unwrap_or_default();
+            let [v4, v6] = probe::targets(o, ipv4, ipv6);
+            v4.or(v6).map(|ip| (o.name.as_str(), SocketAddr::new(ip, cfg.mesh.port)))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| a.0.cmp(b.0));
+    let names = candidates.iter().map(|o| o.0).collect::<Vec<_>>();
+    pick(&names, name, cfg.mesh.fanout)
+        .into_iter()
+        .map(|i| MeshTarget {
+            name: candidates[i].0.to_string(),
+            addr: candidates[i].1.to_string(),
+        })
+        .collect()
+}
+
+// 任一匹配的规则超限即为劣化
+fn degraded(rules: &[MeshRule], from: &str, to: &str, latency: u32, loss: u32) -> bool {
+    rules.iter().any(|r| r.applies(from, to) && r.degraded(latency, loss))
+}
+
+// 替换 from 的全部链路, 劣化及恢复时各告警一次
+pub fn record(cfg: &Config, servers: &[HostStat], from: &str, results: Vec<MeshResult>) {
+    let ts = now();
+    let links = results
+        .iter()
+        .map(|o| {
+            let link = Link {
+                latency: o.latency,
+                loss: o.loss.min(100),
+                ts,
+            };
+            (o.name.to_string(), link)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let location = |name: &str| servers.iter().find(|o| o.name == name).map(|o| o.location.as_str());
+    let alias = |name: &str| {
+        servers
+            .iter()
+            .find(|o| o.name == name)
+            .map(|o| o.alias.to_string())
+            .unwrap_or_else(|| name.to_string())
+    };
+    let mut msgs = Vec::new();
+    {
+        let mut degraded_set = DEGRADED.lock().unwrap();
+        degraded_set.retain(|(f, t)| f != from || links.contains_key(t));
+        let from_loc = location(from).unwrap_or_default();
+        for (to, link) in links.iter() {
+            let to_loc = location(to).unwrap_or_default();
+            let key = (from.to_string(), to.to_string());
+            let bad = degraded(&cfg.mesh.rules, from_loc, to_loc, link.latency, link.loss);
+            let link_name = format!("{} -> {}", alias(from), alias(to));
+            if bad && degraded_set.insert(key.clone()) {
+                msgs.push(format!(
+                    "❗ mesh link {link_name} degraded: latency {}ms, loss {}%",
+                    link.latency, link.loss
+                ));
+            } else if !bad && degraded_set.remove(&key) {
+                msgs.push(format!("✅ mesh link {link_name} recovered: latency {}ms, loss {}%", link.latency, link.loss));
+            }
+        }
+    }
+    LINKS.write().unwrap().insert(from.to_string(), links);
+
+    if !msgs.is_empty() {
+        // 通知方式会同步发送请求, 不阻塞上报
+        thread::spawn(move || {
+            for msg in msgs {
+                notifier::alert(&msg);
+            }
+        });
+    }
+}
+
+// 延迟矩阵, latency/loss 为 from => to => 值, 不包含超过 stale 未更新的链路
+pub fn matrix(stale: u64) -> Value {
+    let expire = now().saturating_sub(stale);
+    let mut hosts = HashSet::new();
+    let mut latency = Map::new();
+    let mut loss = Map::new();
+    for (from, links) in LINKS.read().unwrap().iter() {
+        let fresh = links.iter().filter(|(_, o)| o.ts >= expire).collect::<Vec<_>>();
+        if fresh.is_empty() {
+            continue;
+        }
+        hosts.insert(from.to_string());
+        hosts.extend(fresh.iter().map(|(to, _)| to.to_string()));
+        latency.insert(from.to_string(), fresh.iter().map(|(to, o)| (to.to_string(), json!(o.latency))).collect());
+        loss.insert(from.to_string(), fresh.iter().map(|(to, o)| (to.to_string(), json!(o.loss))).collect());
+    }
+    let mut hosts = hosts.into_iter().collect::<Vec<_>>();
+    hosts.sort();
+    let mut degraded = DEGRADED.lock().unwrap().iter().cloned().collect::<Vec<_>>();
+    degraded.sort();
+    json!({
+        "updated": now(),
+        "hosts": hosts,
+        "latency": latency,
+        "loss": loss,
+        "degraded": degraded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mesh() {
+        let names = ["a", "b", "c", "d"];
+        assert_eq!(pick(&names, "b", 0), vec![2, 3, 0]);
+        assert_eq!(pick(&names, "d", 2), vec![0, 1]);
+        assert_eq!(pick(&names, "x", 2), vec![0, 1]);
+
+        let rules = vec![MeshRule {
+            from: "CN".to_string(),
+            to: "*".to_string(),
+            max_latency: 200,
+            max_loss: 0,
+        }];
+        assert!(degraded(&rules, "CN", "US", 300, 0));
+        assert!(!degraded(&rules, "CN", "US", 100, 50));
+        assert!(!degraded(&rules, "US", "CN", 300, 0));
+    }
+}
@@ -0,0 +1,198 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// 服务自身运行指标, 通过 /api/admin/metrics.json 查看
+static COUNTERS: Lazy<Mutex<BTreeMap<&'static str, u64>>> = Lazy::new(Default::default);
+static GAUGES: Lazy<Mutex<BTreeMap<&'static str, u64>>> = Lazy::new(Default::default);
+static HISTOGRAMS: Lazy<Mutex<BTreeMap<&'static str, Histogram>>> = Lazy::new(Default::default);
+// 按路由(如 /json/history.json, /widget/:host)统计的 http 请求
+static ROUTES: Lazy<Mutex<BTreeMap<String, Route>>> = Lazy::new(Default::default);
+
+// 直方图分桶上限, 耗时(ms) 与大小(bytes)
+const MS_BUCKETS: &[u64] = &[1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+const BYTES_BUCKETS: &[u64] = &[
+    1 << 10,
+    4 << 10,
+    16 << 10,
+    64 << 10,
+    256 << 10,
+    1 << 20,
+    4 << 20,
+    16 << 20,
+];
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [u64],
+    // 每个桶的计数, 最后一个为超出最大上限的部分
+    counts: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+
+    fn observe(&mut self, v: u64) {
+        let idx = self.bounds.iter().position(|&b| v <= b).unwrap_or(self.bounds.len());
+        self.counts[idx] += 1;
+        self.count += 1;
+        self.sum += v;
+        self.max = self.max.max(v);
+    }
+
+    // 分位数的近似值, 取所在桶的上限, 超出最大上限时取最大值
+    fn quantile(&self, q: f64) -> u64 {
+        let rank = (self.count as f64 * q).ceil() as u64;
+        let mut cumulative = 0;
+        for (idx, n) in self.counts.iter().enumerate() {
+            cumulative += n;
+            if cumulative >= rank && cumulative > 0 {
+                return self.bounds.get(idx).copied().unwrap_or(self.max).min(self.max);
+            }
+        }
+        0
+    }
+
+    // 与 prometheus 一致, 桶为累计计数
+    fn to_json(&self) -> Value {
+        let mut cumulative = 0;
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(idx, n)| {
+                cumulative += n;
+                let le = self.bounds.get(idx).map(|o| json!(o)).unwrap_or_else(|| json!("+Inf"));
+                json!({ "le": le, "count": cumulative })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "count": self.count,
+            "sum": self.sum,
+            "max": self.max,
+            "buckets": buckets,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    hits: u64,
+    errors: u64,
+    latency: Histogram,
+}
+
+impl Route {
+    fn to_json(&self) -> Value {
+        json!({
+            "hits": self.hits,
+            "errors": self.errors,
+            "error_rate": self.errors as f64 / self.hits.max(1) as f64,
+            "p95_ms": self.latency.quantile(0.95),
+            "max_ms": self.latency.max,
+        })
+    }
+}
+
+pub fn inc(name: &'static str) {
+    add(name, 1);
+}
+
+pub fn add(name: &'static str, v: u64) {
+    if let Ok(mut counters) = COUNTERS.lock() {
+        *counters.entry(name).or_default() += v;
+    }
+}
+
+pub fn gauge(name: &'static str, v: u64) {
+    if let Ok(mut gauges) = GAUGES.lock() {
+        gauges.insert(name, v);
+    }
+}
+
+fn observe(name: &'static str, bounds: &'static [u64], v: u64) {
+    if let Ok(mut histograms) = HISTOGRAMS.lock() {
+        histograms.entry(name).or_insert_with(|| Histogram::new(bounds)).observe(v);
+    }
+}
+
+// 耗时, 单位 ms
+pub fn observe_ms(name: &'static str, v: u64) {
+    observe(name, MS_BUCKETS, v);
+}
+
+// 响应大小, 单位 bytes
+pub fn observe_bytes(name: &'static str, v: u64) {
+    observe(name, BYTES_BUCKETS, v);
+}
+
+// 5xx 计为错误
+pub fn observe_route(route: &str, status: u16, ms: u64) {
+    if let Ok(mut routes) = ROUTES.lock() {
+        let o = routes.entry(route.to_string()).or_insert_with(|| Route {
+            hits: 0,
+            errors: 0,
+            latency: Histogram::new(MS_BUCKETS),
+        });
+        o.hits += 1;
+        if status >= 500 {
+            o.errors += 1;
+        }
+        o.latency.observe(ms);
+    }
+}
+
+pub fn snapshot() -> Value {
+    let counters = COUNTERS.lock().map(|o| o.clone()).unwrap_or_default();
+    let gauges = GAUGES.lock().map(|o| o.clone()).unwrap_or_default();
+    let histograms = HISTOGRAMS
+        .lock()
+        .map(|o| o.iter().map(|(k, v)| (*k, v.to_json())).collect::<BTreeMap<_, _>>())
+        .unwrap_or_default();
+    let routes = ROUTES
+        .lock()
+        .map(|o| o.iter().map(|(k, v)| (k.to_string(), v.to_json())).collect::<BTreeMap<_, _>>())
+        .unwrap_or_default();
+    json!({
+        "counters": counters,
+        "gauges": gauges,
+        "histograms": histograms,
+        "routes": routes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut h = Histogram::new(&[10, 100]);
+        for v in [1, 10, 50, 1000] {
+            h.observe(v);
+        }
+        assert_eq!(h.counts, vec![2, 1, 1]);
+        assert_eq!((h.count, h.sum, h.max), (4, 1061, 1000));
+
+        let v = h.to_json();
+        assert_eq!(v["buckets"][1], json!({ "le": 100, "count": 3 }));
+        assert_eq!(v["buckets"][2], json!({ "le": "+Inf", "count": 4 }));
+
+        assert_eq!(h.quantile(0.5), 10);
+        assert_eq!(h.quantile(0.95), 1000);
+        assert_eq!(Histogram::new(MS_BUCKETS).quantile(0.95), 0);
+    }
+}
@@ -0,0 +1,393 @@
+#![deny(warnings)]
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection, TransactionBehavior};
+
+// stats.db 结构变更, 按版本号顺序执行, 已发布的版本不能再修改, 新的变更追加到末尾
+// 旧版本创建的库没有 schema_version 表, 第 1 版使用 IF NOT EXISTS 保证可以重复执行
+const MIGRATIONS: &[(u32, &str, &str)] = &[
+    (
+        1,
+        "init",
+        "
+        CREATE TABLE IF NOT EXISTS hosts (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            alias TEXT,
+            UNIQUE(name)
+        );
+
+        CREATE TABLE IF NOT EXISTS stats (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            cpu_usage REAL,
+            memory_total INTEGER,
+            memory_used INTEGER,
+            network_in INTEGER,
+            network_out INTEGER,
+            network_in_speed INTEGER,
+            network_out_speed INTEGER,
+            online BOOLEAN,
+            FOREIGN KEY (host_id) REFERENCES hosts(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS disk_stats (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            mount_point TEXT NOT NULL,
+            disk_total INTEGER,
+            disk_used INTEGER,
+            FOREIGN KEY (host_id) REFERENCES hosts(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS aggregated_stats (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            interval_minutes INTEGER NOT NULL,
+            cpu_usage REAL,
+            memory_total INTEGER,
+            memory_used INTEGER,
+            network_in INTEGER,
+            network_out INTEGER,
+            network_in_speed INTEGER,
+            network_out_speed INTEGER,
+            online BOOLEAN,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            UNIQUE(host_id, timestamp, interval_minutes)
+        );
+
+        CREATE TABLE IF NOT EXISTS aggregated_disk_stats (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            interval_minutes INTEGER NOT NULL,
+            mount_point TEXT NOT NULL,
+            disk_total INTEGER,
+            disk_used INTEGER,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            UNIQUE(host_id, timestamp, interval_minutes, mount_point)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_agg_stats_host_time ON aggregated_stats(host_id, timestamp, interval_minutes);
+        CREATE INDEX IF NOT EXISTS idx_agg_disk_stats_host_time ON aggregated_disk_stats(host_id, timestamp, interval_minutes);
+        CREATE INDEX IF NOT EXISTS idx_stats_host_time ON stats(host_id, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_disk_stats_host_time ON disk_stats(host_id, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_stats_timestamp ON stats(timestamp);
+        CREATE INDEX IF NOT EXISTS idx_disk_stats_timestamp ON disk_stats(timestamp);
+
+        CREATE TABLE IF NOT EXISTS last_network (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            network_in INTEGER NOT NULL,
+            network_out INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            UNIQUE(host_id)
+        );
+        ",
+    ),
+    (
+        2,
+        "leader_lease",
+        "
+        CREATE TABLE IF NOT EXISTS leader_lease (
+            name TEXT PRIMARY KEY,
+            holder TEXT NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+        ",
+    ),
+    (
+        3,
+        "probe_stats",
+        "
+        CREATE TABLE IF NOT EXISTS probe_stats (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            ok BOOLEAN NOT NULL,
+            latency INTEGER,
+            FOREIGN KEY (host_id) REFERENCES hosts(id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_probe_stats_host_time ON probe_stats(host_id, timestamp);
+
+        CREATE TABLE IF NOT EXISTS aggregated_probe_stats (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            interval_minutes INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            success_rate REAL,
+            latency REAL,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            UNIQUE(host_id, timestamp, interval_minutes, kind)
+        );
+        ",
+    ),
+    (
+        4,
+        "covering_indexes",
+        "
+        -- 历史查询按 (主机, 时间) 或 (级别, 主机, 时间) 范围读取, 索引带上查询的列, 不再回表
+        DROP INDEX IF EXISTS idx_stats_host_time;
+        DROP INDEX IF EXISTS idx_disk_stats_host_time;
+        DROP INDEX IF EXISTS idx_agg_stats_host_time;
+        DROP INDEX IF EXISTS idx_agg_disk_stats_host_time;
+        -- 清理已按主机进行, 只按时间的索引不再使用
+        DROP INDEX IF EXISTS idx_stats_timestamp;
+        DROP INDEX IF EXISTS idx_disk_stats_timestamp;
+
+        CREATE INDEX IF NOT EXISTS idx_stats_host_time_cover ON stats(
+            host_id, timestamp, cpu_usage, memory_total, memory_used,
+            network_in, network_out, network_in_speed, network_out_speed, online
+        );
+        CREATE INDEX IF NOT EXISTS idx_disk_stats_host_time_cover ON disk_stats(
+            host_id, timestamp, mount_point, disk_total, disk_used
+        );
+        CREATE INDEX IF NOT EXISTS idx_agg_stats_interval_host_time ON aggregated_stats(
+            interval_minutes, host_id, timestamp, cpu_usage, memory_total, memory_used,
+            network_in, network_out, network_in_speed, network_out_speed, online
+        );
+        CREATE INDEX IF NOT EXISTS idx_agg_disk_stats_interval_host_time ON aggregated_disk_stats(
+            interval_minutes, host_id, timestamp, mount_point, disk_total, disk_used
+        );
+        CREATE INDEX IF NOT EXISTS idx_probe_stats_time_cover ON probe_stats(
+            timestamp, host_id, kind, ok, latency
+        );
+        CREATE INDEX IF NOT EXISTS idx_agg_probe_stats_interval_time ON aggregated_probe_stats(
+            interval_minutes, timestamp, host_id, kind, success_rate, latency
+        );
+        ",
+    ),
+    (
+        5,
+        "events",
+        "
+        -- 主机上下线及服务告警, 主机被删除后仍保留, 所以记录主机名而不是 host_id
+        CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY,
+            ts INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            alias TEXT NOT NULL,
+            message TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_events_ts ON events(ts);
+        ",
+    ),
+    (
+        6,
+        "host_labels",
+        "
+        -- 主机最近一次的标签(json), 主机下线或删除后仍可按标签筛选历史数据
+        ALTER TABLE hosts ADD COLUMN labels TEXT NOT NULL DEFAULT '{}';
+        ",
+    ),
+    (
+        7,
+        "host_hidden",
+        "
+        -- 隐藏的主机照常入库, 只是不在公开的 stats.json 中展示
+        ALTER TABLE hosts ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0;
+        ",
+    ),
+    (
+        8,
+        "host_secret",
+        "
+        -- 主机的独立上报密码, 为空时使用配置中的密码
+        ALTER TABLE hosts ADD COLUMN secret TEXT NOT NULL DEFAULT '';
+        ",
+    ),
+    (
+        9,
+        "host_token",
+        "
+        -- 主机的上报令牌, 只保存摘要
+        ALTER TABLE hosts ADD COLUMN token TEXT NOT NULL DEFAULT '';
+        ",
+    ),
+    (
+        10,
+        "announcements",
+        "
+        -- 站点公告, start/end 为 unix 时间(s), 0 表示不限
+        CREATE TABLE IF NOT EXISTS announcements (
+            id INTEGER PRIMARY KEY,
+            message TEXT NOT NULL,
+            severity TEXT NOT NULL DEFAULT 'info',
+            start_ts INTEGER NOT NULL DEFAULT 0,
+            end_ts INTEGER NOT NULL DEFAULT 0,
+            updated_at INTEGER NOT NULL
+        );
+        ",
+    ),
+    (
+        11,
+        "daily_rollups",
+        "
+        -- 按天(UTC)汇总的最小/平均/最大值, 由最细的聚合数据计算, 不归档, 用于长期趋势
+        CREATE TABLE IF NOT EXISTS daily_stats (
+            host_id INTEGER NOT NULL,
+            day INTEGER NOT NULL,
+            samples INTEGER NOT NULL,
+            cpu_min REAL, cpu_avg REAL, cpu_max REAL,
+            memory_total REAL,
+            memory_min REAL, memory_avg REAL, memory_max REAL,
+            network_in_min REAL, network_in_avg REAL, network_in_max REAL,
+            network_out_min REAL, network_out_avg REAL, network_out_max REAL,
+            online_ratio REAL,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            PRIMARY KEY (host_id, day)
+        );
+        CREATE TABLE IF NOT EXISTS daily_disk_stats (
+            host_id INTEGER NOT NULL,
+            day INTEGER NOT NULL,
+            mount_point TEXT NOT NULL,
+            disk_total REAL,
+            used_min REAL, used_avg REAL, used_max REAL,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            PRIMARY KEY (host_id, day, mount_point)
+        );
+        ",
+    ),
+    (
+        12,
+        "disk_devices",
+        "
+        -- 磁盘设备(文件系统 UUID)对应的历史数据名称, disk_stats 等表的 mount_point 列保存的是 series
+        -- 设备首次出现时 series 为当时的挂载点, 之后改挂载点历史数据不变, 换盘时可以把新设备指到原来的 series
+        CREATE TABLE IF NOT EXISTS disk_devices (
+            host_id INTEGER NOT NULL,
+            device_id TEXT NOT NULL,
+            series TEXT NOT NULL,
+            mount_point TEXT NOT NULL,
+            last_seen INTEGER NOT NULL,
+            FOREIGN KEY (host_id) REFERENCES hosts(id),
+            PRIMARY KEY (host_id, device_id)
+        );
+        ",
+    ),
+    (
+        13,
+        "disk_inodes",
+        "
+        -- 磁盘的 inode 总数及已用, 之前的数据及不支持的文件系统为 0
+        ALTER TABLE disk_stats ADD COLUMN inode_total INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE disk_stats ADD COLUMN inode_used INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE aggregated_disk_stats ADD COLUMN inode_total INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE aggregated_disk_stats ADD COLUMN inode_used INTEGER NOT NULL DEFAULT 0;
+
+        -- 历史查询同时读取 inode, 覆盖索引加上这两列
+        DROP INDEX IF EXISTS idx_disk_stats_host_time_cover;
+        DROP INDEX IF EXISTS idx_agg_disk_stats_interval_host_time;
+        CREATE INDEX IF NOT EXISTS idx_disk_stats_host_time_cover ON disk_stats(
+            host_id, timestamp, mount_point, disk_total, disk_used, inode_total, inode_used
+        );
+        CREATE INDEX IF NOT EXISTS idx_agg_disk_stats_interval_host_time ON aggregated_disk_stats(
+            interval_minutes, host_id, timestamp, mount_point, disk_total, disk_used, inode_total, inode_used
+        );
+        ",
+    ),
+    (
+        14,
+        "event_private",
+        "
+        -- 服务告警及规则告警可能包含来源 IP/错误信息等, 只在管理接口展示, 公开的 feed.xml/graphql 只有上下线
+        ALTER TABLE events ADD COLUMN private INTEGER NOT NULL DEFAULT 0;
+        UPDATE events SET private = 1 WHERE kind NOT IN ('NodeUp', 'NodeDown');
+        ",
+    ),
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|o| o.0).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?)
+}
+
+// 启动时执行未应用的变更, 每个版本一个事务, 返回执行的个数
+pub fn run(conn: &mut Connection) -> Result<usize> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        );",
+    )?;
+
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(anyhow::anyhow!(
+            "stats.db schema version {} is newer than this binary supports ({}), please upgrade",
+            current,
+            latest_version()
+        ));
+    }
+
+    let mut applied = 0;
+    for (version, name, sql) in MIGRATIONS.iter().filter(|o| o.0 > current) {
+        // 多个实例共用数据库时, 拿到写锁后再确认一次版本
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if current_version(&tx)? >= *version {
+            continue;
+        }
+        tx.execute_batch(sql)
+            .map_err(|e| anyhow::anyhow!("migration {} `{}` failed: {}", version, name, e))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)",
+            params![version, name, Utc::now().timestamp()],
+        )?;
+        tx.commit()?;
+        eprintln!("✨ stats.db migrated to version {version} `{name}`");
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_ordered() {
+        for w in MIGRATIONS.windows(2) {
+            assert!(w[0].0 < w[1].0);
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run(&mut conn).unwrap(), MIGRATIONS.len());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        // 重复执行不会再应用
+        assert_eq!(run(&mut conn).unwrap(), 0);
+
+        // 没有 schema_version 表的旧库
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].2).unwrap();
+        conn.execute("INSERT INTO hosts (name, alias) VALUES ('h1', 'n1')", []).unwrap();
+        assert_eq!(run(&mut conn).unwrap(), MIGRATIONS.len());
+        let n: i64 = conn.query_row("SELECT COUNT(*) FROM hosts", [], |row| row.get(0)).unwrap();
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn test_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?, 'future', 0)",
+            params![latest_version() + 1],
+        )
+        .unwrap();
+        assert!(run(&mut conn).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+#![deny(warnings)]
+use anyhow::Result;
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+use tokio::time;
+
+use crate::config::Mirror;
+use crate::metrics;
+use crate::outbound;
+use crate::G_STATS_MGR;
+
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    outbound::client_builder("")
+        .connect_timeout(Duration::from_secs(5))
+        .user_agent(format!("{}/{}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+async fn fetch(cfg: &Mirror, path: &str) -> Result<Bytes> {
+    let url = format!("{}{}", cfg.primary.trim_end_matches('/'), path);
+    let resp = HTTP_CLIENT
+        .get(url)
+        .timeout(Duration::from_secs(cfg.timeout))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(resp.bytes().await?)
+}
+
+// 镜像模式: 定期从主节点拉取 stats.json, 本实例只提供公开的展示页面和接口
+pub async fn run(cfg: &'static Mirror) {
+    eprintln!("✨ run in mirror mode, pull stats from `{}`", cfg.primary);
+    let mut interval = time::interval(Duration::from_secs(cfg.interval.max(1)));
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        match fetch(cfg, "/json/stats.json").await {
+            Ok(body) => {
+                if serde_json::from_slice::<serde::de::IgnoredAny>(&body).is_err() {
+                    error!("mirror got invalid stats.json from primary");
+                    metrics::inc("mirror_pull_failed");
+                    continue;
+                }
+                if let Some(mgr) = G_STATS_MGR.get() {
+                    mgr.set_stats_json(body);
+                }
+                metrics::inc("mirror_pulled");
+            }
+            Err(err) => {
+                error!("mirror pull stats error => {:?}", err);
+                metrics::inc("mirror_pull_failed");
+            }
+        }
+    }
+}
+
+// 历史数据不做同步, 直接转发到主节点
+pub async fn history(cfg: &Mirror, query: Option<&str>) -> Result<Bytes> {
+    match query {
+        Some(q) => fetch(cfg, &format!("/json/history.json?{q}")).await,
+        None => fetch(cfg, "/json/history.json").await,
+    }
+}
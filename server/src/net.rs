@@ -0,0 +1,58 @@
+#![deny(warnings)]
+use anyhow::{anyhow, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use tokio::net::TcpListener;
+
+// 解析侦听地址, 支持 0.0.0.0:8080 / [::]:8080 / localhost:8080
+pub fn parse_addr(addr: &str) -> Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("invalid listen addr `{addr}`"))
+}
+
+// 侦听 [::] 时关闭 IPV6_V6ONLY, 同时接受 ipv4 连接, 不依赖系统的 bindv6only 设置
+pub fn bind(addr: &str) -> Result<TcpListener> {
+    let addr = parse_addr(addr)?;
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+// Host / X-Forwarded-Host 规范化, 取第一个值, 裸 ipv6 地址补上方括号
+pub fn normalize_host(host: &str) -> String {
+    let host = host.split(',').next().unwrap_or_default().trim();
+    if host.parse::<IpAddr>().map(|ip| ip.is_ipv6()).unwrap_or(false) {
+        return format!("[{host}]");
+    }
+    host.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_addr() {
+        assert!(parse_addr("0.0.0.0:8080").unwrap().is_ipv4());
+        assert!(parse_addr("[::]:8080").unwrap().is_ipv6());
+        assert_eq!(parse_addr("[::1]:9394").unwrap().port(), 9394);
+        assert!(parse_addr("::1").is_err());
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("example.com:8080"), "example.com:8080");
+        assert_eq!(normalize_host("[2001:db8::1]:8080"), "[2001:db8::1]:8080");
+        assert_eq!(normalize_host("2001:db8::1"), "[2001:db8::1]");
+        assert_eq!(normalize_host("a.com, b.com"), "a.com");
+        assert_eq!(normalize_host("127.0.0.1"), "127.0.0.1");
+    }
+}
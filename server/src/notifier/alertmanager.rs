@@ -0,0 +1,298 @@
+#![deny(warnings)]
+// 以 Alertmanager webhook 格式发送事件, Karma/Grafana OnCall 等可直接接收
+// 主机下线为 firing, 恢复上线时发送同一告警的 resolved; 告警规则等文字消息为 ServerStatusAlert, 在 resolve_timeout 后自动结束
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, SecondsFormat, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use tokio::time::Duration;
+
+use crate::live;
+use crate::notifier::{block_on, get_tag, Event, HostStat, NOTIFIER_HANDLE, TEST_MSG};
+use crate::outbound;
+
+pub const KIND: &str = "alertmanager";
+// 未结束的告警的 endsAt
+const ZERO_TIME: &str = "0001-01-01T00:00:00Z";
+
+fn default_timeout() -> u64 {
+    10
+}
+fn default_resolve_timeout() -> u64 {
+    3600
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct Config {
+    pub enabled: bool,
+    // 实例名, 用于路由规则引用, 为空时使用 kind
+    #[serde(default = "Default::default")]
+    pub name: String,
+    pub url: String,
+    // 附加的请求头, 如 Authorization
+    #[serde(default = "Default::default")]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    // 为空时使用全局代理
+    #[serde(default = "Default::default")]
+    pub proxy: String,
+    // externalURL 及 generatorURL, 为空时使用 server_url 去掉 /report
+    #[serde(default = "Default::default")]
+    pub external_url: String,
+    // 文字消息的告警在多少秒后自动结束
+    #[serde(default = "default_resolve_timeout")]
+    pub resolve_timeout: u64,
+    // 附加到每条告警的标签, 如 cluster = "glow"
+    #[serde(default = "Default::default")]
+    pub labels: BTreeMap<String, String>,
+    // 只接收匹配的主机的上下线通知, 如 env:prod,provider:aws, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub selector: String,
+}
+
+// 下线中的主机的 startsAt, 恢复时 resolved 使用同一时间
+static STARTS: Lazy<Mutex<HashMap<String, DateTime<Utc>>>> = Lazy::new(Default::default);
+
+fn ts(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn fingerprint(labels: &BTreeMap<String, String>) -> String {
+    let s = labels
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{:x}", md5::compute(s))[..16].to_string()
+}
+
+pub struct AlertManager {
+    config: &'static Config,
+    http_client: reqwest::Client,
+}
+
+impl AlertManager {
+    pub fn new(cfg: &'static Config) -> Self {
+        Self {
+            config: cfg,
+            http_client: outbound::http_client(&cfg.proxy),
+        }
+    }
+
+    fn external_url(&self) -> String {
+        match self.config.external_url.is_empty() {
+            true => crate::G_CONFIG
+                .get()
+                .map(|o| o.public_url().to_string())
+                .unwrap_or_default(),
+            false => self.config.external_url.to_string(),
+        }
+    }
+
+    fn labels(&self, alertname: &str) -> BTreeMap<String, String> {
+        let mut labels = self.config.labels.clone();
+        labels.insert("alertname".into(), alertname.into());
+        labels
+    }
+
+    fn host_labels(&self, stat: &HostStat) -> BTreeMap<String, String> {
+        let mut labels = self.labels("NodeDown");
+        labels.insert("severity".into(), "critical".into());
+        for (k, v) in stat.labels.iter() {
+            labels.insert(k.into(), v.into());
+        }
+        for k in ["location", "type", "region", "zone", "provider", "gid"] {
+            if let Some(v) = stat.label(k).filter(|o| !o.is_empty()) {
+                labels.insert(k.into(), v.into());
+            }
+        }
+        labels.insert("instance".into(), stat.name.to_string());
+        labels
+    }
+
+    fn payload(
+        &self,
+        status: &str,
+        labels: BTreeMap<String, String>,
+        annotations: Value,
+        starts: DateTime<Utc>,
+        ends: Option<DateTime<Utc>>,
+    ) -> Value {
+        let external_url = self.external_url();
+        let alertname = labels.get("alertname").cloned().unwrap_or_default();
+        json!({
+            "version": "4",
+            "groupKey": format!("{{}}:{{alertname=\"{alertname}\"}}"),
+            "truncatedAlerts": 0,
+            "status": status,
+            "receiver": self.config.name,
+            "groupLabels": { "alertname": alertname },
+            "commonLabels": labels,
+            "commonAnnotations": annotations,
+            "externalURL": external_url,
+            "alerts": [{
+                "status": status,
+                "labels": labels,
+                "annotations": annotations,
+                "startsAt": ts(starts),
+                "endsAt": ends.map(ts).unwrap_or(ZERO_TIME.to_string()),
+                "generatorURL": external_url,
+                "fingerprint": fingerprint(&labels),
+            }],
+        })
+    }
+
+    fn host_payload(&self, e: &Event, stat: &HostStat, now: DateTime<Utc>) -> Option<Value> {
+        let labels = self.host_labels(stat);
+        match e {
+            Event::NodeDown => {
+                let starts = *STARTS.lock().unwrap().entry(stat.name.to_string()).or_insert(now);
+                let annotations = json!({ "summary": format!("{} is offline", stat.alias) });
+                Some(self.payload("firing", labels, annotations, starts, None))
+            }
+            Event::NodeUp => {
+                let starts = STARTS.lock().unwrap().remove(&stat.name).unwrap_or(now);
+                let annotations = json!({ "summary": format!("{} is online", stat.alias) });
+                Some(self.payload("resolved", labels, annotations, starts, Some(now)))
+            }
+            // 客户端的自定义通知依赖各通知方式的模板, 这里不发送
+            Event::Custom => None,
+        }
+    }
+
+    fn message_payload(&self, content: &str, now: DateTime<Utc>) -> Value {
+        let mut labels = self.labels("ServerStatusAlert");
+        labels.insert("severity".into(), "warning".into());
+        // 每条消息单独成一个告警, 避免相同标签的告警被合并
+        labels.insert(
+            "message_id".into(),
+            format!("{:x}", md5::compute(content))[..8].to_string(),
+        );
+        let ends = now + ChronoDuration::seconds(self.config.resolve_timeout as i64);
+        self.payload("firing", labels, json!({ "summary": content }), now, Some(ends))
+    }
+
+    fn post(&self, body: Value) -> Result<()> {
+        let content = body.to_string();
+        if super::dry_run(&self.config.name, &format!("POST {}\n{content}", self.config.url)) {
+            return Ok(());
+        }
+        let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
+        let http_client = self.http_client.clone();
+        let cfg = self.config;
+        handle.spawn(async move {
+            let result = request(&http_client, cfg, content).await;
+            if let Err(err) = &result {
+                error!("alertmanager send msg error => {:?}", err);
+            }
+            live::delivered(&cfg.name, result);
+        });
+        Ok(())
+    }
+}
+
+async fn request(http_client: &reqwest::Client, cfg: &Config, content: String) -> Result<String> {
+    let mut builder = http_client
+        .post(&cfg.url)
+        .timeout(Duration::from_secs(cfg.timeout))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(content);
+    for (k, v) in cfg.headers.iter() {
+        builder = builder.header(k, v);
+    }
+    let resp = builder.send().await?;
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("{} {status} => {body}", cfg.url);
+    }
+    Ok(format!("{} {status} => {body}", cfg.url))
+}
+
+impl crate::notifier::Notifier for AlertManager {
+    fn kind(&self) -> &'static str {
+        KIND
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn selector(&self) -> &str {
+        &self.config.selector
+    }
+
+    fn send_notify(&self, content: String) -> Result<()> {
+        if content.is_empty() {
+            return Ok(());
+        }
+        self.post(self.message_payload(&content, Utc::now()))
+    }
+
+    fn deliver_test(&self) -> Result<String> {
+        let body = self.message_payload(TEST_MSG, Utc::now()).to_string();
+        block_on(request(&self.http_client, self.config, body))
+    }
+
+    fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
+        info!("alertmanager notify {} {}", get_tag(e), stat.name);
+        match self.host_payload(e, stat, Utc::now()) {
+            Some(body) => self.post(body),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let cfg = Box::leak(Box::new(Config {
+            name: "am".to_string(),
+            external_url: "https://status.example.com".to_string(),
+            resolve_timeout: 60,
+            labels: BTreeMap::from([("cluster".to_string(), "glow".to_string())]),
+            ..Default::default()
+        }));
+        let am = AlertManager::new(cfg);
+        let stat = HostStat {
+            name: "h-payload".to_string(),
+            alias: "web".to_string(),
+            location: "us".to_string(),
+            ..Default::default()
+        };
+        let t0 = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let t1 = t0 + ChronoDuration::seconds(90);
+
+        let down = am.host_payload(&Event::NodeDown, &stat, t0).unwrap();
+        let alert = &down["alerts"][0];
+        assert_eq!(down["status"], "firing");
+        assert_eq!(alert["labels"]["alertname"], "NodeDown");
+        assert_eq!(alert["labels"]["instance"], "h-payload");
+        assert_eq!(alert["labels"]["location"], "us");
+        assert_eq!(alert["labels"]["cluster"], "glow");
+        assert_eq!(alert["startsAt"], "2024-01-01T00:00:00Z");
+        assert_eq!(alert["endsAt"], ZERO_TIME);
+        assert_eq!(alert["generatorURL"], "https://status.example.com");
+
+        let up = am.host_payload(&Event::NodeUp, &stat, t1).unwrap();
+        assert_eq!(up["alerts"][0]["status"], "resolved");
+        assert_eq!(up["alerts"][0]["startsAt"], "2024-01-01T00:00:00Z");
+        assert_eq!(up["alerts"][0]["endsAt"], "2024-01-01T00:01:30Z");
+        assert_eq!(up["alerts"][0]["fingerprint"], alert["fingerprint"]);
+        assert!(am.host_payload(&Event::Custom, &stat, t1).is_none());
+
+        let msg = am.message_payload("❗h1 alert cpu", t0);
+        assert_eq!(msg["alerts"][0]["labels"]["alertname"], "ServerStatusAlert");
+        assert_eq!(msg["alerts"][0]["annotations"]["summary"], "❗h1 alert cpu");
+        assert_eq!(msg["alerts"][0]["endsAt"], "2024-01-01T00:01:00Z");
+    }
+}
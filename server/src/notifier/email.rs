@@ -10,13 +10,17 @@ use minijinja::context;
 use serde::{Deserialize, Serialize};
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::live;
+use crate::notifier::{block_on, get_tag, Event, HostStat, NOTIFIER_HANDLE, TEST_MSG};
 
-const KIND: &str = "email";
+pub const KIND: &str = "email";
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     pub enabled: bool,
+    // 实例名, 用于路由规则引用, 为空时使用 kind
+    #[serde(default = "Default::default")]
+    pub name: String,
     pub server: String,
     pub username: String,
     pub password: String,
@@ -26,6 +30,9 @@ pub struct Config {
     pub online_tpl: String,
     pub offline_tpl: String,
     pub custom_tpl: String,
+    // 只接收匹配的主机的上下线及自定义通知, 如 env:prod,provider:aws, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub selector: String,
 }
 
 pub struct Email {
@@ -35,51 +42,65 @@ pub struct Email {
 impl Email {
     pub fn new(cfg: &'static Config) -> Self {
         let o = Self { config: cfg };
-        add_template(KIND, get_tag(&Event::NodeUp), o.config.online_tpl.to_string());
-        add_template(KIND, get_tag(&Event::NodeDown), o.config.offline_tpl.to_string());
-        add_template(KIND, get_tag(&Event::Custom), o.config.custom_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::NodeUp), o.config.online_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::NodeDown), o.config.offline_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::Custom), o.config.custom_tpl.to_string());
         o
     }
-}
-
-impl crate::notifier::Notifier for Email {
-    fn kind(&self) -> &'static str {
-        KIND
-    }
 
-    fn send_notify(&self, html_content: String) -> Result<()> {
+    fn message(&self, html_content: String) -> Result<Message> {
         let mut builder = Message::builder()
             .subject(self.config.subject.to_string())
-            .from(format!("ServerStatus <{}>", self.config.username).parse().unwrap());
+            .from(format!("ServerStatus <{}>", self.config.username).parse()?);
 
-        let mailboxes: Mailboxes = self.config.to.parse().expect("Invalid email addresses");
+        let mailboxes: Mailboxes = self.config.to.parse()?;
         for mailbox in mailboxes.iter() {
             builder = builder.to(mailbox.clone());
         }
 
-        let email = builder
-            .multipart(
-                MultiPart::alternative().singlepart(
-                    SinglePart::builder()
-                        .header(header::ContentType::TEXT_HTML)
-                        .body(html_content),
-                ),
-            )
-            .unwrap();
+        let email = builder.multipart(
+            MultiPart::alternative().singlepart(
+                SinglePart::builder()
+                    .header(header::ContentType::TEXT_HTML)
+                    .body(html_content),
+            ),
+        )?;
+        Ok(email)
+    }
 
+    fn mailer(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
         let creds = Credentials::new(self.config.username.to_string(), self.config.password.to_string());
+        Ok(AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.config.server)?
+            .credentials(creds)
+            .build())
+    }
+}
+
+impl crate::notifier::Notifier for Email {
+    fn kind(&self) -> &'static str {
+        KIND
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn selector(&self) -> &str {
+        &self.config.selector
+    }
 
-        let smtp_server = self.config.server.to_string();
+    fn send_notify(&self, html_content: String) -> Result<()> {
+        if super::dry_run(self.name(), &html_content) {
+            return Ok(());
+        }
+        let email = self.message(html_content)?;
+        let mailer = self.mailer()?;
         let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
+        let name = self.config.name.as_str();
         handle.spawn(async move {
-            let mailer: AsyncSmtpTransport<Tokio1Executor> =
-                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_server.as_str())
-                    .unwrap()
-                    .credentials(creds)
-                    .build();
-
             // Send the email
-            match mailer.send(email).await {
+            let result = mailer.send(email).await.map(|o| o.code().to_string());
+            match &result {
                 Ok(_) => {
                     info!("Email sent successfully!");
                 }
@@ -87,14 +108,22 @@ impl crate::notifier::Notifier for Email {
                     error!("Could not send email: {:?}", err);
                 }
             }
+            live::delivered(name, result);
         });
 
         Ok(())
     }
 
+    fn deliver_test(&self) -> Result<String> {
+        let email = self.message(TEST_MSG.to_string())?;
+        let mailer = self.mailer()?;
+        let resp = block_on(mailer.send(email))?;
+        Ok(format!("{} {}", resp.code(), resp.message().collect::<Vec<_>>().join(" ")))
+    }
+
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
         render_template(
-            self.kind(),
+            self.name(),
             get_tag(e),
             context!(host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
@@ -2,6 +2,7 @@
 use anyhow::Result;
 use chrono::Local;
 use minijinja::context;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -11,13 +12,57 @@ use tokio::io::AsyncWriteExt;
 use crate::jinja::{add_template, render_template};
 use crate::notifier::{Event, HostStat, NOTIFIER_HANDLE};
 
-const KIND: &str = "log";
+pub const KIND: &str = "log";
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     pub enabled: bool,
+    // 实例名, 用于路由规则引用, 为空时使用 kind
+    #[serde(default = "Default::default")]
+    pub name: String,
     pub log_dir: String,
     pub tpl: String,
+    // 只接收匹配的主机的上下线及自定义通知, 如 env:prod,provider:aws, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub selector: String,
+}
+
+// 第一个 log 实例的目录, dry-run 记录写入这里
+pub static LOG_DIR: OnceCell<String> = OnceCell::new();
+
+// 追加写入当日的日志文件
+pub fn append(log_dir: &str, content: String) {
+    let mut line = content;
+    if !line.ends_with('\n') {
+        line.push('\n');
+    }
+    let dt = Local::now().format("%Y-%m-%d").to_string();
+    let log_file = Path::new(log_dir)
+        .join(format!("ssr.log.{dt}"))
+        .to_string_lossy()
+        .to_string();
+
+    let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
+    handle.spawn(async move {
+        //
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(true)
+            .open(&log_file)
+            .await
+            .unwrap_or_else(|_| panic!("can't create log `{log_file}"));
+
+        // 一次写入整行, 避免并发写入时内容交错
+        let _ = file
+            .write(line.as_bytes())
+            .await
+            .unwrap_or_else(|_| panic!("can't write log `{log_file}"));
+
+        file.flush()
+            .await
+            .unwrap_or_else(|_| panic!("can't flush log `{log_file}"));
+    });
 }
 
 pub struct Log {
@@ -28,10 +73,11 @@ impl Log {
     pub fn new(cfg: &'static Config) -> Self {
         let o = Self { config: cfg };
 
-        add_template(KIND, "tpl", o.config.tpl.to_string());
+        add_template(&cfg.name, "tpl", o.config.tpl.to_string());
 
         // build dir
         fs::create_dir_all(&cfg.log_dir).unwrap_or_else(|_| panic!("can't create dir `{}", cfg.log_dir));
+        let _ = LOG_DIR.set(cfg.log_dir.to_string());
         o
     }
 }
@@ -41,50 +87,26 @@ impl crate::notifier::Notifier for Log {
         KIND
     }
 
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn selector(&self) -> &str {
+        &self.config.selector
+    }
+
     fn send_notify(&self, content: String) -> Result<()> {
         if content.is_empty() {
             return Ok(());
         }
 
-        let dt = Local::now().format("%Y-%m-%d").to_string();
-        let log_file = Path::new(&self.config.log_dir)
-            .join(format!("ssr.log.{dt}"))
-            .to_string_lossy()
-            .to_string();
-
-        let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
-        handle.spawn(async move {
-            //
-            let mut file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(&log_file)
-                .await
-                .unwrap_or_else(|_| panic!("can't create log `{log_file}"));
-
-            let _ = file
-                .write(content.as_bytes())
-                .await
-                .unwrap_or_else(|_| panic!("can't write log `{log_file}"));
-
-            if !content.ends_with('\n') {
-                let _ = file
-                    .write(b"\n")
-                    .await
-                    .unwrap_or_else(|_| panic!("can't write log `{log_file}"));
-            }
-
-            file.flush()
-                .await
-                .unwrap_or_else(|_| panic!("can't flush log `{log_file}"));
-        });
+        append(&self.config.log_dir, content);
         Ok(())
     }
 
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
         render_template(
-            self.kind(),
+            self.name(),
             "tpl",
             context!(event => e, host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
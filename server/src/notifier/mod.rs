@@ -1,19 +1,265 @@
-use anyhow::Result;
-use once_cell::sync::Lazy;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 use tokio::runtime::Handle;
 
+use crate::config::NotifyRoute;
+use crate::labels::Selector;
 use crate::payload::HostStat;
 
+pub mod alertmanager;
 pub mod email;
 pub mod log;
+pub mod silence;
 pub mod tgbot;
 pub mod webhook;
 pub mod wechat;
 
 pub static NOTIFIER_HANDLE: Lazy<Mutex<Option<Handle>>> = Lazy::new(Default::default);
 
+pub const TEST_MSG: &str = "❗ServerStatus test msg";
+
+type Notifiers = Arc<Mutex<Vec<Box<dyn Notifier + Send>>>>;
+static NOTIFIERS: OnceCell<Notifiers> = OnceCell::new();
+
+pub fn set_notifiers(notifiers: Notifiers) {
+    let _ = NOTIFIERS.set(notifiers);
+}
+
+type Boxed = Box<dyn Notifier + Send>;
+
+struct Entry {
+    check: fn(toml::Table) -> Result<()>,
+    build: fn(toml::Table) -> Result<Boxed>,
+}
+
+// 通知方式注册表, kind => 配置校验及构造, 新增通知方式只需在这里注册
+static REGISTRY: Lazy<HashMap<&'static str, Entry>> = Lazy::new(|| {
+    HashMap::from([
+        (
+            tgbot::KIND,
+            Entry {
+                check: |t| parse::<tgbot::Config>(t).map(|_| ()),
+                build: |t| Ok(Box::new(tgbot::TGBot::new(leak(t)?))),
+            },
+        ),
+        (
+            wechat::KIND,
+            Entry {
+                check: |t| parse::<wechat::Config>(t).map(|_| ()),
+                build: |t| Ok(Box::new(wechat::WeChat::new(leak(t)?))),
+            },
+        ),
+        (
+            email::KIND,
+            Entry {
+                check: |t| parse::<email::Config>(t).map(|_| ()),
+                build: |t| Ok(Box::new(email::Email::new(leak(t)?))),
+            },
+        ),
+        (
+            log::KIND,
+            Entry {
+                check: |t| parse::<log::Config>(t).map(|_| ()),
+                build: |t| Ok(Box::new(log::Log::new(leak(t)?))),
+            },
+        ),
+        (
+            webhook::KIND,
+            Entry {
+                check: |t| parse::<webhook::Config>(t).map(|_| ()),
+                build: |t| Ok(Box::new(webhook::Webhook::new(leak(t)?))),
+            },
+        ),
+        (
+            alertmanager::KIND,
+            Entry {
+                check: |t| parse::<alertmanager::Config>(t).map(|_| ()),
+                build: |t| Ok(Box::new(alertmanager::AlertManager::new(leak(t)?))),
+            },
+        ),
+    ])
+});
+
+fn parse<T: DeserializeOwned>(t: toml::Table) -> Result<T> {
+    Ok(toml::Value::Table(t).try_into()?)
+}
+
+// 实例在整个运行期间使用, 配置直接 leak 为 'static
+fn leak<T: DeserializeOwned>(t: toml::Table) -> Result<&'static T> {
+    Ok(Box::leak(Box::new(parse(t)?)))
+}
+
+pub fn kind_of(t: &toml::Table) -> &str {
+    t.get("kind").and_then(|o| o.as_str()).unwrap_or_default()
+}
+
+pub fn name_of(t: &toml::Table) -> &str {
+    t.get("name").and_then(|o| o.as_str()).unwrap_or_default()
+}
+
+fn entry(t: &toml::Table) -> Result<&'static Entry> {
+    let kind = kind_of(t);
+    REGISTRY
+        .get(kind)
+        .ok_or_else(|| anyhow::anyhow!("unknown notifier kind `{kind}`"))
+}
+
+// 校验通知实例的配置
+pub fn check(t: &toml::Table) -> Result<()> {
+    (entry(t)?.check)(t.clone())
+}
+
+// 按配置构造已启用的通知实例, 配置错误的实例跳过
+pub fn build(tables: Vec<toml::Table>) -> Vec<Boxed> {
+    let mut notifiers = Vec::new();
+    for t in tables {
+        if !t.get("enabled").and_then(|o| o.as_bool()).unwrap_or_default() {
+            continue;
+        }
+        let name = name_of(&t).to_string();
+        match entry(&t).and_then(|o| (o.build)(t)) {
+            Ok(o) => notifiers.push(o),
+            Err(err) => eprintln!("❗notifier `{name}` is invalid, ignored: {err}"),
+        }
+    }
+    notifiers
+}
+
+// 启动通知实例的后台任务, 如 tgbot 交互命令
+pub fn start() {
+    if let Some(notifiers) = NOTIFIERS.get() {
+        for notifier in &*notifiers.lock().unwrap() {
+            notifier.start();
+        }
+    }
+}
+
+// 服务自身的告警(如数据库损坏), 直接发给所有已启用的通知方式
+pub fn alert(msg: &str) {
+    if !crate::leader::is_notify_leader() {
+        return;
+    }
+    crate::events::alert(msg);
+    if let Some(notifiers) = NOTIFIERS.get() {
+        for notifier in &*notifiers.lock().unwrap() {
+            if let Err(err) = notifier.send_notify(msg.to_string()) {
+                error!("{} send alert error => {:?}", notifier.name(), err);
+            }
+        }
+    }
+}
+
+// 外部告警等不对应主机的告警, 按告警自带的标签路由
+pub fn alert_labels(msg: &str, labels: &HashMap<String, String>) {
+    if !crate::leader::is_notify_leader() {
+        return;
+    }
+    crate::events::alert(msg);
+    if let Some(notifiers) = NOTIFIERS.get() {
+        for notifier in &*notifiers.lock().unwrap() {
+            if !routed_by(notifier.as_ref(), |k| labels.get(k).map(String::as_str)) {
+                continue;
+            }
+            if let Err(err) = notifier.send_notify(msg.to_string()) {
+                error!("{} send alert error => {:?}", notifier.name(), err);
+            }
+        }
+    }
+}
+
+// 主机相关的告警(如告警规则), 按 selector 及 notify_routes 路由, 静默或已确认的主机不发送
+pub fn alert_host(msg: &str, stat: &HostStat) {
+    if !crate::leader::is_notify_leader() {
+        return;
+    }
+    crate::events::host_alert(stat, msg);
+    if silence::is_muted(&Event::Custom, &stat.name) {
+        dry_run_route("*", &Event::Custom, &stat.name, "muted");
+        return;
+    }
+    if let Some(notifiers) = NOTIFIERS.get() {
+        for notifier in &*notifiers.lock().unwrap() {
+            if !routed(notifier.as_ref(), stat) {
+                continue;
+            }
+            if let Err(err) = notifier.send_notify(msg.to_string()) {
+                error!("{} send alert error => {:?}", notifier.name(), err);
+            }
+        }
+    }
+}
+
+pub fn is_dry_run() -> bool {
+    crate::G_CONFIG.get().map(|o| o.notify_dry_run).unwrap_or_default()
+}
+
+// 演练记录写入 log 通知方式, 未启用时输出到服务日志
+fn dry_run_log(line: String) {
+    match log::LOG_DIR.get() {
+        Some(log_dir) => log::append(log_dir, line),
+        None => info!("{line}"),
+    }
+}
+
+// 演练模式下拦截实际发送, 记录渲染后的内容, 返回 true 表示已拦截
+pub fn dry_run(notifier: &str, content: &str) -> bool {
+    if !is_dry_run() {
+        return false;
+    }
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+    dry_run_log(format!("[dry-run] {now} {notifier} deliver =>\n{content}"));
+    true
+}
+
+// 演练模式下记录路由结果
+pub fn dry_run_route(notifier: &str, e: &Event, name: &str, decision: &str) {
+    if is_dry_run() {
+        let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+        dry_run_log(format!("[dry-run] {now} {notifier} route {} {name} => {decision}", get_tag(e)));
+    }
+}
+
+// 在通知 runtime 上同步等待发送结果, 不能在 runtime 的工作线程内调用
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
+    handle.block_on(f)
+}
+
+// 已启用的通知方式及其测试接口
+pub fn list() -> Vec<serde_json::Value> {
+    NOTIFIERS
+        .get()
+        .map(|notifiers| {
+            notifiers
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|o| {
+                    serde_json::json!({
+                        "name": o.name(),
+                        "kind": o.kind(),
+                        "selector": o.selector(),
+                        "test": format!("/api/admin/notifiers/{}/test", o.name()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 通过指定名称的通知实例发送测试消息, 未启用时返回 None
+pub fn test(name: &str) -> Option<Result<String>> {
+    let notifiers = NOTIFIERS.get()?.lock().unwrap();
+    let notifier = notifiers.iter().find(|o| o.name() == name)?;
+    Some(notifier.deliver_test())
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub enum Event {
     NodeUp,
@@ -29,12 +275,161 @@ fn get_tag(e: &Event) -> &'static str {
     }
 }
 
+// 每日汇总等带图片的消息, 不支持图片的通知方式只发送文字
+pub fn send_image(caption: &str, png: Option<Vec<u8>>) {
+    if let Some(notifiers) = NOTIFIERS.get() {
+        for notifier in &*notifiers.lock().unwrap() {
+            let result = match &png {
+                Some(png) => notifier.send_image(caption.to_string(), png.clone()),
+                None => notifier.send_notify(caption.to_string()),
+            };
+            if let Err(err) = result {
+                error!("{} send image error => {:?}", notifier.name(), err);
+            }
+        }
+    }
+}
+
+// 按通知方式的 selector 及 notify_routes 路由, selector 配置无效时不过滤, 避免漏发
+pub fn routed(notifier: &dyn Notifier, stat: &HostStat) -> bool {
+    routed_by(notifier, |k| stat.label(k))
+}
+
+fn routed_by<'a>(notifier: &dyn Notifier, get: impl Fn(&str) -> Option<&'a str> + Copy) -> bool {
+    let selected = Selector::parse(notifier.selector()).map(|o| o.matches(get)).unwrap_or(true);
+    let routes = crate::G_CONFIG.get().map(|o| o.notify_routes.as_slice()).unwrap_or_default();
+    selected && route_allows(routes, notifier.name(), get, Utc::now())
+}
+
+// 路由规则在 now 是否处于生效时段, 时段外的规则视为不匹配, 可以配合其他规则按时间切换通知方式
+pub fn active_at(route: &NotifyRoute, now: DateTime<Utc>) -> Result<bool> {
+    if route.hours.is_empty() {
+        return Ok(true);
+    }
+    let (start, end) = route
+        .hours
+        .split_once('-')
+        .ok_or_else(|| anyhow!("invalid hours `{}`, expect HH:MM-HH:MM", route.hours))?;
+    let parse = |s: &str| NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|err| anyhow!("invalid time `{s}`: {err}"));
+    let (start, end) = (parse(start)?, parse(end)?);
+    let t = match route.timezone.is_empty() {
+        true => now.with_timezone(&Local).time(),
+        false => {
+            let tz: chrono_tz::Tz = route.timezone.parse().map_err(|err| anyhow!("{err}"))?;
+            now.with_timezone(&tz).time()
+        }
+    };
+    Ok(match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= t && t < end,
+        std::cmp::Ordering::Greater => t >= start || t < end,
+        std::cmp::Ordering::Equal => true,
+    })
+}
+
+// 有规则匹配主机时只发给规则中列出的实例, 没有规则匹配时发给全部实例, 时段配置无效时按全天生效
+fn route_allows<'a>(
+    routes: &[NotifyRoute],
+    name: &str,
+    get: impl Fn(&str) -> Option<&'a str> + Copy,
+    now: DateTime<Utc>,
+) -> bool {
+    let mut matched = routes
+        .iter()
+        .filter(|r| Selector::parse(&r.selector).map(|o| o.matches(get)).unwrap_or(false))
+        .filter(|r| active_at(r, now).unwrap_or(true))
+        .peekable();
+    matched.peek().is_none() || matched.any(|r| r.notifiers.iter().any(|o| o == name))
+}
+
 pub trait Notifier {
     fn kind(&self) -> &'static str;
+    // 实例名, 同一 kind 可以有多个实例
+    fn name(&self) -> &str {
+        self.kind()
+    }
+    // 主机标签选择器, 为空时接收全部主机的通知
+    fn selector(&self) -> &str {
+        ""
+    }
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()>;
     // send notify impl
     fn send_notify(&self, content: String) -> Result<()>;
+    fn send_image(&self, caption: String, _png: Vec<u8>) -> Result<()> {
+        self.send_notify(caption)
+    }
     fn notify_test(&self) -> Result<()> {
-        self.send_notify("❗ServerStatus test msg".to_string())
+        self.send_notify(TEST_MSG.to_string())
+    }
+    // 同步发送测试消息并返回发送结果, 不经过 dry-run
+    fn deliver_test(&self) -> Result<String> {
+        self.notify_test().map(|_| "queued".to_string())
+    }
+    // 通知实例自己的后台任务
+    fn start(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(selector: &str, notifier: &str, hours: &str, timezone: &str) -> NotifyRoute {
+        NotifyRoute {
+            selector: selector.to_string(),
+            notifiers: vec![notifier.to_string()],
+            hours: hours.to_string(),
+            timezone: timezone.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_active_at() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let day = route("", "pushover", "09:00-22:00", "Asia/Shanghai");
+        // 01:30 UTC 为上海 09:30
+        assert!(active_at(&day, at("2024-01-01T01:30:00Z")).unwrap());
+        assert!(!active_at(&day, at("2024-01-01T14:30:00Z")).unwrap());
+        let night = route("", "email", "22:00-09:00", "Asia/Shanghai");
+        assert!(!active_at(&night, at("2024-01-01T01:30:00Z")).unwrap());
+        assert!(active_at(&night, at("2024-01-01T14:30:00Z")).unwrap());
+        // 夏令时, 纽约 7 月为 UTC-4
+        let ny = route("", "email", "09:00-17:00", "America/New_York");
+        assert!(active_at(&ny, at("2024-07-01T13:30:00Z")).unwrap());
+        assert!(!active_at(&ny, at("2024-01-01T13:30:00Z")).unwrap());
+
+        assert!(active_at(&route("", "email", "", ""), at("2024-01-01T00:00:00Z")).unwrap());
+        assert!(active_at(&route("", "email", "9-22", ""), Utc::now()).is_err());
+        assert!(active_at(&route("", "email", "09:00-22:00", "Mars/Base"), Utc::now()).is_err());
+
+        // 白天通过 pushover, 其余时间通过 email
+        let routes = vec![day, night];
+        let none = |_: &str| None;
+        assert!(route_allows(&routes, "pushover", none, at("2024-01-01T01:30:00Z")));
+        assert!(!route_allows(&routes, "email", none, at("2024-01-01T01:30:00Z")));
+        assert!(!route_allows(&routes, "pushover", none, at("2024-01-01T14:30:00Z")));
+        assert!(route_allows(&routes, "email", none, at("2024-01-01T14:30:00Z")));
+    }
+
+    #[test]
+    fn test_route_allows() {
+        let now = Utc::now();
+        let routes = vec![
+            route("env:prod", "ops", "", ""),
+            route("team:db", "dba", "", ""),
+        ];
+        let labels = |env: &'static str, team: &'static str| {
+            move |k: &str| match k {
+                "env" => Some(env),
+                "team" => Some(team),
+                _ => None,
+            }
+        };
+
+        assert!(route_allows(&routes, "ops", labels("prod", "web"), now));
+        assert!(!route_allows(&routes, "dba", labels("prod", "web"), now));
+        assert!(route_allows(&routes, "dba", labels("prod", "db"), now));
+        assert!(route_allows(&routes, "ops", labels("prod", "db"), now));
+        // 没有规则匹配时发给全部实例
+        assert!(route_allows(&routes, "dba", labels("dev", "web"), now));
+        assert!(route_allows(&[], "any", labels("dev", "web"), now));
     }
 }
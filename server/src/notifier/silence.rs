@@ -0,0 +1,74 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::live;
+use crate::notifier::Event;
+
+// 主机静默截止时间, 只在当前实例内存中, 重启或 leader 切换后失效
+static SILENCED: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(Default::default);
+// 已确认的主机, 下一次上下线前不再发送告警
+static ACKED: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+pub fn silence(name: &str, secs: u64) -> u64 {
+    let until = now().saturating_add(secs);
+    SILENCED.lock().unwrap().insert(name.to_string(), until);
+    live::publish("silence", json!({ "action": "silence", "name": name, "until": until }));
+    until
+}
+
+pub fn unsilence(name: &str) -> bool {
+    let silenced = SILENCED.lock().unwrap().remove(name).is_some();
+    let removed = ACKED.lock().unwrap().remove(name) || silenced;
+    if removed {
+        live::publish("silence", json!({ "action": "unsilence", "name": name }));
+    }
+    removed
+}
+
+pub fn ack(name: &str) {
+    ACKED.lock().unwrap().insert(name.to_string());
+    live::publish("silence", json!({ "action": "ack", "name": name }));
+}
+
+// 上下线时清除确认状态, 返回是否跳过本次通知
+pub fn is_muted(e: &Event, name: &str) -> bool {
+    let mut acked = ACKED.lock().unwrap();
+    let muted = match e {
+        Event::NodeUp | Event::NodeDown => {
+            acked.remove(name);
+            false
+        }
+        Event::Custom => acked.contains(name),
+    };
+    let mut silenced = SILENCED.lock().unwrap();
+    match silenced.get(name) {
+        Some(&until) if until > now() => true,
+        Some(_) => {
+            silenced.remove(name);
+            muted
+        }
+        None => muted,
+    }
+}
+
+// 静默中的主机及截止时间, 已确认的主机截止时间为 0
+pub fn list() -> Vec<(String, u64)> {
+    let now = now();
+    let mut o = SILENCED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, &until)| until > now)
+        .map(|(name, &until)| (name.to_string(), until))
+        .collect::<Vec<_>>();
+    o.extend(ACKED.lock().unwrap().iter().map(|name| (name.to_string(), 0)));
+    o.sort();
+    o
+}
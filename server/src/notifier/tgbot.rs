@@ -1,6 +1,6 @@
 #![deny(warnings)]
 use anyhow::Result;
-use log::{error, info};
+use log::{error, info, warn};
 use minijinja::context;
 use reqwest;
 use serde::{Deserialize, Serialize};
@@ -8,19 +8,55 @@ use std::collections::HashMap;
 use tokio::time::Duration;
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::live;
+use crate::outbound;
+use crate::notifier::{block_on, get_tag, silence, Event, HostStat, NOTIFIER_HANDLE, TEST_MSG};
+use crate::config::parse_duration;
+use crate::{leader, G_STATS_MGR};
+use stat_common::utils::bytes2human;
 
-const KIND: &str = "tgbot";
+pub const KIND: &str = "tgbot";
+// getUpdates 长轮询超时, 秒
+const POLL_TIMEOUT: u64 = 30;
+// 未指定时长时 /silence 默认静默时间
+const DEFAULT_SILENCE: u64 = 3600;
+// sendPhoto caption 长度限制
+const MAX_CAPTION: usize = 1024;
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     pub enabled: bool,
+    // 实例名, 用于路由规则引用, 为空时使用 kind
+    #[serde(default = "Default::default")]
+    pub name: String,
     pub bot_token: String,
     pub chat_id: String,
     pub title: String,
     pub online_tpl: String,
     pub offline_tpl: String,
     pub custom_tpl: String,
+    // 为空时使用全局代理
+    #[serde(default = "Default::default")]
+    pub proxy: String,
+    // 交互命令 /status /ack /silence, 通过 getUpdates 长轮询接收
+    #[serde(default = "Default::default")]
+    pub commands: bool,
+    // 允许执行命令的 chat id, 为空时只允许 chat_id
+    #[serde(default = "Default::default")]
+    pub allowed_chat_ids: Vec<String>,
+    // 只接收匹配的主机的上下线及自定义通知, 如 env:prod,provider:aws, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub selector: String,
+}
+
+impl Config {
+    fn is_allowed(&self, chat_id: i64) -> bool {
+        let id = chat_id.to_string();
+        match self.allowed_chat_ids.is_empty() {
+            true => self.chat_id == id,
+            false => self.allowed_chat_ids.contains(&id),
+        }
+    }
 }
 
 pub struct TGBot {
@@ -34,23 +70,52 @@ impl TGBot {
         let o = Self {
             config: cfg,
             tg_url: format!("https://api.telegram.org/bot{}/sendMessage", &cfg.bot_token),
-            http_client: reqwest::Client::new(),
+            http_client: outbound::http_client(&cfg.proxy),
         };
 
-        add_template(KIND, get_tag(&Event::NodeUp), o.config.online_tpl.to_string());
-        add_template(KIND, get_tag(&Event::NodeDown), o.config.offline_tpl.to_string());
-        add_template(KIND, get_tag(&Event::Custom), o.config.custom_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::NodeUp), o.config.online_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::NodeDown), o.config.offline_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::Custom), o.config.custom_tpl.to_string());
 
         o
     }
 }
 
+// 发送文字消息, 返回 telegram 的响应内容
+async fn send_message(http_client: &reqwest::Client, tg_url: &str, data: &HashMap<&str, String>) -> Result<String> {
+    let resp = http_client
+        .post(tg_url)
+        .timeout(Duration::from_secs(5))
+        .json(data)
+        .send()
+        .await
+        // url 中包含 bot token, 不能出现在错误信息里
+        .map_err(|e| e.without_url())?;
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("{status} => {body}");
+    }
+    Ok(body)
+}
+
 impl crate::notifier::Notifier for TGBot {
     fn kind(&self) -> &'static str {
         KIND
     }
 
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn selector(&self) -> &str {
+        &self.config.selector
+    }
+
     fn send_notify(&self, html_content: String) -> Result<()> {
+        if super::dry_run(self.name(), &html_content) {
+            return Ok(());
+        }
         let mut data = HashMap::new();
         data.insert("chat_id", self.config.chat_id.to_string());
         data.insert("parse_mode", "HTML".to_string());
@@ -59,21 +124,77 @@ impl crate::notifier::Notifier for TGBot {
         let tg_url = self.tg_url.to_string();
         let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
         let http_client = self.http_client.clone();
+        let name = self.config.name.as_str();
         handle.spawn(async move {
-            match http_client
-                .post(&tg_url)
-                .timeout(Duration::from_secs(5))
-                .json(&data)
-                .send()
-                .await
-            {
+            let result = send_message(&http_client, &tg_url, &data).await;
+            match &result {
                 Ok(resp) => {
-                    info!("tg send msg resp => {:?}", resp);
+                    info!("tg send msg resp => {}", resp);
                 }
                 Err(err) => {
                     error!("tg send msg error => {:?}", err);
                 }
             }
+            live::delivered(name, result);
+        });
+
+        Ok(())
+    }
+
+    fn start(&self) {
+        if self.config.commands {
+            tokio::spawn(serve_commands(self.config));
+        }
+    }
+
+    fn deliver_test(&self) -> Result<String> {
+        let data = HashMap::from([
+            ("chat_id", self.config.chat_id.to_string()),
+            ("parse_mode", "HTML".to_string()),
+            ("text", TEST_MSG.to_string()),
+        ]);
+        block_on(send_message(&self.http_client, &self.tg_url, &data))
+    }
+
+    fn send_image(&self, caption: String, png: Vec<u8>) -> Result<()> {
+        if super::dry_run(self.name(), &format!("{caption}\n[image {} bytes]", png.len())) {
+            return Ok(());
+        }
+        let base_url = format!("https://api.telegram.org/bot{}", &self.config.bot_token);
+        let chat_id = self.config.chat_id.to_string();
+        let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
+        let http_client = self.http_client.clone();
+        handle.spawn(async move {
+            // caption 过长时先单独发送文字
+            let mut form = reqwest::multipart::Form::new().text("chat_id", chat_id.to_string());
+            if caption.chars().count() > MAX_CAPTION {
+                let data = HashMap::from([("chat_id", chat_id), ("text", caption)]);
+                if let Err(err) = http_client
+                    .post(format!("{base_url}/sendMessage"))
+                    .timeout(Duration::from_secs(5))
+                    .json(&data)
+                    .send()
+                    .await
+                {
+                    error!("tg send msg error => {:?}", err);
+                }
+            } else {
+                form = form.text("caption", caption);
+            }
+            let photo = match reqwest::multipart::Part::bytes(png).file_name("snapshot.png").mime_str("image/png") {
+                Ok(o) => o,
+                Err(err) => return error!("tg build photo error => {:?}", err),
+            };
+            match http_client
+                .post(format!("{base_url}/sendPhoto"))
+                .timeout(Duration::from_secs(30))
+                .multipart(form.part("photo", photo))
+                .send()
+                .await
+            {
+                Ok(resp) => info!("tg send photo resp => {:?}", resp),
+                Err(err) => error!("tg send photo error => {:?}", err),
+            }
         });
 
         Ok(())
@@ -81,7 +202,7 @@ impl crate::notifier::Notifier for TGBot {
 
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
         render_template(
-            self.kind(),
+            self.name(),
             get_tag(e),
             context!(host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
@@ -100,3 +221,192 @@ impl crate::notifier::Notifier for TGBot {
         })
     }
 }
+
+#[derive(Debug, Deserialize)]
+struct Updates {
+    #[serde(default = "Default::default")]
+    result: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+// 交互命令, 多实例时只由 notify leader 轮询, 避免 getUpdates 冲突
+pub async fn serve_commands(cfg: &'static Config) {
+    let base_url = format!("https://api.telegram.org/bot{}", &cfg.bot_token);
+    let http_client = outbound::http_client(&cfg.proxy);
+    let mut offset = 0_i64;
+    loop {
+        if !leader::is_notify_leader() {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            continue;
+        }
+        let updates = match http_client
+            .get(format!("{base_url}/getUpdates"))
+            .query(&[("offset", offset), ("timeout", POLL_TIMEOUT as i64)])
+            .timeout(Duration::from_secs(POLL_TIMEOUT + 10))
+            .send()
+            .await
+        {
+            Ok(resp) => resp.json::<Updates>().await,
+            Err(err) => Err(err),
+        };
+        let updates = match updates {
+            Ok(o) => o.result,
+            Err(err) => {
+                error!("tg getUpdates error => {:?}", err);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        for update in updates {
+            offset = offset.max(update.update_id + 1);
+            let Some(Message { chat, text: Some(text) }) = update.message else {
+                continue;
+            };
+            if !cfg.is_allowed(chat.id) {
+                warn!("tg command from unauthorized chat {} => {}", chat.id, text);
+                continue;
+            }
+            let stats = G_STATS_MGR.get().unwrap().get_stats();
+            let Some(reply) = handle_command(&text, &stats.servers) else {
+                continue;
+            };
+            let data = HashMap::from([("chat_id", chat.id.to_string()), ("text", reply)]);
+            if let Err(err) = http_client
+                .post(format!("{base_url}/sendMessage"))
+                .timeout(Duration::from_secs(5))
+                .json(&data)
+                .send()
+                .await
+            {
+                error!("tg reply error => {:?}", err);
+            }
+        }
+    }
+}
+
+fn host_status(o: &HostStat) -> String {
+    let online = o.online4 || o.online6;
+    let human = |v: u64| bytes2human(v, 1, o.si);
+    let mut lines = vec![
+        format!("{} {} ({}) {}", if online { "🟢" } else { "🔴" }, o.alias, o.name, o.location),
+        format!("uptime: {}", o.uptime_str),
+        format!("load: {:.2} {:.2} {:.2}", o.load_1, o.load_5, o.load_15),
+        format!("cpu: {:.1}%", o.cpu),
+        format!("mem: {} / {}", human(o.memory_used * 1024), human(o.memory_total * 1024)),
+        format!("disk: {} / {}", human(o.hdd_used * 1024 * 1024), human(o.hdd_total * 1024 * 1024)),
+        format!("net: ↓{}/s ↑{}/s", human(o.network_rx), human(o.network_tx)),
+    ];
+    if let Some(&(_, until)) = silence::list().iter().find(|(name, _)| name == &o.name) {
+        lines.push(match until {
+            0 => "acked".to_string(),
+            _ => format!("silenced until {}", chrono::DateTime::from_timestamp(until as i64, 0).unwrap_or_default()),
+        });
+    }
+    lines.join("\n")
+}
+
+// 返回回复内容, 不是命令时返回 None
+fn handle_command(text: &str, servers: &[HostStat]) -> Option<String> {
+    let mut args = text.split_whitespace();
+    // 群组中为 /status@bot_name
+    let cmd = args.next()?.strip_prefix('/')?.split('@').next()?;
+    let host = args.next();
+    let find = |name: &str| servers.iter().find(|o| o.name == name || o.alias == name);
+
+    let reply = match (cmd, host) {
+        ("status", None) => {
+            let online = servers.iter().filter(|o| o.online4 || o.online6).count();
+            let mut lines = vec![format!("🟢 {online}/{} online", servers.len())];
+            for o in servers.iter().filter(|o| !(o.online4 || o.online6)) {
+                lines.push(format!("🔴 {} ({}) offline", o.alias, o.name));
+            }
+            for (name, until) in silence::list() {
+                lines.push(match until {
+                    0 => format!("🔕 {name} acked"),
+                    _ => format!("🔕 {name} silenced for {}m", (until as i64 - chrono::Utc::now().timestamp()).max(0) / 60),
+                });
+            }
+            lines.join("\n")
+        }
+        ("status", Some(name)) => match find(name) {
+            Some(o) => host_status(o),
+            None => format!("unknown host `{name}`"),
+        },
+        ("ack", Some(name)) => match find(name) {
+            Some(o) => {
+                silence::ack(&o.name);
+                format!("✅ {} acked, alerts muted until it goes up or down", o.alias)
+            }
+            None => format!("unknown host `{name}`"),
+        },
+        ("silence", Some(name)) => match (find(name), args.next().map(parse_duration).unwrap_or(Some(DEFAULT_SILENCE))) {
+            (Some(o), Some(secs)) if secs > 0 => {
+                silence::silence(&o.name, secs);
+                format!("🔕 {} silenced for {}m", o.alias, secs / 60)
+            }
+            (Some(_), _) => "invalid duration, e.g. 30m 2h 1d".to_string(),
+            (None, _) => format!("unknown host `{name}`"),
+        },
+        ("unsilence", Some(name)) => match find(name) {
+            Some(o) if silence::unsilence(&o.name) => format!("🔔 {} unsilenced", o.alias),
+            Some(o) => format!("{} is not silenced", o.alias),
+            None => format!("unknown host `{name}`"),
+        },
+        ("start" | "help", _) => [
+            "/status - all hosts",
+            "/status <host> - host detail",
+            "/ack <host> - mute alerts until the host goes up or down",
+            "/silence <host> [2h] - mute all notifications for a while",
+            "/unsilence <host>",
+        ]
+        .join("\n"),
+        ("ack" | "silence" | "unsilence", None) => format!("usage: /{cmd} <host>"),
+        _ => return None,
+    };
+    Some(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_command() {
+        let servers = vec![
+            HostStat { name: "h1".to_string(), alias: "n1".to_string(), online4: true, ..Default::default() },
+            HostStat { name: "h2".to_string(), alias: "n2".to_string(), ..Default::default() },
+        ];
+        assert_eq!(handle_command("hello", &servers), None);
+        assert_eq!(handle_command("/unknown", &servers), None);
+        let status = handle_command("/status@my_bot", &servers).unwrap();
+        assert!(status.starts_with("🟢 1/2 online\n🔴 n2 (h2) offline"));
+        assert!(handle_command("/status n1", &servers).unwrap().starts_with("🟢 n1 (h1)"));
+        assert_eq!(handle_command("/ack h9", &servers).unwrap(), "unknown host `h9`");
+
+        assert!(handle_command("/silence n2 30m", &servers).unwrap().contains("silenced for 30m"));
+        assert!(silence::is_muted(&Event::NodeUp, "h2"));
+        assert!(handle_command("/unsilence h2", &servers).unwrap().contains("unsilenced"));
+        assert!(!silence::is_muted(&Event::Custom, "h2"));
+
+        handle_command("/ack h1", &servers).unwrap();
+        assert!(silence::is_muted(&Event::Custom, "h1"));
+        assert!(!silence::is_muted(&Event::NodeDown, "h1"));
+        assert!(!silence::is_muted(&Event::Custom, "h1"));
+    }
+}
@@ -2,6 +2,7 @@
 // #![allow(unused)]
 use anyhow::Result;
 use chrono::Local;
+use minijinja::context;
 use reqwest;
 use rhai::serde::{from_dynamic, to_dynamic};
 use rhai::{Array, Dynamic, Engine, ImmutableString, Scope, AST};
@@ -9,9 +10,35 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tokio::time::Duration;
 
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::jinja::{add_template, render_template};
+use crate::live;
+use crate::outbound;
+use crate::notifier::{block_on, get_tag, Event, HostStat, NOTIFIER_HANDLE, TEST_MSG};
 
-const KIND: &str = "webhook";
+pub const KIND: &str = "webhook";
+const SCHEMA_HEADER: &str = "X-ServerStatus-Schema-Version";
+// 1: host 为 stats.json 中的结构; 2: host 中额外包含 ip_info, sys_info, disks
+const LATEST_SCHEMA_VERSION: u32 = 2;
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+fn default_schema_version() -> u32 {
+    1
+}
+
+// 按事件定制请求, 未配置的事件走 script
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct EventTpl {
+    // 为空时使用 receiver.method
+    #[serde(default = "Default::default")]
+    pub method: String,
+    // 为空时使用 receiver.headers 中的 content-type
+    #[serde(default = "Default::default")]
+    pub content_type: String,
+    // jinja 模板, 渲染结果为空时不发送
+    pub body: String,
+}
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Receiver {
@@ -21,13 +48,43 @@ pub struct Receiver {
     pub username: Option<String>,
     pub password: Option<String>,
     pub timeout: u32,
+    #[serde(default = "Default::default")]
     pub script: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    // key: NodeUp | NodeDown | Custom
+    #[serde(default = "Default::default")]
+    pub events: HashMap<String, EventTpl>,
+}
+
+// 按 schema 版本生成 host 数据, 旧版本结构保持不变
+fn host_payload(stat: &HostStat, schema_version: u32) -> Result<serde_json::Value> {
+    let mut host = serde_json::to_value(stat)?;
+    if schema_version >= 2 {
+        if let Some(o) = host.as_object_mut() {
+            o.insert("ip_info".into(), serde_json::to_value(stat.ip_info.as_ref())?);
+            o.insert("sys_info".into(), serde_json::to_value(stat.sys_info.as_ref())?);
+            o.insert("disks".into(), serde_json::to_value(&stat.disks)?);
+        }
+    }
+    Ok(host)
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub enabled: bool,
+    // 实例名, 用于路由规则引用, 为空时使用 kind
+    #[serde(default = "Default::default")]
+    pub name: String,
+    // 为空时使用全局代理
+    #[serde(default = "Default::default")]
+    pub proxy: String,
     pub receiver: Vec<Receiver>,
+    // 只接收匹配的主机的上下线及自定义通知, 如 env:prod,provider:aws, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub selector: String,
 }
 
 pub struct Webhook {
@@ -57,7 +114,7 @@ impl Webhook {
     pub fn new(cfg: &'static Config) -> Self {
         let mut o = Self {
             config: cfg,
-            http_client: reqwest::Client::new(),
+            http_client: outbound::http_client(&cfg.proxy),
             engine: Engine::new(),
             ast_list: Vec::new(),
         };
@@ -66,60 +123,110 @@ impl Webhook {
         o.engine.register_fn("join", join);
         o.engine.register_fn("now_str", now_str);
 
-        for r in o.config.receiver.iter() {
-            if r.enabled {
+        for (idx, r) in o.config.receiver.iter().enumerate() {
+            if r.enabled && !r.script.is_empty() {
                 let ast = o.engine.compile(&r.script).unwrap();
                 o.ast_list.push(Some(ast));
             } else {
                 o.ast_list.push(None);
             }
+            if r.schema_version > LATEST_SCHEMA_VERSION {
+                warn!("webhook `{}` schema_version {} not supported, use {}", r.url, r.schema_version, LATEST_SCHEMA_VERSION);
+            }
+            for (tag, tpl) in r.events.iter() {
+                add_template(&cfg.name, format!("{idx}.{tag}"), tpl.body.to_string());
+            }
         }
 
         o
     }
-    fn call_webhook(&self, r: &'static Receiver, content: String) -> Result<()> {
+    fn call_webhook(&self, r: &'static Receiver, tpl: Option<&'static EventTpl>, content: String) -> Result<()> {
         if content.is_empty() {
             return Ok(());
         }
 
+        let method = match tpl.map(|o| o.method.as_str()).filter(|o| !o.is_empty()) {
+            Some(o) => o,
+            None => r.method.as_str(),
+        };
+        let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())?;
+        if super::dry_run(&self.config.name, &format!("{method} {}\n{content}", r.url)) {
+            return Ok(());
+        }
+
         let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
         let http_client = self.http_client.clone();
+        let name = self.config.name.as_str();
         handle.spawn(async move {
-            let mut http_client_builder = http_client
-                .post(&r.url)
-                .timeout(Duration::from_secs(r.timeout.into()))
-                .body(reqwest::Body::from(content.into_bytes()));
-
-            for (k, v) in r.headers.iter() {
-                http_client_builder = http_client_builder.header(k, v);
-            }
-
-            if r.username.is_some()
-                && r.password.is_some()
-                && !r.username.as_ref().unwrap().is_empty()
-                && !r.password.as_ref().unwrap().is_empty()
-            {
-                http_client_builder = http_client_builder.basic_auth(r.username.as_ref().unwrap(), r.password.as_ref());
-            }
-
-            //
-            match http_client_builder.send().await {
+            let result = request(&http_client, r, tpl, method, content).await;
+            match &result {
                 Ok(resp) => {
-                    info!("webhook send msg resp => {:?}", resp);
+                    info!("webhook send msg resp => {}", resp);
                 }
                 Err(err) => {
                     error!("webhook send msg error => {:?}", err);
                 }
             }
+            live::delivered(name, result);
         });
         Ok(())
     }
 }
+
+// 调用 webhook, 返回响应状态及内容
+async fn request(
+    http_client: &reqwest::Client,
+    r: &Receiver,
+    tpl: Option<&EventTpl>,
+    method: reqwest::Method,
+    content: String,
+) -> Result<String> {
+    let mut http_client_builder = http_client
+        .request(method, &r.url)
+        .timeout(Duration::from_secs(r.timeout.into()))
+        .header(SCHEMA_HEADER, r.schema_version.min(LATEST_SCHEMA_VERSION))
+        .body(reqwest::Body::from(content.into_bytes()));
+
+    let content_type = tpl.map(|o| o.content_type.as_str()).filter(|o| !o.is_empty());
+    for (k, v) in r.headers.iter() {
+        // 事件模板指定了 content-type 时覆盖全局配置
+        if content_type.is_some() && k.eq_ignore_ascii_case("content-type") {
+            continue;
+        }
+        http_client_builder = http_client_builder.header(k, v);
+    }
+    if let Some(content_type) = content_type {
+        http_client_builder = http_client_builder.header(reqwest::header::CONTENT_TYPE, content_type);
+    }
+
+    if let (Some(username), Some(password)) = (r.username.as_ref(), r.password.as_ref()) {
+        if !username.is_empty() && !password.is_empty() {
+            http_client_builder = http_client_builder.basic_auth(username, Some(password));
+        }
+    }
+
+    let resp = http_client_builder.send().await?;
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("{} {status} => {body}", r.url);
+    }
+    Ok(format!("{} {status} => {body}", r.url))
+}
+
 impl crate::notifier::Notifier for Webhook {
     fn kind(&self) -> &'static str {
         KIND
     }
 
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn selector(&self) -> &str {
+        &self.config.selector
+    }
+
     fn send_notify(&self, content: String) -> Result<()> {
         info!("{}", content);
         Ok(())
@@ -130,32 +237,63 @@ impl crate::notifier::Notifier for Webhook {
             if !r.enabled {
                 continue;
             }
-            self.call_webhook(r, "❗ServerStatus test msg".into())?;
+            self.call_webhook(r, None, TEST_MSG.into())?;
         }
         Ok(())
     }
 
+    fn deliver_test(&self) -> Result<String> {
+        let mut results = Vec::new();
+        for r in self.config.receiver.iter().filter(|o| o.enabled) {
+            let method = reqwest::Method::from_bytes(r.method.to_uppercase().as_bytes())?;
+            results.push(block_on(request(&self.http_client, r, None, method, TEST_MSG.into()))?);
+        }
+        Ok(results.join("\n"))
+    }
+
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
         for (idx, r) in self.config.receiver.iter().enumerate() {
             if !r.enabled {
                 continue;
             }
 
+            let tag = get_tag(e);
+            let schema_version = r.schema_version.min(LATEST_SCHEMA_VERSION);
+            let host = host_payload(stat, schema_version)?;
+
+            // jinja 模板
+            if let Some(tpl) = r.events.get(tag) {
+                let body = render_template(
+                    self.name(),
+                    &format!("{idx}.{tag}"),
+                    context!(event => tag, host => host, config => r, ip_info => stat.ip_info, sys_info => stat.sys_info, schema_version => schema_version, now => now_str().as_str()),
+                    false,
+                )?;
+                if !body.trim().is_empty() {
+                    self.call_webhook(r, Some(tpl), body)?;
+                }
+                continue;
+            }
+
+            let ast = match self.ast_list[idx].as_ref() {
+                Some(o) => o,
+                None => continue,
+            };
+
             let mut scope = Scope::new();
-            scope.push("event", get_tag(e));
-            scope.push("host", to_dynamic(stat)?);
+            scope.push("event", tag);
+            scope.push("host", to_dynamic(&host)?);
             scope.push("config", to_dynamic(r)?);
             scope.push("ip_info", to_dynamic(stat.ip_info.as_ref())?);
             scope.push("sys_info", to_dynamic(stat.sys_info.as_ref())?);
+            scope.push("schema_version", schema_version as i64);
 
-            let res: Dynamic = self
-                .engine
-                .eval_ast_with_scope(&mut scope, self.ast_list[idx].as_ref().unwrap())?;
+            let res: Dynamic = self.engine.eval_ast_with_scope(&mut scope, ast)?;
 
             // [notify, json_body/content]
             if let Ok(v) = from_dynamic::<Array>(&res) {
                 if v.len() >= 2 && from_dynamic::<bool>(&v[0]).unwrap_or_default() {
-                    self.call_webhook(r, serde_json::to_string(&v[1]).unwrap_or_default())?
+                    self.call_webhook(r, None, serde_json::to_string(&v[1]).unwrap_or_default())?
                 }
             }
         }
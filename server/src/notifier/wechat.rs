@@ -9,16 +9,23 @@ use std::collections::HashMap;
 use tokio::time::Duration;
 
 use crate::jinja::{add_template, render_template};
-use crate::notifier::{get_tag, Event, HostStat, NOTIFIER_HANDLE};
+use crate::live;
+use crate::outbound;
+use crate::notifier::{block_on, get_tag, Event, HostStat, NOTIFIER_HANDLE, TEST_MSG};
 
 // https://qydev.weixin.qq.com/wiki/index.php?title=%E4%B8%BB%E5%8A%A8%E8%B0%83%E7%94%A8
 // https://qydev.weixin.qq.com/wiki/index.php?title=%E5%8F%91%E9%80%81%E6%8E%A5%E5%8F%A3%E8%AF%B4%E6%98%8E
 static TOKEN_URL: &str = "https://qyapi.weixin.qq.com/cgi-bin/gettoken";
-const KIND: &str = "wechat";
+static UPLOAD_URL: &str = "https://qyapi.weixin.qq.com/cgi-bin/media/upload";
+static SEND_URL: &str = "https://qyapi.weixin.qq.com/cgi-bin/message/send";
+pub const KIND: &str = "wechat";
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     pub enabled: bool,
+    // 实例名, 用于路由规则引用, 为空时使用 kind
+    #[serde(default = "Default::default")]
+    pub name: String,
     pub corp_id: String,
     pub corp_secret: String,
     pub agent_id: String,
@@ -26,6 +33,12 @@ pub struct Config {
     pub online_tpl: String,
     pub offline_tpl: String,
     pub custom_tpl: String,
+    // 为空时使用全局代理
+    #[serde(default = "Default::default")]
+    pub proxy: String,
+    // 只接收匹配的主机的上下线及自定义通知, 如 env:prod,provider:aws, 为空表示全部
+    #[serde(default = "Default::default")]
+    pub selector: String,
 }
 
 pub struct WeChat {
@@ -37,86 +50,149 @@ impl WeChat {
     pub fn new(cfg: &'static Config) -> Self {
         let o = Self {
             config: cfg,
-            http_client: reqwest::Client::new(),
+            http_client: outbound::http_client(&cfg.proxy),
         };
-        add_template(KIND, get_tag(&Event::NodeUp), o.config.online_tpl.to_string());
-        add_template(KIND, get_tag(&Event::NodeDown), o.config.offline_tpl.to_string());
-        add_template(KIND, get_tag(&Event::Custom), o.config.custom_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::NodeUp), o.config.online_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::NodeDown), o.config.offline_tpl.to_string());
+        add_template(&cfg.name, get_tag(&Event::Custom), o.config.custom_tpl.to_string());
 
         o
     }
 }
 
+async fn access_token(http_client: &reqwest::Client, corp_id: &str, corp_secret: &str) -> Result<String> {
+    let data = HashMap::from([("corpid", corp_id), ("corpsecret", corp_secret)]);
+    let resp = http_client
+        .post(TOKEN_URL)
+        .timeout(Duration::from_secs(5))
+        .json(&data)
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    resp["access_token"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("get access_token failed => {resp}"))
+}
+
+// 发送文字消息, 返回接口的响应内容
+async fn send_text(http_client: &reqwest::Client, cfg: &Config, content: String) -> Result<String> {
+    let token = access_token(http_client, &cfg.corp_id, &cfg.corp_secret).await?;
+    let req_data = serde_json::json!({
+        "touser": "@all",
+        "agentid": cfg.agent_id,
+        "msgtype": "text",
+        "text": {
+            "content": content,
+        },
+        "safe": 0
+    });
+    let resp = http_client
+        .post(format!("{SEND_URL}?access_token={token}"))
+        .timeout(Duration::from_secs(5))
+        .json(&req_data)
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    if resp["errcode"].as_i64().unwrap_or_default() != 0 {
+        anyhow::bail!("send msg failed => {resp}");
+    }
+    Ok(resp.to_string())
+}
+
+// 上传临时素材后发送图片消息
+async fn send_image(http_client: &reqwest::Client, cfg: &Config, caption: String, png: Vec<u8>) -> Result<()> {
+    let token = access_token(http_client, &cfg.corp_id, &cfg.corp_secret).await?;
+    let part = reqwest::multipart::Part::bytes(png)
+        .file_name("snapshot.png")
+        .mime_str("image/png")?;
+    let resp = http_client
+        .post(format!("{UPLOAD_URL}?access_token={token}&type=image"))
+        .timeout(Duration::from_secs(30))
+        .multipart(reqwest::multipart::Form::new().part("media", part))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    let media_id = resp["media_id"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("upload image failed => {resp}"))?;
+
+    for req_data in [
+        serde_json::json!({"touser": "@all", "agentid": cfg.agent_id, "msgtype": "text", "text": {"content": caption}, "safe": 0}),
+        serde_json::json!({"touser": "@all", "agentid": cfg.agent_id, "msgtype": "image", "image": {"media_id": media_id}, "safe": 0}),
+    ] {
+        let resp = http_client
+            .post(format!("{SEND_URL}?access_token={token}"))
+            .timeout(Duration::from_secs(5))
+            .json(&req_data)
+            .send()
+            .await?;
+        info!("wechat send msg resp => {:?}", resp);
+    }
+    Ok(())
+}
+
 impl crate::notifier::Notifier for WeChat {
     fn kind(&self) -> &'static str {
         KIND
     }
 
-    fn send_notify(&self, text_content: String) -> Result<()> {
-        // get access_token
-        let mut data = HashMap::new();
-        data.insert("corpid", self.config.corp_id.to_string());
-        data.insert("corpsecret", self.config.corp_secret.to_string());
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn selector(&self) -> &str {
+        &self.config.selector
+    }
 
+    fn send_notify(&self, text_content: String) -> Result<()> {
+        if super::dry_run(self.name(), &text_content) {
+            return Ok(());
+        }
         let http_client = self.http_client.clone();
+        let cfg = self.config;
         let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
-        let agent_id = self.config.agent_id.to_string();
         handle.spawn(async move {
-            match http_client
-                .post(TOKEN_URL)
-                .timeout(Duration::from_secs(5))
-                .json(&data)
-                .send()
-                .await
-            {
+            let result = send_text(&http_client, cfg, text_content).await;
+            match &result {
                 Ok(resp) => {
-                    info!("wechat get access token resp => {:?}", resp);
-                    let json_res = resp.json::<HashMap<String, serde_json::Value>>().await;
-                    if let Ok(json_data) = json_res {
-                        if let Some(access_token) = json_data.get("access_token") {
-                            if let Some(token) = access_token.as_str() {
-                                let req_url =
-                                    format!("https://qyapi.weixin.qq.com/cgi-bin/message/send?access_token={token}");
-                                let req_data = serde_json::json!({
-                                    "touser": "@all",
-                                    "agentid": agent_id,
-                                    "msgtype": "text",
-                                    "text": {
-                                        "content": text_content,
-                                    },
-                                    "safe": 0
-                                });
-
-                                match http_client
-                                    .post(&req_url)
-                                    .timeout(Duration::from_secs(5))
-                                    .json(&req_data)
-                                    .send()
-                                    .await
-                                {
-                                    Ok(resp) => {
-                                        info!("wechat send msg resp => {:?}", resp);
-                                    }
-                                    Err(err) => {
-                                        error!("wechat send msg error => {:?}", err);
-                                    }
-                                }
-                            }
-                        }
-                    }
+                    info!("wechat send msg resp => {}", resp);
                 }
                 Err(err) => {
-                    error!("wechat get access_token error => {:?}", err);
+                    error!("wechat send msg error => {:?}", err);
                 }
             }
+            live::delivered(&cfg.name, result);
         });
 
         Ok(())
     }
 
+    fn deliver_test(&self) -> Result<String> {
+        block_on(send_text(&self.http_client, self.config, TEST_MSG.to_string()))
+    }
+
+    fn send_image(&self, caption: String, png: Vec<u8>) -> Result<()> {
+        if super::dry_run(self.name(), &format!("{caption}\n[image {} bytes]", png.len())) {
+            return Ok(());
+        }
+        let http_client = self.http_client.clone();
+        let cfg = self.config;
+        let handle = NOTIFIER_HANDLE.lock().unwrap().as_ref().unwrap().clone();
+        handle.spawn(async move {
+            if let Err(err) = send_image(&http_client, cfg, caption, png).await {
+                error!("wechat send image error => {:?}", err);
+            }
+        });
+        Ok(())
+    }
+
     fn notify(&self, e: &Event, stat: &HostStat) -> Result<()> {
         render_template(
-            self.kind(),
+            self.name(),
             get_tag(e),
             context!(host => stat, config => self.config, ip_info => stat.ip_info, sys_info => stat.sys_info),
             true,
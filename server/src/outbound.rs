@@ -0,0 +1,109 @@
+#![deny(warnings)]
+use anyhow::Result;
+use hickory_resolver::config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use hyper_v014::client::connect::dns::Name;
+use once_cell::sync::OnceCell;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::config::Dns;
+use crate::G_CONFIG;
+
+static RESOLVER: OnceCell<Arc<DnsResolver>> = OnceCell::new();
+
+// 自定义 dns 解析, 不依赖系统配置
+struct DnsResolver(TokioAsyncResolver);
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let lookup = resolver.lookup_ip(name.as_str()).await?;
+            let addrs: Addrs = Box::new(
+                lookup
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, 0))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+// ip 或 ip:port
+fn parse_addr(s: &str, default_port: u16) -> Result<SocketAddr> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip = s.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()?;
+    Ok(SocketAddr::new(ip, default_port))
+}
+
+pub fn init_resolver(cfg: &Dns) -> Result<()> {
+    if cfg.servers.is_empty() && cfg.doh.is_empty() {
+        return Ok(());
+    }
+
+    let mut resolver_cfg = ResolverConfig::new();
+    for server in cfg.servers.iter() {
+        let addr = parse_addr(server, 53)?;
+        resolver_cfg.add_name_server(NameServerConfig::new(addr, Protocol::Udp));
+        resolver_cfg.add_name_server(NameServerConfig::new(addr, Protocol::Tcp));
+    }
+    // ip[:port]#tls_name, 如 1.1.1.1#cloudflare-dns.com
+    for doh in cfg.doh.iter() {
+        let (addr, tls_name) = doh
+            .split_once('#')
+            .ok_or_else(|| anyhow::anyhow!("invalid doh server `{doh}`, eg: 1.1.1.1#cloudflare-dns.com"))?;
+        let mut ns = NameServerConfig::new(parse_addr(addr, 443)?, Protocol::Https);
+        ns.tls_dns_name = Some(tls_name.to_string());
+        resolver_cfg.add_name_server(ns);
+    }
+
+    let resolver = TokioAsyncResolver::tokio(resolver_cfg, ResolverOpts::default());
+    if RESOLVER.set(Arc::new(DnsResolver(resolver))).is_err() {
+        error!("can't set RESOLVER");
+    }
+    eprintln!("✨ use custom dns resolver, servers: {:?}, doh: {:?}", cfg.servers, cfg.doh);
+    Ok(())
+}
+
+// 不使用代理, 用于单个通知方式绕过全局代理
+const DIRECT: &str = "direct";
+
+// 服务端对外 http 请求统一从这里创建 client
+// proxy 为空时使用全局配置, 支持 http://, https://, socks5://, socks5h://
+pub fn client_builder(proxy: &str) -> ClientBuilder {
+    let mut builder = reqwest::Client::builder();
+    if let Some(resolver) = RESOLVER.get() {
+        builder = builder.dns_resolver(resolver.clone());
+    }
+
+    let (global_proxy, no_proxy) = G_CONFIG
+        .get()
+        .map(|cfg| (cfg.proxy.as_str(), cfg.no_proxy.as_str()))
+        .unwrap_or_default();
+    let proxy = if proxy.is_empty() { global_proxy } else { proxy };
+
+    if proxy.eq_ignore_ascii_case(DIRECT) {
+        builder = builder.no_proxy();
+    } else if !proxy.is_empty() {
+        match Proxy::all(proxy) {
+            Ok(o) => builder = builder.proxy(o.no_proxy(NoProxy::from_string(no_proxy))),
+            Err(err) => error!("invalid proxy `{proxy}` => {:?}", err),
+        }
+    }
+
+    builder
+}
+
+pub fn http_client(proxy: &str) -> reqwest::Client {
+    client_builder(proxy).build().unwrap_or_else(|err| {
+        error!("build http client error => {:?}", err);
+        reqwest::Client::new()
+    })
+}
@@ -1,8 +1,11 @@
 #![deny(warnings)]
 use serde::{Deserialize, Serialize};
-use stat_common::server_status::{DiskInfo, IpInfo, SysInfo};
+use stat_common::server_status::{CgroupStat, DiskInfo, IpInfo, MeshTarget, SysInfo, WatchResult};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::announce::Announcement;
+use crate::labels::Labels;
+
 fn default_as_true() -> bool {
     true
 }
@@ -16,6 +19,13 @@ pub struct HostStat {
     pub host_type: String,
     #[serde(default = "Default::default")]
     pub location: String,
+    // 由服务端配置填充
+    #[serde(skip_deserializing)]
+    pub region: String,
+    #[serde(skip_deserializing)]
+    pub zone: String,
+    #[serde(skip_deserializing)]
+    pub provider: String,
     #[serde(default = "default_as_true")]
     pub notify: bool,
     #[serde(default = "bool::default")]
@@ -69,8 +79,9 @@ pub struct HostStat {
     pub hdd_total: u64,
     pub hdd_used: u64,
 
-    #[serde(skip_deserializing)]
-    pub labels: String,
+    // 对外仍输出 ; 分隔的字符串
+    #[serde(skip_deserializing, serialize_with = "Labels::serialize_str")]
+    pub labels: Labels,
     #[serde(skip_deserializing)]
     pub custom: String,
 
@@ -93,24 +104,135 @@ pub struct HostStat {
     pub pos: usize,
     #[serde(skip_serializing, skip_deserializing)]
     pub disabled: bool,
+    // 入库序号, 用于判断 stats.json 片段是否需要重新序列化
+    #[serde(skip_serializing, skip_deserializing)]
+    pub seq: u64,
 
     // false: KiB (1024), true: KB (1000)
     #[serde(default = "Default::default")]
     pub si: bool,
     #[serde(skip_serializing_if = "Vec::is_empty", default = "Default::default")]
     pub disks: Vec<DiskInfo>,
+    // 各挂载点 inode 使用率(%)的最大值, 由服务端根据 disks 计算, 告警规则用于发现 inode 耗尽
+    #[serde(skip_deserializing)]
+    pub inode_used_percent: f64,
+    // 客户端检查的进程/systemd unit 状态
+    #[serde(skip_serializing_if = "Vec::is_empty", default = "Default::default")]
+    pub watch: Vec<WatchResult>,
+    // 客户端开启 --cgroups 时按内存排序的 systemd slice/service 用量
+    #[serde(skip_serializing_if = "Vec::is_empty", default = "Default::default")]
+    pub cgroups: Vec<CgroupStat>,
+
+    // 服务端探测结果, 与客户端自报的 online4/online6 相互独立
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub probe4: Option<ProbeResult>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub probe6: Option<ProbeResult>,
+
+    // 接收时测得的时钟偏差(s), 由服务端填写, 持续偏差时才在 stats.json 中输出
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub clock_skew: i64,
+    // 重启后从数据库恢复, 尚未重新上报
+    #[serde(skip_deserializing, skip_serializing_if = "is_false")]
+    pub stale: bool,
+    // 已配置但从未上报过
+    #[serde(skip_deserializing, skip_serializing_if = "is_false")]
+    pub never_seen: bool,
+    // 同一主机名被多台机器使用
+    #[serde(skip_deserializing, skip_serializing_if = "is_false")]
+    pub conflict: bool,
+    // 不在公开的 stats.json 中展示, 只出现在管理接口
+    #[serde(skip_deserializing, skip_serializing_if = "is_false")]
+    pub hidden: bool,
+}
+
+impl HostStat {
+    // 没有上报 inode 的磁盘不参与计算
+    pub fn max_inode_percent(&self) -> f64 {
+        self.disks
+            .iter()
+            .filter(|o| o.inode_total > 0)
+            .map(|o| o.inode_used as f64 * 100.0 / o.inode_total as f64)
+            .fold(0.0, f64::max)
+    }
+
+    // 标签选择器取值, 内置字段优先, 其它 key 取自 labels
+    pub fn label(&self, key: &str) -> Option<&str> {
+        match key {
+            "name" => Some(&self.name),
+            "alias" => Some(&self.alias),
+            "type" => Some(&self.host_type),
+            "location" => Some(&self.location),
+            "region" => Some(&self.region),
+            "zone" => Some(&self.zone),
+            "provider" => Some(&self.provider),
+            "gid" => Some(&self.gid),
+            _ => self.labels.get(key),
+        }
+    }
+}
+
+fn is_zero(v: &i64) -> bool {
+    *v == 0
+}
+
+fn is_false(v: &bool) -> bool {
+    !*v
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub ok: bool,
+    // ms
+    pub latency: u32,
+    pub ts: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StatsResp {
     pub updated: u64,
     pub servers: Vec<HostStat>,
+    // 生效中的站点公告
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub announcements: Vec<Announcement>,
 }
 impl StatsResp {
     pub fn new() -> Self {
         Self {
             updated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
             servers: Vec::new(),
+            announcements: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReportAck {
+    pub code: i32,
+    pub message: String,
+    // 建议的上报间隔(s), 0 表示使用客户端配置
+    pub interval: u32,
+    // 服务端接收时间(s)
+    pub server_ts: u64,
+    // 客户端 latest_ts 与 server_ts 的差(s), 正数表示客户端时间偏快
+    pub skew: i64,
+    // 延迟矩阵的探测目标, 未启用 mesh 时为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mesh: Vec<MeshTarget>,
+    // 需要客户端检查的进程/systemd unit, 未配置时为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watch: Vec<String>,
+}
+impl ReportAck {
+    pub fn ok(interval: u32, server_ts: u64, skew: i64) -> Self {
+        Self {
+            code: 0,
+            message: "ok".to_string(),
+            interval,
+            server_ts,
+            skew,
+            mesh: Vec::new(),
+            watch: Vec::new(),
         }
     }
 }
@@ -0,0 +1,160 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Probe;
+use crate::db::Database;
+use crate::metrics;
+use crate::payload::{HostStat, ProbeResult};
+use crate::{G_CONFIG, G_STATS_MGR};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeMethod {
+    // tcp 连接指定端口, 连接被拒绝也视为可达
+    #[default]
+    Tcp,
+    // 调用系统 ping 命令
+    Icmp,
+}
+
+// 入库时的 kind, 与 RESULTS 中的下标对应
+const KINDS: [&str; 2] = ["probe4", "probe6"];
+
+// name => [ipv4, ipv6]
+static RESULTS: Lazy<Mutex<HashMap<String, [Option<ProbeResult>; 2]>>> = Lazy::new(Default::default);
+
+pub fn get(name: &str) -> (Option<ProbeResult>, Option<ProbeResult>) {
+    RESULTS
+        .lock()
+        .ok()
+        .and_then(|o| o.get(name).copied())
+        .map(|[v4, v6]| (v4, v6))
+        .unwrap_or_default()
+}
+
+// 配置的地址优先, 否则按协议族使用上报的 ip_info.query
+pub fn targets(stat: &HostStat, ipv4: &str, ipv6: &str) -> [Option<IpAddr>; 2] {
+    let reported = stat
+        .ip_info
+        .as_ref()
+        .and_then(|o| o.query.parse::<IpAddr>().ok());
+    let pick = |configured: &str, v6: bool| {
+        configured
+            .parse::<IpAddr>()
+            .ok()
+            .or(reported)
+            .filter(|ip| ip.is_ipv6() == v6)
+    };
+    [pick(ipv4, false), pick(ipv6, true)]
+}
+
+fn probe(cfg: &Probe, ip: IpAddr) -> ProbeResult {
+    let timeout = Duration::from_secs(cfg.timeout.max(1));
+    let start = Instant::now();
+    let ok = match cfg.method {
+        ProbeMethod::Tcp => match TcpStream::connect_timeout(&SocketAddr::new(ip, cfg.port), timeout) {
+            Ok(_) => true,
+            Err(e) => e.kind() == ErrorKind::ConnectionRefused,
+        },
+        ProbeMethod::Icmp => Command::new("ping")
+            .args(["-c", "1", "-W", &timeout.as_secs().to_string(), &ip.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false),
+    };
+    ProbeResult {
+        ok,
+        latency: if ok { start.elapsed().as_millis() as u32 } else { 0 },
+        ts: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+    }
+}
+
+fn probe_all(cfg: &'static Probe, db: &Database) {
+    let stats = match G_STATS_MGR.get() {
+        Some(mgr) => mgr.get_all_stats(),
+        None => return,
+    };
+    let hosts_map = &G_CONFIG.get().unwrap().hosts_map;
+
+    let mut jobs = Vec::new();
+    for stat in stats.servers.iter().filter(|o| !o.disabled) {
+        let (ipv4, ipv6) = hosts_map
+            .get(&stat.name)
+            .map(|o| (o.ipv4.as_str(), o.ipv6.as_str()))
+            .unwrap_or_default();
+        for (idx, ip) in targets(stat, ipv4, ipv6).into_iter().enumerate() {
+            if let Some(ip) = ip {
+                jobs.push((stat.name.to_string(), idx, ip));
+            }
+        }
+    }
+
+    let mut records = Vec::with_capacity(jobs.len());
+    for chunk in jobs.chunks(cfg.concurrency.max(1)) {
+        let results = thread::scope(|s| {
+            let handles = chunk
+                .iter()
+                .map(|(_, _, ip)| s.spawn(|| probe(cfg, *ip)))
+                .collect::<Vec<_>>();
+            handles.into_iter().map(|h| h.join().unwrap_or_default()).collect::<Vec<_>>()
+        });
+
+        let mut map = RESULTS.lock().unwrap();
+        for ((name, idx, ip), r) in chunk.iter().zip(results) {
+            metrics::inc("probe_total");
+            if !r.ok {
+                metrics::inc("probe_failed");
+                debug!("probe {name} {ip} failed");
+            }
+            map.entry(name.to_string()).or_default()[*idx] = Some(r);
+            records.push((name.to_string(), KINDS[*idx], r));
+        }
+    }
+
+    if let Err(err) = db.save_probe_results(&records) {
+        error!("save probe results error => {:?}", err);
+    }
+}
+
+pub fn run(cfg: &'static Probe, db: Arc<Database>) {
+    thread::spawn(move || loop {
+        probe_all(cfg, &db);
+        thread::sleep(Duration::from_secs(cfg.interval.max(1)));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stat_common::server_status::IpInfo;
+
+    #[test]
+    fn test_targets() {
+        let mut stat = HostStat::default();
+        assert_eq!(targets(&stat, "", ""), [None, None]);
+
+        stat.ip_info = Some(IpInfo {
+            query: "1.2.3.4".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(targets(&stat, "", ""), [Some("1.2.3.4".parse().unwrap()), None]);
+        assert_eq!(
+            targets(&stat, "", "2001:db8::1"),
+            [Some("1.2.3.4".parse().unwrap()), Some("2001:db8::1".parse().unwrap())]
+        );
+        assert_eq!(targets(&stat, "5.6.7.8", "")[0], Some("5.6.7.8".parse().unwrap()));
+
+        stat.ip_info.as_mut().unwrap().query = "2001:db8::2".to_string();
+        assert_eq!(targets(&stat, "", ""), [None, Some("2001:db8::2".parse().unwrap())]);
+    }
+}
@@ -0,0 +1,316 @@
+#![deny(warnings)]
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+
+use crate::metrics;
+
+// 队列满时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    // 拒绝上报, 返回 429 + Retry-After, 由客户端退避重试
+    #[default]
+    Reject,
+    // 阻塞上报方, 直到有空位
+    Block,
+    // 丢弃最旧的数据
+    DropOldest,
+    // 丢弃新数据
+    DropNewest,
+    // 溢出部分写入磁盘, 空闲时回放
+    Spill,
+}
+
+// 队列已满, 上报方应在 retry_after 秒后重试
+#[derive(Debug, Clone, Copy)]
+pub struct Busy {
+    pub retry_after: u64,
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ingest queue is full, retry after {}s", self.retry_after)
+    }
+}
+
+impl std::error::Error for Busy {}
+
+struct Inner {
+    queue: VecDeque<Value>,
+    // 磁盘上待回放的条数
+    spilled: usize,
+}
+
+// report() 与入库线程之间的有界队列, 存放原始上报数据以便原样落盘
+pub struct StatQueue {
+    capacity: usize,
+    overflow: Overflow,
+    retry_after: u64,
+    spill_path: Option<PathBuf>,
+    inner: Mutex<Inner>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl StatQueue {
+    pub fn new(capacity: usize, overflow: Overflow, retry_after: u64, spill_path: &str) -> Self {
+        let spill_path = if spill_path.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(spill_path))
+        };
+
+        // 上次未处理完的数据
+        let spilled = spill_path
+            .as_ref()
+            .and_then(|p| fs::File::open(p).ok())
+            .map(|f| BufReader::new(f).lines().count())
+            .unwrap_or(0);
+        if spilled > 0 {
+            eprintln!("✨ {spilled} stats pending in spill file, replay them");
+        }
+        metrics::gauge("ingest_queue_capacity", capacity as u64);
+        metrics::gauge("ingest_queue_spill_pending", spilled as u64);
+
+        Self {
+            capacity: capacity.max(1),
+            overflow,
+            retry_after: retry_after.max(1),
+            spill_path,
+            inner: Mutex::new(Inner {
+                queue: VecDeque::with_capacity(capacity),
+                spilled,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    pub fn push(&self, stat: Value) -> Result<(), Busy> {
+        let mut inner = self.inner.lock().unwrap();
+        let full = inner.queue.len() >= self.capacity;
+
+        // 磁盘上还有积压时继续追加到磁盘, 保证先后顺序
+        if (full && self.overflow == Overflow::Spill) || (inner.spilled > 0 && self.spill_path.is_some()) {
+            match self.spill(&[stat]) {
+                Ok(n) if n > 0 => {
+                    inner.spilled += n;
+                    metrics::add("ingest_queue_spilled", n as u64);
+                    metrics::gauge("ingest_queue_spill_pending", inner.spilled as u64);
+                }
+                Ok(_) => metrics::inc("ingest_queue_dropped"),
+                Err(err) => {
+                    error!("spill stat error => {:?}", err);
+                    metrics::inc("ingest_queue_dropped");
+                }
+            }
+            self.not_empty.notify_one();
+            return Ok(());
+        }
+
+        if full {
+            match self.overflow {
+                Overflow::Reject => {
+                    metrics::inc("ingest_queue_rejected");
+                    return Err(Busy {
+                        retry_after: self.retry_after,
+                    });
+                }
+                Overflow::Block | Overflow::Spill => {
+                    inner = self
+                        .not_full
+                        .wait_while(inner, |o| o.queue.len() >= self.capacity)
+                        .unwrap();
+                }
+                Overflow::DropOldest => {
+                    inner.queue.pop_front();
+                    metrics::inc("ingest_queue_dropped");
+                }
+                Overflow::DropNewest => {
+                    metrics::inc("ingest_queue_dropped");
+                    return Ok(());
+                }
+            }
+        }
+        inner.queue.push_back(stat);
+        metrics::gauge("ingest_queue_depth", inner.queue.len() as u64);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    // (当前长度, 容量)
+    pub fn depth(&self) -> (usize, usize) {
+        (self.inner.lock().unwrap().queue.len(), self.capacity)
+    }
+
+    pub fn pop(&self) -> Value {
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            if inner.queue.is_empty() && inner.spilled > 0 {
+                // 每次最多回放 capacity 条, 其余留在磁盘上
+                match self.replay(self.capacity) {
+                    Ok((stats, consumed)) => {
+                        inner.queue.extend(stats);
+                        inner.spilled = inner.spilled.saturating_sub(consumed);
+                    }
+                    Err(err) => {
+                        error!("replay spill file error => {:?}", err);
+                        // 文件已不存在时没有可回放的数据, 其它错误保留计数, 下次再试
+                        if err.downcast_ref::<io::Error>().is_some_and(|o| o.kind() == io::ErrorKind::NotFound) {
+                            inner.spilled = 0;
+                        }
+                    }
+                }
+                metrics::gauge("ingest_queue_spill_pending", inner.spilled as u64);
+            }
+            if let Some(stat) = inner.queue.pop_front() {
+                metrics::gauge("ingest_queue_depth", inner.queue.len() as u64);
+                self.not_full.notify_one();
+                return stat;
+            }
+            inner = self.not_empty.wait(inner).unwrap();
+        }
+    }
+
+    // 退出前把内存中未处理的数据落盘, 下次启动回放
+    pub fn persist(&self) -> Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        let stats = inner.queue.drain(..).collect::<Vec<_>>();
+        let n = self.spill(&stats)?;
+        inner.spilled += n;
+        Ok(n)
+    }
+
+    fn spill(&self, stats: &[Value]) -> Result<usize> {
+        let path = match self.spill_path.as_ref() {
+            Some(p) => p,
+            None => return Ok(0),
+        };
+        if stats.is_empty() {
+            return Ok(0);
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        for stat in stats {
+            let mut line = serde_json::to_vec(stat)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+        }
+        file.flush()?;
+        Ok(stats.len())
+    }
+
+    // 读出最前面的 limit 条, 剩余部分写入临时文件后替换原文件, 返回 (数据, 读取的行数)
+    fn replay(&self, limit: usize) -> Result<(Vec<Value>, usize)> {
+        let path = match self.spill_path.as_ref() {
+            Some(p) => p,
+            None => return Ok((Vec::new(), 0)),
+        };
+        let mut lines = BufReader::new(fs::File::open(path)?).lines();
+        let mut stats = Vec::new();
+        let mut consumed = 0;
+        for line in lines.by_ref().take(limit) {
+            consumed += 1;
+            match serde_json::from_str::<Value>(&line?) {
+                Ok(stat) => stats.push(stat),
+                Err(err) => error!("invalid spilled stat => {:?}", err),
+            }
+        }
+
+        let tmp = path.with_extension("replay");
+        let mut rest = 0;
+        {
+            let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+            for line in lines {
+                writeln!(file, "{}", line?)?;
+                rest += 1;
+            }
+            file.flush()?;
+        }
+        if rest > 0 {
+            fs::rename(&tmp, path)?;
+        } else {
+            fs::remove_file(&tmp)?;
+            fs::remove_file(path)?;
+        }
+        Ok((stats, consumed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn drain(q: &StatQueue, n: usize) -> Vec<Value> {
+        (0..n).map(|_| q.pop()).collect()
+    }
+
+    #[test]
+    fn test_overflow() {
+        let q = StatQueue::new(2, Overflow::Reject, 5, "");
+        assert!(q.push(json!(1)).is_ok() && q.push(json!(2)).is_ok());
+        assert_eq!(q.push(json!(3)).unwrap_err().retry_after, 5);
+        assert_eq!(drain(&q, 2), [json!(1), json!(2)]);
+
+        let q = StatQueue::new(2, Overflow::DropOldest, 1, "");
+        for i in 1..=3 {
+            q.push(json!(i)).unwrap();
+        }
+        assert_eq!(q.depth(), (2, 2));
+        assert_eq!(drain(&q, 2), [json!(2), json!(3)]);
+
+        let q = StatQueue::new(2, Overflow::DropNewest, 1, "");
+        for i in 1..=3 {
+            q.push(json!(i)).unwrap();
+        }
+        assert_eq!(drain(&q, 2), [json!(1), json!(2)]);
+
+        // 满了以后阻塞到有空位
+        let q = Arc::new(StatQueue::new(1, Overflow::Block, 1, ""));
+        q.push(json!(1)).unwrap();
+        let pusher = {
+            let q = q.clone();
+            thread::spawn(move || q.push(json!(2)).unwrap())
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!pusher.is_finished());
+        assert_eq!(q.pop(), json!(1));
+        pusher.join().unwrap();
+        assert_eq!(q.pop(), json!(2));
+    }
+
+    #[test]
+    fn test_spill() {
+        let path = std::env::temp_dir().join(format!("spill-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let q = StatQueue::new(2, Overflow::Spill, 1, path.to_str().unwrap());
+        for i in 1..=5 {
+            q.push(json!(i)).unwrap();
+        }
+        assert_eq!(q.depth(), (2, 2));
+        assert_eq!(drain(&q, 3), [json!(1), json!(2), json!(3)]);
+        // 每次只回放 capacity 条, 磁盘上有积压时新数据也写到磁盘, 保持先后顺序
+        assert_eq!(q.depth(), (1, 2));
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        q.push(json!(6)).unwrap();
+        assert_eq!(drain(&q, 3), [json!(4), json!(5), json!(6)]);
+        assert!(!path.exists());
+
+        // 退出时落盘, 重启后回放
+        q.push(json!(7)).unwrap();
+        assert_eq!(q.persist().unwrap(), 1);
+        let q = StatQueue::new(2, Overflow::Spill, 1, path.to_str().unwrap());
+        assert_eq!(q.pop(), json!(7));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,125 @@
+#![deny(warnings)]
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::config::{Config, HostGroup};
+use crate::notifier;
+use crate::payload::HostStat;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+// 已告警的 (gid, 配额项)
+static VIOLATED: Lazy<Mutex<HashSet<(String, &'static str)>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub online: usize,
+    // 本月流量合计(bytes)
+    pub traffic: u64,
+}
+
+fn usage(servers: &[HostStat]) -> HashMap<&str, Usage> {
+    let mut o: HashMap<&str, Usage> = HashMap::new();
+    for s in servers.iter().filter(|s| !s.gid.is_empty()) {
+        let u = o.entry(s.gid.as_str()).or_default();
+        if s.online4 || s.online6 {
+            u.online += 1;
+        }
+        u.traffic += s.network_in.saturating_sub(s.last_network_in) + s.network_out.saturating_sub(s.last_network_out);
+    }
+    o
+}
+
+// 各配额项是否超限及说明
+fn evaluate(group: &HostGroup, u: Usage) -> Vec<(&'static str, bool, String)> {
+    let mut o = Vec::new();
+    if group.max_traffic > 0 {
+        let used = u.traffic as f64 / GIB as f64;
+        o.push((
+            "traffic",
+            u.traffic > group.max_traffic * GIB,
+            format!("monthly traffic {used:.1}GiB / {}GiB", group.max_traffic),
+        ));
+    }
+    if group.min_online > 0 {
+        o.push((
+            "online",
+            u.online < group.min_online,
+            format!("online members {} / min {}", u.online, group.min_online),
+        ));
+    }
+    o
+}
+
+// 在 timer 线程中调用, 超限及恢复时各告警一次
+pub fn check(cfg: &Config, servers: &[HostStat]) {
+    let usage = usage(servers);
+    let mut msgs = Vec::new();
+    {
+        let mut violated = VIOLATED.lock().unwrap();
+        for group in cfg.hosts_group.iter() {
+            let u = usage.get(group.gid.as_str()).copied().unwrap_or_default();
+            for (item, bad, detail) in evaluate(group, u) {
+                let key = (group.gid.to_string(), item);
+                if bad && violated.insert(key.clone()) {
+                    msgs.push(format!("❗group {} quota exceeded: {}", group.gid, detail));
+                } else if !bad && violated.remove(&key) {
+                    msgs.push(format!("✅ group {} quota recovered: {}", group.gid, detail));
+                }
+            }
+        }
+    }
+    if !msgs.is_empty() {
+        thread::spawn(move || {
+            for msg in msgs {
+                notifier::alert(&msg);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let host = |gid: &str, online: bool, traffic: u64| HostStat {
+            gid: gid.to_string(),
+            online4: online,
+            online6: false,
+            network_in: traffic,
+            ..Default::default()
+        };
+        let servers = vec![host("g1", true, 3 * GIB), host("g1", false, GIB), host("g2", true, 0), host("", true, 0)];
+        let usage = usage(&servers);
+        assert_eq!(usage["g1"], Usage { online: 1, traffic: 4 * GIB });
+        assert!(!usage.contains_key(""));
+
+        let group = HostGroup {
+            gid: "g1".to_string(),
+            password: String::new(),
+            location: String::new(),
+            region: String::new(),
+            zone: String::new(),
+            provider: String::new(),
+            r#type: String::new(),
+            notify: true,
+            pos: 0,
+            weight: 0,
+            labels: Default::default(),
+            resolution: String::new(),
+            units: Default::default(),
+            max_traffic: 3,
+            min_online: 2,
+            secrets: Default::default(),
+            watch: Vec::new(),
+        };
+        let o = evaluate(&group, usage["g1"]);
+        assert_eq!(o.iter().map(|(item, bad, _)| (*item, *bad)).collect::<Vec<_>>(), [("traffic", true), ("online", true)]);
+        assert_eq!(o[0].2, "monthly traffic 4.0GiB / 3GiB");
+        assert!(evaluate(&HostGroup { max_traffic: 0, min_online: 0, ..group }, usage["g1"]).is_empty());
+    }
+}
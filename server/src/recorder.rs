@@ -0,0 +1,135 @@
+#![deny(warnings)]
+// 上报录制及回放, 用于复现用户反馈的聚合/告警问题
+// 录制文件每行一条已接收的原始上报 {"ts": 接收时间(ms), "data": {...}}
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::auth::Reporter;
+use crate::G_STATS_MGR;
+
+static RECORDER: OnceCell<Mutex<File>> = OnceCell::new();
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Entry {
+    pub ts: u64,
+    pub data: Value,
+}
+
+pub fn init(path: &str) -> Result<()> {
+    if path.is_empty() {
+        return Ok(());
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = RECORDER.set(Mutex::new(file));
+    eprintln!("✨ recording accepted reports to {path}");
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    RECORDER.get().is_some()
+}
+
+fn line(ts: u64, data: Value) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(&Entry { ts, data })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+pub fn record(ts: u64, data: Value) {
+    let Some(file) = RECORDER.get() else {
+        return;
+    };
+    if let Err(err) = line(ts, data).and_then(|o| Ok(file.lock().unwrap().write_all(&o)?)) {
+        error!("record report error => {:?}", err);
+    }
+}
+
+pub fn read(path: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(o) => entries.push(o),
+            // 录制中途退出时最后一行可能不完整
+            Err(err) => warn!("invalid recorded report => {:?}", err),
+        }
+    }
+    Ok(entries)
+}
+
+// 第 n 条相对回放开始的延迟(ms), 及改写后的 latest_ts: 回放时的时间, 主机不会因为时间过旧被判定下线
+fn schedule(entry: &Entry, first_ts: u64, speed: f64, start_ms: u64) -> (u64, u64) {
+    let delay = ((entry.ts - first_ts) as f64 / speed) as u64;
+    (delay, (start_ms + delay) / 1000)
+}
+
+// 按原速度(speed = 1)或加速回放, 经过 StatsMgr::report 的完整流程
+pub fn replay(path: &str, speed: f64) -> Result<()> {
+    let mut entries = read(path)?;
+    entries.sort_by_key(|o| o.ts);
+    let speed = if speed > 0.0 { speed } else { 1.0 };
+    eprintln!("✨ replay {} reports from {path} at {speed}x", entries.len());
+    thread::spawn(move || {
+        let Some(first_ts) = entries.first().map(|o| o.ts) else {
+            return;
+        };
+        let now_ms = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let start_ms = now_ms();
+        let mgr = G_STATS_MGR.get().unwrap();
+        let (total, mut failed) = (entries.len(), 0);
+        for mut entry in entries {
+            let (delay, latest_ts) = schedule(&entry, first_ts, speed, start_ms);
+            let elapsed = now_ms() - start_ms;
+            if delay > elapsed {
+                thread::sleep(Duration::from_millis(delay - elapsed));
+            }
+            if entry.data["latest_ts"].as_u64().unwrap_or_default() > 0 {
+                entry.data["latest_ts"] = latest_ts.into();
+            }
+            // 只用于匹配主机名, 原上报已经过认证
+            let name = entry.data["name"].as_str().unwrap_or_default().to_string();
+            if let Err(err) = mgr.report(entry.data, &Reporter::Host(name)) {
+                failed += 1;
+                warn!("replay report error => {:?}", err);
+            }
+        }
+        eprintln!("✨ replay done, {total} reports, {failed} failed");
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_replay() {
+        let path = std::env::temp_dir().join(format!("ss-record-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut file = File::create(path).unwrap();
+        file.write_all(&line(3000, json!({ "name": "h2", "latest_ts": 3 })).unwrap())
+            .unwrap();
+        file.write_all(&line(1000, json!({ "name": "h1", "latest_ts": 1 })).unwrap())
+            .unwrap();
+        file.write_all(b"{\"ts\":").unwrap();
+
+        let entries = read(path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].data["name"], "h2");
+        assert_eq!(schedule(&entries[1], 1000, 1.0, 10_000), (0, 10));
+        assert_eq!(schedule(&entries[0], 1000, 1.0, 10_000), (2000, 12));
+        assert_eq!(schedule(&entries[0], 1000, 4.0, 10_000), (500, 10));
+        std::fs::remove_file(path).unwrap();
+    }
+}
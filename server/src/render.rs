@@ -0,0 +1,138 @@
+#![deny(warnings)]
+use anyhow::Result;
+use bytes::Bytes;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+
+use crate::payload::{HostStat, StatsResp};
+
+// 会影响序列化结果的字段: 入库序号 + timer 线程会修改的字段 + 标签内容的 hash(管理接口修改标签时离线主机的序号不变)
+type Fingerprint = (u64, bool, bool, bool, u64);
+
+fn fingerprint(stat: &HostStat) -> Fingerprint {
+    let mut hasher = DefaultHasher::new();
+    stat.labels.hash(&mut hasher);
+    (stat.seq, stat.online4, stat.online6, stat.disabled, hasher.finish())
+}
+
+// stats.json 增量渲染, 按主机缓存序列化片段, 只重新序列化有变化的主机
+#[derive(Default)]
+pub struct Renderer {
+    frags: HashMap<String, (Fingerprint, Bytes)>,
+}
+
+impl Renderer {
+    // 输出与 serde_json::to_vec(resp) 一致
+    pub fn render(&mut self, resp: &StatsResp) -> Result<Bytes> {
+        let mut frags = HashMap::with_capacity(resp.servers.len());
+        let mut buf = Vec::with_capacity(self.frags.values().map(|(_, b)| b.len() + 1).sum::<usize>() + 64);
+
+        write!(buf, "{{\"updated\":{},\"servers\":[", resp.updated)?;
+        for (idx, stat) in resp.servers.iter().enumerate() {
+            if idx > 0 {
+                buf.push(b',');
+            }
+            let fp = fingerprint(stat);
+            let (name, frag) = match self.frags.remove_entry(&stat.name) {
+                Some((name, (o, frag))) if o == fp => (name, frag),
+                _ => (stat.name.to_string(), Bytes::from(serde_json::to_vec(stat)?)),
+            };
+            buf.extend_from_slice(&frag);
+            frags.insert(name, (fp, frag));
+        }
+        buf.push(b']');
+        if !resp.announcements.is_empty() {
+            buf.extend_from_slice(b",\"announcements\":");
+            serde_json::to_writer(&mut buf, &resp.announcements)?;
+        }
+        buf.push(b'}');
+
+        // 未出现在本次结果中的主机直接丢弃
+        self.frags = frags;
+        Ok(Bytes::from(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::Labels;
+    use std::time::Instant;
+
+    fn fake_resp(n: usize) -> StatsResp {
+        let mut resp = StatsResp::new();
+        for i in 0..n {
+            resp.servers.push(HostStat {
+                name: format!("h{i}"),
+                alias: format!("node-{i}"),
+                labels: Labels::parse("os=linux;"),
+                online4: true,
+                cpu: i as f64,
+                memory_total: 1 << 30,
+                seq: i as u64,
+                ..Default::default()
+            });
+        }
+        resp
+    }
+
+    #[test]
+    fn test_render_same_as_serde() {
+        let mut renderer = Renderer::default();
+        let mut resp = fake_resp(3);
+        assert_eq!(renderer.render(&resp).unwrap(), serde_json::to_vec(&resp).unwrap());
+
+        // 新数据
+        resp.servers[1].cpu = 99.0;
+        resp.servers[1].seq = 100;
+        // timer 线程修改
+        resp.servers[2].online4 = false;
+        // 主机移除
+        resp.servers.remove(0);
+        assert_eq!(renderer.render(&resp).unwrap(), serde_json::to_vec(&resp).unwrap());
+        assert_eq!(renderer.frags.len(), 2);
+
+        // 只修改标签内容, 数量不变
+        resp.servers[0].labels = Labels::parse("os=bsd;");
+        assert_eq!(renderer.render(&resp).unwrap(), serde_json::to_vec(&resp).unwrap());
+
+        resp.servers.clear();
+        assert_eq!(renderer.render(&resp).unwrap(), serde_json::to_vec(&resp).unwrap());
+
+        resp.announcements.push(Default::default());
+        assert_eq!(renderer.render(&resp).unwrap(), serde_json::to_vec(&resp).unwrap());
+    }
+
+    // cargo test -p stat_server --release -- --ignored bench_render --nocapture
+    #[test]
+    #[ignore]
+    fn bench_render() {
+        const HOSTS: usize = 500;
+        const ROUNDS: usize = 200;
+        let mut resp = fake_resp(HOSTS);
+
+        let now = Instant::now();
+        for _ in 0..ROUNDS {
+            std::hint::black_box(serde_json::to_vec(&resp).unwrap());
+        }
+        let full = now.elapsed() / ROUNDS as u32;
+
+        let mut renderer = Renderer::default();
+        renderer.render(&resp).unwrap();
+        let now = Instant::now();
+        for round in 0..ROUNDS {
+            // 每轮约 10% 主机有新上报
+            for (i, stat) in resp.servers.iter_mut().enumerate() {
+                if i % 10 == round % 10 {
+                    stat.seq += HOSTS as u64;
+                }
+            }
+            std::hint::black_box(renderer.render(&resp).unwrap());
+        }
+        let incr = now.elapsed() / ROUNDS as u32;
+
+        println!("{HOSTS} hosts: full serialize {full:?}/round, incremental {incr:?}/round");
+    }
+}
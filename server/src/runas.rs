@@ -0,0 +1,107 @@
+#![deny(warnings)]
+// 以 root 启动侦听 80/443 等端口后降权运行, 以及 pid 文件和新建文件的 umask
+use anyhow::{anyhow, Result};
+use std::fs;
+
+use crate::config::RunAs;
+
+// 八进制, 如 "027"
+fn parse_umask(s: &str) -> Result<u32> {
+    let mode = u32::from_str_radix(s.trim_start_matches("0o"), 8).map_err(|_| anyhow!("invalid umask `{s}`"))?;
+    if mode > 0o777 {
+        return Err(anyhow!("invalid umask `{s}`"));
+    }
+    Ok(mode)
+}
+
+// 在侦听端口之后, 打开数据库等文件之前调用
+pub fn apply(cfg: &RunAs) -> Result<()> {
+    if !cfg.umask.is_empty() {
+        set_umask(parse_umask(&cfg.umask)?);
+    }
+    if !cfg.pid_file.is_empty() {
+        fs::write(&cfg.pid_file, format!("{}\n", std::process::id()))?;
+    }
+    if !cfg.user.is_empty() || !cfg.group.is_empty() {
+        let (uid, gid) = drop_privileges(&cfg.user, &cfg.group)?;
+        eprintln!("✨ running as uid {uid} gid {gid}");
+    }
+    Ok(())
+}
+
+// 降权后可能没有权限删除, 忽略错误
+pub fn remove_pid_file(cfg: &RunAs) {
+    if !cfg.pid_file.is_empty() {
+        if let Err(err) = fs::remove_file(&cfg.pid_file) {
+            warn!("remove pid file {} error => {:?}", cfg.pid_file, err);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_umask(mode: u32) {
+    unsafe { libc::umask(mode as libc::mode_t) };
+}
+
+#[cfg(not(unix))]
+fn set_umask(_mode: u32) {
+    eprintln!("❗umask is only supported on unix, ignored");
+}
+
+#[cfg(unix)]
+fn drop_privileges(user: &str, group: &str) -> Result<(u32, u32)> {
+    use std::ffi::CString;
+
+    let (mut uid, mut gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    if !user.is_empty() {
+        let name = CString::new(user)?;
+        let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+        if pw.is_null() {
+            return Err(anyhow!("user `{user}` not found"));
+        }
+        (uid, gid) = unsafe { ((*pw).pw_uid, (*pw).pw_gid) };
+    }
+    if !group.is_empty() {
+        let name = CString::new(group)?;
+        let gr = unsafe { libc::getgrnam(name.as_ptr()) };
+        if gr.is_null() {
+            return Err(anyhow!("group `{group}` not found"));
+        }
+        gid = unsafe { (*gr).gr_gid };
+    }
+    if unsafe { libc::geteuid() } != 0 {
+        return match unsafe { (libc::geteuid(), libc::getegid()) } == (uid, gid) {
+            true => Ok((uid, gid)),
+            false => Err(anyhow!("run_as user/group requires starting as root")),
+        };
+    }
+
+    // 先清除附加组及设置组, 设置用户后不再有权限修改
+    unsafe {
+        if libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+            return Err(anyhow!("drop privileges error => {}", std::io::Error::last_os_error()));
+        }
+    }
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        return Err(anyhow!("drop privileges error => can regain root"));
+    }
+    Ok((uid, gid))
+}
+
+#[cfg(not(unix))]
+fn drop_privileges(_user: &str, _group: &str) -> Result<(u32, u32)> {
+    Err(anyhow!("run_as user/group is only supported on unix"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_umask() {
+        assert_eq!(parse_umask("027").unwrap(), 0o027);
+        assert_eq!(parse_umask("0o077").unwrap(), 0o077);
+        assert!(parse_umask("089").is_err());
+        assert!(parse_umask("1000").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+#![deny(warnings)]
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+use crate::config::Ingest;
+
+// 客户端上报的文本字段, labels / custom 等由服务端配置填充
+const TEXT_FIELDS: [&str; 6] = ["alias", "type", "location", "gid", "version", "frame"];
+// 嵌套对象中的文本字段一并处理
+const NESTED_FIELDS: [&str; 2] = ["sys_info", "ip_info"];
+// 百分比字段: cpu 使用率, 丢包率
+const PERCENT_FIELDS: [&str; 4] = ["cpu", "ping_10010", "ping_189", "ping_10086"];
+// 非负的浮点字段
+const NON_NEGATIVE_FIELDS: [&str; 6] = ["load_1", "load_5", "load_15", "time_10010", "time_189", "time_10086"];
+// 已用量不超过总量
+const USAGE_FIELDS: [(&str, &str); 3] = [
+    ("memory_used", "memory_total"),
+    ("swap_used", "swap_total"),
+    ("hdd_used", "hdd_total"),
+];
+// 会被主题以 html/js 拼接渲染的字符, 正常的主机信息中不会出现
+const MARKUP_CHARS: [char; 3] = ['<', '>', '`'];
+// 客户端上报的排序权重上限, 与配置的权重相加
+const MAX_WEIGHT: u64 = u32::MAX as u64;
+// 客户端只上报内存最多的 20 个 cgroup
+const MAX_CGROUPS: usize = 64;
+
+fn is_unsafe(c: &char) -> bool {
+    c.is_control() || MARKUP_CHARS.contains(c)
+}
+
+// 去除控制字符及 html 标记字符并截断, 返回是否有改动
+fn clean_str(v: &mut Value, max_len: usize) -> bool {
+    let Value::String(s) = v else {
+        return false;
+    };
+    if s.chars().count() <= max_len && !s.chars().any(|c| is_unsafe(&c)) {
+        return false;
+    }
+    *s = s.chars().filter(|c| !is_unsafe(c)).take(max_len).collect();
+    true
+}
+
+fn clean_object(o: &mut Map<String, Value>, max_len: usize) -> usize {
+    o.values_mut().map(|v| clean_str(v, max_len) as usize).sum()
+}
+
+// 数值限制在 [min, max], 非法值(如 NaN 序列化后的 null)置 0
+fn clamp_f64(o: &mut Map<String, Value>, key: &str, min: f64, max: f64) -> bool {
+    let Some(v) = o.get_mut(key) else {
+        return false;
+    };
+    let n = v.as_f64().filter(|n| n.is_finite());
+    let fixed = n.map(|n| n.clamp(min, max)).unwrap_or_default();
+    if n == Some(fixed) {
+        return false;
+    }
+    *v = fixed.into();
+    true
+}
+
+fn clamp_usage(o: &mut Map<String, Value>, used: &str, total: &str) -> bool {
+    let total = o.get(total).and_then(Value::as_u64).unwrap_or_default();
+    match o.get_mut(used) {
+        Some(v) if v.as_u64().is_some_and(|n| n > total) => {
+            *v = total.into();
+            true
+        }
+        _ => false,
+    }
+}
+
+// 上报数据进入 stat_map 及入库前的清洗, 返回修正的字段数, 主机名非法时拒绝
+pub fn sanitize(data: &mut Value, ingest: &Ingest) -> Result<usize> {
+    let Some(o) = data.as_object_mut() else {
+        bail!("stat is not an object");
+    };
+    let max_len = ingest.max_field_len;
+    let name = o.get("name").and_then(Value::as_str).unwrap_or_default();
+    if name.is_empty() || name.chars().count() > max_len || name.chars().any(|c| is_unsafe(&c)) {
+        bail!("invalid host name");
+    }
+
+    let mut fixed = 0;
+    for key in TEXT_FIELDS {
+        fixed += o.get_mut(key).is_some_and(|v| clean_str(v, max_len)) as usize;
+    }
+    for key in NESTED_FIELDS {
+        if let Some(Value::Object(nested)) = o.get_mut(key) {
+            fixed += clean_object(nested, max_len);
+        }
+    }
+
+    for key in PERCENT_FIELDS {
+        fixed += clamp_f64(o, key, 0.0, 100.0) as usize;
+    }
+    for key in NON_NEGATIVE_FIELDS {
+        fixed += clamp_f64(o, key, 0.0, f64::MAX) as usize;
+    }
+    for (used, total) in USAGE_FIELDS {
+        fixed += clamp_usage(o, used, total) as usize;
+    }
+    if let Some(v) = o
+        .get_mut("weight")
+        .filter(|v| v.as_u64().is_some_and(|n| n > MAX_WEIGHT))
+    {
+        *v = MAX_WEIGHT.into();
+        fixed += 1;
+    }
+
+    if let Some(Value::Array(disks)) = o.get_mut("disks") {
+        if disks.len() > ingest.max_disks {
+            fixed += disks.len() - ingest.max_disks;
+            disks.truncate(ingest.max_disks);
+        }
+        for disk in disks.iter_mut().filter_map(Value::as_object_mut) {
+            fixed += clean_object(disk, max_len);
+            fixed += clamp_usage(disk, "used", "total") as usize;
+            fixed += clamp_usage(disk, "inode_used", "inode_total") as usize;
+        }
+    }
+    if let Some(Value::Array(mesh)) = o.get_mut("mesh") {
+        for result in mesh.iter_mut().filter_map(Value::as_object_mut) {
+            fixed += clean_object(result, max_len);
+            if let Some(v) = result.get_mut("loss").filter(|v| v.as_u64().is_some_and(|n| n > 100)) {
+                *v = 100.into();
+                fixed += 1;
+            }
+        }
+    }
+    if let Some(Value::Array(cgroups)) = o.get_mut("cgroups") {
+        if cgroups.len() > MAX_CGROUPS {
+            fixed += cgroups.len() - MAX_CGROUPS;
+            cgroups.truncate(MAX_CGROUPS);
+        }
+        for cgroup in cgroups.iter_mut().filter_map(Value::as_object_mut) {
+            fixed += clean_object(cgroup, max_len);
+            fixed += clamp_f64(cgroup, "cpu", 0.0, 100.0) as usize;
+        }
+    }
+    Ok(fixed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sanitize() {
+        let ingest = Ingest {
+            max_field_len: 8,
+            max_disks: 1,
+            ..Default::default()
+        };
+        let mut data = json!({
+            "name": "h1",
+            "alias": "<b>\nalias-too-long",
+            "location": "cn",
+            "cpu": 250.0,
+            "load_1": -1.0,
+            "time_189": null,
+            "memory_total": 100,
+            "memory_used": 200,
+            "weight": u64::MAX,
+            "sys_info": { "host_name": "a\u{0}b", "cpu_num": 4 },
+            "disks": [
+                { "name": "sda", "total": 10, "used": 20, "inode_total": 4, "inode_used": 5 },
+                { "name": "sdb", "total": 10, "used": 5 },
+            ],
+        });
+        assert_eq!(sanitize(&mut data, &ingest).unwrap(), 10);
+        assert_eq!(data["alias"], "balias-t");
+        assert_eq!(data["location"], "cn");
+        assert_eq!(data["cpu"], 100.0);
+        assert_eq!(data["load_1"], 0.0);
+        assert_eq!(data["time_189"], 0.0);
+        assert_eq!(data["memory_used"], 100);
+        assert_eq!(data["weight"], MAX_WEIGHT);
+        assert_eq!(data["sys_info"], json!({ "host_name": "ab", "cpu_num": 4 }));
+        assert_eq!(data["disks"], json!([{ "name": "sda", "total": 10, "used": 10, "inode_total": 4, "inode_used": 4 }]));
+        // 已经合法的数据不再改动
+        assert_eq!(sanitize(&mut data, &ingest).unwrap(), 0);
+
+        assert!(sanitize(&mut json!({ "name": "" }), &ingest).is_err());
+        assert!(sanitize(&mut json!({ "name": "name-too-long" }), &ingest).is_err());
+        assert!(sanitize(&mut json!({ "name": "<h1>" }), &ingest).is_err());
+        assert!(sanitize(&mut json!([]), &ingest).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+#![deny(warnings)]
+use axum::{
+    extract::Request,
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::SecurityHeaders;
+use crate::G_CONFIG;
+
+const FRAME_ANCESTORS: &str = "frame-ancestors";
+
+// 允许被 iframe 嵌入的路径
+fn embeddable(cfg: &SecurityHeaders, path: &str) -> bool {
+    cfg.frame_allow
+        .iter()
+        .any(|o| !o.is_empty() && path.starts_with(o.as_str()))
+}
+
+// 请求路径对应的安全头
+pub fn headers_for(cfg: &SecurityHeaders, path: &str) -> Vec<(HeaderName, String)> {
+    let mut o = vec![(header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string())];
+    if !cfg.referrer_policy.is_empty() {
+        o.push((header::REFERRER_POLICY, cfg.referrer_policy.to_string()));
+    }
+
+    let mut csp = cfg
+        .csp
+        .split(';')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if embeddable(cfg, path) {
+        // 嵌入的页面由 frame_ancestors 决定允许的来源, 忽略全局 csp 中的 frame-ancestors
+        csp.retain(|o| !o.starts_with(FRAME_ANCESTORS));
+        if !cfg.frame_ancestors.is_empty() {
+            csp.push(format!("{FRAME_ANCESTORS} {}", cfg.frame_ancestors));
+        }
+    } else if !cfg.frame_options.is_empty() {
+        o.push((header::X_FRAME_OPTIONS, cfg.frame_options.to_string()));
+    }
+    if !csp.is_empty() {
+        o.push((header::CONTENT_SECURITY_POLICY, csp.join("; ")));
+    }
+    o
+}
+
+// 处理函数已设置的头不覆盖
+pub async fn headers(req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    let mut resp = next.run(req).await;
+    if let Some(cfg) = G_CONFIG.get().map(|o| &o.security_headers) {
+        let headers = resp.headers_mut();
+        for (k, v) in headers_for(cfg, &path) {
+            if headers.contains_key(&k) {
+                continue;
+            }
+            match HeaderValue::from_str(&v) {
+                Ok(v) => {
+                    headers.insert(k, v);
+                }
+                Err(err) => warn!("invalid security header {} => {:?}", k, err),
+            }
+        }
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(o: &[(HeaderName, String)], k: HeaderName) -> Option<&str> {
+        o.iter().find(|(name, _)| *name == k).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_headers_for() {
+        let mut cfg = SecurityHeaders::default();
+        let o = headers_for(&cfg, "/");
+        assert_eq!(get(&o, header::X_FRAME_OPTIONS), Some("DENY"));
+        assert_eq!(get(&o, header::X_CONTENT_TYPE_OPTIONS), Some("nosniff"));
+        assert!(get(&o, header::CONTENT_SECURITY_POLICY).is_none());
+        // 小卡片允许嵌入
+        assert!(get(&headers_for(&cfg, "/widget/h1"), header::X_FRAME_OPTIONS).is_none());
+
+        cfg.csp = "default-src 'self'; frame-ancestors 'none';".to_string();
+        cfg.frame_ancestors = "https://example.com".to_string();
+        let o = headers_for(&cfg, "/");
+        assert_eq!(
+            get(&o, header::CONTENT_SECURITY_POLICY),
+            Some("default-src 'self'; frame-ancestors 'none'")
+        );
+        let o = headers_for(&cfg, "/widget/h1");
+        assert_eq!(
+            get(&o, header::CONTENT_SECURITY_POLICY),
+            Some("default-src 'self'; frame-ancestors https://example.com")
+        );
+    }
+}
@@ -0,0 +1,55 @@
+#![deny(warnings)]
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const SHARDS: usize = 16;
+
+// 按主机名分片的 map, 入库线程和 timer 线程各自只锁住一个分片
+pub struct ShardedMap<V> {
+    shards: Vec<Mutex<HashMap<String, V>>>,
+}
+
+impl<V> ShardedMap<V> {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    fn index(key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % SHARDS
+    }
+
+    pub fn shard(&self, key: &str) -> MutexGuard<'_, HashMap<String, V>> {
+        self.shards[Self::index(key)]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // 依次锁住每个分片, guard 释放后才会锁下一个
+    pub fn shards(&self) -> impl Iterator<Item = MutexGuard<'_, HashMap<String, V>>> {
+        self.shards
+            .iter()
+            .map(|o| o.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn retain<F: FnMut(&String, &mut V) -> bool>(&self, mut f: F) {
+        for mut shard in self.shards() {
+            shard.retain(&mut f);
+        }
+    }
+}
+
+impl<V> From<HashMap<String, V>> for ShardedMap<V> {
+    fn from(map: HashMap<String, V>) -> Self {
+        let o = Self::new();
+        for (k, v) in map {
+            o.shard(&k).insert(k, v);
+        }
+        o
+    }
+}
@@ -0,0 +1,197 @@
+#![deny(warnings)]
+use anyhow::Result;
+use font8x8::legacy::BASIC_LEGACY;
+use stat_common::utils::bytes2human;
+
+use crate::payload::HostStat;
+
+// 8x8 点阵字体放大倍数
+const SCALE: usize = 2;
+pub const CHAR_W: usize = 8 * SCALE;
+const ROW_H: usize = 8 * SCALE + 12;
+const PAD: usize = 12;
+
+pub type Rgb = [u8; 3];
+const BG: Rgb = [255, 255, 255];
+const STRIPE: Rgb = [246, 247, 249];
+const HEAD_BG: Rgb = [52, 58, 64];
+const HEAD_FG: Rgb = [255, 255, 255];
+pub const FG: Rgb = [33, 37, 41];
+const GREEN: Rgb = [40, 167, 69];
+const AMBER: Rgb = [230, 140, 0];
+const RED: Rgb = [220, 53, 69];
+
+const TITLES: [&str; 9] = ["Name", "Status", "Uptime", "Load", "CPU", "RAM", "Disk", "Net in|out", "Month in|out"];
+
+// RGB 画布, 图表也用于叠加坐标轴文字
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    pub buf: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            buf: BG.repeat(width * height),
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, color: Rgb) {
+        for row in y..(y + h).min(self.height) {
+            for col in x..(x + w).min(self.width) {
+                let i = (row * self.width + col) * 3;
+                self.buf[i..i + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    // 只有 ascii 字形, 其它字符显示为 ?
+    pub fn text(&mut self, x: usize, y: usize, s: &str, color: Rgb) {
+        for (n, c) in s.chars().enumerate() {
+            let glyph = BASIC_LEGACY[if c.is_ascii() { c as usize } else { '?' as usize }];
+            for (gy, bits) in glyph.iter().enumerate() {
+                for gx in 0..8 {
+                    if bits & (1 << gx) != 0 {
+                        self.fill(x + n * CHAR_W + gx * SCALE, y + gy * SCALE, SCALE, SCALE, color);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn png(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.buf)?;
+        Ok(out)
+    }
+}
+
+fn uptime(secs: u64) -> String {
+    match secs {
+        0..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn usage(used: u64, total: u64) -> (String, Rgb) {
+    if total == 0 {
+        return ("-".to_string(), FG);
+    }
+    let pct = used as f64 * 100.0 / total as f64;
+    (format!("{pct:.0}%"), level(pct))
+}
+
+fn level(pct: f64) -> Rgb {
+    match pct {
+        p if p >= 90.0 => RED,
+        p if p >= 80.0 => AMBER,
+        _ => FG,
+    }
+}
+
+fn row(o: &HostStat) -> Vec<(String, Rgb)> {
+    let online = o.online4 || o.online6;
+    let human = |v: u64| bytes2human(v, 1, o.si);
+    // 点阵字体只有 ascii, 别名含其它字符时使用主机名
+    let name = if o.alias.is_ascii() { &o.alias } else { &o.name };
+    vec![
+        (name.to_string(), FG),
+        (if online { "up" } else { "down" }.to_string(), if online { GREEN } else { RED }),
+        (uptime(o.uptime), FG),
+        (format!("{:.2}", o.load_1), FG),
+        (format!("{:.0}%", o.cpu), level(o.cpu)),
+        usage(o.memory_used, o.memory_total),
+        usage(o.hdd_used, o.hdd_total),
+        (format!("{}|{}", human(o.network_rx), human(o.network_tx)), FG),
+        (
+            format!(
+                "{}|{}",
+                human(o.network_in.saturating_sub(o.last_network_in)),
+                human(o.network_out.saturating_sub(o.last_network_out))
+            ),
+            FG,
+        ),
+    ]
+}
+
+// 主机列表表格, 与 /txt 的列相同
+pub fn render(title: &str, servers: &[HostStat]) -> Result<Vec<u8>> {
+    let rows = servers.iter().map(row).collect::<Vec<_>>();
+    let widths = (0..TITLES.len())
+        .map(|i| {
+            rows.iter()
+                .map(|r| r[i].0.chars().count())
+                .chain([TITLES[i].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let width = (PAD * 2 + widths.iter().map(|w| w * CHAR_W + PAD * 2).sum::<usize>()).max(title.len() * CHAR_W + PAD * 2);
+    let height = PAD * 2 + ROW_H * (rows.len() + 2);
+    let mut canvas = Canvas::new(width, height);
+    let text_y = (ROW_H - CHAR_W) / 2;
+
+    canvas.text(PAD, PAD + text_y, title, FG);
+    let mut y = PAD + ROW_H;
+    canvas.fill(PAD, y, width - PAD * 2, ROW_H, HEAD_BG);
+    let mut x = PAD;
+    for (i, t) in TITLES.iter().enumerate() {
+        canvas.text(x + PAD, y + text_y, t, HEAD_FG);
+        x += widths[i] * CHAR_W + PAD * 2;
+    }
+    for (n, r) in rows.iter().enumerate() {
+        y += ROW_H;
+        if n % 2 == 1 {
+            canvas.fill(PAD, y, width - PAD * 2, ROW_H, STRIPE);
+        }
+        let mut x = PAD;
+        for (i, (s, color)) in r.iter().enumerate() {
+            canvas.text(x + PAD, y + text_y, s, *color);
+            x += widths[i] * CHAR_W + PAD * 2;
+        }
+    }
+    canvas.png()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let servers = vec![
+            HostStat {
+                name: "h1".to_string(),
+                alias: "n1".to_string(),
+                online4: true,
+                cpu: 95.0,
+                memory_total: 100,
+                memory_used: 85,
+                ..Default::default()
+            },
+            HostStat {
+                name: "h2".to_string(),
+                alias: "主机2".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(row(&servers[1])[0].0, "h2");
+        assert_eq!(row(&servers[0])[4], ("95%".to_string(), RED));
+        assert_eq!(row(&servers[0])[5], ("85%".to_string(), AMBER));
+
+        let data = render("ServerStatus 2024-06-01", &servers).unwrap();
+        let decoder = png::Decoder::new(&data[..]);
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.height as usize, PAD * 2 + ROW_H * 4);
+        assert!(info.width as usize > TITLES.iter().map(|t| t.len() * CHAR_W).sum::<usize>());
+    }
+}
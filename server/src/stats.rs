@@ -1,50 +1,290 @@
 #![allow(unused)]
 use anyhow::Result;
+use bytes::Bytes;
 use chrono::{Datelike, Local, Timelike};
 use lazy_static::lazy_static;
 use once_cell::sync::OnceCell;
+use serde::Deserialize;
 use std::borrow::Borrow;
 use std::borrow::BorrowMut;
 use std::borrow::Cow;
 use std::collections::binary_heap::Iter;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Write as _;
-use std::sync::mpsc::sync_channel;
-use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Condvar, Mutex, RwLock};
 use std::thread;
 use std::time::Duration;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::adaptive::Sampler;
+use crate::alerts;
+use crate::announce::{self, Announcement};
+use crate::archive;
+use crate::auth::{self, Forbidden, Reporter};
+use crate::chaos;
+use crate::cluster;
+use crate::compact;
+use crate::conflict::{self, OnConflict};
+use crate::compare;
+use crate::events;
+use crate::expiry;
+use crate::forecast;
 use crate::config::Host;
-use crate::db::Database;
-use crate::db::{DiskRecord, HostStatRecord};
-use crate::notifier::{Event, Notifier};
-use crate::payload::{HostStat, StatsResp};
+use crate::db::{Database, Resolution};
+use crate::encoding::Format;
+use crate::leader;
+use crate::live;
+use crate::metrics;
+use crate::db::{DiskRecord, HistoryRecords, HostStatRecord, ProbeRecord};
+use crate::notifier::{self, Event, Notifier};
+use crate::payload::{HostStat, ReportAck, StatsResp};
+use crate::labels::Selector;
+use crate::mesh;
+use crate::probe;
+use crate::queue::StatQueue;
+use crate::quota;
+use crate::recorder;
+use crate::render::Renderer;
+use crate::sanitize;
+use crate::shard::ShardedMap;
+use crate::traffic;
+use crate::watchdog;
+use crate::G_CONFIG;
 
 const SAVE_INTERVAL: u64 = 60;
+// 无上报无访问时最长的重建间隔(s)
+const MAX_IDLE_SECS: u64 = 60;
 
-static STAT_SENDER: OnceCell<SyncSender<Cow<HostStat>>> = OnceCell::new();
+static STAT_QUEUE: OnceCell<StatQueue> = OnceCell::new();
+static SAMPLER: OnceCell<Sampler> = OnceCell::new();
+
+// 数据变更信号, 唤醒 timer 线程重建 StatsResp
+#[derive(Default)]
+struct Refresh {
+    dirty: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Refresh {
+    fn notify(&self) {
+        if let Ok(mut dirty) = self.dirty.lock() {
+            *dirty = true;
+            self.cond.notify_one();
+        }
+    }
+
+    fn wait(&self, timeout: Duration) {
+        if let Ok(dirty) = self.dirty.lock() {
+            if let Ok((mut dirty, _)) = self.cond.wait_timeout_while(dirty, timeout, |o| !*o) {
+                *dirty = false;
+            }
+        }
+    }
+}
+
+// 用主机配置补齐未上报的离线项, 跳过下线检查及通知, 重新上报后恢复; 禁用及分组主机返回 false
+fn offline_from_host(stat: &mut HostStat, hosts_map: &ShardedMap<Host>) -> bool {
+    let hosts_map = hosts_map.shard(&stat.name);
+    let info = match hosts_map.get(&stat.name) {
+        Some(o) if !o.disabled && o.gid.is_empty() => o,
+        _ => return false,
+    };
+    stat.alias = match info.alias.is_empty() {
+        true => stat.name.to_owned(),
+        false => info.alias.to_owned(),
+    };
+    stat.location = info.location.to_owned();
+    stat.host_type = info.r#type.to_owned();
+    stat.notify = info.notify;
+    stat.pos = info.pos;
+    stat.weight = info.weight;
+    stat.labels = info.labels.to_owned();
+    stat.region = info.region.to_owned();
+    stat.zone = info.zone.to_owned();
+    stat.provider = info.provider.to_owned();
+    stat.si = info.units.si(stat.si);
+    stat.last_network_in = info.last_network_in;
+    stat.last_network_out = info.last_network_out;
+    stat.uptime_str = "-".to_string();
+    stat.online4 = false;
+    stat.online6 = false;
+    stat.disabled = true;
+    true
+}
+
+// 单台主机的历史数据, 消耗 probes 中对应主机的探测记录
+fn host_history(
+    host_name: String,
+    si: bool,
+    records: &[HostStatRecord],
+    probes: &mut HashMap<String, HashMap<String, Vec<ProbeRecord>>>,
+) -> serde_json::Value {
+    // 使用最新记录的基本信息
+    let latest = &records[records.len() - 1];
+    
+    let mut host_data = serde_json::json!({
+        "name": host_name,
+        "alias": latest.alias,
+        "online": latest.online,
+        "si": si,
+        "data_points": records.len(),
+        "cpu_history": [],
+        "memory_history": [],
+        "network_in_history": [],
+        "network_out_history": [],
+        "disks_history": {},  // 改为对象，每个挂载点一个数组
+        "probe_history": {}   // 服务端探测, 每个 kind 一个数组
+    });
+    
+    // 创建临时变量来存储历史数据
+    let mut cpu_data = Vec::new();
+    let mut memory_data = Vec::new();
+    let mut network_in_data = Vec::new();
+    let mut network_out_data = Vec::new();
+    
+    // 初始化磁盘挂载点
+    let mut mount_points = HashSet::new();
+    for record in records {
+        for disk in &record.disks {
+            mount_points.insert(disk.mount_point.clone());
+        }
+    }
+    
+    // 为每个挂载点创建数组
+    let mut disk_data_map: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    for mount_point in &mount_points {
+        disk_data_map.insert(mount_point.clone(), Vec::new());
+    }
+    
+    for record in records {
+        cpu_data.push(serde_json::json!({
+            "timestamp": record.timestamp,
+            "value": record.cpu
+        }));
+        
+        let mem_percent = if record.memory_total > 0 {
+            (record.memory_used as f64 / record.memory_total as f64) * 100.0
+        } else {
+            0.0
+        };
+        
+        memory_data.push(serde_json::json!({
+            "timestamp": record.timestamp,
+            "value": mem_percent,
+            "total": record.memory_total,
+            "used": record.memory_used
+        }));
+        
+        network_in_data.push(serde_json::json!({
+            "timestamp": record.timestamp,
+            "value": record.network_in_speed,
+            "total": record.network_in
+        }));
+        
+        network_out_data.push(serde_json::json!({
+            "timestamp": record.timestamp,
+            "value": record.network_out_speed,
+            "total": record.network_out
+        }));
+        
+        // 处理每个磁盘
+        for disk in &record.disks {
+            if let Some(disk_array) = disk_data_map.get_mut(&disk.mount_point) {
+                let disk_percent = if disk.total > 0 {
+                    (disk.used as f64 / disk.total as f64) * 100.0
+                } else {
+                    0.0
+                };
+                
+                disk_array.push(serde_json::json!({
+                    "timestamp": record.timestamp,
+                    "value": disk_percent,
+                    "total": disk.total,
+                    "used": disk.used,
+                    "inode_total": disk.inode_total,
+                    "inode_used": disk.inode_used
+                }));
+            }
+        }
+    }
+    
+    // 将收集的数据添加到 host_data
+    host_data["cpu_history"] = serde_json::json!(cpu_data);
+    host_data["memory_history"] = serde_json::json!(memory_data);
+    host_data["network_in_history"] = serde_json::json!(network_in_data);
+    host_data["network_out_history"] = serde_json::json!(network_out_data);
+    
+    // 添加磁盘数据
+    let disks_obj = host_data["disks_history"].as_object_mut().unwrap();
+    for (mount_point, data) in disk_data_map {
+        disks_obj.insert(mount_point, serde_json::json!(data));
+    }
+
+    // 探测数据, value 为延迟(ms), success 为成功率(%)
+    let probes_obj = host_data["probe_history"].as_object_mut().unwrap();
+    for (kind, data) in probes.remove(&host_name).unwrap_or_default() {
+        let data = data
+            .iter()
+            .map(|o| {
+                serde_json::json!({
+                    "timestamp": o.timestamp,
+                    "value": o.latency,
+                    "success": o.success_rate
+                })
+            })
+            .collect::<Vec<_>>();
+        probes_obj.insert(kind, serde_json::json!(data));
+    }
+    host_data
+}
 
 pub struct StatsMgr {
-    resp_json: Arc<Mutex<String>>,
-    stats_data: Arc<Mutex<StatsResp>>,
+    // 只在 timer 线程重建后整体替换, 读多写少
+    resp_json: Arc<RwLock<Bytes>>,
+    // 由 stats.json 转换的其它格式, 名称 => (生成时的 stats.json, 结果), stats.json 变化后按需重新生成
+    derived: Arc<Mutex<HashMap<&'static str, (Bytes, Bytes)>>>,
+    // 公开的数据, 不含隐藏的主机
+    stats_data: Arc<RwLock<Arc<StatsResp>>>,
+    // 全部主机, 供管理接口及服务端内部使用
+    all_data: Arc<RwLock<Arc<StatsResp>>>,
+    hidden: Arc<RwLock<HashSet<String>>>,
+    announcements: Arc<RwLock<Vec<Announcement>>>,
+    // 由 init 创建, 供重命名等管理操作修改
+    hosts_map: Arc<ShardedMap<Host>>,
+    stat_map: Arc<ShardedMap<Cow<'static, HostStat>>>,
     db: Arc<Database>, // 数据库字段
+    refresh: Arc<Refresh>,
+    // 最近一次重建 StatsResp 的时间
+    updated: Arc<AtomicU64>,
+    // 由 init 创建, 管理接口模拟上下线时发送通知
+    notifier_tx: Option<SyncSender<(Event, Cow<'static, HostStat>)>>,
 }
 
 impl StatsMgr {
-    pub fn new() -> Self {
-        // 创建数据库连接
-        let db = Database::new("stats.db").expect("Failed to initialize database");
-        
+    pub fn new(db: Arc<Database>) -> Self {
         Self {
-            resp_json: Arc::new(Mutex::new("{}".to_string())),
-            stats_data: Arc::new(Mutex::new(StatsResp::new())),
-            db: Arc::new(db),
+            resp_json: Arc::new(RwLock::new(Bytes::from_static(b"{}"))),
+            derived: Arc::new(Mutex::new(HashMap::new())),
+            stats_data: Arc::new(RwLock::new(Arc::new(StatsResp::new()))),
+            all_data: Arc::new(RwLock::new(Arc::new(StatsResp::new()))),
+            hidden: Arc::new(RwLock::new(HashSet::new())),
+            announcements: Arc::new(RwLock::new(Vec::new())),
+            hosts_map: Arc::new(ShardedMap::new()),
+            stat_map: Arc::new(ShardedMap::new()),
+            db,
+            refresh: Arc::new(Refresh::default()),
+            updated: Arc::new(AtomicU64::new(0)),
+            notifier_tx: None,
         }
     }
 
+    pub fn db(&self) -> Arc<Database> {
+        self.db.clone()
+    }
+
     // 从数据库加载网络数据，替代原来从stats.json加载
     fn load_last_network(&mut self, hosts_map: &mut HashMap<String, Host>) {
         // 从数据库加载最后的网络数据
@@ -60,37 +300,116 @@ impl StatsMgr {
         }
     }
 
+    // 重启后从数据库恢复每台主机最近的状态, 从未上报的主机用占位项, 都标记为离线, 不发送通知
+    fn load_known_hosts(&self, hosts_map: &ShardedMap<Host>, stat_map: &ShardedMap<Cow<'static, HostStat>>) {
+        let stats = self.db.latest_stats().unwrap_or_else(|err| {
+            error!("load latest stats error => {:?}", err);
+            Vec::new()
+        });
+        let mut recovered = 0;
+        for mut stat in stats {
+            // 分组主机重新上报后再出现
+            if !offline_from_host(&mut stat, hosts_map) {
+                continue;
+            }
+            stat.stale = true;
+            stat_map.shard(&stat.name).insert(stat.name.to_string(), Cow::Owned(stat));
+            recovered += 1;
+        }
+
+        let mut never_seen = 0;
+        for shard in hosts_map.shards() {
+            let names = shard.keys().cloned().collect::<Vec<_>>();
+            drop(shard);
+            for name in names {
+                if stat_map.shard(&name).contains_key(&name) {
+                    continue;
+                }
+                let mut stat = HostStat {
+                    name,
+                    never_seen: true,
+                    ..Default::default()
+                };
+                if offline_from_host(&mut stat, hosts_map) {
+                    stat_map.shard(&stat.name).insert(stat.name.to_string(), Cow::Owned(stat));
+                    never_seen += 1;
+                }
+            }
+        }
+        eprintln!("✨ recovered {recovered} hosts from stats.db, {never_seen} hosts never reported");
+    }
+
     pub fn init(
         &mut self,
         cfg: &'static crate::config::Config,
         notifies: Arc<Mutex<Vec<Box<dyn Notifier + Send>>>>,
     ) -> Result<()> {
-        let hosts_map_base = Arc::new(Mutex::new(cfg.hosts_map.clone()));
-
-        // load last_network_in/out from database
-        if let Ok(mut hosts_map) = hosts_map_base.lock() {
-            self.load_last_network(&mut hosts_map);
+        events::init(self.db.clone());
+        match self.db.hidden_hosts() {
+            Ok(o) => *self.hidden.write().unwrap() = o,
+            Err(err) => error!("load hidden hosts error => {:?}", err),
+        }
+        match self.db.announcements() {
+            Ok(o) => *self.announcements.write().unwrap() = o,
+            Err(err) => error!("load announcements error => {:?}", err),
         }
+        match self.db.host_secrets() {
+            Ok(o) => auth::load_secrets(o),
+            Err(err) => error!("load host secrets error => {:?}", err),
+        }
+        match self.db.host_tokens() {
+            Ok(o) => auth::load_tokens(o),
+            Err(err) => error!("load host tokens error => {:?}", err),
+        }
+        let mut hosts_map = cfg.hosts_map.clone();
+        // load last_network_in/out from database
+        self.load_last_network(&mut hosts_map);
+        let hosts_map_base: Arc<ShardedMap<Host>> = Arc::new(hosts_map.into());
+        self.hosts_map = hosts_map_base.clone();
 
-        let (stat_tx, stat_rx) = sync_channel(512);
-        STAT_SENDER.set(stat_tx).unwrap();
+        let stat_queue = StatQueue::new(
+            cfg.ingest.queue_size,
+            cfg.ingest.overflow,
+            cfg.ingest.retry_after,
+            &cfg.ingest.spill_path,
+        );
+        if STAT_QUEUE.set(stat_queue).is_err() {
+            error!("can't set STAT_QUEUE");
+        }
+        if SAMPLER.set(Sampler::new(&cfg.adaptive)).is_err() {
+            error!("can't set SAMPLER");
+        }
         let (notifier_tx, notifier_rx) = sync_channel(512);
+        self.notifier_tx = Some(notifier_tx.clone());
 
-        let stat_map: Arc<Mutex<HashMap<String, Cow<HostStat>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let stat_map: Arc<ShardedMap<Cow<HostStat>>> = Arc::new(ShardedMap::new());
+        self.stat_map = stat_map.clone();
+        self.load_known_hosts(&hosts_map_base, &stat_map);
         let db = self.db.clone();
 
         // stat_rx thread
         thread::spawn({
+            let refresh = self.refresh.clone();
             let hosts_group_map = cfg.hosts_group_map.clone();
             let hosts_map = hosts_map_base.clone();
             let stat_map = stat_map.clone();
             let notifier_tx = notifier_tx.clone();
+            let mut seq = 0_u64;
 
             move || loop {
-                while let Ok(mut stat) = stat_rx.recv() {
+                {
+                    let mut stat: Cow<HostStat> = match serde_json::from_value(STAT_QUEUE.get().unwrap().pop()) {
+                        Ok(o) => Cow::Owned(o),
+                        Err(err) => {
+                            error!("invalid stat => {:?}", err);
+                            continue;
+                        }
+                    };
                     trace!("recv stat `{:?}", stat);
 
                     let mut stat_t = stat.to_mut();
+                    seq += 1;
+                    stat_t.seq = seq;
 
                     // group mode
                     if !stat_t.gid.is_empty() {
@@ -98,7 +417,8 @@ impl StatsMgr {
                             stat_t.alias = stat_t.name.to_string();
                         }
 
-                        if let Ok(mut hosts_map) = hosts_map.lock() {
+                        {
+                            let mut hosts_map = hosts_map.shard(&stat_t.name);
                             let host = hosts_map.get(&stat_t.name);
                             if host.is_none() || !host.unwrap().gid.eq(&stat_t.gid) {
                                 if let Some(group) = hosts_group_map.get(&stat_t.gid) {
@@ -117,7 +437,8 @@ impl StatsMgr {
                     }
 
                     //
-                    if let Ok(mut hosts_map) = hosts_map.lock() {
+                    {
+                        let mut hosts_map = hosts_map.shard(&stat_t.name);
                         let host_info = hosts_map.get_mut(&stat_t.name);
                         if host_info.is_none() {
                             error!("invalid stat `{:?}", stat_t);
@@ -141,12 +462,60 @@ impl StatsMgr {
                         stat_t.disabled = info.disabled;
                         stat_t.weight += info.weight;
                         stat_t.labels = info.labels.to_owned();
+                        stat_t.region = info.region.to_owned();
+                        stat_t.zone = info.zone.to_owned();
+                        stat_t.provider = info.provider.to_owned();
+                        stat_t.si = info.units.si(stat_t.si);
+                        stat_t.inode_used_percent = (stat_t.max_inode_percent() * 10.0).round() / 10.0;
 
                         // !group
                         if !info.alias.is_empty() {
                             stat_t.alias = info.alias.to_owned();
                         }
 
+                        // 同名主机冲突, 两台机器用同一个名字上报时数据会交错
+                        if cfg.ingest.on_conflict != OnConflict::Off {
+                            if let Some(fp) = conflict::fingerprint(stat_t) {
+                                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                                let verdict = conflict::observe(&stat_t.name, &fp, now);
+                                if let Some(fps) = verdict.detected {
+                                    metrics::inc("host_conflict_detected");
+                                    let msg = format!(
+                                        "⚠️ {} is reported by multiple machines ({}), stats may interleave",
+                                        stat_t.name,
+                                        fps.join(", ")
+                                    );
+                                    warn!("{}", msg);
+                                    thread::spawn(move || notifier::alert(&msg));
+                                }
+                                if cfg.ingest.on_conflict == OnConflict::Reject && !verdict.owner {
+                                    metrics::inc("report_rejected_conflict");
+                                    continue;
+                                }
+                                stat_t.conflict = verdict.conflict;
+                            }
+                        }
+
+                        // 时钟偏差, 偶尔的网络/队列延迟不算, 连续超过阈值才标记
+                        let samples = cfg.ingest.clock_skew_samples;
+                        if samples > 0 && stat_t.clock_skew.unsigned_abs() > cfg.ingest.max_clock_skew {
+                            info.skew_samples = info.skew_samples.saturating_add(1);
+                            if info.skew_samples == samples {
+                                metrics::inc("clock_skew_detected");
+                                let msg = format!(
+                                    "⏰ {} clock is {}s off the server for {} reports, history may be out of order",
+                                    stat_t.name, stat_t.clock_skew, samples
+                                );
+                                warn!("{}", msg);
+                                thread::spawn(move || notifier::alert(&msg));
+                            }
+                        } else {
+                            info.skew_samples = 0;
+                        }
+                        if samples == 0 || info.skew_samples < samples {
+                            stat_t.clock_skew = 0;
+                        }
+
                         // info.latest_ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
                         // stat_t.latest_ts = info.latest_ts;
 
@@ -187,43 +556,57 @@ impl StatsMgr {
 
                         info!("update stat `{:?}", stat_t);
                         // 修改 stat_rx 线程中的借用逻辑
-                        if let Ok(mut host_stat_map) = stat_map.lock() {
-                            // 创建一个临时变量来存储 pre_stat 的信息
-                            let mut need_notify = false;
-                            let mut ip_info_to_copy = None;
-                            
-                            // 先检查是否存在之前的状态
-                            if let Some(pre_stat) = host_stat_map.get(&stat_t.name) {
-                                if stat_t.ip_info.is_none() {
-                                    ip_info_to_copy = pre_stat.ip_info.clone();
-                                }
-                                
-                                if stat_t.notify && (pre_stat.latest_ts + cfg.offline_threshold < stat_t.latest_ts) {
-                                    need_notify = true;
-                                }
-                            }
-                            
-                            // 应用之前收集的信息
-                            if let Some(ip_info) = ip_info_to_copy {
-                                stat_t.ip_info = Some(ip_info);  // 使用Some包装，因为ip_info是IpInfo类型而不是Option<IpInfo>
-                            }
-                            
-                            // 保存到数据库
-                            if let Err(e) = db.save_stat(&stat_t) {
-                                error!("Failed to save stat to database: {}", e);
+                        // 创建一个临时变量来存储 pre_stat 的信息
+                        let mut need_notify = false;
+                        let mut ip_info_to_copy = None;
+
+                        // 先检查是否存在之前的状态
+                        if let Some(pre_stat) = stat_map.shard(&stat_t.name).get(&stat_t.name) {
+                            if stat_t.ip_info.is_none() {
+                                ip_info_to_copy = pre_stat.ip_info.clone();
                             }
-                            
-                            // 克隆一份用于通知和存储
-                            let stat_clone: Cow<'static, HostStat> = Cow::Owned(stat_t.clone());
-                            
-                            // 发送通知
-                            if need_notify {
-                                notifier_tx.send((Event::NodeUp, stat_clone.clone()));
+
+                            // 恢复或占位的主机重新上报时不发上线通知, 与重启前的行为一致
+                            if stat_t.notify
+                                && !pre_stat.stale
+                                && !pre_stat.never_seen
+                                && (pre_stat.latest_ts + cfg.offline_threshold < stat_t.latest_ts)
+                            {
+                                need_notify = true;
                             }
-                            
-                            // 插入到 map 中
-                            host_stat_map.insert(stat_t.name.to_string(), stat_clone);
                         }
+
+                        // 应用之前收集的信息
+                        if let Some(ip_info) = ip_info_to_copy {
+                            stat_t.ip_info = Some(ip_info);  // 使用Some包装，因为ip_info是IpInfo类型而不是Option<IpInfo>
+                        }
+
+                        // 服务端探测结果
+                        (stat_t.probe4, stat_t.probe6) = probe::get(&stat_t.name);
+
+                        // 保存到数据库, 不占用 stat_map 的锁
+                        if let Err(e) = db.save_stat(stat_t) {
+                            error!("Failed to save stat to database: {}", e);
+                        }
+
+                        // 告警规则, 流量突增及进程状态
+                        if stat_t.notify {
+                            alerts::check(stat_t, stat_t.latest_ts);
+                            traffic::check(stat_t, stat_t.latest_ts);
+                            watchdog::check(stat_t);
+                        }
+
+                        // 克隆一份用于通知和存储
+                        let stat_clone: Cow<'static, HostStat> = Cow::Owned(stat_t.clone());
+
+                        // 发送通知
+                        if need_notify {
+                            notifier_tx.send((Event::NodeUp, stat_clone.clone()));
+                        }
+
+                        // 插入到 map 中
+                        stat_map.shard(&stat_t.name).insert(stat_t.name.to_string(), stat_clone);
+                        refresh.notify();
                     }
                 }
             }
@@ -233,35 +616,68 @@ impl StatsMgr {
         thread::spawn({
             let resp_json = self.resp_json.clone();
             let stats_data = self.stats_data.clone();
+            let all_data = self.all_data.clone();
+            let hidden = self.hidden.clone();
+            let announcements = self.announcements.clone();
             let hosts_map = hosts_map_base.clone();
             let stat_map = stat_map.clone();
             let notifier_tx = notifier_tx.clone();
             let db = self.db.clone();
+            let refresh = self.refresh.clone();
+            let updated = self.updated.clone();
+            let min_refresh = Duration::from_millis(cfg.refresh_interval);
+            let mut renderer = Renderer::default();
+            let mut latest_build = Instant::now();
+            let mut wait = Duration::ZERO;
             let mut latest_notify_ts = 0_u64;
             let mut latest_save_ts = 0_u64;
             let mut latest_group_gc = 0_u64;
             let mut latest_alert_check_ts = 0_u64;
+            // 启动后等成员重新上报再检查分组配额及分组告警
+            let quota_check_ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + cfg.offline_threshold;
             move || loop {
-                thread::sleep(Duration::from_millis(500));
+                // 有新上报/访问或到达下一个检查点(下线判定, 告警, gc)时才重建
+                refresh.wait(wait);
+                let elapsed = latest_build.elapsed();
+                if elapsed < min_refresh {
+                    thread::sleep(min_refresh - elapsed);
+                }
+                latest_build = Instant::now();
 
                 let mut resp = StatsResp::new();
                 let now = resp.updated;
                 let mut notified = false;
+                let mut notify_pending = false;
+                let mut next_check_ts = now + MAX_IDLE_SECS;
+
+                // 模拟下线到期, 与管理接口恢复一样发送上线通知
+                for name in chaos::expired(now) {
+                    if let Some(stat) = stat_map.shard(&name).get(&name) {
+                        notifier_tx.send((Event::NodeUp, stat.clone()));
+                    }
+                }
+                if let Some(ts) = chaos::next_expiry() {
+                    next_check_ts = next_check_ts.min(ts);
+                }
 
                 // group gc
                 if latest_group_gc + cfg.group_gc < now {
                     latest_group_gc = now;
                     //
-                    if let Ok(mut hosts_map) = hosts_map.lock() {
-                        hosts_map.retain(|_, o| o.gid.is_empty() || o.latest_ts + cfg.group_gc >= now);
-                    }
+                    hosts_map.retain(|_, o| o.gid.is_empty() || o.latest_ts + cfg.group_gc >= now);
                     //
-                    if let Ok(mut stat_map) = stat_map.lock() {
-                        stat_map.retain(|_, o| o.gid.is_empty() || o.latest_ts + cfg.group_gc >= now);
-                    }
+                    let mut expired = Vec::new();
+                    stat_map.retain(|_, o| {
+                        let keep = o.gid.is_empty() || o.latest_ts + cfg.group_gc >= now;
+                        if !keep {
+                            expired.push(o.as_ref().clone());
+                        }
+                        keep
+                    });
+                    expiry::expired(cfg, db.clone(), expired);
                 }
 
-                if let Ok(mut host_stat_map) = stat_map.lock() {
+                for mut host_stat_map in stat_map.shards() {
                     for (_, stat) in host_stat_map.iter_mut() {
                         if stat.disabled {
                             resp.servers.push(stat.as_ref().clone());
@@ -269,37 +685,36 @@ impl StatsMgr {
                         }
                         let stat = stat.borrow_mut();
                         let o = stat.to_mut();
-                        // 30s 下线
-                        if o.latest_ts + cfg.offline_threshold < now {
+                        // 30s 下线, 模拟下线的主机已在管理接口发送过通知
+                        let forced = chaos::is_forced(&o.name, now);
+                        let offline = forced || o.latest_ts + cfg.offline_threshold < now;
+                        if offline {
                             o.online4 = false;
                             o.online6 = false;
+                        } else {
+                            next_check_ts = next_check_ts.min(o.latest_ts + cfg.offline_threshold + 1);
                         }
 
                         // labels
                         const OS_LIST: [&str; 10] = [
                             "centos", "debian", "ubuntu", "arch", "windows", "macos", "pi", "android", "linux", "freebsd"
                         ];
-                        if !o.labels.contains("os=") {
+                        if !o.labels.contains_key("os") {
                             if let Some(sys_info) = &o.sys_info {
                                 let os_r = format!("{} {}",sys_info.os_release.to_lowercase(),sys_info.os_name.to_lowercase());
-                                for s in OS_LIST.iter() {
-                                    if os_r.contains(s) {
-                                        if o.labels.is_empty() {
-                                            write!(o.labels, "os={s}");
-                                        } else {
-                                            write!(o.labels, ";os={s}");
-                                        }
-                                        break;
-                                    }
+                                if let Some(s) = OS_LIST.iter().find(|s| os_r.contains(*s)) {
+                                    o.labels.insert("os", s);
                                 }
                             }
                         }
 
                         // client notify
-                        if o.notify {
+                        if o.notify && !forced {
+                            notify_pending = true;
                             // notify check /30 s
                             if latest_notify_ts + cfg.notify_interval < now {
-                                if o.online4 || o.online6 {
+                                // 以上报是否超时判定在线, 仅 ipv6 可达或探测失败的主机不视为掉线
+                                if !offline {
                                     notifier_tx.send((Event::Custom, stat.clone()));
                                 } else {
                                     o.disabled = true;
@@ -311,8 +726,32 @@ impl StatsMgr {
 
                         resp.servers.push(stat.as_ref().clone());
                     }
-                    if notified {
-                        latest_notify_ts = now;
+                }
+                if notified {
+                    latest_notify_ts = now;
+                }
+
+                if notify_pending {
+                    next_check_ts = next_check_ts.min(latest_notify_ts + cfg.notify_interval + 1);
+                }
+                next_check_ts = next_check_ts.min(latest_group_gc + cfg.group_gc + 1);
+                wait = Duration::from_secs(next_check_ts.saturating_sub(now));
+
+                if now >= quota_check_ts {
+                    quota::check(cfg, &resp.servers);
+                    alerts::check_groups(&resp.servers, now);
+                }
+
+                if let Ok(hidden) = hidden.read() {
+                    for o in resp.servers.iter_mut() {
+                        o.hidden = hidden.contains(&o.name);
+                    }
+                }
+
+                if let Ok(list) = announcements.read() {
+                    resp.announcements = announce::active(&list, now as i64);
+                    if let Some(ts) = announce::next_change(&list, now as i64) {
+                        wait = wait.min(Duration::from_secs(ts as u64 - now));
                     }
                 }
 
@@ -336,12 +775,35 @@ impl StatsMgr {
                     }
                 }
                 
-                if let Ok(mut o) = resp_json.lock() {
-                    *o = serde_json::to_string(&resp).unwrap();
+                // 没有隐藏的主机时公开数据与全部数据共用
+                let all = Arc::new(resp);
+                let resp = match all.servers.iter().any(|o| o.hidden) {
+                    true => Arc::new(StatsResp {
+                        updated: all.updated,
+                        servers: all.servers.iter().filter(|o| !o.hidden).cloned().collect(),
+                        announcements: all.announcements.clone(),
+                    }),
+                    false => all.clone(),
+                };
+
+                let render_start = Instant::now();
+                match renderer.render(&resp) {
+                    Ok(json) => {
+                        metrics::observe_ms("stats_render_ms", render_start.elapsed().as_millis() as u64);
+                        metrics::observe_bytes("stats_json_bytes", json.len() as u64);
+                        if let Ok(mut o) = resp_json.write() {
+                            *o = json;
+                        }
+                    }
+                    Err(err) => error!("render stats json error => {:?}", err),
                 }
-                if let Ok(mut o) = stats_data.lock() {
+                if let Ok(mut o) = stats_data.write() {
                     *o = resp;
                 }
+                if let Ok(mut o) = all_data.write() {
+                    *o = all;
+                }
+                updated.store(now, Ordering::Relaxed);
             }
         });
 
@@ -349,11 +811,31 @@ impl StatsMgr {
         thread::spawn(move || loop {
             while let Ok(msg) = notifier_rx.recv() {
                 let (e, stat) = msg;
+                // 多实例时只由 leader 发送及记录事件
+                if !leader::is_notify_leader() {
+                    trace!("not notify leader, skip {:?}", e);
+                    continue;
+                }
+                events::record(&e, stat.borrow());
+                if notifier::silence::is_muted(&e, &stat.name) {
+                    trace!("{} is silenced, skip {:?}", stat.name, e);
+                    notifier::dry_run_route("*", &e, &stat.name, "muted");
+                    continue;
+                }
                 let notifiers = &*notifies.lock().unwrap();
                 trace!("recv notify => {:?}, {:?}", e, stat);
                 for notifier in notifiers {
-                    trace!("{} notify {:?} => {:?}", notifier.kind(), e, stat);
-                    notifier.notify(&e, stat.borrow());
+                    if !notifier::routed(notifier.as_ref(), stat.borrow()) {
+                        trace!("{} skip {} by selector or route", notifier.name(), stat.name);
+                        notifier::dry_run_route(notifier.name(), &e, &stat.name, "skipped by selector or route");
+                        continue;
+                    }
+                    notifier::dry_run_route(notifier.name(), &e, &stat.name, "selected");
+                    trace!("{} notify {:?} => {:?}", notifier.name(), e, stat);
+                    if let Err(err) = notifier.notify(&e, stat.borrow()) {
+                        error!("{} notify {} error => {:?}", notifier.name(), stat.name, err);
+                        live::delivered(notifier.name(), Err(err));
+                    }
                 }
             }
         });
@@ -361,33 +843,295 @@ impl StatsMgr {
         Ok(())
     }
 
-    pub fn get_stats(&self) -> Arc<Mutex<StatsResp>> {
-        self.stats_data.clone()
+    // 按需刷新, 有访问时最多每秒重建一次
+    fn touch(&self) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        if self.updated.load(Ordering::Relaxed) < now {
+            self.refresh.notify();
+        }
+    }
+
+    pub fn get_stats(&self) -> Arc<StatsResp> {
+        self.touch();
+        self.stats_data.read().unwrap().clone()
+    }
+
+    // 包含隐藏的主机
+    pub fn get_all_stats(&self) -> Arc<StatsResp> {
+        self.touch();
+        self.all_data.read().unwrap().clone()
+    }
+
+    pub fn set_hidden(&self, name: &str, hidden: bool) -> Result<()> {
+        self.db.set_hidden(name, hidden)?;
+        let mut o = self.hidden.write().unwrap();
+        match hidden {
+            true => o.insert(name.to_string()),
+            false => o.remove(name),
+        };
+        drop(o);
+        self.refresh.notify();
+        Ok(())
+    }
+
+    pub fn announcements(&self) -> Vec<Announcement> {
+        self.announcements.read().unwrap().clone()
+    }
+
+    // 新建或更新公告, 要更新的公告不存在时返回 None
+    pub fn save_announcement(&self, mut o: Announcement) -> Result<Option<Announcement>> {
+        match self.db.save_announcement(&o)? {
+            Some(id) => o.id = id,
+            None => return Ok(None),
+        }
+        let mut list = self.announcements.write().unwrap();
+        match list.iter_mut().find(|a| a.id == o.id) {
+            Some(a) => *a = o.clone(),
+            None => list.push(o.clone()),
+        }
+        drop(list);
+        self.refresh.notify();
+        Ok(Some(o))
+    }
+
+    pub fn delete_announcement(&self, id: i64) -> Result<bool> {
+        let deleted = self.db.delete_announcement(id)?;
+        self.announcements.write().unwrap().retain(|o| o.id != id);
+        self.refresh.notify();
+        Ok(deleted)
+    }
+
+    // 模拟主机下线(until 为截止时间)或恢复, 只修改内存中的状态并经过正常的通知流程, 主机不存在时返回 false
+    pub fn chaos(&self, name: &str, until: Option<u64>) -> bool {
+        let Some(mut stat) = self.stat_map.shard(name).get(name).cloned() else {
+            return false;
+        };
+        let event = match until {
+            Some(until) => {
+                chaos::force_offline(name, until);
+                let o = stat.to_mut();
+                (o.online4, o.online6) = (false, false);
+                warn!("chaos: {name} forced offline until {until}");
+                Event::NodeDown
+            }
+            None if chaos::restore(name) => {
+                warn!("chaos: {name} restored");
+                Event::NodeUp
+            }
+            None => return true,
+        };
+        if let Some(tx) = self.notifier_tx.as_ref() {
+            let _ = tx.send((event, stat));
+        }
+        self.refresh.notify();
+        true
+    }
+
+    // 设置主机的独立上报密码, 为空时恢复使用配置中的密码
+    pub fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        self.db.set_secret(name, secret)?;
+        auth::set_secret(name, secret);
+        Ok(())
+    }
+
+    // 签发主机的上报令牌, 旧令牌随即失效, 只保存摘要
+    pub fn issue_token(&self, name: &str) -> Result<String> {
+        let token = auth::new_token();
+        let digest = auth::token_digest(&token);
+        self.db.set_token(name, &digest)?;
+        auth::set_token(name, &digest);
+        Ok(token)
+    }
+
+    pub fn revoke_token(&self, name: &str) -> Result<()> {
+        self.db.set_token(name, "")?;
+        auth::set_token(name, "");
+        Ok(())
+    }
+
+    // 重命名或合并主机, 数据库及内存中的状态一起迁移, 新名称已在线时以新名称的状态为准
+    pub fn rename_host(&self, from: &str, to: &str) -> Result<(bool, usize)> {
+        let result = self.db.rename_host(from, to)?;
+        // 已归档的历史数据一起迁移, 否则改名后更早的数据仍在旧名称下
+        archive::rename(&G_CONFIG.get().unwrap().archive, from, to)
+            .map_err(|err| anyhow::anyhow!("rename archive `{from}` => `{to}` error: {err}"))?;
+
+        let pre = self.stat_map.shard(from).remove(from);
+        if let Some(mut stat) = pre {
+            let mut stat_map = self.stat_map.shard(to);
+            if !stat_map.contains_key(to) {
+                let o = stat.to_mut();
+                o.name = to.to_string();
+                if o.alias == from {
+                    o.alias = to.to_string();
+                }
+                stat_map.insert(to.to_string(), stat);
+            }
+        }
+
+        // 流量基准跟着主机走, 新名称还没有基准时沿用旧的
+        let pre = self.hosts_map.shard(from).get(from).map(|o| (o.last_network_in, o.last_network_out));
+        if let Some((last_in, last_out)) = pre {
+            if let Some(o) = self.hosts_map.shard(to).get_mut(to) {
+                if o.last_network_in == 0 {
+                    o.last_network_in = last_in;
+                    o.last_network_out = last_out;
+                }
+            }
+        }
+
+        let mut hidden = self.hidden.write().unwrap();
+        if hidden.remove(from) {
+            hidden.insert(to.to_string());
+        }
+        drop(hidden);
+        // 独立密码及令牌随 hosts 表一起迁移, 合并时以新名称的为准
+        auth::load_secrets(self.db.host_secrets()?);
+        auth::load_tokens(self.db.host_tokens()?);
+
+        self.refresh.notify();
+        Ok(result)
+    }
+
+    pub fn get_stats_json(&self) -> Bytes {
+        self.touch();
+        self.resp_json.read().unwrap().clone()
     }
 
-    pub fn get_stats_json(&self) -> String {
-        self.resp_json.lock().unwrap().to_string()
+    fn derived(&self, name: &'static str, f: impl FnOnce(&[u8]) -> Result<Bytes>) -> Result<Bytes> {
+        let json = self.get_stats_json();
+        let mut cache = self.derived.lock().unwrap();
+        match cache.get(name) {
+            Some((src, o)) if src.as_ptr() == json.as_ptr() && src.len() == json.len() => Ok(o.clone()),
+            _ => {
+                let o = f(&json)?;
+                cache.insert(name, (json, o.clone()));
+                Ok(o)
+            }
+        }
     }
 
-    pub fn report(&self, data: serde_json::Value) -> Result<()> {
-        lazy_static! {
-            static ref SENDER: SyncSender<Cow<'static, HostStat>> = STAT_SENDER.get().unwrap().clone();
+    pub fn get_compact_json(&self) -> Result<Bytes> {
+        self.derived("compact", |json| {
+            let compact = compact::render(json)?;
+            metrics::observe_bytes("compact_json_bytes", compact.len() as u64);
+            Ok(compact)
+        })
+    }
+
+    // 按 Accept 协商的 stats.json 格式
+    pub fn get_stats_as(&self, format: Format) -> Result<Bytes> {
+        match format {
+            Format::Json => Ok(self.get_stats_json()),
+            _ => self.derived(format.content_type(), |json| format.transcode(json)),
         }
+    }
 
-        match serde_json::from_value(data) {
+    // 镜像模式下由 mirror 线程写入主节点的数据
+    pub fn set_stats_json(&self, json: Bytes) {
+        if let Ok(mut o) = self.resp_json.write() {
+            *o = json;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.updated.store(now, Ordering::Relaxed);
+    }
+
+    // 队列已满时返回 queue::Busy, 主机名与上报凭据不匹配时返回 auth::Forbidden
+    pub fn report(&self, mut data: serde_json::Value, reporter: &Reporter) -> Result<ReportAck> {
+        let cfg = G_CONFIG.get().unwrap();
+        let ingest = &cfg.ingest;
+        let server_ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mut interval = 0;
+        let mut skew = 0;
+        let mut mesh = Vec::new();
+        let mut watch = Vec::new();
+        let raw = recorder::enabled().then(|| data.clone());
+        match sanitize::sanitize(&mut data, ingest) {
+            Ok(0) => {}
+            Ok(n) => metrics::add("report_sanitized_fields", n as u64),
+            Err(err) => {
+                metrics::inc("report_rejected_invalid");
+                warn!("report rejected => {}", err);
+                return Err(err);
+            }
+        }
+        match HostStat::deserialize(&data) {
             Ok(stat) => {
                 trace!("send stat => {:?} ", stat);
-                SENDER.send(Cow::Owned(stat));
+                if !reporter.allows(cfg, &stat.name, &stat.gid) {
+                    metrics::inc("report_rejected_auth");
+                    warn!("{} report rejected => not allowed for {}", stat.name, reporter);
+                    return Err(Forbidden(format!("{reporter} is not allowed to report `{}`", stat.name)).into());
+                }
+                if stat.latest_ts > 0 {
+                    skew = stat.latest_ts as i64 - server_ts as i64;
+                }
+                data["clock_skew"] = skew.into();
+                let latest_ts = ingest.latest_ts(stat.latest_ts, server_ts).map_err(|err| {
+                    metrics::inc("report_rejected_ts");
+                    warn!("{} report rejected => {}", stat.name, err);
+                    err
+                })?;
+                if latest_ts != stat.latest_ts {
+                    data["latest_ts"] = latest_ts.into();
+                }
+                if cfg.mesh.enabled {
+                    let stats = self.all_data.read().unwrap().clone();
+                    self.record_mesh(&stats.servers, &stat.name, &data);
+                    mesh = mesh::targets(cfg, &stats.servers, &stat.name);
+                }
+                cluster::publish(&data);
+                STAT_QUEUE.get().unwrap().push(data)?;
+                if let Some(raw) = raw {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+                    recorder::record(now, raw);
+                }
+                if let Some(sampler) = SAMPLER.get() {
+                    interval = sampler.interval(&stat);
+                }
+                watch = watchdog::targets(cfg, &stat.name, &stat.gid);
             }
             Err(err) => {
                 error!("report error => {:?}", err);
             }
         };
+        Ok(ReportAck {
+            mesh,
+            watch,
+            ..ReportAck::ok(interval, server_ts, skew)
+        })
+    }
+
+    fn record_mesh(&self, servers: &[HostStat], name: &str, data: &serde_json::Value) {
+        if let Some(results) = data.get("mesh").and_then(|o| Vec::deserialize(o).ok()) {
+            mesh::record(G_CONFIG.get().unwrap(), servers, name, results);
+        }
+    }
+
+    // 集群中其它实例转发过来的上报, 不再转发
+    pub fn ingest(&self, data: serde_json::Value) -> Result<()> {
+        if G_CONFIG.get().unwrap().mesh.enabled {
+            if let Some(name) = data["name"].as_str() {
+                let stats = self.all_data.read().unwrap().clone();
+                self.record_mesh(&stats.servers, name, &data);
+            }
+        }
+        STAT_QUEUE.get().unwrap().push(data)?;
         Ok(())
     }
 
+    // 入库队列的 (长度, 容量), 未初始化时为 None
+    pub fn queue_depth(&self) -> Option<(usize, usize)> {
+        STAT_QUEUE.get().map(|q| q.depth())
+    }
+
+    pub fn persist_queue(&self) -> Result<usize> {
+        STAT_QUEUE.get().map(|q| q.persist()).unwrap_or(Ok(0))
+    }
+
     pub fn get_all_info(&self) -> Result<serde_json::Value> {
-        let data = self.stats_data.lock().unwrap();
+        self.touch();
+        let data = self.get_all_stats();
         let mut resp_json = serde_json::to_value(&*data)?;
         // for skip_serializing
         if let Some(srv_list) = resp_json["servers"].as_array_mut() {
@@ -398,20 +1142,202 @@ impl StatsMgr {
                     if !stat.disks.is_empty() {
                         srv.insert("disks".into(), serde_json::to_value(&stat.disks)?);
                     }
+                    let forecast = forecast::get(&stat.name);
+                    if !forecast.is_empty() {
+                        srv.insert("disk_forecast".into(), serde_json::to_value(forecast)?);
+                    }
                 }
             }
         }
         Ok(resp_json)
     }
     
-    // 在 StatsMgr 实现中添加
-    pub fn get_stats_by_timerange(&self, start_time: i64, end_time: i64) -> Result<serde_json::Value> {
-        let stats = self.db.get_stats_by_timerange(start_time, end_time)?;
+    // 主机名 => 数据精度策略, 动态加入的分组主机按上报的 gid 使用分组的配置
+    pub fn resolution_policy(&self) -> impl Fn(&str) -> Resolution {
+        let cfg = G_CONFIG.get().unwrap();
+        let gids = self
+            .all_data
+            .read()
+            .unwrap()
+            .servers
+            .iter()
+            .filter(|o| !o.gid.is_empty())
+            .map(|o| (o.name.to_string(), o.gid.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |name| cfg.resolution(name, gids.get(name).map(String::as_str).unwrap_or_default())
+    }
+
+    // 早于归档时间的部分从 parquet 读取, 拼接在数据库数据之前
+    // 只合并 [from, to) 范围内的主机
+    fn merge_archive(
+        &self,
+        stats: &mut HashMap<String, Vec<HostStatRecord>>,
+        start_time: i64,
+        end_time: i64,
+        (from, to): (Option<&str>, Option<&str>),
+    ) -> Result<()> {
+        let cfg = G_CONFIG.get().unwrap();
+        let end_time = end_time.min(archive::cutoff(&cfg.archive) - 1);
+        let policy = self.resolution_policy();
+        for (_, name) in self.db.list_hosts()? {
+            if from.is_some_and(|o| name.as_str() < o) || to.is_some_and(|o| name.as_str() >= o) {
+                continue;
+            }
+            // 归档中只有聚合数据, 短时间范围使用最细的聚合级别
+            let res = policy(&name);
+            let interval = match res.pick_interval(end_time - start_time) {
+                0 => match res.intervals.first() {
+                    Some(&o) => o,
+                    None => continue,
+                },
+                o => o,
+            };
+            let alias = stats
+                .get(&name)
+                .and_then(|o| o.first())
+                .map(|o| o.alias.to_string())
+                .or_else(|| cfg.hosts_map.get(&name).map(|o| o.alias.to_string()))
+                .unwrap_or_else(|| name.to_string());
+            let archived = archive::read_history(&cfg.archive, &name, &alias, interval, start_time, end_time)?;
+            if !archived.is_empty() {
+                stats.entry(name).or_default().splice(0..0, archived);
+            }
+        }
+        Ok(())
+    }
+
+    // 标签选择器匹配的主机名, 不在线的主机按数据库中最近一次的标签匹配
+    pub fn select(&self, selector: &Selector) -> HashSet<String> {
+        let stats = self.get_all_stats();
+        let mut names = stats
+            .servers
+            .iter()
+            .filter(|o| selector.matches(|k| o.label(k)))
+            .map(|o| o.name.to_string())
+            .collect::<HashSet<_>>();
+        match self.db.host_labels() {
+            Ok(hosts) => {
+                for (name, labels) in hosts.iter() {
+                    if stats.servers.iter().all(|o| &o.name != name)
+                        && selector.matches(|k| if k == "name" { Some(name.as_str()) } else { labels.get(k) })
+                    {
+                        names.insert(name.to_string());
+                    }
+                }
+            }
+            Err(err) => error!("get host labels error => {:?}", err),
+        }
+        names
+    }
+
+    // 主机的显示单位, 已下线或删除的主机按配置
+    pub fn si(&self, name: &str) -> bool {
+        if let Some(o) = self.stat_map.shard(name).get(name) {
+            return o.si;
+        }
+        match self.hosts_map.shard(name).get(name) {
+            Some(o) => o.units.si(false),
+            None => G_CONFIG.get().unwrap().site.units.si(false),
+        }
+    }
+
+    // 数据库及归档中的历史数据, name => 按时间排序的记录
+    pub fn history_records(&self, start_time: i64, end_time: i64) -> Result<HashMap<String, Vec<HostStatRecord>>> {
+        Ok(self.history_page(start_time, end_time, None, None, true)?.0)
+    }
+
+    // 单台主机的历史数据, deadline 已过时 history_page 只查询 from 这一台
+    pub fn host_records(&self, host: &str, start_time: i64, end_time: i64) -> Result<Vec<HostStatRecord>> {
+        let (mut stats, _) = self.history_page(start_time, end_time, Some(host), Some(Instant::now()), true)?;
+        Ok(stats.remove(host).unwrap_or_default())
+    }
+
+    // 从主机 from 开始的一页历史数据, 超过 deadline 时同时返回下一页的起始主机名
+    // public 为 true 时不包含隐藏的主机, 与 stats.json 一致
+    fn history_page(
+        &self,
+        start_time: i64,
+        end_time: i64,
+        from: Option<&str>,
+        deadline: Option<Instant>,
+        public: bool,
+    ) -> Result<(HistoryRecords, Option<String>)> {
+        let (mut stats, cursor) =
+            self.db
+                .get_stats_by_timerange(start_time, end_time, self.resolution_policy(), from, deadline)?;
+        let cfg = G_CONFIG.get().unwrap();
+        if cfg.archive.enabled && start_time < archive::cutoff(&cfg.archive) {
+            self.merge_archive(&mut stats, start_time, end_time, (from, cursor.as_deref()))?;
+        }
+        if public {
+            let hidden = self.hidden.read().unwrap();
+            stats.retain(|name, _| !hidden.contains(name));
+        }
+        Ok((stats, cursor))
+    }
+
+    // 最近 days 天的日汇总, 隐藏或不存在的主机返回 None
+    pub fn trends(&self, host: &str, days: i64) -> Result<Option<serde_json::Value>> {
+        if self.hidden.read().unwrap().contains(host) || !self.db.list_hosts()?.iter().any(|o| o.1 == host) {
+            return Ok(None);
+        }
+        let today = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 / 86400 * 86400;
+        let (points, disks) = self.db.daily_stats(host, today - (days - 1) * 86400)?;
+        let mut mounts: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+        for o in disks {
+            mounts.entry(o.mount_point).or_default().push(serde_json::json!({
+                "day": o.day,
+                "total": o.total,
+                "used": o.used,
+            }));
+        }
+        Ok(Some(serde_json::json!({
+            "name": host,
+            "days": days,
+            "si": self.si(host),
+            "points": points,
+            "disks": mounts,
+        })))
+    }
+
+    // name => kind => 探测记录
+    pub fn probe_records(&self, start_time: i64, end_time: i64) -> Result<HashMap<String, HashMap<String, Vec<ProbeRecord>>>> {
+        self.db.get_probe_by_timerange(start_time, end_time)
+    }
+
+    // 多台主机同一指标按统一步长对齐的序列
+    pub fn compare(&self, names: &[String], metric: compare::Metric, start_time: i64, end_time: i64) -> Result<serde_json::Value> {
+        let policy = self.resolution_policy();
+        let time_range = end_time - start_time;
+        let step = compare::step(names.iter().map(|name| policy(name).pick_interval(time_range)), time_range);
+        let records = self.history_records(start_time, end_time)?;
+        Ok(compare::align(names, records, metric, (start_time, end_time), step))
+    }
+
+    // 查询耗时超过 history_budget_ms 时返回部分主机, truncated 为 true, 以 cursor 参数继续查询剩余的主机
+    pub fn get_stats_by_timerange(
+        &self,
+        start_time: i64,
+        end_time: i64,
+        from: Option<&str>,
+        public: bool,
+    ) -> Result<serde_json::Value> {
+        let started = Instant::now();
+        let budget_ms = G_CONFIG.get().unwrap().db.history_budget_ms;
+        let deadline = (budget_ms > 0).then(|| started + Duration::from_millis(budget_ms));
+        let (stats, cursor) = self.history_page(start_time, end_time, from, deadline, public)?;
+        let mut probes = self.probe_records(start_time, end_time)?;
+        let query_ms = started.elapsed().as_millis() as u64;
         
         let mut result = serde_json::json!({
             "updated": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            "truncated": cursor.is_some(),
             "servers": []
         });
+        if let Some(cursor) = cursor {
+            metrics::inc("history_truncated");
+            result["cursor"] = cursor.into();
+        }
         
         let servers = result["servers"].as_array_mut().unwrap();
         
@@ -419,107 +1345,77 @@ impl StatsMgr {
             if records.is_empty() {
                 continue;
             }
-            
-            // 使用最新记录的基本信息
-            let latest = &records[records.len() - 1];
-            
-            let mut host_data = serde_json::json!({
-                "name": host_name,
-                "alias": latest.alias,
-                "online": latest.online,
-                "data_points": records.len(),
-                "cpu_history": [],
-                "memory_history": [],
-                "network_in_history": [],
-                "network_out_history": [],
-                "disks_history": {}  // 改为对象，每个挂载点一个数组
-            });
-            
-            // 创建临时变量来存储历史数据
-            let mut cpu_data = Vec::new();
-            let mut memory_data = Vec::new();
-            let mut network_in_data = Vec::new();
-            let mut network_out_data = Vec::new();
-            
-            // 初始化磁盘挂载点
-            let mut mount_points = HashSet::new();
-            for record in &records {
-                for disk in &record.disks {
-                    mount_points.insert(disk.mount_point.clone());
+            let si = self.si(&host_name);
+            servers.push(host_history(host_name, si, &records, &mut probes));
+        }
+
+        let elapsed = started.elapsed().as_millis() as u64;
+        metrics::observe_ms("history_query_ms", elapsed);
+        let slow_query_ms = G_CONFIG.get().unwrap().db.slow_query_ms;
+        if slow_query_ms > 0 && elapsed >= slow_query_ms {
+            metrics::inc("history_slow_queries");
+            let points = servers.iter().filter_map(|o| o["data_points"].as_u64()).sum::<u64>();
+            warn!(
+                "slow history query {}ms (db {}ms), range [{}, {}] {}s, {} hosts, {} points",
+                elapsed,
+                query_ms,
+                start_time,
+                end_time,
+                end_time - start_time,
+                servers.len(),
+                points
+            );
+        }
+
+        Ok(result)
+    }
+
+    // 逐台主机查询并交给 sink, 不在内存中保留完整的结果, sink 返回 false 时(客户端断开)停止
+    // 超过 history_budget_ms 时返回剩余主机的 cursor
+    pub fn stream_history(
+        &self,
+        start_time: i64,
+        end_time: i64,
+        from: Option<&str>,
+        mut sink: impl FnMut(serde_json::Value) -> bool,
+    ) -> Result<Option<String>> {
+        let mut probes = self.probe_records(start_time, end_time)?;
+        let budget_ms = G_CONFIG.get().unwrap().db.history_budget_ms;
+        self.stream_records(start_time, end_time, from, budget_ms, |host_name, records| {
+            let si = self.si(&host_name);
+            sink(host_history(host_name, si, &records, &mut probes))
+        })
+    }
+
+    // 同 stream_history, sink 收到的是主机名及原始记录, 跳过没有数据的主机, budget_ms 为 0 时不限制耗时
+    pub fn stream_records(
+        &self,
+        start_time: i64,
+        end_time: i64,
+        from: Option<&str>,
+        budget_ms: u64,
+        mut sink: impl FnMut(String, Vec<HostStatRecord>) -> bool,
+    ) -> Result<Option<String>> {
+        let started = Instant::now();
+        let mut from = from.map(str::to_string);
+        let cursor = loop {
+            // deadline 已过时每页只查询一台主机
+            let (stats, cursor) = self.history_page(start_time, end_time, from.as_deref(), Some(Instant::now()), true)?;
+            for (host_name, records) in stats {
+                if !records.is_empty() && !sink(host_name, records) {
+                    return Ok(None);
                 }
             }
-            
-            // 为每个挂载点创建数组
-            let mut disk_data_map: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
-            for mount_point in &mount_points {
-                disk_data_map.insert(mount_point.clone(), Vec::new());
-            }
-            
-            for record in &records {
-                cpu_data.push(serde_json::json!({
-                    "timestamp": record.timestamp,
-                    "value": record.cpu
-                }));
-                
-                let mem_percent = if record.memory_total > 0 {
-                    (record.memory_used as f64 / record.memory_total as f64) * 100.0
-                } else {
-                    0.0
-                };
-                
-                memory_data.push(serde_json::json!({
-                    "timestamp": record.timestamp,
-                    "value": mem_percent,
-                    "total": record.memory_total,
-                    "used": record.memory_used
-                }));
-                
-                network_in_data.push(serde_json::json!({
-                    "timestamp": record.timestamp,
-                    "value": record.network_in_speed,
-                    "total": record.network_in
-                }));
-                
-                network_out_data.push(serde_json::json!({
-                    "timestamp": record.timestamp,
-                    "value": record.network_out_speed,
-                    "total": record.network_out
-                }));
-                
-                // 处理每个磁盘
-                for disk in &record.disks {
-                    if let Some(disk_array) = disk_data_map.get_mut(&disk.mount_point) {
-                        let disk_percent = if disk.total > 0 {
-                            (disk.used as f64 / disk.total as f64) * 100.0
-                        } else {
-                            0.0
-                        };
-                        
-                        disk_array.push(serde_json::json!({
-                            "timestamp": record.timestamp,
-                            "value": disk_percent,
-                            "total": disk.total,
-                            "used": disk.used
-                        }));
-                    }
+            match cursor {
+                Some(o) if budget_ms > 0 && started.elapsed() >= Duration::from_millis(budget_ms) => {
+                    metrics::inc("history_truncated");
+                    break Some(o);
                 }
+                Some(o) => from = Some(o),
+                None => break None,
             }
-            
-            // 将收集的数据添加到 host_data
-            host_data["cpu_history"] = serde_json::json!(cpu_data);
-            host_data["memory_history"] = serde_json::json!(memory_data);
-            host_data["network_in_history"] = serde_json::json!(network_in_data);
-            host_data["network_out_history"] = serde_json::json!(network_out_data);
-            
-            // 添加磁盘数据
-            let disks_obj = host_data["disks_history"].as_object_mut().unwrap();
-            for (mount_point, data) in disk_data_map {
-                disks_obj.insert(mount_point, serde_json::json!(data));
-            }
-            
-            servers.push(host_data);
-        }
-        
-        Ok(result)
+        };
+        metrics::observe_ms("history_query_ms", started.elapsed().as_millis() as u64);
+        Ok(cursor)
     }
 }
@@ -0,0 +1,170 @@
+#![deny(warnings)]
+use serde::Serialize;
+use stat_common::utils::bytes2human;
+use std::collections::BTreeMap;
+
+use crate::payload::HostStat;
+
+// 一组主机的汇总, cpu/load 为在线主机的平均值, 其余为合计
+#[derive(Debug, Default, Serialize)]
+pub struct Rollup {
+    pub name: String,
+    pub total: usize,
+    pub online: usize,
+    pub cpu: f64,
+    pub load_1: f64,
+    pub memory_total: u64,
+    pub memory_used: u64,
+    pub hdd_total: u64,
+    pub hdd_used: u64,
+    pub network_rx: u64,
+    pub network_tx: u64,
+    pub network_in: u64,
+    pub network_out: u64,
+    pub human: Human,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Rollup>,
+}
+
+// 按 Summary.si 转换的可读值, 与 detail 页一致, 内存按 KiB/KB, 磁盘按 MiB/MB 上报
+#[derive(Debug, Default, Serialize)]
+pub struct Human {
+    pub memory_total: String,
+    pub memory_used: String,
+    pub hdd_total: String,
+    pub hdd_used: String,
+    pub network_rx: String,
+    pub network_tx: String,
+    pub network_in: String,
+    pub network_out: String,
+}
+
+impl Rollup {
+    pub fn new(name: &str, servers: &[&HostStat]) -> Self {
+        let mut o = Rollup {
+            name: name.to_string(),
+            total: servers.len(),
+            ..Default::default()
+        };
+        for s in servers.iter().filter(|s| s.online4 || s.online6) {
+            o.online += 1;
+            o.cpu += s.cpu;
+            o.load_1 += s.load_1;
+            o.memory_total += s.memory_total;
+            o.memory_used += s.memory_used;
+            o.hdd_total += s.hdd_total;
+            o.hdd_used += s.hdd_used;
+            o.network_rx += s.network_rx;
+            o.network_tx += s.network_tx;
+            o.network_in += s.network_in;
+            o.network_out += s.network_out;
+        }
+        if o.online > 0 {
+            o.cpu = (o.cpu / o.online as f64 * 100.0).round() / 100.0;
+            o.load_1 = (o.load_1 / o.online as f64 * 100.0).round() / 100.0;
+        }
+        o
+    }
+
+    fn humanize(&mut self, si: bool) {
+        let unit: u64 = if si { 1000 } else { 1024 };
+        let human = |v: u64| bytes2human(v, 1, si);
+        self.human = Human {
+            memory_total: human(self.memory_total * unit),
+            memory_used: human(self.memory_used * unit),
+            hdd_total: human(self.hdd_total * unit * unit),
+            hdd_used: human(self.hdd_used * unit * unit),
+            network_rx: human(self.network_rx),
+            network_tx: human(self.network_tx),
+            network_in: human(self.network_in),
+            network_out: human(self.network_out),
+        };
+        for o in self.children.iter_mut() {
+            o.humanize(si);
+        }
+    }
+}
+
+// 按 key 分组, 未配置的主机归入空字符串一组
+fn group<'a>(servers: &[&'a HostStat], key: fn(&HostStat) -> &str) -> BTreeMap<String, Vec<&'a HostStat>> {
+    let mut groups: BTreeMap<String, Vec<&HostStat>> = BTreeMap::new();
+    for &s in servers {
+        groups.entry(key(s).to_string()).or_default().push(s);
+    }
+    groups
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    // 可读值使用的单位, 对应 [site] units
+    pub si: bool,
+    pub total: Rollup,
+    // region => zone 两级
+    pub regions: Vec<Rollup>,
+    pub providers: Vec<Rollup>,
+}
+
+pub fn summary(servers: &[HostStat], si: bool) -> Summary {
+    let all = servers.iter().collect::<Vec<_>>();
+    let regions = group(&all, |o| &o.region)
+        .into_iter()
+        .map(|(region, hosts)| Rollup {
+            children: group(&hosts, |o| &o.zone)
+                .into_iter()
+                .map(|(zone, hosts)| Rollup::new(&zone, &hosts))
+                .collect(),
+            ..Rollup::new(&region, &hosts)
+        })
+        .collect();
+    let providers = group(&all, |o| &o.provider)
+        .into_iter()
+        .map(|(provider, hosts)| Rollup::new(&provider, &hosts))
+        .collect();
+    let mut o = Summary {
+        si,
+        total: Rollup::new("", &all),
+        regions,
+        providers,
+    };
+    for rollup in std::iter::once(&mut o.total).chain(o.regions.iter_mut()).chain(o.providers.iter_mut()) {
+        rollup.humanize(si);
+    }
+    o
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let host = |region: &str, zone: &str, provider: &str, online: bool, cpu: f64| HostStat {
+            region: region.to_string(),
+            zone: zone.to_string(),
+            provider: provider.to_string(),
+            online4: online,
+            online6: false,
+            cpu,
+            memory_total: 100,
+            ..Default::default()
+        };
+        let servers = vec![
+            host("asia", "hk-1", "aws", true, 10.0),
+            host("asia", "hk-2", "aws", true, 20.0),
+            host("asia", "hk-2", "gcp", false, 90.0),
+            host("", "", "", true, 50.0),
+        ];
+        let o = summary(&servers, false);
+        assert_eq!((o.total.total, o.total.online, o.total.memory_total), (4, 3, 300));
+        assert_eq!(o.regions.len(), 2);
+        assert_eq!(o.regions[0].name, "");
+        let asia = &o.regions[1];
+        assert_eq!((asia.total, asia.online, asia.cpu), (3, 2, 15.0));
+        assert_eq!(asia.children.iter().map(|z| z.name.as_str()).collect::<Vec<_>>(), ["hk-1", "hk-2"]);
+        assert_eq!((asia.children[1].total, asia.children[1].online), (2, 1));
+        assert_eq!(o.providers.iter().map(|p| (p.name.as_str(), p.total)).collect::<Vec<_>>(), [("", 1), ("aws", 2), ("gcp", 1)]);
+        assert_eq!(o.total.human.memory_total, "300.0K");
+        assert_eq!(asia.children[0].human.memory_total, "100.0K");
+        assert_eq!(summary(&servers, true).total.human.memory_total, "300.0K");
+    }
+}
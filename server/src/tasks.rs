@@ -0,0 +1,107 @@
+#![deny(warnings)]
+use chrono::Utc;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::metrics;
+use crate::notifier;
+
+// 后台维护任务的运行状态, 通过 /api/admin/tasks 查看
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TaskStatus {
+    pub runs: u64,
+    pub failures: u64,
+    // 连续失败次数, 成功后清零
+    pub consecutive_failures: u32,
+    pub last_run: i64,
+    pub last_success: i64,
+    pub last_duration_ms: u64,
+    pub last_error: Option<String>,
+}
+
+static TASKS: Lazy<Mutex<BTreeMap<&'static str, TaskStatus>>> = Lazy::new(Default::default);
+// 连续失败多少次后告警, 0 不告警
+static ALERT_AFTER: OnceCell<u32> = OnceCell::new();
+
+pub fn init(alert_after: u32) {
+    let _ = ALERT_AFTER.set(alert_after);
+}
+
+// 执行并记录结果, 连续失败达到阈值时告警一次, 之后恢复时再通知一次
+pub fn run<T, E: Display>(name: &'static str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let start = Instant::now();
+    let result = f();
+    let alert_after = ALERT_AFTER.get().copied().unwrap_or(0);
+
+    let msg = {
+        let mut tasks = TASKS.lock().unwrap();
+        let status = tasks.entry(name).or_default();
+        let now = Utc::now().timestamp();
+        status.runs += 1;
+        status.last_run = now;
+        status.last_duration_ms = start.elapsed().as_millis() as u64;
+        match &result {
+            Ok(_) => {
+                let recovered = alert_after > 0 && status.consecutive_failures >= alert_after;
+                status.consecutive_failures = 0;
+                status.last_success = now;
+                status.last_error = None;
+                recovered.then(|| format!("✅ task `{name}` recovered"))
+            }
+            Err(err) => {
+                metrics::inc("task_failed");
+                status.failures += 1;
+                status.consecutive_failures += 1;
+                status.last_error = Some(err.to_string());
+                error!("task `{}` failed ({} in a row) => {}", name, status.consecutive_failures, err);
+                (alert_after > 0 && status.consecutive_failures == alert_after).then(|| {
+                    format!(
+                        "❗task `{name}` failed {} times in a row, last error: {err}",
+                        status.consecutive_failures
+                    )
+                })
+            }
+        }
+    };
+    if let Some(msg) = msg {
+        notifier::alert(&msg);
+    }
+    result
+}
+
+pub fn get(name: &str) -> Option<TaskStatus> {
+    TASKS.lock().unwrap().get(name).cloned()
+}
+
+pub fn snapshot() -> Value {
+    let tasks = TASKS.lock().map(|o| o.clone()).unwrap_or_default();
+    serde_json::to_value(tasks).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        init(2);
+        let _ = run("t", || Err::<(), _>("e1"));
+        let _ = run("t", || Err::<(), _>("e2"));
+        let status = TASKS.lock().unwrap().get("t").cloned().unwrap();
+        assert_eq!(status.runs, 2);
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.last_error.as_deref(), Some("e2"));
+
+        assert_eq!(run("t", || Ok::<_, String>(1)), Ok(1));
+        let status = TASKS.lock().unwrap().get("t").cloned().unwrap();
+        assert_eq!(status.failures, 2);
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.last_error.is_none());
+        assert!(status.last_success > 0);
+    }
+}
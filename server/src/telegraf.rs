@@ -0,0 +1,241 @@
+#![deny(warnings)]
+// 接收 Telegraf outputs.http(data_format = "json") 的上报, 把识别的指标转换为 StatRequest 后按普通上报处理
+// 认证与 /report 相同, 主机凭据上报时主机名为用户名, 分组凭据上报时主机名取 host 标签
+use anyhow::{bail, Result};
+use axum::{
+    body::Bytes,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use stat_common::server_status::{DiskInfo, StatRequest};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::auth::{self, Reporter};
+use crate::queue::Busy;
+use crate::G_STATS_MGR;
+
+// 主机名 => 上次的网络总流量, 用于计算速率
+static COUNTERS: Lazy<Mutex<HashMap<String, Counter>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Counter {
+    ts: u64,
+    rx: u64,
+    tx: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metric {
+    name: String,
+    #[serde(default = "Default::default")]
+    tags: HashMap<String, String>,
+    #[serde(default = "Default::default")]
+    fields: HashMap<String, Value>,
+    #[serde(default = "Default::default")]
+    timestamp: u64,
+}
+
+impl Metric {
+    fn tag(&self, key: &str) -> &str {
+        self.tags.get(key).map(String::as_str).unwrap_or_default()
+    }
+
+    fn field(&self, key: &str) -> f64 {
+        self.fields.get(key).and_then(Value::as_f64).unwrap_or_default()
+    }
+
+    fn uint(&self, key: &str) -> u64 {
+        self.field(key).max(0.0) as u64
+    }
+}
+
+// 支持 batch 格式 {"metrics": [..]}, 单个指标及指标数组
+fn parse(body: &[u8]) -> Result<Vec<Metric>> {
+    let v: Value = serde_json::from_slice(body)?;
+    let metrics = match v {
+        Value::Object(mut o) if o.contains_key("metrics") => o.remove("metrics").unwrap_or_default(),
+        Value::Object(o) => Value::Array(vec![Value::Object(o)]),
+        o => o,
+    };
+    Ok(serde_json::from_value(metrics)?)
+}
+
+// 按主机分组, 没有 host 标签时归到 default
+fn group(metrics: Vec<Metric>, default: &str) -> BTreeMap<String, Vec<Metric>> {
+    let mut hosts = BTreeMap::<String, Vec<Metric>>::new();
+    for o in metrics {
+        let host = match o.tag("host") {
+            "" => default.to_string(),
+            host => host.to_string(),
+        };
+        hosts.entry(host).or_default().push(o);
+    }
+    hosts
+}
+
+// 内存/swap 为 KiB, hdd 为 MiB, 与客户端一致; 网络速率根据上次的总流量计算
+fn convert(name: &str, metrics: &[Metric], last: Option<Counter>) -> (StatRequest, Counter) {
+    let mut stat = StatRequest {
+        name: name.to_string(),
+        version: "telegraf".to_string(),
+        frame: "data".to_string(),
+        ..Default::default()
+    };
+    let mut counter = Counter::default();
+    for o in metrics {
+        stat.latest_ts = stat.latest_ts.max(o.timestamp);
+        match o.name.as_str() {
+            "cpu" if o.tag("cpu") == "cpu-total" => stat.cpu = (100.0 - o.field("usage_idle")).clamp(0.0, 100.0),
+            "mem" => {
+                stat.memory_total = o.uint("total") / 1024;
+                stat.memory_used = o.uint("used") / 1024;
+            }
+            "swap" => {
+                stat.swap_total = o.uint("total") / 1024;
+                stat.swap_used = o.uint("used") / 1024;
+            }
+            "system" => {
+                stat.load_1 = o.field("load1");
+                stat.load_5 = o.field("load5");
+                stat.load_15 = o.field("load15");
+                stat.uptime = o.uint("uptime");
+            }
+            "processes" => {
+                stat.process = o.uint("total") as u32;
+                stat.thread = o.uint("total_threads") as u32;
+            }
+            "netstat" => {
+                stat.tcp = o.fields.keys().filter(|k| k.starts_with("tcp_")).map(|k| o.uint(k)).sum::<u64>() as u32;
+                stat.udp = o.uint("udp_socket") as u32;
+            }
+            "disk" => {
+                let (total, used) = (o.uint("total"), o.uint("used"));
+                stat.hdd_total += total / 1024 / 1024;
+                stat.hdd_used += used / 1024 / 1024;
+                stat.disks.push(DiskInfo {
+                    name: o.tag("device").to_string(),
+                    mount_point: o.tag("path").to_string(),
+                    file_system: o.tag("fstype").to_string(),
+                    total,
+                    used,
+                    free: o.uint("free"),
+                    device_id: String::new(),
+                    inode_total: o.uint("inodes_total"),
+                    inode_used: o.uint("inodes_used"),
+                });
+            }
+            "net" if !matches!(o.tag("interface"), "" | "all" | "lo") => {
+                counter.rx += o.uint("bytes_recv");
+                counter.tx += o.uint("bytes_sent");
+                counter.ts = counter.ts.max(o.timestamp);
+            }
+            _ => {}
+        }
+    }
+    stat.network_in = counter.rx;
+    stat.network_out = counter.tx;
+    if let Some(last) = last.filter(|o| counter.ts > o.ts && counter.rx >= o.rx && counter.tx >= o.tx) {
+        let dt = counter.ts - last.ts;
+        stat.network_rx = (counter.rx - last.rx) / dt;
+        stat.network_tx = (counter.tx - last.tx) / dt;
+    }
+    (stat, counter)
+}
+
+fn report(body: &[u8], reporter: &Reporter) -> Result<usize> {
+    let Some(mgr) = G_STATS_MGR.get() else {
+        return Ok(0);
+    };
+    let metrics = parse(body)?;
+    // 主机凭据只能上报自己, 忽略 host 标签
+    let hosts = match reporter {
+        Reporter::Host(user) => BTreeMap::from([(user.to_string(), metrics)]),
+        Reporter::Group { .. } => group(metrics, ""),
+    };
+    for (name, metrics) in hosts.iter() {
+        if name.is_empty() {
+            bail!("host tag is required");
+        }
+        let last = COUNTERS.lock().unwrap().get(name).copied();
+        let (stat, counter) = convert(name, metrics, last);
+        let mut data = serde_json::to_value(stat)?;
+        if let Reporter::Group { gid, .. } = reporter {
+            data["gid"] = gid.to_string().into();
+        }
+        mgr.report(data, reporter)?;
+        if counter.ts > 0 {
+            COUNTERS.lock().unwrap().insert(name.to_string(), counter);
+        }
+    }
+    Ok(hosts.len())
+}
+
+// POST /report/telegraf
+pub async fn handler(auth::HostAuth(reporter): auth::HostAuth, body: Bytes) -> Response {
+    // overflow = "block" 时队列满会等待, 不能占用 runtime 的工作线程
+    let result = tokio::task::spawn_blocking(move || report(&body, &reporter)).await;
+    match result.map_err(anyhow::Error::from).and_then(|o| o) {
+        Ok(n) => Json(json!({ "code": 0, "message": "ok", "hosts": n })).into_response(),
+        Err(err) => match err.downcast_ref::<Busy>() {
+            Some(busy) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(axum::http::header::RETRY_AFTER, busy.retry_after.to_string())],
+            )
+                .into_response(),
+            None if err.is::<auth::Forbidden>() => StatusCode::FORBIDDEN.into_response(),
+            None => {
+                warn!("telegraf report rejected => {:?}", err);
+                (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let body = br#"{"metrics": [
+            {"name": "cpu", "tags": {"cpu": "cpu-total", "host": "h1"}, "fields": {"usage_idle": 87.5}, "timestamp": 1700000060},
+            {"name": "cpu", "tags": {"cpu": "cpu0", "host": "h1"}, "fields": {"usage_idle": 10.0}, "timestamp": 1700000060},
+            {"name": "mem", "tags": {"host": "h1"}, "fields": {"total": 2147483648, "used": 1073741824}, "timestamp": 1700000060},
+            {"name": "disk", "tags": {"host": "h1", "path": "/", "device": "sda1", "fstype": "ext4"},
+             "fields": {"total": 10737418240, "used": 5368709120, "free": 5368709120, "inodes_total": 655360, "inodes_used": 1024}, "timestamp": 1700000060},
+            {"name": "net", "tags": {"host": "h1", "interface": "eth0"}, "fields": {"bytes_recv": 16000, "bytes_sent": 4000}, "timestamp": 1700000060},
+            {"name": "net", "tags": {"host": "h1", "interface": "lo"}, "fields": {"bytes_recv": 99999, "bytes_sent": 99999}, "timestamp": 1700000060},
+            {"name": "system", "tags": {"host": "h2"}, "fields": {"load1": 0.5, "uptime": 3600}, "timestamp": 1700000060}
+        ]}"#;
+        let hosts = group(parse(body).unwrap(), "");
+        assert_eq!(hosts.keys().collect::<Vec<_>>(), ["h1", "h2"]);
+
+        let last = Counter {
+            ts: 1700000000,
+            rx: 10000,
+            tx: 1000,
+        };
+        let (stat, counter) = convert("h1", &hosts["h1"], Some(last));
+        assert_eq!(stat.latest_ts, 1700000060);
+        assert_eq!(stat.cpu, 12.5);
+        assert_eq!((stat.memory_total, stat.memory_used), (2097152, 1048576));
+        assert_eq!((stat.hdd_total, stat.hdd_used), (10240, 5120));
+        assert_eq!(stat.disks[0].mount_point, "/");
+        assert_eq!((stat.disks[0].inode_total, stat.disks[0].inode_used), (655360, 1024));
+        assert_eq!((stat.network_in, stat.network_out), (16000, 4000));
+        assert_eq!((stat.network_rx, stat.network_tx), (100, 50));
+        assert_eq!(counter, Counter { ts: 1700000060, rx: 16000, tx: 4000 });
+
+        let (stat, _) = convert("h2", &hosts["h2"], None);
+        assert_eq!((stat.load_1, stat.uptime, stat.network_rx), (0.5, 3600, 0));
+
+        // 单个指标
+        let one = parse(br#"{"name": "mem", "fields": {"total": 1024}, "timestamp": 1}"#).unwrap();
+        assert_eq!(group(one, "h3").keys().collect::<Vec<_>>(), ["h3"]);
+    }
+}
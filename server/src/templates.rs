@@ -0,0 +1,107 @@
+#![deny(warnings)]
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::assets::Asset;
+use crate::jinja;
+
+// 页面模板: (tag, 文件名, 是否按 html 转义)
+const PAGES: [(&str, &str, bool); 4] = [
+    ("detail", "detail.jinja.html", true),
+    ("map", "map.jinja.html", true),
+    ("widget", "widget.jinja.html", true),
+    ("client-init", "client-init.jinja.sh", false),
+];
+// 检查 templates_dir 中文件变化的间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+fn embedded(file: &str) -> String {
+    let data = Asset::get(&format!("/jinja/{file}")).unwrap_or_else(|| panic!("{file} not found"));
+    String::from_utf8(data.data.into()).unwrap()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|o| o.modified()).ok()
+}
+
+// 加载 templates_dir 中的模板, 不存在时恢复内置的模板
+fn load(kind: &str, dir: &Path, (tag, file, html): (&str, &str, bool)) -> Result<Option<PathBuf>> {
+    let path = dir.join(file);
+    if !path.is_file() {
+        jinja::try_add_template(kind, tag, embedded(file), html)?;
+        return Ok(None);
+    }
+    jinja::try_add_template(kind, tag, fs::read_to_string(&path)?, html)?;
+    Ok(Some(path))
+}
+
+fn reload(kind: &str, dir: &Path, page: (&str, &str, bool)) {
+    match load(kind, dir, page) {
+        Ok(Some(path)) => info!("✨ template {} loaded from {}", page.0, path.display()),
+        Ok(None) => info!("✨ template {} restored to built-in", page.0),
+        Err(err) => error!("❗load template {} error, keep the previous one => {:?}", page.1, err),
+    }
+}
+
+pub fn init(kind: &'static str, dir: &str) -> Result<()> {
+    for (tag, file, html) in PAGES {
+        jinja::try_add_template(kind, tag, embedded(file), html)?;
+    }
+    if dir.is_empty() {
+        return Ok(());
+    }
+
+    let dir = PathBuf::from(dir);
+    let mut seen = HashMap::new();
+    for page in PAGES {
+        seen.insert(page.1, modified(&dir.join(page.1)));
+        if dir.join(page.1).is_file() {
+            reload(kind, &dir, page);
+        }
+    }
+    thread::spawn(move || loop {
+        thread::sleep(WATCH_INTERVAL);
+        for page in PAGES {
+            let mtime = modified(&dir.join(page.1));
+            if seen.insert(page.1, mtime) != Some(mtime) {
+                reload(kind, &dir, page);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::context;
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("tpl-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let page = PAGES[3];
+        let render = || jinja::render_template("test-tpl", "client-init", context!(pass => "<p>"), false).unwrap();
+
+        assert!(load("test-tpl", &dir, page).unwrap().is_none());
+        assert!(render().starts_with("#!/"));
+
+        fs::write(dir.join(page.1), "pass={{ pass }}").unwrap();
+        assert!(load("test-tpl", &dir, page).unwrap().is_some());
+        assert_eq!(render(), "pass=<p>");
+
+        // 语法错误时保留原模板
+        fs::write(dir.join(page.1), "{% if %}").unwrap();
+        assert!(load("test-tpl", &dir, page).is_err());
+        assert_eq!(render(), "pass=<p>");
+
+        fs::remove_file(dir.join(page.1)).unwrap();
+        assert!(load("test-tpl", &dir, page).unwrap().is_none());
+        assert!(render().starts_with("#!/"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
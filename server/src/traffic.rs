@@ -0,0 +1,256 @@
+#![deny(warnings)]
+// 流量突增告警, 按方向检查上报的 network_rx/network_tx, 超过绝对阈值或基线倍数并持续一段时间时通知
+// 基线为未超限时速率的指数移动平均, 突增期间不更新, 避免被突增本身抬高
+use anyhow::{anyhow, Result};
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::config::{self, Config, Direction, TrafficAlert};
+use crate::labels::Selector;
+use crate::notifier;
+use crate::payload::HostStat;
+
+// 基线低于这个速率时按这个速率计算倍数, 避免空闲主机的少量流量触发
+const MIN_BASELINE_MBPS: f64 = 1.0;
+
+static RULES: OnceCell<Vec<Rule>> = OnceCell::new();
+static STATE: Lazy<Mutex<HashMap<(usize, String), Track>>> = Lazy::new(Default::default);
+
+pub struct Rule {
+    name: String,
+    direction: Direction,
+    mbps: f64,
+    factor: f64,
+    baseline: u64,
+    duration: u64,
+    selector: Selector,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    // Mbps
+    baseline: f64,
+    // 基线已累计的时间(s), 达到 rule.baseline 后才按倍数检查
+    warm: u64,
+    last_ts: u64,
+    since: Option<u64>,
+    firing: bool,
+    peak: f64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Fire { rate: f64, baseline: f64 },
+    Resolve { rate: f64, peak: f64 },
+}
+
+pub fn compile(rule: &TrafficAlert) -> Result<Rule> {
+    if rule.mbps <= 0.0 && rule.factor <= 0.0 {
+        return Err(anyhow!("mbps or factor is required"));
+    }
+    if rule.factor > 0.0 && rule.factor <= 1.0 {
+        return Err(anyhow!("factor must be greater than 1"));
+    }
+    let duration = |s: &str| config::parse_duration(s).ok_or_else(|| anyhow!("invalid duration `{s}`"));
+    Ok(Rule {
+        name: rule.name.to_string(),
+        direction: rule.direction,
+        mbps: rule.mbps,
+        factor: rule.factor,
+        baseline: duration(&rule.baseline)?.max(60),
+        duration: duration(&rule.duration)?,
+        selector: Selector::parse(&rule.selector).map_err(|err| anyhow!("invalid selector: {err}"))?,
+    })
+}
+
+pub fn init(cfg: &Config) -> Result<()> {
+    let rules = cfg.traffic_alerts.iter().map(compile).collect::<Result<Vec<_>>>()?;
+    if !rules.is_empty() {
+        eprintln!("✨ {} traffic alerts loaded", rules.len());
+    }
+    let _ = RULES.set(rules);
+    Ok(())
+}
+
+fn rate(direction: Direction, stat: &HostStat) -> f64 {
+    let bytes = match direction {
+        Direction::In => stat.network_rx,
+        Direction::Out => stat.network_tx,
+    };
+    bytes as f64 * 8.0 / 1e6
+}
+
+// 基线预热期间只按绝对阈值检查
+fn exceeds(rule: &Rule, track: &Track, rate: f64) -> bool {
+    let warm = track.warm >= rule.baseline;
+    let abs = rate > rule.mbps;
+    let rel = warm && rate > track.baseline.max(MIN_BASELINE_MBPS) * rule.factor;
+    match (rule.mbps > 0.0, rule.factor > 0.0) {
+        (true, true) => abs && (rel || !warm),
+        (true, false) => abs,
+        (false, true) => rel,
+        (false, false) => false,
+    }
+}
+
+fn update(rule: &Rule, track: &mut Track, rate: f64, now: u64) -> Option<Change> {
+    let dt = match track.last_ts {
+        0 => 0,
+        ts => now.saturating_sub(ts).min(rule.baseline),
+    };
+    track.last_ts = now;
+
+    if !exceeds(rule, track, rate) {
+        let alpha = match track.warm {
+            0 => 1.0,
+            _ => 1.0 - (-(dt as f64) / rule.baseline as f64).exp(),
+        };
+        track.baseline += alpha * (rate - track.baseline);
+        track.warm += dt.max(1);
+        track.since = None;
+        if track.firing {
+            track.firing = false;
+            return Some(Change::Resolve { rate, peak: track.peak });
+        }
+        return None;
+    }
+
+    track.peak = match track.since {
+        Some(_) => track.peak.max(rate),
+        None => rate,
+    };
+    let since = *track.since.get_or_insert(now);
+    if !track.firing && now - since >= rule.duration {
+        track.firing = true;
+        return Some(Change::Fire {
+            rate,
+            baseline: track.baseline,
+        });
+    }
+    None
+}
+
+fn message(rule: &Rule, stat: &HostStat, change: &Change, public_url: &str) -> String {
+    let direction = match rule.direction {
+        Direction::In => "inbound",
+        Direction::Out => "outbound",
+    };
+    match change {
+        Change::Fire { rate, baseline } => {
+            let mut msg = format!(
+                "📈 {} {direction} traffic spike {rate:.1} Mbps, baseline {baseline:.1} Mbps ({:.1}x), lasted {}s ({})",
+                stat.alias,
+                rate / baseline.max(MIN_BASELINE_MBPS),
+                rule.duration,
+                rule.name
+            );
+            if !public_url.is_empty() {
+                msg.push_str(&format!("\n{public_url}/chart/{}/network.png?range=1h", stat.name));
+            }
+            msg
+        }
+        Change::Resolve { rate, peak } => format!(
+            "✅ {} {direction} traffic back to normal {rate:.1} Mbps, peak {peak:.1} Mbps ({})",
+            stat.alias, rule.name
+        ),
+    }
+}
+
+// 在入库线程中调用, 通知在单独的线程中发送
+pub fn check(stat: &HostStat, now: u64) {
+    let Some(rules) = RULES.get().filter(|o| !o.is_empty()) else {
+        return;
+    };
+    let public_url = crate::G_CONFIG.get().map(|o| o.public_url()).unwrap_or_default();
+    let mut msgs = Vec::new();
+    {
+        let mut state = STATE.lock().unwrap();
+        for (idx, rule) in rules.iter().enumerate() {
+            if !rule.selector.matches(|k| stat.label(k)) {
+                continue;
+            }
+            let track = state.entry((idx, stat.name.to_string())).or_default();
+            if let Some(change) = update(rule, track, rate(rule.direction, stat), now) {
+                msgs.push(message(rule, stat, &change, public_url));
+            }
+        }
+    }
+    if !msgs.is_empty() {
+        let stat = stat.clone();
+        thread::spawn(move || {
+            for msg in msgs {
+                notifier::alert_host(&msg, &stat);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(mbps: f64, factor: f64) -> TrafficAlert {
+        TrafficAlert {
+            name: "spike".to_string(),
+            enabled: true,
+            direction: Direction::Out,
+            mbps,
+            factor,
+            baseline: "10m".to_string(),
+            duration: "60s".to_string(),
+            selector: String::new(),
+        }
+    }
+
+    fn rule(mbps: f64, factor: f64) -> Rule {
+        compile(&alert(mbps, factor)).unwrap()
+    }
+
+    #[test]
+    fn test_update() {
+        assert!(compile(&alert(0.0, 0.0)).is_err());
+        assert!(compile(&alert(0.0, 0.5)).is_err());
+
+        // 绝对阈值
+        let abs = rule(100.0, 0.0);
+        let mut track = Track::default();
+        assert_eq!(update(&abs, &mut track, 150.0, 1000), None);
+        assert_eq!(update(&abs, &mut track, 200.0, 1030), None);
+        assert_eq!(update(&abs, &mut track, 120.0, 1060), Some(Change::Fire { rate: 120.0, baseline: 0.0 }));
+        assert_eq!(update(&abs, &mut track, 180.0, 1090), None);
+        assert_eq!(update(&abs, &mut track, 50.0, 1120), Some(Change::Resolve { rate: 50.0, peak: 200.0 }));
+
+        // 基线倍数, 预热 10 分钟内不检查
+        let rel = rule(0.0, 5.0);
+        let mut track = Track::default();
+        let mut now = 1000;
+        while now <= 1600 {
+            assert_eq!(update(&rel, &mut track, 10.0, now), None);
+            now += 10;
+        }
+        assert!((track.baseline - 10.0).abs() < 1e-6);
+        assert_eq!(update(&rel, &mut track, 80.0, now), None);
+        // 突增期间基线不变
+        assert_eq!(
+            update(&rel, &mut track, 60.0, now + 60),
+            Some(Change::Fire { rate: 60.0, baseline: track.baseline })
+        );
+        assert!((track.baseline - 10.0).abs() < 1e-6);
+        assert!(matches!(update(&rel, &mut track, 12.0, now + 70), Some(Change::Resolve { .. })));
+
+        let stat = HostStat {
+            name: "h1".to_string(),
+            alias: "web".to_string(),
+            network_tx: 100_000_000 / 8,
+            ..Default::default()
+        };
+        assert_eq!(rate(Direction::Out, &stat), 100.0);
+        let msg = message(&abs, &stat, &Change::Fire { rate: 100.0, baseline: 4.0 }, "https://ssr.rs");
+        assert_eq!(
+            msg,
+            "📈 web outbound traffic spike 100.0 Mbps, baseline 4.0 Mbps (25.0x), lasted 60s (spike)\nhttps://ssr.rs/chart/h1/network.png?range=1h"
+        );
+    }
+}
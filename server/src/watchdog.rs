@@ -0,0 +1,93 @@
+#![deny(warnings)]
+// 进程/systemd unit/侦听端口监控, 目标随上报响应下发给客户端, 客户端在下次上报中带回状态, 不在运行及恢复时各通知一次
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::config::Config;
+use crate::notifier;
+use crate::payload::HostStat;
+
+// 已通知不在运行的 (主机, 目标)
+static DOWN: Lazy<Mutex<HashSet<(String, String)>>> = Lazy::new(Default::default);
+
+// 主机自己的配置优先, 否则使用所在分组的配置
+pub fn targets(cfg: &Config, name: &str, gid: &str) -> Vec<String> {
+    match cfg.hosts_map.get(name) {
+        Some(host) => host.watch.clone(),
+        None => cfg.hosts_group_map.get(gid).map(|o| o.watch.clone()).unwrap_or_default(),
+    }
+}
+
+fn changes(down: &mut HashSet<(String, String)>, stat: &HostStat, targets: &[String]) -> Vec<String> {
+    down.retain(|(name, target)| name != &stat.name || targets.contains(target));
+    let mut msgs = Vec::new();
+    for o in stat.watch.iter().filter(|o| targets.contains(&o.name)) {
+        let key = (stat.name.to_string(), o.name.to_string());
+        if !o.running && down.insert(key.clone()) {
+            msgs.push(format!("❗{} watched {} is {}", stat.alias, o.name, o.state));
+        } else if o.running && down.remove(&key) {
+            msgs.push(format!("✅ {} watched {} is {} again", stat.alias, o.name, o.state));
+        }
+    }
+    msgs
+}
+
+// 在入库线程中调用, 通知在单独的线程中发送
+pub fn check(stat: &HostStat) {
+    let Some(cfg) = crate::G_CONFIG.get() else {
+        return;
+    };
+    let targets = targets(cfg, &stat.name, &stat.gid);
+    if targets.is_empty() && stat.watch.is_empty() {
+        return;
+    }
+    let msgs = changes(&mut DOWN.lock().unwrap(), stat, &targets);
+    if !msgs.is_empty() {
+        let stat = stat.clone();
+        thread::spawn(move || {
+            for msg in msgs {
+                notifier::alert_host(&msg, &stat);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stat_common::server_status::WatchResult;
+
+    fn result(name: &str, running: bool, state: &str) -> WatchResult {
+        WatchResult {
+            name: name.to_string(),
+            running,
+            state: state.to_string(),
+            count: running as u32,
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let targets = vec!["nginx".to_string(), "systemd:redis.service".to_string()];
+        let mut down = HashSet::new();
+        let mut stat = HostStat {
+            name: "h1".to_string(),
+            alias: "web".to_string(),
+            watch: vec![result("nginx", true, "running"), result("systemd:redis.service", false, "failed")],
+            ..Default::default()
+        };
+        assert_eq!(changes(&mut down, &stat, &targets), ["❗web watched systemd:redis.service is failed"]);
+        assert!(changes(&mut down, &stat, &targets).is_empty());
+
+        stat.watch = vec![result("nginx", false, "missing"), result("systemd:redis.service", true, "active")];
+        assert_eq!(
+            changes(&mut down, &stat, &targets),
+            ["❗web watched nginx is missing", "✅ web watched systemd:redis.service is active again"]
+        );
+        // 不再监控的目标不通知恢复
+        assert!(changes(&mut down, &stat, &targets[1..]).is_empty());
+        assert!(down.is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+// 端到端测试: 启动编译好的 stat_server, 数据及通知日志都在临时目录, 上报走真实的 /report
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const CONFIG: &str = r#"
+http_addr = "127.0.0.1:{port}"
+db_path = "{dir}/stats.db"
+offline_threshold = 30
+admin_user = "admin"
+admin_pass = "pass"
+hosts = [
+  {name = "h1", password = "p1", alias = "n1", location = "🏠", type = "kvm", labels = "os=debian;env=prod;"},
+  {name = "h2", password = "p2", alias = "n2", location = "🏢", type = "kvm", region = "asia", provider = "aws"},
+  {name = "h3", password = "p3", alias = "n3", location = "us", type = "kvm", notify = false},
+]
+
+[log]
+enabled = true
+log_dir = "{dir}/logs"
+tpl = "{{event}} {{host.name}}"
+"#;
+
+// 退出时结束服务端进程
+struct Server {
+    child: Child,
+    base: String,
+    dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn sample(name: &str, latest_ts: u64) -> Value {
+    json!({
+        "name": name,
+        "uptime": 100,
+        "load_1": 0.1, "load_5": 0.1, "load_15": 0.1,
+        "ping_10010": 0.0, "ping_189": 0.0, "ping_10086": 0.0,
+        "time_10010": 0, "time_189": 0, "time_10086": 0,
+        "tcp": 1, "udp": 1, "process": 1, "thread": 1,
+        "network_rx": 100, "network_tx": 100, "network_in": 1000, "network_out": 1000,
+        "cpu": 5.0,
+        "memory_total": 1000, "memory_used": 500, "swap_total": 0, "swap_used": 0,
+        "hdd_total": 1000, "hdd_used": 100,
+        "latest_ts": latest_ts,
+        "disks": [{"name": "sda", "mount_point": "/", "file_system": "ext4", "total": 100, "used": 50, "free": 50}],
+    })
+}
+
+impl Server {
+    async fn start() -> Server {
+        let dir = std::env::temp_dir().join(format!("ss-harness-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = CONFIG
+            .replace("{port}", &port.to_string())
+            .replace("{dir}", &dir.to_string_lossy());
+        std::fs::write(dir.join("config.toml"), config).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_stat_server"))
+            .arg("-c")
+            .arg(dir.join("config.toml"))
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Server {
+            child,
+            base: format!("http://127.0.0.1:{port}"),
+            dir,
+            client: reqwest::Client::new(),
+        };
+        server
+            .wait(|| async { server.get("/healthz").await.map(|o| o.0 == StatusCode::OK).unwrap_or(false) })
+            .await;
+        server
+    }
+
+    async fn get(&self, path: &str) -> reqwest::Result<(StatusCode, String)> {
+        let resp = self.client.get(format!("{}{}", self.base, path)).send().await?;
+        Ok((resp.status(), resp.text().await?))
+    }
+
+    async fn get_json(&self, path: &str) -> Value {
+        let (status, body) = self.get(path).await.unwrap();
+        assert!(status.is_success(), "GET {path} => {status}");
+        serde_json::from_str(&body).unwrap()
+    }
+
+    async fn report(&self, name: &str, password: &str, latest_ts: u64) -> StatusCode {
+        self.client
+            .post(format!("{}/report", self.base))
+            .basic_auth(name, Some(password))
+            .json(&sample(name, latest_ts))
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    // stats.json 及通知等异步更新, 轮询直到满足或超时
+    async fn wait<F, Fut>(&self, pred: F)
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(15);
+        while !pred().await {
+            assert!(Instant::now() < deadline, "wait timeout");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    // 通知日志, 每行如 `NodeDown h1`
+    fn notifications(&self) -> String {
+        read_dir_all(&self.dir.join("logs"))
+    }
+}
+
+fn read_dir_all(dir: &Path) -> String {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|o| std::fs::read_to_string(o.path()).ok())
+        .collect()
+}
+
+fn host(o: &Value, name: &str) -> Value {
+    o["servers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|s| s["name"] == name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn test_harness() {
+    let server = Server::start().await;
+
+    // 超时的上报 => 下线通知, notify = false 的主机不通知
+    // 同一轮检查通知所有主机, 先于 h1 上报
+    let ts = now() - 60;
+    assert_eq!(server.report("h2", "p2", ts).await, StatusCode::OK);
+    assert_eq!(server.report("h3", "p3", ts).await, StatusCode::OK);
+
+    // report => stats.json
+    assert_eq!(server.report("h1", "p1", now()).await, StatusCode::OK);
+    assert_eq!(server.report("h1", "bad", now()).await, StatusCode::UNAUTHORIZED);
+    server
+        .wait(|| async { host(&server.get_json("/json/stats.json").await, "h1")["online4"] == true })
+        .await;
+    let stats = server.get_json("/json/stats.json").await;
+    assert_eq!(host(&stats, "h1")["alias"], "n1");
+    assert_eq!(host(&stats, "h2")["online4"], false);
+
+    // history
+    assert_eq!(server.report("h1", "p1", now()).await, StatusCode::OK);
+    let history = server.get_json("/json/history.json?label=name:h1").await;
+    assert!(history.to_string().contains("\"h1\""), "{history}");
+
+    // alert
+    server
+        .wait(|| async { server.notifications().lines().any(|o| o == "NodeDown h2") })
+        .await;
+    assert!(!server.notifications().lines().any(|o| o.ends_with(" h3")));
+    assert!(server.dir.join("stats.db").exists());
+    assert_eq!(server.get("/readyz").await.unwrap().0, StatusCode::OK);
+}
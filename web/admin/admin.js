@@ -0,0 +1,271 @@
+'use strict';
+
+// jwt 只保存在当前标签页
+const TOKEN_KEY = 'ssr_admin_token';
+const PAGES = ['hosts', 'alerts', 'notifiers', 'system'];
+
+const $ = (id) => document.getElementById(id);
+
+// 主机名等字段来自上报数据, 只通过 textContent 写入
+function el(tag, attrs, ...children) {
+  const node = document.createElement(tag);
+  Object.entries(attrs || {}).forEach(([k, v]) => {
+    if (k.startsWith('on')) node.addEventListener(k.slice(2), v);
+    else node.setAttribute(k, v);
+  });
+  children.flat().forEach((c) => node.append(c instanceof Node ? c : String(c ?? '')));
+  return node;
+}
+
+function toast(msg) {
+  const t = $('toast');
+  t.textContent = msg;
+  t.hidden = false;
+  clearTimeout(toast.timer);
+  toast.timer = setTimeout(() => { t.hidden = true; }, 8000);
+}
+
+function fmtTime(ts) {
+  return ts ? new Date(ts * 1000).toLocaleString() : '-';
+}
+
+async function api(path, body) {
+  const opts = { headers: { Authorization: `Bearer ${sessionStorage.getItem(TOKEN_KEY)}` } };
+  if (body !== undefined) {
+    opts.method = 'POST';
+    opts.headers['Content-Type'] = 'application/json';
+    opts.body = JSON.stringify(body);
+  }
+  const resp = await fetch(`/api/admin/${path}`, opts);
+  if (resp.status === 401 || resp.status === 403) {
+    logout();
+    throw new Error('session expired');
+  }
+  const data = await resp.json().catch(() => ({}));
+  if (!resp.ok) throw new Error(data.message || data.error || resp.statusText);
+  return data;
+}
+
+// 执行操作后提示结果并刷新当前页面
+async function act(fn, done) {
+  try {
+    const data = await fn();
+    toast(done ? done(data) : 'ok');
+    route();
+  } catch (e) {
+    toast(`❗ ${e.message}`);
+  }
+}
+
+const hostPath = (name, action) => `hosts/${encodeURIComponent(name)}/${action}`;
+
+function hostActions(o) {
+  return [
+    el('button', { onclick: () => act(() => api(hostPath(o.name, 'hidden'), { hidden: !o.hidden })) }, o.hidden ? 'Unhide' : 'Hide'),
+    el('button', {
+      onclick: () => {
+        const to = prompt(`Rename ${o.name} to`, o.name);
+        if (to && to !== o.name) act(() => api(hostPath(o.name, 'rename'), { to }));
+      },
+    }, 'Rename'),
+    el('button', {
+      onclick: () => {
+        const secret = prompt(`Report secret for ${o.name} (empty to clear)`, '');
+        if (secret !== null) act(() => api(hostPath(o.name, 'secret'), { secret }));
+      },
+    }, 'Secret'),
+    el('button', {
+      onclick: () => act(() => api(hostPath(o.name, 'token'), {}), (d) => `token for ${d.name} (shown only once):\n${d.token}`),
+    }, 'Token'),
+    el('button', {
+      onclick: () => {
+        if (confirm(`Revoke the report token of ${o.name}?`)) act(() => api(hostPath(o.name, 'token'), { revoke: true }));
+      },
+    }, 'Revoke'),
+    el('button', {
+      onclick: () => {
+        const duration = prompt(`Silence ${o.name} for`, '2h');
+        if (duration) act(() => api(hostPath(o.name, 'silence'), { duration }));
+      },
+    }, 'Silence'),
+  ];
+}
+
+async function loadHosts() {
+  const data = await api('stats.json');
+  $('hosts').replaceChildren(...(data.servers || []).map((o) => {
+    const online = o.online4 || o.online6;
+    const tags = [el('span', { class: `tag ${online ? 'up' : 'down'}` }, online ? 'online' : 'offline')];
+    if (o.hidden) tags.push(el('span', { class: 'tag' }, 'hidden'));
+    if (o.conflict) tags.push(el('span', { class: 'tag down' }, 'conflict'));
+    return el('tr', null,
+      el('td', null, o.name),
+      el('td', null, o.alias),
+      el('td', null, o.location),
+      el('td', null, tags),
+      el('td', null, fmtTime(o.latest_ts)),
+      el('td', null, hostActions(o)));
+  }));
+}
+
+// datetime-local 输入按本地时间解析, 为空返回 0
+const toTs = (v) => (v ? Math.floor(new Date(v).getTime() / 1000) : 0);
+
+async function loadAlerts() {
+  const [announcements, silences, alertRules, rules] = await Promise.all([
+    api('announcements.json'), api('silences.json'), api('alerts.json'), api('rules.json')]);
+  $('announcements').replaceChildren(...announcements.map((o) => el('tr', null,
+    el('td', null, o.message),
+    el('td', null, el('span', { class: `tag ${o.severity === 'info' ? '' : 'down'}` }, o.severity)),
+    el('td', null, fmtTime(o.start)),
+    el('td', null, fmtTime(o.end)),
+    el('td', null,
+      el('button', {
+        onclick: () => {
+          const message = prompt('Message', o.message);
+          if (message) act(() => api('announcements', { ...o, message }));
+        },
+      }, 'Edit'),
+      el('button', {
+        onclick: () => {
+          if (confirm('Delete this announcement?')) act(() => api('announcements', { id: o.id, delete: true }));
+        },
+      }, 'Delete')))));
+  $('silences').replaceChildren(...silences.map((o) => el('tr', null,
+    el('td', null, o.name),
+    el('td', null, o.until ? fmtTime(o.until) : 'acknowledged'),
+    el('td', null, el('button', { onclick: () => act(() => api(hostPath(o.name, 'silence'), { unsilence: true })) }, 'Unsilence')))));
+  $('alert-rules').replaceChildren(...alertRules.map((o) => el('tr', null,
+    el('td', null, o.name),
+    el('td', null, o.scope),
+    el('td', null, el('code', null, o.expr)),
+    el('td', null, o.firing.map((name) => el('span', { class: 'tag down' }, name))))));
+  $('rules').replaceChildren(...rules.map((o) => el('div', { class: 'rule' },
+    el('strong', null, `${o.name} (${o.kind})`),
+    el('span', { class: `tag ${o.enabled ? 'up' : 'down'}` }, o.enabled ? 'enabled' : 'disabled'),
+    o.selector ? el('span', { class: 'tag' }, o.selector) : '',
+    ...['online_tpl', 'offline_tpl', 'custom_tpl'].filter((k) => o[k]).map((k) => el('div', null, el('em', null, k), el('pre', null, o[k]))))));
+}
+
+async function loadNotifiers() {
+  const notifiers = await api('notifiers.json');
+  $('notifiers').replaceChildren(...notifiers.map((o) => el('tr', null,
+    el('td', null, o.name),
+    el('td', null, o.kind),
+    el('td', null, o.selector || ''),
+    el('td', null, el('button', {
+      onclick: () => act(() => api(`notifiers/${encodeURIComponent(o.name)}/test`, {}), (d) => `${d.notifier}: ${JSON.stringify(d.result)}`),
+    }, 'Send test')))));
+}
+
+async function loadSystem() {
+  const [guard, tasks, metrics] = await Promise.all([api('auth_guard.json'), api('tasks'), api('metrics.json')]);
+  $('auth-guard').textContent = JSON.stringify(guard, null, 2);
+  $('tasks').textContent = JSON.stringify(tasks, null, 2);
+  $('metrics').textContent = JSON.stringify(metrics, null, 2);
+}
+
+// 实时事件流, 登录期间保持连接, 断开后重连
+const LIVE_MAX = 100;
+
+function liveDetail(o) {
+  switch (o.type) {
+    case 'event': return `${o.kind} ${o.alias || o.name} ${o.message}`;
+    case 'silence': return `${o.action} ${o.name}${o.until ? ` until ${fmtTime(o.until)}` : ''}`;
+    case 'delivery': return `${o.notifier} ${o.ok ? 'sent' : `failed: ${o.error}`}`;
+    case 'lagged': return `${o.skipped} events skipped`;
+    default: return JSON.stringify(o);
+  }
+}
+
+function liveStatus(text, ok) {
+  $('live-status').textContent = text;
+  $('live-status').className = `tag ${ok ? 'up' : 'down'}`;
+}
+
+function connectLive() {
+  const token = sessionStorage.getItem(TOKEN_KEY);
+  if (!token || connectLive.ws) return;
+  const proto = location.protocol === 'https:' ? 'wss' : 'ws';
+  const ws = new WebSocket(`${proto}://${location.host}/ws/events?token=${encodeURIComponent(token)}`);
+  connectLive.ws = ws;
+  ws.onopen = () => liveStatus('connected', true);
+  ws.onmessage = (e) => {
+    const o = JSON.parse(e.data);
+    const bad = (o.type === 'event' && o.kind !== 'NodeUp') || (o.type === 'delivery' && !o.ok);
+    $('live').prepend(el('tr', null,
+      el('td', null, fmtTime(o.ts)),
+      el('td', null, el('span', { class: `tag ${bad ? 'down' : ''}` }, o.type)),
+      el('td', null, liveDetail(o))));
+    while ($('live').children.length > LIVE_MAX) $('live').lastChild.remove();
+  };
+  ws.onclose = () => {
+    connectLive.ws = null;
+    liveStatus('disconnected', false);
+    if (sessionStorage.getItem(TOKEN_KEY)) setTimeout(connectLive, 5000);
+  };
+}
+
+const LOADERS = { hosts: loadHosts, alerts: loadAlerts, notifiers: loadNotifiers, system: loadSystem };
+
+function route() {
+  const page = PAGES.includes(location.hash.slice(1)) ? location.hash.slice(1) : PAGES[0];
+  PAGES.forEach((p) => { $(`page-${p}`).hidden = p !== page; });
+  document.querySelectorAll('header nav a').forEach((a) => a.classList.toggle('active', a.hash === `#${page}`));
+  LOADERS[page]().catch((e) => toast(`❗ ${e.message}`));
+}
+
+function logout() {
+  sessionStorage.removeItem(TOKEN_KEY);
+  if (connectLive.ws) connectLive.ws.close();
+  $('app').hidden = true;
+  $('login').hidden = false;
+}
+
+function start() {
+  $('login').hidden = true;
+  $('app').hidden = false;
+  connectLive();
+  route();
+}
+
+$('login-form').addEventListener('submit', async (e) => {
+  e.preventDefault();
+  const form = new FormData(e.target);
+  const resp = await fetch('/api/admin/authorize', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ username: form.get('username'), password: form.get('password') }),
+  });
+  const data = await resp.json().catch(() => ({}));
+  if (!resp.ok) {
+    $('login-error').textContent = data.error || resp.statusText;
+    return;
+  }
+  $('login-error').textContent = '';
+  sessionStorage.setItem(TOKEN_KEY, data.access_token);
+  start();
+});
+
+$('silence-form').addEventListener('submit', (e) => {
+  e.preventDefault();
+  const form = new FormData(e.target);
+  act(() => api(hostPath(form.get('name'), 'silence'), { duration: form.get('duration') }));
+});
+
+$('announcement-form').addEventListener('submit', (e) => {
+  e.preventDefault();
+  const form = new FormData(e.target);
+  act(() => api('announcements', {
+    message: form.get('message'),
+    severity: form.get('severity'),
+    start: toTs(form.get('start')),
+    end: toTs(form.get('end')),
+  }), () => { e.target.reset(); return 'published'; });
+});
+
+$('logout').addEventListener('click', logout);
+window.addEventListener('hashchange', route);
+
+if (sessionStorage.getItem(TOKEN_KEY)) start();
+else logout();
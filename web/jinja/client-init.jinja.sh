@@ -14,6 +14,11 @@ export SSR_PKG_VERSION={{pkg_version}}
 export SSR_CLIENT_OPTS='{{client_opts}}'
 export SSR_WORKSPACE={{workspace}}
 export SSR_CN={{cn}}
+{%- if proxy is startingwith("http") %}
+# 下载客户端也走代理
+export http_proxy='{{proxy}}'
+export https_proxy='{{proxy}}'
+{%- endif %}
 
 Info="\033[32m[info]\033[0m"
 Error="\033[31m[err]\033[0m"